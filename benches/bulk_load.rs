@@ -0,0 +1,45 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use graph::BTreeGraph;
+
+const NODES: u64 = 1_000_000;
+
+fn entries() -> Vec<(u64, u64)> {
+    (0..NODES).map(|i| (i, i)).collect()
+}
+
+fn bulk_load(c: &mut Criterion) {
+    let mut group = c.benchmark_group("load 1M sorted entries");
+    group.sample_size(10);
+    group.bench_function("collect", |b| {
+        b.iter_batched(
+            entries,
+            |entries| entries.into_iter().collect::<BTreeGraph<_, _>>(),
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("from_sorted_iter", |b| {
+        b.iter_batched(
+            entries,
+            |entries| BTreeGraph::from_sorted_iter(entries).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bulk_load);
+criterion_main!(benches);
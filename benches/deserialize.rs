@@ -0,0 +1,96 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use graph::{BTreeGraph, BTreeGraphSeed, GraphDeserializeOptions};
+use serde::de::{
+    value::{Error, MapDeserializer},
+    DeserializeSeed,
+};
+
+const NODES: u64 = 1_000_000;
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// A map deserializer that does not provide a size hint, as is the
+/// case for e.g. some bincode or CBOR streams.
+fn input() -> MapDeserializer<'static, impl Iterator<Item = (u64, u64)>, Error> {
+    MapDeserializer::new((0..NODES).filter(|_| true).map(|i| (i, i)))
+}
+
+fn load(seed: BTreeGraphSeed<u64, u64>) -> BTreeGraph<u64, u64> {
+    seed.deserialize(input()).unwrap()
+}
+
+fn count_allocations(name: &str, seed: impl Fn() -> BTreeGraphSeed<u64, u64>) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let graph = load(seed());
+    let after = ALLOCATIONS.load(Ordering::Relaxed);
+    drop(graph);
+    println!("{name}: {} allocations", after - before);
+}
+
+fn deserialize(c: &mut Criterion) {
+    let default = BTreeGraphSeed::default;
+    let sized = || {
+        BTreeGraphSeed::new(GraphDeserializeOptions {
+            initial_capacity: NODES as usize,
+            ..GraphDeserializeOptions::default()
+        })
+    };
+    let chunked = || {
+        BTreeGraphSeed::new(GraphDeserializeOptions {
+            chunk_capacity: 1 << 16,
+            ..GraphDeserializeOptions::default()
+        })
+    };
+
+    count_allocations("default options", default);
+    count_allocations("initial capacity", sized);
+    count_allocations("chunk capacity", chunked);
+
+    let mut group = c.benchmark_group("deserialize 1M nodes");
+    group.sample_size(10);
+    group.bench_function("default options", |b| b.iter(|| load(default())));
+    group.bench_function("initial capacity", |b| b.iter(|| load(sized())));
+    group.bench_function("chunk capacity", |b| b.iter(|| load(chunked())));
+    group.finish();
+}
+
+criterion_group!(benches, deserialize);
+criterion_main!(benches);
@@ -0,0 +1,42 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use graph::BTreeGraph;
+use rayon::prelude::*;
+
+const NODES: u64 = 800_000;
+
+fn evaluate(value: &mut f64) {
+    *value = (0..64).fold(*value, |acc, i| (acc + i as f64).sqrt());
+}
+
+fn par_values(c: &mut Criterion) {
+    let mut graph = (0..NODES)
+        .map(|i| (i, i as f64))
+        .collect::<BTreeGraph<_, _>>();
+
+    let mut group = c.benchmark_group("evaluate 800k nodes");
+    group.sample_size(10);
+    group.bench_function("values_mut", |b| {
+        b.iter(|| graph.values_mut().for_each(evaluate))
+    });
+    group.bench_function("par_values_mut", |b| {
+        b.iter(|| graph.par_values_mut().for_each(evaluate))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, par_values);
+criterion_main!(benches);
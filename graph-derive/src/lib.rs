@@ -0,0 +1,234 @@
+/******************************************************************************
+ * Copyright 2025 ContinuousC                                                 *
+ *                                                                            *
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may *
+ * not use this file except in compliance with the License. You may  obtain a *
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          *
+ *                                                                            *
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software *
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT *
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the *
+ * License for the  specific language  governing permissions  and limitations *
+ * under the License.                                                         *
+ ******************************************************************************/
+
+//! Derive macros for the `graph` crate. Use them through the
+//! `derive` feature of `graph`, which re-exports them.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Ident, TokenStream as TokenStream2, TokenTree};
+use quote::{format_ident, quote, ToTokens};
+use syn::{
+    parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Fields, Member, Type, WhereClause,
+};
+
+/// Derive `graph::VisitRefs` by visiting every field in turn. All
+/// fields must implement `VisitRefs` for the node type, except those
+/// marked `#[graph(skip)]`.
+///
+/// The node type defaults to the type itself. Other types, e.g. an
+/// edge type holding references to nodes, set it with
+/// `#[graph(node = Node)]`.
+///
+/// With `#[graph(key = K)]`, `graph::VisitRefsBy<K, _>` is derived as
+/// well, as required by `replace_ref_by`. The visited fields must then
+/// implement it too.
+#[proc_macro_derive(VisitRefs, attributes(graph))]
+pub fn derive_visit_refs(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// A struct or enum variant: the path used in patterns and the
+/// visited fields.
+struct Variant<'a> {
+    path: TokenStream2,
+    fields: Vec<(Member, &'a Type)>,
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let (node, key) = container_attrs(&input.attrs)?;
+    let node = node.unwrap_or_else(|| parse_quote!(#name #ty_generics));
+
+    let variants = match &input.data {
+        Data::Struct(data) => vec![Variant {
+            path: quote!(Self),
+            fields: visited_fields(&data.fields)?,
+        }],
+        Data::Enum(data) => data
+            .variants
+            .iter()
+            .map(|variant| {
+                let ident = &variant.ident;
+                Ok(Variant {
+                    path: quote!(Self::#ident),
+                    fields: visited_fields(&variant.fields)?,
+                })
+            })
+            .collect::<syn::Result<_>>()?,
+        Data::Union(_) => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "VisitRefs cannot be derived for unions",
+            ))
+        }
+    };
+
+    /* Only field types mentioning a type parameter are bounded, so
+     * that recursive types (e.g. `Box<Self>` fields) do not lead to
+     * cyclic bounds. */
+    let params = input
+        .generics
+        .type_params()
+        .map(|param| &param.ident)
+        .collect::<Vec<_>>();
+    let bounded = |bound: TokenStream2| {
+        let mut where_clause = where_clause.cloned().unwrap_or_else(|| WhereClause {
+            where_token: Default::default(),
+            predicates: Default::default(),
+        });
+        for variant in &variants {
+            for (_, ty) in &variant.fields {
+                if mentions(ty.to_token_stream(), &params) {
+                    where_clause.predicates.push(parse_quote!(#ty: #bound));
+                }
+            }
+        }
+        where_clause
+    };
+    let where_clause = bounded(quote!(::graph::VisitRefs<#node>));
+
+    /* Match arms calling `method` on every visited field. */
+    let arms = |method: TokenStream2| {
+        variants
+            .iter()
+            .map(|variant| {
+                let path = &variant.path;
+                let members = variant.fields.iter().map(|(member, _)| member);
+                let bindings = (0..variant.fields.len())
+                    .map(|i| format_ident!("__field{}", i))
+                    .collect::<Vec<_>>();
+                quote! {
+                    #path { #(#members: #bindings,)* .. } => {
+                        #(#method(#bindings, f);)*
+                    }
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+    let arms_mut = arms(quote!(::graph::VisitRefs::<#node>::visit_refs_mut));
+    let arms_by_mut = key
+        .as_ref()
+        .map(|key| arms(quote!(::graph::VisitRefsBy::<#key, #node>::visit_refs_by_mut)));
+    let arms = arms(quote!(::graph::VisitRefs::<#node>::visit_refs));
+
+    /* Matching on a reference to an empty enum is not exhaustive. */
+    let scrutinee = if variants.is_empty() {
+        quote!(*self)
+    } else {
+        quote!(self)
+    };
+
+    let visit_refs_by = key.zip(arms_by_mut).map(|(key, arms)| {
+        let where_clause = bounded(quote!(::graph::VisitRefsBy<#key, #node>));
+        quote! {
+            #[automatically_derived]
+            impl #impl_generics ::graph::VisitRefsBy<#key, #node> for #name #ty_generics
+            #where_clause
+            {
+                #[allow(unused_variables)]
+                fn visit_refs_by_mut(
+                    &mut self,
+                    f: &mut impl FnMut(&#key, &mut ::graph::Ref<#node>) -> ::core::option::Option<#key>,
+                ) {
+                    match #scrutinee {
+                        #(#arms)*
+                    }
+                }
+            }
+        }
+    });
+
+    Ok(quote! {
+        #[automatically_derived]
+        impl #impl_generics ::graph::VisitRefs<#node> for #name #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn visit_refs(&self, f: &mut impl FnMut(&::graph::Ref<#node>)) {
+                match #scrutinee {
+                    #(#arms)*
+                }
+            }
+
+            #[allow(unused_variables)]
+            fn visit_refs_mut(&mut self, f: &mut impl FnMut(&mut ::graph::Ref<#node>)) {
+                match #scrutinee {
+                    #(#arms_mut)*
+                }
+            }
+        }
+
+        #visit_refs_by
+    })
+}
+
+/// The node and key types set with `#[graph(node = ...)]` and
+/// `#[graph(key = ...)]`, if any.
+fn container_attrs(attrs: &[Attribute]) -> syn::Result<(Option<Type>, Option<Type>)> {
+    let (mut node, mut key) = (None, None);
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("graph")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("node") {
+                node = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("key") {
+                key = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("unsupported graph attribute"))
+            }
+        })?;
+    }
+    Ok((node, key))
+}
+
+/// The fields to visit, i.e. those not marked `#[graph(skip)]`.
+fn visited_fields(fields: &Fields) -> syn::Result<Vec<(Member, &Type)>> {
+    let mut visited = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let mut skip = false;
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("graph"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("skip") {
+                    skip = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported graph attribute"))
+                }
+            })?;
+        }
+        if !skip {
+            let member = match &field.ident {
+                Some(ident) => Member::Named(ident.clone()),
+                None => Member::Unnamed(i.into()),
+            };
+            visited.push((member, &field.ty));
+        }
+    }
+    Ok(visited)
+}
+
+fn mentions(tokens: TokenStream2, params: &[&Ident]) -> bool {
+    tokens.into_iter().any(|token| match token {
+        TokenTree::Ident(ident) => params.contains(&&ident),
+        TokenTree::Group(group) => mentions(group.stream(), params),
+        _ => false,
+    })
+}
@@ -0,0 +1,400 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+use alloc::{format, string::String, vec::Vec};
+#[cfg(not(feature = "debug-borrows"))]
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+#[cfg(feature = "runtime-borrow-check")]
+use core::sync::atomic::AtomicIsize;
+#[cfg(feature = "debug-borrows")]
+use core::sync::atomic::AtomicU64;
+#[cfg(any(feature = "debug-borrows", feature = "runtime-borrow-check"))]
+use core::sync::atomic::Ordering;
+
+use crate::Ref;
+
+#[cfg(feature = "debug-borrows")]
+static NEXT_SCOPE: AtomicU64 = AtomicU64::new(1);
+
+/// A scope in which mutable borrows of multiple nodes are handed out
+/// at once (e.g. by a mutable iterator), relying on the nodes being
+/// distinct. Since the borrows are plain references, there is no way
+/// to tell when they end, so they are tracked until the scope itself
+/// is dropped.
+///
+/// With the `debug-borrows` feature, each slot records the live scope
+/// in which it is mutably borrowed, if any. A second mutable borrow of
+/// the same node, within the same or another live scope, panics, as
+/// does a plain or guarded borrow of the node while the scope lives.
+/// On drop, the scope releases its slots by scanning `nodes` again.
+/// A scope that is leaked (e.g. through `mem::forget`) leaves its
+/// slots marked. Without the feature, this is a no-op.
+pub(crate) struct BorrowScope<'a, T: 'a, I>
+where
+    I: Iterator<Item = &'a Ref<T>> + Clone,
+{
+    #[cfg(feature = "debug-borrows")]
+    id: u64,
+    #[cfg(feature = "debug-borrows")]
+    nodes: I,
+    #[cfg(not(feature = "debug-borrows"))]
+    _nodes: PhantomData<I>,
+}
+
+impl<'a, T: 'a, I> BorrowScope<'a, T, I>
+where
+    I: Iterator<Item = &'a Ref<T>> + Clone,
+{
+    /// Open a scope borrowing (a subset of) `nodes`.
+    pub(crate) fn new(nodes: I) -> Self {
+        #[cfg(not(feature = "debug-borrows"))]
+        let _ = nodes;
+        Self {
+            #[cfg(feature = "debug-borrows")]
+            id: NEXT_SCOPE.fetch_add(1, Ordering::Relaxed),
+            #[cfg(feature = "debug-borrows")]
+            nodes,
+            #[cfg(not(feature = "debug-borrows"))]
+            _nodes: PhantomData,
+        }
+    }
+
+    /// Record a mutable borrow of `node`. Panics if the node is
+    /// already mutably borrowed in this or another live scope. The
+    /// message includes the node's slot and the string returned by
+    /// `describe`.
+    ///
+    /// Safety: `node` must be valid for the graph being borrowed.
+    #[inline]
+    #[track_caller]
+    pub(crate) unsafe fn check<F>(&self, node: &Ref<T>, describe: F)
+    where
+        F: FnOnce() -> String,
+    {
+        #[cfg(feature = "debug-borrows")]
+        {
+            let slot = node.as_ptr();
+            match (*slot)
+                .borrow
+                .compare_exchange(0, self.id, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => {}
+                Err(id) if id == self.id => panic!(
+                    "aliasing violation: node in slot {slot:p}{} is mutably borrowed twice",
+                    describe()
+                ),
+                Err(_) => panic!(
+                    "aliasing violation: node in slot {slot:p}{} is mutably borrowed \
+                     by another live iterator",
+                    describe()
+                ),
+            }
+        }
+        #[cfg(not(feature = "debug-borrows"))]
+        let _ = (node, describe);
+    }
+}
+
+#[cfg(feature = "debug-borrows")]
+impl<'a, T: 'a, I> Drop for BorrowScope<'a, T, I>
+where
+    I: Iterator<Item = &'a Ref<T>> + Clone,
+{
+    fn drop(&mut self) {
+        for node in self.nodes.clone() {
+            /* Safety: the nodes outlive the scope. */
+            let borrow = unsafe { &(*node.as_ptr()).borrow };
+            let _ = borrow.compare_exchange(self.id, 0, Ordering::Relaxed, Ordering::Relaxed);
+        }
+    }
+}
+
+/// With the `debug-borrows` feature, panic if `node` is mutably
+/// borrowed in a live `BorrowScope`.
+///
+/// Safety: `node` must be valid for the graph being borrowed.
+#[inline]
+#[track_caller]
+unsafe fn check_scopes<T>(node: &Ref<T>) {
+    #[cfg(feature = "debug-borrows")]
+    {
+        let slot = node.as_ptr();
+        if (*slot).borrow.load(Ordering::Relaxed) != 0 {
+            panic!(
+                "aliasing violation: node in slot {slot:p} is mutably borrowed by a live iterator"
+            );
+        }
+    }
+    #[cfg(not(feature = "debug-borrows"))]
+    let _ = node;
+}
+
+/// A shared borrow of a node, returned by `Graph::borrow_guard`.
+/// With the `runtime-borrow-check` feature, the borrow is registered
+/// with the node's slot until the guard is dropped, as with a
+/// `RefCell`. Without the feature, this is a plain reference.
+pub struct BorrowGuard<'a, T> {
+    value: &'a T,
+    #[cfg(feature = "runtime-borrow-check")]
+    _registration: Registration<'a>,
+}
+
+/// A mutable borrow of a node, returned by `Graph::borrow_guard_mut`.
+/// See `BorrowGuard`.
+pub struct BorrowGuardMut<'a, T> {
+    value: &'a mut T,
+    #[cfg(feature = "runtime-borrow-check")]
+    _registration: Registration<'a>,
+}
+
+impl<'a, T> BorrowGuard<'a, T> {
+    /// Register a shared borrow of `node`. Panics if the node is
+    /// mutably borrowed through a guard or a live `BorrowScope`, or if
+    /// it is empty.
+    ///
+    /// Safety: `node` must be valid for the graph being borrowed.
+    #[track_caller]
+    pub(crate) unsafe fn new(node: &Ref<T>) -> Self {
+        check_scopes(node);
+        #[cfg(feature = "runtime-borrow-check")]
+        let registration = Registration::new(node, false);
+        Self {
+            value: node.get_unchecked(),
+            #[cfg(feature = "runtime-borrow-check")]
+            _registration: registration,
+        }
+    }
+}
+
+impl<'a, T> BorrowGuardMut<'a, T> {
+    /// Register a mutable borrow of `node`. Panics if the node is
+    /// borrowed through another guard or a live `BorrowScope`, or if
+    /// it is empty.
+    ///
+    /// Safety: `node` must be valid for the graph being borrowed.
+    #[track_caller]
+    pub(crate) unsafe fn new(node: &Ref<T>) -> Self {
+        check_scopes(node);
+        #[cfg(feature = "runtime-borrow-check")]
+        let registration = Registration::new(node, true);
+        Self {
+            value: node.get_unchecked_mut(),
+            #[cfg(feature = "runtime-borrow-check")]
+            _registration: registration,
+        }
+    }
+}
+
+impl<T> Deref for BorrowGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T> Deref for BorrowGuardMut<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T> DerefMut for BorrowGuardMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value
+    }
+}
+
+/// A borrow registered with a slot's guard count, which is released
+/// when dropped. The count is positive for shared borrows and -1 for
+/// a mutable one.
+#[cfg(feature = "runtime-borrow-check")]
+struct Registration<'a> {
+    guards: &'a AtomicIsize,
+    exclusive: bool,
+}
+
+#[cfg(feature = "runtime-borrow-check")]
+impl Registration<'_> {
+    #[track_caller]
+    unsafe fn new<T>(node: &Ref<T>, exclusive: bool) -> Self {
+        let slot = node.as_ptr();
+        let guards = &(*slot).guards;
+        let registered = if exclusive {
+            guards
+                .compare_exchange(0, -1, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        } else {
+            guards
+                .fetch_update(Ordering::Acquire, Ordering::Relaxed, |n| {
+                    (n >= 0).then_some(n + 1)
+                })
+                .is_ok()
+        };
+        if !registered {
+            panic!("aliasing violation: node in slot {slot:p} is already borrowed through a guard");
+        }
+        Self { guards, exclusive }
+    }
+}
+
+#[cfg(feature = "runtime-borrow-check")]
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        if self.exclusive {
+            self.guards.store(0, Ordering::Release);
+        } else {
+            self.guards.fetch_sub(1, Ordering::Release);
+        }
+    }
+}
+
+/// With the `runtime-borrow-check` feature, panic if `node` is
+/// borrowed through a guard that conflicts with a plain borrow (any
+/// guard if `exclusive`, a mutable one otherwise). With the
+/// `debug-borrows` feature, panic if it is mutably borrowed in a live
+/// `BorrowScope`. Without either feature, this is a no-op.
+///
+/// Safety: `node` must be valid for the graph being borrowed.
+#[inline]
+#[track_caller]
+pub(crate) unsafe fn check_unguarded<T>(node: &Ref<T>, exclusive: bool) {
+    check_scopes(node);
+    #[cfg(feature = "runtime-borrow-check")]
+    {
+        let slot = node.as_ptr();
+        let guards = (*slot).guards.load(Ordering::Acquire);
+        if guards < 0 || (exclusive && guards > 0) {
+            panic!("aliasing violation: node in slot {slot:p} is already borrowed through a guard");
+        }
+    }
+    #[cfg(not(feature = "runtime-borrow-check"))]
+    let _ = (node, exclusive);
+}
+
+/// Describe the positions of the index entries referring to `node`,
+/// for use in `BorrowScope::check` panic messages.
+pub(crate) fn index_entries<'a, T: 'a>(
+    index: impl Iterator<Item = &'a Ref<T>>,
+    node: &Ref<T>,
+) -> String {
+    let entries = index
+        .enumerate()
+        .filter(|(_, other)| *other == node)
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    format!(" (index entries {entries:?})")
+}
+
+#[cfg(test)]
+mod test {
+
+    #[cfg(feature = "debug-borrows")]
+    use alloc::string::String;
+
+    use crate::Graph;
+
+    #[test]
+    fn borrow_guards() {
+        let mut graph = Graph::new();
+        let a = graph.insert(1);
+        let b = graph.insert(2);
+
+        *graph.borrow_guard_mut(&a) += 10;
+        let (x, y) = (graph.borrow_guard(&a), graph.borrow_guard(&a));
+        assert_eq!(*x + *y + *graph.borrow_guard(&b), 24);
+        assert_eq!(*graph.borrow(&a), 11);
+    }
+
+    /// Simulate a mutable borrow through a smuggled reference while
+    /// a guard is held. The registration panics before the mutable
+    /// reference is created.
+    #[cfg(feature = "runtime-borrow-check")]
+    #[test]
+    #[should_panic(expected = "is already borrowed through a guard")]
+    fn conflicting_guards() {
+        let mut graph = Graph::new();
+        let a = graph.insert(1);
+        let _shared = graph.borrow_guard(&a);
+        let _ = unsafe { super::BorrowGuardMut::new(&a) };
+    }
+
+    #[cfg(feature = "runtime-borrow-check")]
+    #[test]
+    #[should_panic(expected = "is already borrowed through a guard")]
+    fn guard_conflicts_with_plain_borrow() {
+        let mut graph = Graph::new();
+        let a = graph.insert(1);
+        let _exclusive = unsafe { super::BorrowGuardMut::new(&a) };
+        graph.borrow(&a);
+    }
+
+    /// Simulate two mutable iterators handing out the same node,
+    /// e.g. two graphs sharing an index entry by mistake.
+    #[cfg(feature = "debug-borrows")]
+    #[test]
+    #[should_panic(expected = "is mutably borrowed by another live iterator")]
+    fn overlapping_scopes() {
+        let mut graph = Graph::new();
+        let a = graph.insert(1);
+        let b = graph.insert(2);
+        let nodes = [a.clone(), b];
+        let first = super::BorrowScope::new(nodes.iter());
+        let second = super::BorrowScope::new(nodes[..1].iter());
+        unsafe {
+            first.check(&a, String::new);
+            second.check(&a, String::new);
+        }
+    }
+
+    #[cfg(feature = "debug-borrows")]
+    #[test]
+    #[should_panic(expected = "is mutably borrowed by a live iterator")]
+    fn borrow_during_scope() {
+        let mut graph = Graph::new();
+        let a = graph.insert(1);
+        let nodes = [a.clone()];
+        let scope = super::BorrowScope::new(nodes.iter());
+        unsafe { scope.check(&a, String::new) };
+        graph.borrow(&a);
+    }
+
+    #[cfg(feature = "debug-borrows")]
+    #[test]
+    fn scopes_release_on_drop() {
+        let mut graph = Graph::new();
+        let a = graph.insert(1);
+        let nodes = [a.clone()];
+        for _ in 0..2 {
+            let scope = super::BorrowScope::new(nodes.iter());
+            unsafe { scope.check(&a, String::new) };
+        }
+        assert_eq!(*graph.borrow(&a), 1);
+        *graph.borrow_many_mut([&a])[0] += 1;
+        *graph.borrow_mut(&a) += 1;
+        assert_eq!(*graph.borrow(&a), 3);
+    }
+
+    #[cfg(feature = "runtime-borrow-check")]
+    #[test]
+    fn guards_release_on_drop() {
+        let mut graph = Graph::new();
+        let a = graph.insert(1);
+        drop(graph.borrow_guard(&a));
+        *graph.borrow_guard_mut(&a) += 1;
+        *graph.borrow_mut(&a) += 1;
+        assert_eq!(*graph.borrow_guard(&a), 3);
+    }
+}
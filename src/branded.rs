@@ -0,0 +1,377 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+//! Graphs whose references are tied to their graph at the type level.
+//!
+//! A `BrandedGraph<'brand, T>` only exists inside the closure passed
+//! to [`BrandedGraph::scope`], which introduces a fresh, invariant
+//! `'brand` lifetime. References returned by the graph carry the
+//! same brand, so using them with another graph does not compile:
+//!
+//! ```compile_fail
+//! use graph::BrandedGraph;
+//!
+//! BrandedGraph::scope(|mut a| {
+//!     BrandedGraph::scope(|b| {
+//!         let node = a.insert(1u32);
+//!         b.borrow(&node);
+//!     })
+//! });
+//! ```
+//!
+//! Nor can a reference escape its scope:
+//!
+//! ```compile_fail
+//! use graph::BrandedGraph;
+//!
+//! let node = BrandedGraph::scope(|mut graph| graph.insert(1));
+//! ```
+//!
+//! Nodes that store references to other nodes mention the brand in
+//! their type. Such graphs are created through a [`BrandedNodes`]
+//! implementation, which names the node type for any brand:
+//!
+//! ```
+//! use graph::{BrandedNodes, BrandedRef};
+//!
+//! struct Node<'brand> {
+//!     value: u32,
+//!     next: Option<BrandedRef<'brand, Node<'brand>>>,
+//! }
+//!
+//! struct Nodes;
+//!
+//! impl BrandedNodes for Nodes {
+//!     type Node<'brand> = Node<'brand>;
+//! }
+//!
+//! let sum = Nodes::scope(|mut graph| {
+//!     let last = graph.insert(Node { value: 2, next: None });
+//!     let first = graph.insert(Node { value: 1, next: Some(last) });
+//!     let next = graph.borrow(&first).next.as_ref().unwrap();
+//!     graph.borrow(&first).value + graph.borrow(next).value
+//! });
+//! assert_eq!(sum, 3);
+//! ```
+//!
+//! An existing graph can be branded temporarily with
+//! [`Graph::scope`], e.g. for a hot loop. Its references are branded
+//! once with [`BrandedGraph::brand`], which checks the generation:
+//!
+//! ```
+//! use graph::Graph;
+//!
+//! let mut graph = Graph::new();
+//! let nodes = graph.insert_batch(0..10u32);
+//! graph.scope(|graph| {
+//!     let nodes = nodes.iter().map(|node| graph.brand(node).unwrap()).collect::<Vec<_>>();
+//!     for _ in 0..100 {
+//!         nodes.iter().for_each(|node| *graph.borrow_mut(node) += 1);
+//!     }
+//! });
+//! assert_eq!(*graph.borrow(&nodes[9]), 109);
+//! ```
+//!
+//! Since the brand guarantees that the reference belongs to the
+//! graph, borrowing skips the generation check. Borrowing a node
+//! that was promised but not yet created, or that was removed,
+//! still panics.
+
+use core::{
+    fmt::{self, Debug, Formatter},
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+};
+
+use crate::{Graph, Ref};
+
+/// An invariant lifetime marker.
+type Brand<'brand> = PhantomData<fn(&'brand ()) -> &'brand ()>;
+
+/// A graph whose references are branded with the `'brand` lifetime.
+pub struct BrandedGraph<'brand, T> {
+    graph: Graph<T>,
+    brand: Brand<'brand>,
+}
+
+/// A reference to a node in the `BrandedGraph` with the same brand.
+pub struct BrandedRef<'brand, T> {
+    node: Ref<T>,
+    brand: Brand<'brand>,
+}
+
+/// A family of node types, parameterized by the brand, for graphs
+/// whose nodes hold branded references.
+pub trait BrandedNodes {
+    type Node<'brand>;
+
+    /// Run `f` with a new, empty graph carrying a unique brand.
+    fn scope<F, R>(f: F) -> R
+    where
+        F: for<'brand> FnOnce(BrandedGraph<'brand, Self::Node<'brand>>) -> R,
+    {
+        f(BrandedGraph::new())
+    }
+}
+
+impl<T> BrandedGraph<'_, T> {
+    /// Run `f` with a new, empty graph carrying a unique brand. The
+    /// node type cannot depend on the brand; use
+    /// [`BrandedNodes::scope`] for nodes holding branded references.
+    pub fn scope<F, R>(f: F) -> R
+    where
+        F: for<'brand> FnOnce(BrandedGraph<'brand, T>) -> R,
+    {
+        f(BrandedGraph::new())
+    }
+}
+
+impl<T> Graph<T> {
+    /// Run `f` with this graph carrying a unique brand. References
+    /// obtained before can be branded with `BrandedGraph::brand`. The
+    /// graph keeps its generation, so they remain valid afterwards.
+    pub fn scope<F, R>(&mut self, f: F) -> R
+    where
+        F: for<'brand> FnOnce(&mut BrandedGraph<'brand, T>) -> R,
+    {
+        let mut restore = Restore {
+            branded: BrandedGraph {
+                graph: core::mem::take(self),
+                brand: PhantomData,
+            },
+            target: self,
+        };
+        f(&mut restore.branded)
+    }
+}
+
+/// Puts a graph back after `Graph::scope`, even when unwinding.
+struct Restore<'a, 'brand, T> {
+    target: &'a mut Graph<T>,
+    branded: BrandedGraph<'brand, T>,
+}
+
+impl<T> Drop for Restore<'_, '_, T> {
+    fn drop(&mut self) {
+        core::mem::swap(self.target, &mut self.branded.graph);
+    }
+}
+
+impl<'brand, T> BrandedGraph<'brand, T> {
+    /// Only called by the scope functions, which guarantee that the
+    /// brand is unique.
+    fn new() -> Self {
+        Self {
+            graph: Graph::new(),
+            brand: PhantomData,
+        }
+    }
+
+    /// Insert a node into the graph.
+    pub fn insert(&mut self, value: T) -> BrandedRef<'brand, T> {
+        BrandedRef::new(self.graph.insert(value))
+    }
+
+    /// Reserve an empty slot in the graph, to be filled by `create`.
+    pub fn promise(&mut self) -> BrandedRef<'brand, T> {
+        BrandedRef::new(self.graph.promise())
+    }
+
+    /// Create a node that has previously been promised or
+    /// removed. Panics if the node already exists.
+    pub fn create(&mut self, node: &BrandedRef<'brand, T>, value: T) {
+        let r = unsafe { node.node.try_replace_unchecked(value) };
+        assert!(r.is_none(), "node already exists");
+    }
+
+    /// Remove the value from the graph. Panics if the node was
+    /// previously removed.
+    pub fn remove(&mut self, node: BrandedRef<'brand, T>) -> T {
+        unsafe { node.node.try_remove_unchecked() }.expect("node was already removed")
+    }
+
+    /// Borrow the value from the graph. Panics if the node was
+    /// removed or if it was promised but not yet created.
+    pub fn borrow(&self, node: &BrandedRef<'brand, T>) -> &T {
+        unsafe { node.node.get_unchecked() }
+    }
+
+    /// Mutably borrow the value from the graph. Panics if the node
+    /// was removed or if it was promised but not yet created.
+    pub fn borrow_mut(&mut self, node: &BrandedRef<'brand, T>) -> &mut T {
+        unsafe { node.node.get_unchecked_mut() }
+    }
+
+    /// Mutably borrow several nodes at once. The nodes are checked
+    /// to be distinct. Panics if a node was removed or if it was
+    /// promised but not yet created.
+    pub fn borrow_many_mut<const N: usize>(
+        &mut self,
+        nodes: [&BrandedRef<'brand, T>; N],
+    ) -> [&mut T; N] {
+        assert!(
+            nodes
+                .iter()
+                .enumerate()
+                .all(|(i, node)| nodes[..i].iter().all(|other| other != node)),
+            "borrowed the same node more than once"
+        );
+        nodes.map(|node| unsafe { node.node.get_unchecked_mut() })
+    }
+
+    /// Brand a reference obtained outside of the scope, after
+    /// checking that it belongs to the graph. Since the branded
+    /// methods do not check references, this check is kept with the
+    /// `unsafe` feature.
+    pub fn brand(&self, node: &Ref<T>) -> Option<BrandedRef<'brand, T>> {
+        self.graph.owns(node).then(|| BrandedRef::new(node.clone()))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.graph.iter_mut()
+    }
+
+    /// Give up the brand and return the underlying graph. Branded
+    /// references cannot be used with the returned graph.
+    pub fn into_inner(self) -> Graph<T> {
+        self.graph
+    }
+}
+
+impl<T> BrandedRef<'_, T> {
+    fn new(node: Ref<T>) -> Self {
+        Self {
+            node,
+            brand: PhantomData,
+        }
+    }
+
+    /// The unbranded reference, for use with `Graph::borrow` on the
+    /// graph returned by `BrandedGraph::into_inner`.
+    pub fn unbrand(&self) -> &Ref<T> {
+        &self.node
+    }
+}
+
+impl<T> Clone for BrandedRef<'_, T> {
+    fn clone(&self) -> Self {
+        Self {
+            node: self.node.clone(),
+            brand: PhantomData,
+        }
+    }
+}
+
+impl<T> PartialEq for BrandedRef<'_, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.node == other.node
+    }
+}
+
+impl<T> Eq for BrandedRef<'_, T> {}
+
+impl<T> Hash for BrandedRef<'_, T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.node.hash(state)
+    }
+}
+
+impl<T> Debug for BrandedRef<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("BrandedRef")
+            .field(&self.node.as_ptr())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use crate::{BrandedGraph, BrandedNodes, BrandedRef, Graph};
+
+    #[test]
+    fn cycle() {
+        struct Node<'brand> {
+            value: u32,
+            next: Option<BrandedRef<'brand, Node<'brand>>>,
+        }
+
+        struct Nodes;
+
+        impl BrandedNodes for Nodes {
+            type Node<'brand> = Node<'brand>;
+        }
+
+        let sum = Nodes::scope(|mut graph| {
+            let a = graph.promise();
+            let b = graph.insert(Node {
+                value: 2,
+                next: Some(a.clone()),
+            });
+            graph.create(
+                &a,
+                Node {
+                    value: 1,
+                    next: Some(b.clone()),
+                },
+            );
+            graph.borrow_mut(&b).value += 1;
+            let next = graph.borrow(&a).next.clone().unwrap();
+            graph.borrow(&a).value + graph.borrow(&next).value
+        });
+        assert_eq!(sum, 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "borrowed node that was removed")]
+    fn removed() {
+        BrandedGraph::scope(|mut graph| {
+            let node = graph.insert(1);
+            graph.remove(node.clone());
+            graph.borrow(&node);
+        })
+    }
+
+    #[test]
+    fn graph_scope() {
+        let mut graph = Graph::new();
+        let a = graph.insert(1);
+        let b = graph.insert(2);
+        let other = Graph::<i32>::new().insert(3);
+
+        let sum = graph.scope(|graph| {
+            assert!(graph.brand(&other).is_none());
+            let (a, b) = (graph.brand(&a).unwrap(), graph.brand(&b).unwrap());
+            let c = graph.insert(3);
+            let [x, y] = graph.borrow_many_mut([&a, &b]);
+            std::mem::swap(x, y);
+            *graph.borrow_mut(&c) *= 10;
+            graph.borrow(&a) + graph.borrow(&b) + graph.borrow(&c)
+        });
+
+        assert_eq!(sum, 33);
+        assert_eq!(*graph.borrow(&a), 2);
+        assert_eq!(*graph.borrow(&b), 1);
+        assert_eq!(graph.iter_mut().count(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "borrowed the same node more than once")]
+    fn borrow_many_mut_duplicate() {
+        BrandedGraph::scope(|mut graph| {
+            let node = graph.insert(1);
+            graph.borrow_many_mut([&node, &node]);
+        })
+    }
+}
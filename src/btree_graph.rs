@@ -12,40 +12,122 @@
  * under the License.                                                         * 
  ******************************************************************************/
 
-use std::{
-    borrow::Borrow,
+use alloc::{
+    boxed::Box,
     collections::{btree_map, BTreeMap},
+    vec::Vec,
 };
+#[cfg(feature = "std")]
+use core::hash::{BuildHasher, Hash};
 #[cfg(feature = "serde")]
-use std::{fmt::Formatter, marker::PhantomData};
+use core::marker::PhantomData;
+use core::{
+    borrow::Borrow,
+    fmt::{Debug, Display, Formatter},
+    iter::FusedIterator,
+    ops::Bound,
+};
 
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 #[cfg(feature = "serde")]
 use serde::{
-    de::{Deserializer, MapAccess, Visitor},
+    de::{DeserializeSeed, Deserializer, Error, MapAccess, Visitor},
     ser::{SerializeMap, Serializer},
     Deserialize, Serialize,
 };
 #[cfg(feature = "tsify")]
 use tsify::Tsify;
 
+use crate::borrow_check::{index_entries, BorrowScope};
+#[cfg(feature = "serde")]
+use crate::graph::GraphDeserializeOptions;
+use crate::observer::{replace_observed, Observer};
 use crate::reference::Ref;
-use crate::{graph::Graph, RefBy};
+use crate::refmap::collect_unique;
+#[cfg(feature = "std")]
+use crate::HashGraph;
+use crate::{
+    graph::{BorrowManyError, CreateError, Graph},
+    GraphMut, GraphObserver, KeysView, ProjectedRef, RefBy, RefByRef, RefMap, Resolve, VisitRefs,
+    VisitRefsBy,
+};
 
 /// A graph structure that allows pointer-based references between
 /// nodes.
 #[cfg_attr(feature = "tsify", derive(Tsify))]
 #[cfg_attr(
     feature = "tsify",
-    tsify(from_wasm_abi, into_wasm_abi, type = "{ [key: K]: V }")
+    tsify(
+        from_wasm_abi,
+        into_wasm_abi,
+        type = "K extends string | number ? Record<K, V> : [K, V][]"
+    )
 )]
 pub struct BTreeGraph<K, V> {
     graph: Graph<V>,
     index: BTreeMap<K, Ref<V>>,
+    observer: Observer<K, V>,
+    auto_compact: AutoCompact<BTreeGraph<K, V>>,
 }
 
+/// The fragmentation threshold and the graph's `compact` method,
+/// which is captured when auto-compaction is enabled since it
+/// requires bounds that the removal methods do not have.
+pub(crate) type AutoCompact<G> = Option<(f32, fn(&mut G))>;
+
 pub struct Entry<'a, K, V> {
     graph: &'a mut Graph<V>,
     entry: btree_map::Entry<'a, K, Ref<V>>,
+    observer: &'a mut Observer<K, V>,
+}
+
+/// A cursor over the graph's ordered index. The cursor points to a
+/// node or, past either end, to a "ghost" position, from which it
+/// moves to the first node with `move_next` and to the last node
+/// with `move_prev`.
+pub struct Cursor<'a, K, V> {
+    graph: &'a BTreeGraph<K, V>,
+    current: Option<(&'a K, &'a Ref<V>)>,
+}
+
+/// A cursor over the graph's ordered index, that allows modifying
+/// the graph while traversing it. Since the graph may change under
+/// the cursor, the cursor remembers the current key rather than a
+/// position in the index.
+pub struct CursorMut<'a, K, V> {
+    graph: &'a mut BTreeGraph<K, V>,
+    current: Option<K>,
+}
+
+/// Read-only access to a graph while one of its nodes is borrowed
+/// mutably, as passed to the closure of
+/// [`BTreeGraph::with_node_mut`]. The mutably borrowed node is
+/// hidden: lookups return `None` for it, `borrow` panics and
+/// iteration skips it.
+pub struct GraphReadView<'a, K, V> {
+    graph: &'a BTreeGraph<K, V>,
+    node: &'a Ref<V>,
+}
+
+/// Error returned by [`BTreeGraph::from_sorted_iter`] for the first
+/// key that is not greater than the key before it.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct UnsortedError<K> {
+    key: K,
+}
+
+/// The outcome of a conflict in [`BTreeGraph::merge`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MergeDecision<V> {
+    /// Keep the existing value and drop the incoming one.
+    KeepExisting,
+    /// Replace the existing value with the given one, usually the
+    /// incoming value handed to the callback.
+    Replace(V),
+    /// The existing value was updated in place by the callback,
+    /// using the incoming value.
+    Merged,
 }
 
 impl<K, V> BTreeGraph<K, V> {
@@ -54,48 +136,265 @@ impl<K, V> BTreeGraph<K, V> {
         Self {
             graph: Graph::new(),
             index: BTreeMap::new(),
+            observer: None,
+            auto_compact: None,
+        }
+    }
+
+    /// Decompose the graph into its node arena and key index. The
+    /// observer, if any, is dropped.
+    pub fn into_parts(self) -> (Graph<V>, BTreeMap<K, Ref<V>>) {
+        (self.graph, self.index)
+    }
+
+    /// Discard the key index and return the node arena. References
+    /// to the nodes, including those held in `RefBy` or `RefMap`
+    /// handles, remain valid for the returned graph.
+    pub fn into_graph(self) -> Graph<V> {
+        self.graph
+    }
+
+    /// Reassemble a graph from parts obtained through `into_parts`
+    /// (or built by hand).
+    ///
+    /// # Safety
+    ///
+    /// Every reference in the index must belong to `graph` (as checked
+    /// by `Graph::owns`) and no two keys may refer to the same node.
+    pub unsafe fn from_parts(graph: Graph<V>, index: BTreeMap<K, Ref<V>>) -> Self {
+        Self {
+            graph,
+            index,
+            observer: None,
+            auto_compact: None,
+        }
+    }
+
+    /// Convert the graph into a `HashGraph` by rebuilding only the
+    /// index. The nodes stay in place, so all references to them
+    /// remain valid. The observer and auto-compaction setting, if
+    /// any, are dropped.
+    #[cfg(feature = "std")]
+    pub fn into_hash_graph<S>(self) -> HashGraph<K, V, S>
+    where
+        K: Hash + Eq,
+        S: BuildHasher + Default,
+    {
+        let (graph, index) = self.into_parts();
+        unsafe { HashGraph::from_parts(graph, index.into_iter().collect()) }
+    }
+
+    /// Transform the graph's keys by rebuilding only the index. The
+    /// nodes stay in place, so all references to them remain valid.
+    /// If `f` maps multiple keys to the same key, the node for the
+    /// largest original key is indexed and the others are removed.
+    /// Use `try_map_keys` to detect this instead. The observer and
+    /// auto-compaction setting, if any, are dropped.
+    ///
+    /// Keyed references keep their old keys. Those held outside the
+    /// graph can be converted with `RefBy::map_key`. Nodes storing
+    /// keys of type `K` need a new node type, and thus a new graph:
+    /// build it with `filter_map_values`, creating the references
+    /// dangling, and resolve it.
+    pub fn map_keys<K2, F>(self, mut f: F) -> BTreeGraph<K2, V>
+    where
+        K2: Ord,
+        F: FnMut(K) -> K2,
+    {
+        let (graph, index) = self.into_parts();
+        let mut keys = BTreeMap::new();
+        for (key, node) in index {
+            if let Some(shadowed) = keys.insert(f(key), node) {
+                unsafe { shadowed.discard_unchecked() };
+            }
+        }
+        unsafe { BTreeGraph::from_parts(graph, keys) }
+    }
+
+    /// Transform the graph's keys, like `map_keys`. Fails with the
+    /// first transformed key that occurs more than once.
+    pub fn try_map_keys<K2, F>(self, mut f: F) -> Result<BTreeGraph<K2, V>, K2>
+    where
+        K2: Ord,
+        F: FnMut(K) -> K2,
+    {
+        let (graph, index) = self.into_parts();
+        let index = collect_unique(index.into_iter().map(|(k, v)| (f(k), v)))?;
+        Ok(unsafe { BTreeGraph::from_parts(graph, index) })
+    }
+
+    /// Build a graph from entries in strictly ascending key order,
+    /// as produced by iterating or serializing a `BTreeGraph`. The
+    /// nodes are allocated in a single chunk and the index is built
+    /// in bulk, which is faster than `collect` for large inputs.
+    /// Fails with the first key that is out of order or duplicated.
+    pub fn from_sorted_iter<I>(iter: I) -> Result<Self, UnsortedError<K>>
+    where
+        K: Ord,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut entries = Vec::<(K, V)>::new();
+        for (key, value) in iter {
+            if entries.last().is_some_and(|(last, _)| *last >= key) {
+                return Err(UnsortedError { key });
+            }
+            entries.push((key, value));
         }
+
+        let mut graph = Graph::with_capacity(entries.len());
+        let index = entries
+            .into_iter()
+            .map(|(key, value)| (key, graph.insert(value)))
+            .collect();
+        Ok(Self {
+            graph,
+            index,
+            observer: None,
+            auto_compact: None,
+        })
     }
 
     pub fn with_capacity(n: usize) -> Self {
         Self {
             graph: Graph::with_capacity(n),
             index: BTreeMap::new(), // ::with_capacity(n)
+            observer: None,
+            auto_compact: None,
         }
     }
 
+    /// Prepare the arena for (at least) `additional` more nodes.
+    pub fn reserve(&mut self, additional: usize) {
+        self.graph.reserve(additional);
+    }
+
     pub fn index(&self) -> &BTreeMap<K, Ref<V>> {
         &self.index
     }
 
+    /// Notify `observer` of every subsequent insertion, replacement
+    /// and removal of a node, replacing any previous observer. Without
+    /// an observer, no notifications are prepared.
+    pub fn set_observer<O>(&mut self, observer: O)
+    where
+        O: GraphObserver<K, V> + Send + 'static,
+    {
+        self.observer = Some(Box::new(observer));
+    }
+
+    /// Remove the observer, returning it.
+    pub fn take_observer(&mut self) -> Option<Box<dyn GraphObserver<K, V> + Send>> {
+        self.observer.take()
+    }
+
+    /// Remove all nodes from the graph. As with `Graph::clear`, all
+    /// references to the old nodes become invalid.
+    pub fn clear(&mut self) {
+        self.index.clear();
+        self.graph.clear();
+        if let Some(observer) = &mut self.observer {
+            observer.on_clear();
+        }
+    }
+
     /// Insert a node into the graph. The returned NodePtr can be used
-    /// to reference this node.
+    /// to reference this node. If the key was promised or already
+    /// exists, the value is stored in the key's slot, so that existing
+    /// references to the node remain valid (and see the new value)
+    /// and no slot is left behind.
     pub fn insert(&mut self, key: K, value: V) -> Ref<V>
     where
         K: Ord,
     {
+        self.upsert(key, value).0
+    }
+
+    /// Like `insert`, but also returns the value of the node that
+    /// was displaced, if the key existed and was not merely promised.
+    pub fn upsert(&mut self, key: K, value: V) -> (Ref<V>, Option<V>)
+    where
+        K: Ord,
+    {
+        if let Some(node) = self.index.get(&key).cloned() {
+            let old = replace_observed(&mut self.observer, &key, &node, value);
+            return (node, old);
+        }
         let node = self.graph.insert(value);
-        if let Some(old_node) = self.index.insert(key, node.clone()) {
-            unsafe {
-                old_node.try_remove_unchecked().unwrap();
-            }
+        if let Some(observer) = &mut self.observer {
+            observer.on_insert(&key, unsafe { node.get_unchecked() });
         }
-        node
+        self.index.insert(key, node.clone());
+        (node, None)
     }
 
+    /// Reserve an empty slot for the key, to be filled by `insert`
+    /// or `create`. If the key was already promised, the existing
+    /// slot is returned. Otherwise, an existing node with the same
+    /// key is removed, leaving any references to it dangling.
     pub fn promise(&mut self, key: K) -> Ref<V>
     where
         K: Ord,
     {
+        if let Some(node) = self.promised(&key) {
+            return node;
+        }
         let node = self.graph.promise();
-        if let Some(old_node) = self.index.insert(key, node.clone()) {
-            unsafe {
-                old_node.try_remove_unchecked().unwrap();
+        if let Some(observer) = &mut self.observer {
+            if let Some(old) = existing(&self.index, &key) {
+                observer.on_remove(&key, Some(old));
             }
         }
+        if let Some(old_node) = self.index.insert(key, node.clone()) {
+            unsafe { old_node.try_remove_unchecked() };
+        }
         node
     }
 
+    fn promised(&self, key: &K) -> Option<Ref<V>>
+    where
+        K: Ord,
+    {
+        self.index
+            .get(key)
+            .filter(|node| unsafe { node.is_promised_unchecked() })
+            .cloned()
+    }
+
+    fn notify_remove(&mut self, key: &K, value: Option<&V>) {
+        if let Some(observer) = &mut self.observer {
+            observer.on_remove(key, value);
+        }
+    }
+
+    /// Put a value back into a node that was removed from the graph,
+    /// and index it under `key`, so that existing references to the
+    /// node become valid again. The node must belong to this graph
+    /// and must not be in the index.
+    pub(crate) fn restore(&mut self, key: K, node: &Ref<V>, value: V)
+    where
+        K: Ord,
+    {
+        self.graph.create(node, value);
+        if let Some(observer) = &mut self.observer {
+            observer.on_insert(&key, unsafe { node.get_unchecked() });
+        }
+        self.index.insert(key, node.clone());
+    }
+
+    /// Notify the observer that the node was filled, looking up its
+    /// key. Nodes that are not in the index are not reported.
+    fn notify_create(&mut self, node: &Ref<V>, old: Option<&V>) {
+        if let Some(observer) = &mut self.observer {
+            if let Some((key, _)) = self.index.iter().find(|(_, other)| *other == node) {
+                let new = unsafe { node.get_unchecked() };
+                match old {
+                    Some(old) => observer.on_replace(key, old, new),
+                    None => observer.on_insert(key, new),
+                }
+            }
+        }
+    }
+
     /// Remove a node from the graph. You are responsible to make sure
     /// no pointers to the node will be dereferenced from this point
     /// on.
@@ -104,16 +403,202 @@ impl<K, V> BTreeGraph<K, V> {
         K: Borrow<Q> + Ord,
         Q: Ord + ?Sized,
     {
-        unsafe {
-            let node = self.index.remove(key)?;
-            Some(node.try_remove_unchecked().unwrap())
+        let (key, node) = self.index.remove_entry(key)?;
+        let value = unsafe { node.try_remove_unchecked().unwrap() };
+        self.notify_remove(&key, Some(&value));
+        self.maybe_compact();
+        Some(value)
+    }
+
+    /// Redirect every reference to `from` stored in the graph's
+    /// nodes to `to`, then remove `from`, returning its key and
+    /// value. This coalesces two nodes into one. Note that the keys
+    /// of redirected keyed references (e.g. `RefBy`) are left
+    /// unchanged; use `replace_ref_by` to update them as well. Panics
+    /// if either reference belongs to a different graph.
+    pub fn replace_ref(&mut self, from: &Ref<V>, to: &Ref<V>) -> Option<(K, V)>
+    where
+        K: Ord + Clone,
+        V: VisitRefs<V>,
+    {
+        self.replace_ref_with(from, to, |_, _, _| {})
+    }
+
+    /// Like `replace_ref`, but also replace the keys of redirected
+    /// keyed references by the key of `to`.
+    pub fn replace_ref_by(&mut self, from: &Ref<V>, to: &Ref<V>) -> Option<(K, V)>
+    where
+        K: Ord + Clone,
+        V: VisitRefs<V> + VisitRefsBy<K, V>,
+    {
+        self.replace_ref_with(from, to, |value, key, to_key| {
+            value.visit_refs_by_mut(&mut |k, r| (r == to && k == key).then(|| to_key.clone()))
+        })
+    }
+
+    /// Redirect the references to `from`, calling `rekey` with each
+    /// node value and the keys of `from` and `to`, and remove `from`.
+    fn replace_ref_with<F>(&mut self, from: &Ref<V>, to: &Ref<V>, mut rekey: F) -> Option<(K, V)>
+    where
+        K: Ord + Clone,
+        V: VisitRefs<V>,
+        F: FnMut(&mut V, &K, &K),
+    {
+        assert!(self.graph.owns(from) && self.graph.owns(to));
+        if from == to {
+            return None;
+        }
+        let (mut key, mut to_key) = (None, None);
+        for (k, node) in &self.index {
+            if node == from {
+                key = Some(k.clone());
+            } else if node == to {
+                to_key = Some(k.clone());
+            }
+        }
+        for node in self.index.values() {
+            if let Some(value) = unsafe { node.try_get_unchecked_mut() } {
+                value.visit_refs_mut(&mut |r| {
+                    if r == from {
+                        *r = to.clone();
+                    }
+                });
+                if let (Some(key), Some(to_key)) = (&key, &to_key) {
+                    rekey(value, key, to_key);
+                }
+            }
+        }
+        let (key, node) = self.index.remove_entry(&key?)?;
+        let value = unsafe { node.try_remove_unchecked() };
+        self.notify_remove(&key, value.as_ref());
+        self.maybe_compact();
+        value.map(|value| (key, value))
+    }
+
+    /// Remove the nodes for which the predicate returns false, giving
+    /// it mutable access to the values. Promised nodes are kept. As
+    /// with `remove`, any remaining references to the removed nodes
+    /// will be dangling.
+    pub fn retain_mut<F>(&mut self, mut f: F)
+    where
+        K: Ord,
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        let observer = &mut self.observer;
+        self.index.retain(|key, node| {
+            let keep = unsafe { node.try_get_unchecked_mut() }.is_none_or(|value| f(key, value));
+            if !keep {
+                let value = unsafe { node.try_remove_unchecked() };
+                if let Some(observer) = observer {
+                    observer.on_remove(key, value.as_ref());
+                }
+            }
+            keep
+        });
+        self.maybe_compact();
+    }
+
+    /// Build a new graph from the values for which `f` returns
+    /// `Some`, under the same keys. Promised nodes are dropped, as
+    /// are the observer and auto-compaction setting.
+    ///
+    /// References cannot be carried over into the new graph, which
+    /// does not exist yet when `f` is called. Values holding keyed
+    /// references should create them dangling (e.g. with
+    /// `RefBy::dangling`) and the new graph should then be resolved.
+    /// References to dropped nodes are reported as missing keys, or
+    /// left unset for optional references.
+    pub fn filter_map_values<U, F>(self, mut f: F) -> BTreeGraph<K, U>
+    where
+        K: Ord,
+        F: FnMut(&V) -> Option<U>,
+    {
+        let mut graph = BTreeGraph::new();
+        for (key, node) in self.index {
+            if let Some(value) = unsafe { node.try_get_unchecked() }.and_then(&mut f) {
+                graph.insert(key, value);
+            }
         }
+        graph
+    }
+
+    /// Remove the nodes with the given keys from the graph, returning
+    /// the removed keys and values in the order of `keys`. Keys that
+    /// are not present, or repeated, are skipped. Promised nodes are
+    /// removed as well, but have no value to return. As with `remove`,
+    /// any remaining references to the removed nodes will be dangling.
+    pub fn remove_many<Q, I>(&mut self, keys: I) -> Vec<(K, V)>
+    where
+        I: IntoIterator<Item = Q>,
+        K: Borrow<Q> + Ord,
+        Q: Ord,
+    {
+        let removed = keys
+            .into_iter()
+            .filter_map(|key| {
+                let (key, node) = self.index.remove_entry(&key)?;
+                let value = unsafe { node.try_remove_unchecked() };
+                self.notify_remove(&key, value.as_ref());
+                Some((key, value?))
+            })
+            .collect();
+        self.maybe_compact();
+        removed
+    }
+
+    /// Remove the node with the smallest key from the graph, returning
+    /// its key and value. Promised nodes before it are removed as
+    /// well, but have no value to return (see `remove_many`). As with
+    /// `remove`, any remaining references to the node will be
+    /// dangling.
+    pub fn pop_first(&mut self) -> Option<(K, V)>
+    where
+        K: Ord,
+    {
+        self.pop_with(BTreeMap::pop_first)
+    }
+
+    /// Remove the node with the largest key from the graph, returning
+    /// its key and value. Promised nodes after it are removed as
+    /// well. See `pop_first`.
+    pub fn pop_last(&mut self) -> Option<(K, V)>
+    where
+        K: Ord,
+    {
+        self.pop_with(BTreeMap::pop_last)
+    }
+
+    fn pop_with<F>(&mut self, mut pop: F) -> Option<(K, V)>
+    where
+        K: Ord,
+        F: FnMut(&mut BTreeMap<K, Ref<V>>) -> Option<(K, Ref<V>)>,
+    {
+        let popped = loop {
+            let Some((key, node)) = pop(&mut self.index) else {
+                break None;
+            };
+            let value = unsafe { node.try_remove_unchecked() };
+            self.notify_remove(&key, value.as_ref());
+            if let Some(value) = value {
+                break Some((key, value));
+            }
+        };
+        self.maybe_compact();
+        popped
     }
 
     pub fn is_empty(&self) -> bool {
         self.index.is_empty()
     }
 
+    /// Estimate the memory used by the graph, in bytes. In addition to
+    /// the arena (see `Graph::memory_usage`), this counts the entries
+    /// in the index, but not the B-tree's internal overhead nor any
+    /// heap memory owned by the keys.
+    pub fn memory_usage(&self) -> usize {
+        self.graph.memory_usage() + self.index.len() * core::mem::size_of::<(K, Ref<V>)>()
+    }
+
     pub fn get_ref<Q>(&self, key: &Q) -> Option<&Ref<V>>
     where
         K: Borrow<Q> + Ord,
@@ -144,7 +629,7 @@ impl<K, V> BTreeGraph<K, V> {
         K: Borrow<Q> + Ord,
         Q: Ord + ?Sized,
     {
-        unsafe { Some(self.get_ref(key)?.try_get_unchecked().unwrap()) }
+        unsafe { Some(self.get_ref(key)?.get_unchecked()) }
     }
 
     pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
@@ -153,7 +638,17 @@ impl<K, V> BTreeGraph<K, V> {
         Q: Ord + ?Sized,
     {
         let (key, value) = self.get_entry(key)?;
-        unsafe { Some((key, value.try_get_unchecked().unwrap())) }
+        unsafe { Some((key, value.get_unchecked())) }
+    }
+
+    /// Get the key, reference and value for a key in a single lookup.
+    pub fn get_full<Q>(&self, key: &Q) -> Option<(&K, &Ref<V>, &V)>
+    where
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
+    {
+        let (key, node) = self.get_entry(key)?;
+        unsafe { Some((key, node, node.get_unchecked())) }
     }
 
     pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
@@ -161,7 +656,7 @@ impl<K, V> BTreeGraph<K, V> {
         K: Borrow<Q> + Ord,
         Q: Ord + ?Sized,
     {
-        unsafe { Some(self.get_ref(key)?.try_get_unchecked_mut().unwrap()) }
+        unsafe { Some(self.get_ref(key)?.get_unchecked_mut()) }
     }
 
     pub fn get_key_value_mut<Q>(&mut self, key: &Q) -> Option<(&K, &mut V)>
@@ -170,11 +665,38 @@ impl<K, V> BTreeGraph<K, V> {
         Q: Ord + ?Sized,
     {
         let (key, value) = self.get_entry(key)?;
-        unsafe { Some((key, value.try_get_unchecked_mut().unwrap())) }
+        unsafe { Some((key, value.get_unchecked_mut())) }
     }
 
     pub fn create(&mut self, node: &Ref<V>, value: V) {
-        self.graph.create(node, value)
+        self.graph.create(node, value);
+        self.notify_create(node, None);
+    }
+
+    /// Set the value of a promised, removed or existing node,
+    /// returning the previous value. See `Graph::create_or_update`.
+    pub fn create_or_update(&mut self, node: &Ref<V>, value: V) -> Option<V> {
+        let old = self.graph.create_or_update(node, value);
+        self.notify_create(node, old.as_ref());
+        old
+    }
+
+    /// Create the promised (or removed) node for `key`. Unlike
+    /// [`create`](Self::create), this never panics: an unknown key or
+    /// an already filled node hands the value back in the error.
+    pub fn try_create<Q>(&mut self, key: &Q, value: V) -> Result<(), CreateError<V>>
+    where
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
+    {
+        let Some((key, node)) = self.index.get_key_value(key) else {
+            return Err(CreateError::UnknownKey { value });
+        };
+        self.graph.try_create(node, value)?;
+        if let Some(observer) = &mut self.observer {
+            observer.on_insert(key, unsafe { node.get_unchecked() });
+        }
+        Ok(())
     }
 
     /// Borrow the value from the graph. Panics if you try to borrow
@@ -197,6 +719,27 @@ impl<K, V> BTreeGraph<K, V> {
         self.graph.borrow_mut(node)
     }
 
+    /// Borrow the projected part of a node from the graph. Panics
+    /// under the same conditions as `borrow`.
+    pub fn borrow_projected<U: ?Sized, F, G>(&self, node: &ProjectedRef<V, U, F, G>) -> &U
+    where
+        F: Fn(&V) -> &U,
+    {
+        self.graph.borrow_projected(node)
+    }
+
+    /// Mutably borrow the projected part of a node from the
+    /// graph. Panics under the same conditions as `borrow_mut`.
+    pub fn borrow_projected_mut<U: ?Sized, F, G>(
+        &mut self,
+        node: &ProjectedRef<V, U, F, G>,
+    ) -> &mut U
+    where
+        G: Fn(&mut V) -> &mut U,
+    {
+        self.graph.borrow_projected_mut(node)
+    }
+
     /// Get mutable references to multiple nodes in the graph. This
     /// may be necessary to create cycles.
     pub fn borrow_many_mut<const N: usize, R>(&mut self, nodes: [R; N]) -> [&mut V; N]
@@ -207,11 +750,48 @@ impl<K, V> BTreeGraph<K, V> {
         self.graph.borrow_many_mut(nodes)
     }
 
-    pub fn iter_ref(&self) -> impl Iterator<Item = (&K, &Ref<V>)> {
+    /// Get mutable references to multiple nodes in the graph, with
+    /// checks that are never compiled out. See
+    /// [`Graph::try_borrow_many_mut`].
+    pub fn try_borrow_many_mut<const N: usize, R>(
+        &mut self,
+        nodes: [R; N],
+    ) -> Result<[&mut V; N], BorrowManyError>
+    where
+        R: AsRef<Ref<V>>,
+    {
+        self.graph.try_borrow_many_mut(nodes)
+    }
+
+    pub fn iter_ref(
+        &self,
+    ) -> impl ExactSizeIterator<Item = (&K, &Ref<V>)> + DoubleEndedIterator + FusedIterator {
         self.index.iter()
     }
 
-    pub fn iter_ref_by(&self) -> impl Iterator<Item = RefBy<K, V>> + '_
+    /// Iterate over the keys and references without cloning them.
+    /// This is the same as `iter_ref`; prefer it over `iter_ref_by`
+    /// when the keys are only read.
+    pub fn iter_ref_pairs(
+        &self,
+    ) -> impl ExactSizeIterator<Item = (&K, &Ref<V>)> + DoubleEndedIterator + FusedIterator {
+        self.iter_ref()
+    }
+
+    /// Like `iter_ref_by`, but yields borrowed `RefByRef` views
+    /// instead of cloning the keys into `RefBy`s.
+    pub fn iter_ref_by_ref(
+        &self,
+    ) -> impl ExactSizeIterator<Item = RefByRef<'_, K, V>> + DoubleEndedIterator + FusedIterator
+    {
+        self.iter_ref().map(|(k, v)| RefByRef::new(k, v))
+    }
+
+    /// Iterate over owned `RefBy`s, cloning every key. See
+    /// `iter_ref_by_ref` for a clone-free alternative.
+    pub fn iter_ref_by(
+        &self,
+    ) -> impl ExactSizeIterator<Item = RefBy<K, V>> + DoubleEndedIterator + FusedIterator + '_
     where
         K: Clone,
     {
@@ -219,111 +799,756 @@ impl<K, V> BTreeGraph<K, V> {
             .map(|(k, v)| RefBy::new(k.clone(), v.clone()))
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+    /// Copy the graph's index into a `RefMap`. The references in the
+    /// map remain valid as long as the nodes are not removed from the
+    /// graph.
+    pub fn to_ref_map(&self) -> RefMap<K, V>
+    where
+        K: Ord + Clone,
+    {
+        self.iter_ref()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    /// Borrow the keys and values as a plain map. Promised nodes are
+    /// left out.
+    pub fn to_btree_map(&self) -> BTreeMap<&K, &V>
+    where
+        K: Ord,
+    {
+        self.index
+            .iter()
+            .filter_map(|(key, node)| Some((key, unsafe { node.try_get_unchecked() }?)))
+            .collect()
+    }
+
+    /// Move the values out of their slots into a plain map, without
+    /// cloning them. Promised nodes are left out.
+    pub fn into_btree_map(self) -> BTreeMap<K, V>
+    where
+        K: Ord,
+    {
+        self.index
+            .into_iter()
+            .filter_map(|(key, node)| Some((key, unsafe { node.try_remove_unchecked() }?)))
+            .collect()
+    }
+
+    /// Iterate over the entries in ascending key order.
+    pub fn iter(
+        &self,
+    ) -> impl ExactSizeIterator<Item = (&K, &V)> + DoubleEndedIterator + FusedIterator {
         unsafe {
             self.iter_ref()
-                .map(|(key, value)| (key, value.try_get_unchecked().unwrap()))
+                .map(|(key, value)| (key, value.get_unchecked()))
         }
     }
 
-    pub fn iter_mut(&self) -> impl Iterator<Item = (&K, &mut V)> {
+    /// Iterate over the entries in ascending key order, yielding the
+    /// key, reference and value of each node.
+    pub fn iter_full(
+        &self,
+    ) -> impl ExactSizeIterator<Item = (&K, &Ref<V>, &V)> + DoubleEndedIterator + FusedIterator
+    {
         unsafe {
             self.iter_ref()
-                .map(|(key, value)| (key, value.try_get_unchecked_mut().unwrap()))
+                .map(|(key, node)| (key, node, node.get_unchecked()))
         }
     }
 
-    pub fn keys(&self) -> impl Iterator<Item = &K> {
-        self.index.keys()
-    }
-
-    pub fn values_ref(&self) -> impl Iterator<Item = &Ref<V>> {
-        self.index.values()
+    /// Iterate mutably over the entries in ascending key order,
+    /// yielding the key, reference and value of each node.
+    pub fn iter_full_mut(
+        &mut self,
+    ) -> impl ExactSizeIterator<Item = (&K, &Ref<V>, &mut V)> + DoubleEndedIterator + FusedIterator
+    {
+        let index = &self.index;
+        let scope = BorrowScope::new(index.values());
+        unsafe {
+            self.iter_ref().map(move |(key, node)| {
+                scope.check(node, || index_entries(index.values(), node));
+                (key, node, node.get_unchecked_mut())
+            })
+        }
     }
 
-    pub fn values(&self) -> impl Iterator<Item = &V> {
-        //self.graph.iter_mut()
+    /// Iterate over the entries in descending key order.
+    pub fn iter_rev(&self) -> impl Iterator<Item = (&K, &V)> {
         unsafe {
-            self.values_ref()
-                .map(|value| value.try_get_unchecked().unwrap())
+            self.index
+                .iter()
+                .rev()
+                .map(|(key, value)| (key, value.get_unchecked()))
         }
     }
 
-    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
-        //self.graph.iter_mut()
+    pub fn iter_mut(
+        &mut self,
+    ) -> impl ExactSizeIterator<Item = (&K, &mut V)> + DoubleEndedIterator + FusedIterator {
+        let index = &self.index;
+        let scope = BorrowScope::new(index.values());
         unsafe {
-            self.values_ref()
-                .map(|value| value.try_get_unchecked_mut().unwrap())
+            self.iter_ref().map(move |(key, value)| {
+                scope.check(value, || index_entries(index.values(), value));
+                (key, value.get_unchecked_mut())
+            })
         }
     }
 
-    pub fn entry(&mut self, key: K) -> Entry<K, V>
+    /// Iterate over the keys in ascending order.
+    pub fn keys(&self) -> impl ExactSizeIterator<Item = &K> + DoubleEndedIterator + FusedIterator {
+        self.index.keys()
+    }
+
+    /// A borrowed view of the graph's keys, e.g. for set operations.
+    pub fn keys_view(&self) -> KeysView<'_, BTreeMap<K, Ref<V>>>
     where
         K: Ord,
     {
-        Entry {
-            graph: &mut self.graph,
-            entry: self.index.entry(key),
-        }
+        KeysView::new(&self.index)
     }
-}
 
-impl<K, V> Default for BTreeGraph<K, V> {
-    fn default() -> Self {
-        Self::new()
+    /// Iterate over the keys in descending order.
+    pub fn keys_rev(&self) -> impl Iterator<Item = &K> {
+        self.index.keys().rev()
     }
-}
 
-impl<K, V> AsRef<Graph<V>> for BTreeGraph<K, V> {
-    fn as_ref(&self) -> &Graph<V> {
-        &self.graph
+    pub fn values_ref(
+        &self,
+    ) -> impl ExactSizeIterator<Item = &Ref<V>> + DoubleEndedIterator + FusedIterator {
+        self.index.values()
     }
-}
-
-impl<K: Ord, V> FromIterator<(K, V)> for BTreeGraph<K, V> {
-    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
-        let iter = iter.into_iter();
-        let ((_, Some(size)) | (size, None)) = iter.size_hint();
-
-        let mut graph = Graph::with_capacity(size);
-        let mut index = BTreeMap::new(); // ::with_capacity(size)
-
-        iter.for_each(|(key, value)| {
-            index.insert(key, graph.insert(value));
-        });
 
-        Self { graph, index }
+    /// Iterate over the values in ascending key order. Since the
+    /// values are visited through the index, this order is
+    /// guaranteed, regardless of insertion order.
+    pub fn values(
+        &self,
+    ) -> impl ExactSizeIterator<Item = &V> + DoubleEndedIterator + FusedIterator {
+        //self.graph.iter_mut()
+        unsafe { self.values_ref().map(|value| value.get_unchecked()) }
     }
-}
 
-#[cfg(feature = "serde")]
-impl<K, V> Serialize for BTreeGraph<K, V>
-where
-    K: Serialize,
-    V: Serialize,
-{
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    /// Check whether any value matches the predicate.
+    pub fn any_value<F>(&self, f: F) -> bool
     where
-        S: Serializer,
+        F: FnMut(&V) -> bool,
     {
-        let mut m = serializer.serialize_map(Some(self.index.len()))?;
-        self.iter()
-            .try_for_each(|(key, value)| m.serialize_entry(key, value))?;
-        m.end()
+        self.values().any(f)
     }
-}
 
-#[cfg(feature = "serde")]
-impl<'de, K, V> Deserialize<'de> for BTreeGraph<K, V>
-where
-    K: Deserialize<'de> + Ord,
-    V: Deserialize<'de>,
-{
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    /// Find the first key, in key order, whose value matches the
+    /// predicate.
+    pub fn find_key<F>(&self, mut f: F) -> Option<&K>
+    where
+        F: FnMut(&V) -> bool,
+    {
+        self.iter().find(|(_, value)| f(value)).map(|(key, _)| key)
+    }
+
+    /// Iterate over the values in descending key order.
+    pub fn values_rev(&self) -> impl Iterator<Item = &V> {
+        unsafe { self.index.values().rev().map(|value| value.get_unchecked()) }
+    }
+
+    pub fn values_mut(
+        &mut self,
+    ) -> impl ExactSizeIterator<Item = &mut V> + DoubleEndedIterator + FusedIterator {
+        let index = &self.index;
+        let scope = BorrowScope::new(index.values());
+        unsafe {
+            self.values_ref().map(move |value| {
+                scope.check(value, || index_entries(index.values(), value));
+                value.get_unchecked_mut()
+            })
+        }
+    }
+
+    /// Iterate over the nodes in parallel.
+    #[cfg(feature = "rayon")]
+    pub fn par_iter(&self) -> impl ParallelIterator<Item = (&K, &V)>
+    where
+        K: Ord + Sync,
+        V: Send + Sync,
+    {
+        unsafe {
+            self.index
+                .par_iter()
+                .map(|(key, value)| (key, value.get_unchecked()))
+        }
+    }
+
+    /// Iterate over the node values in parallel.
+    #[cfg(feature = "rayon")]
+    pub fn par_values(&self) -> impl ParallelIterator<Item = &V>
+    where
+        K: Ord + Sync,
+        V: Send + Sync,
+    {
+        self.par_iter().map(|(_, value)| value)
+    }
+
+    /// Iterate mutably over the node values in parallel. This relies
+    /// on every index entry referring to a distinct slot, so that no
+    /// two threads receive a mutable borrow of the same node. The
+    /// references are collected up front and each is sent to a single
+    /// thread, so the values only need to be `Send`.
+    #[cfg(feature = "rayon")]
+    pub fn par_values_mut(&mut self) -> impl ParallelIterator<Item = &mut V>
+    where
+        K: Sync,
+        V: Send,
+    {
+        debug_assert!(self.has_distinct_slots());
+        let values = ParValues(self.index.values());
+        let scope = BorrowScope::new(values.clone());
+        let nodes = self.index.values().map(ParNode).collect::<Vec<_>>();
+        nodes.into_par_iter().map(move |ParNode(node)| unsafe {
+            scope.check(node, || index_entries(values.clone(), node));
+            node.get_unchecked_mut()
+        })
+    }
+
+    /// Build a graph from shards constructed in parallel. Each shard
+    /// is built on a worker thread, using keyed references (`RefBy`)
+    /// for edges. References to nodes in other shards can be created
+    /// with `RefBy::dangling`. The shards are then merged into a
+    /// single graph, after which all references are resolved against
+    /// the merged graph. When shards contain the same key, the node
+    /// from the last shard is kept. Nodes that were promised but not
+    /// created in a shard are skipped. Fails with the keys of all
+    /// references that could not be resolved.
+    ///
+    /// The nodes must only hold keyed references, which are all
+    /// re-resolved by `Resolve`. Plain `Ref`s into a shard are not
+    /// remapped, and are left dangling when the shard is dropped.
+    #[cfg(feature = "rayon")]
+    pub fn build_parallel<F>(shards: Vec<F>) -> Result<Self, Vec<K>>
+    where
+        F: FnOnce() -> Self + Send,
+        K: Ord + Send + Sync,
+        V: Resolve<K, V> + Send + Sync,
+    {
+        let shards = shards
+            .into_par_iter()
+            .map(|build| build())
+            .collect::<Vec<_>>();
+
+        let mut graph = Self::with_capacity(shards.iter().map(|shard| shard.index.len()).sum());
+        shards.into_iter().for_each(|shard| {
+            let (_nodes, index) = shard.into_parts();
+            index.into_iter().for_each(|(key, node)| {
+                if let Some(value) = unsafe { node.try_remove_unchecked() } {
+                    graph.insert(key, value);
+                }
+            })
+        });
+
+        graph.par_resolve()?;
+        Ok(graph)
+    }
+
+    /// Resolve the references in all nodes against the graph's own
+    /// index, in parallel. See `resolve`.
+    #[cfg(feature = "rayon")]
+    pub fn par_resolve(&mut self) -> Result<(), Vec<K>>
+    where
+        K: Ord + Send + Sync,
+        V: Resolve<K, V> + Send + Sync,
+    {
+        debug_assert!(self.has_distinct_slots());
+        let index = &self.index;
+        let missing = index
+            .par_iter()
+            .filter_map(|(_, node)| unsafe { node.get_unchecked_mut() }.resolve(index).err())
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(missing)
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    fn has_distinct_slots(&self) -> bool {
+        let mut slots = self
+            .values_ref()
+            .map(|value| value.as_ptr())
+            .collect::<Vec<_>>();
+        slots.sort_unstable();
+        slots.windows(2).all(|pair| pair[0] != pair[1])
+    }
+
+    /// Resolve the references in all nodes against the graph's own
+    /// index. Returns the keys that could not be resolved, if any.
+    pub fn resolve(&mut self) -> Result<(), Vec<K>>
+    where
+        K: Ord,
+        V: Resolve<K, V>,
+    {
+        let missing = self
+            .index
+            .values()
+            .filter_map(|node| {
+                unsafe { node.get_unchecked_mut() }
+                    .resolve(&self.index)
+                    .err()
+            })
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(missing)
+        }
+    }
+
+    /// Rebuild the graph's arena with only the live and promised
+    /// nodes, returning the number of reclaimed slots. The graph
+    /// receives a new generation, and the references inside the
+    /// nodes are re-resolved against the index. References held
+    /// outside the graph must be re-resolved as well; stale ones fail
+    /// validation, as do references to keys no longer in the graph.
+    ///
+    /// The references inside the nodes are resolved before anything
+    /// is moved: if some cannot be resolved (e.g. because their key
+    /// was removed), their keys are returned and the arena is left
+    /// as is, since they would otherwise point into freed memory.
+    pub fn compact(&mut self) -> Result<usize, Vec<K>>
+    where
+        K: Ord,
+        V: Resolve<K, V>,
+    {
+        self.resolve_nodes()?;
+        let old = core::mem::replace(&mut self.graph, Graph::with_capacity(self.index.len()));
+        for node in self.index.values_mut() {
+            *node = match unsafe { node.try_remove_unchecked() } {
+                Some(value) => self.graph.insert(value),
+                None => self.graph.promise(),
+            };
+        }
+        match self.resolve_nodes() {
+            Ok(()) => Ok(old.slot_count() - self.index.len()),
+            Err(missing) => {
+                /* Only possible if resolution is not deterministic:
+                 * keep the old nodes allocated. */
+                core::mem::forget(old);
+                Err(missing)
+            }
+        }
+    }
+
+    /// Resolve the references in all nodes against the index,
+    /// skipping promised nodes, unlike `resolve`.
+    fn resolve_nodes(&mut self) -> Result<(), Vec<K>>
+    where
+        K: Ord,
+        V: Resolve<K, V>,
+    {
+        let missing = self
+            .index
+            .values()
+            .filter_map(|node| {
+                unsafe { node.try_get_unchecked_mut() }?
+                    .resolve(&self.index)
+                    .err()
+            })
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(missing)
+        }
+    }
+
+    /// Merge the nodes of `other` into this graph. Keys only present
+    /// in `other` are moved over. For keys present in both graphs,
+    /// `on_conflict` is called with the key, the existing value and
+    /// the incoming value, and decides what to keep: the callback
+    /// may hand the incoming value back to replace the existing one,
+    /// or move parts of it into the existing value and return
+    /// `Merged`. Existing nodes are updated in place, so references
+    /// to them remain valid. Replacements and merges are reported to
+    /// the observer.
+    ///
+    /// References inside moved nodes still point into `other`, so
+    /// the moved, replaced and merged nodes are re-resolved against
+    /// this graph's index (see `resolve`). Returns the keys that
+    /// could not be resolved, if any; in that case, the memory of
+    /// `other` is leaked rather than freed, since the unresolved
+    /// references still point into it. References into `other` held
+    /// outside the graphs become dangling.
+    pub fn merge<F>(&mut self, mut other: Self, mut on_conflict: F) -> Result<(), Vec<K>>
+    where
+        K: Ord + Clone,
+        V: Resolve<K, V>,
+        F: FnMut(&K, &mut V, V) -> MergeDecision<V>,
+    {
+        let mut touched = Vec::new();
+
+        while let Some((key, node)) = other.index.pop_first() {
+            let Some(value) = (unsafe { node.try_remove_unchecked() }) else {
+                if !self.index.contains_key(&key) {
+                    self.promise(key);
+                }
+                continue;
+            };
+            match self.index.get(&key).cloned() {
+                Some(existing) if !unsafe { existing.is_promised_unchecked() } => {
+                    match on_conflict(&key, unsafe { existing.get_unchecked_mut() }, value) {
+                        MergeDecision::KeepExisting => continue,
+                        MergeDecision::Replace(value) => {
+                            replace_observed(&mut self.observer, &key, &existing, value);
+                        }
+                        MergeDecision::Merged => {
+                            if let Some(observer) = &mut self.observer {
+                                observer.on_merge(&key, unsafe { existing.get_unchecked() });
+                            }
+                        }
+                    }
+                }
+                _ => {
+                    self.insert(key.clone(), value);
+                }
+            }
+            touched.push(key);
+        }
+
+        let missing = touched
+            .iter()
+            .filter_map(|key| {
+                unsafe { self.index[key].get_unchecked_mut() }
+                    .resolve(&self.index)
+                    .err()
+            })
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            core::mem::forget(other);
+            Err(missing)
+        }
+    }
+
+    /// Automatically `compact` the graph after a removal, when the
+    /// number of removed slots exceeds `threshold` times the number
+    /// of nodes in the index. Since compaction gives the graph a new
+    /// generation, references held outside the graph must be
+    /// re-resolved after any removal. Compaction is skipped as long
+    /// as a reference inside the nodes cannot be resolved (see
+    /// `compact`), which is reported to the observer's
+    /// `on_compact_failed`.
+    pub fn set_auto_compact(&mut self, threshold: f32)
+    where
+        K: Ord,
+        V: Resolve<K, V>,
+    {
+        self.auto_compact = Some((threshold, |graph| {
+            if let Err(missing) = graph.compact() {
+                if let Some(observer) = &mut graph.observer {
+                    observer.on_compact_failed(&missing);
+                }
+            }
+        }));
+        self.maybe_compact();
+    }
+
+    pub fn disable_auto_compact(&mut self) {
+        self.auto_compact = None;
+    }
+
+    fn maybe_compact(&mut self) {
+        if let Some((threshold, compact)) = self.auto_compact {
+            if fragmented(self.graph.slot_count(), self.index.len(), threshold) {
+                compact(self);
+            }
+        }
+    }
+
+    /// Mutably borrow the node for `key`, while reading the rest of
+    /// the graph through a `GraphReadView`, e.g. to recompute the
+    /// node from its neighbors. Returns `None` if the key is missing
+    /// or its node was promised but not yet created.
+    pub fn with_node_mut<Q, R, F>(&mut self, key: &Q, f: F) -> Option<R>
+    where
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
+        F: FnOnce(&mut V, &GraphReadView<'_, K, V>) -> R,
+    {
+        let node = self.index.get(key)?;
+        /* The view never hands out a reference to this node. */
+        let value = unsafe { node.try_get_unchecked_mut() }?;
+        let view = GraphReadView { graph: self, node };
+        Some(f(value, &view))
+    }
+
+    /// Get a cursor pointing to the first node with a key greater
+    /// than or equal to `start`.
+    pub fn cursor<Q>(&mut self, start: &Q) -> CursorMut<'_, K, V>
+    where
+        K: Borrow<Q> + Ord + Clone,
+        Q: Ord + ?Sized,
+    {
+        self.lower_bound_mut(Bound::Included(start))
+    }
+
+    /// Get a cursor pointing to the first node above `bound`, or to
+    /// the ghost position if there is no such node.
+    pub fn lower_bound<Q>(&self, bound: Bound<&Q>) -> Cursor<'_, K, V>
+    where
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
+    {
+        Cursor {
+            graph: self,
+            current: self.index.range((bound, Bound::Unbounded)).next(),
+        }
+    }
+
+    /// Get a mutable cursor pointing to the first node above
+    /// `bound`, or to the ghost position if there is no such node.
+    pub fn lower_bound_mut<Q>(&mut self, bound: Bound<&Q>) -> CursorMut<'_, K, V>
+    where
+        K: Borrow<Q> + Ord + Clone,
+        Q: Ord + ?Sized,
+    {
+        let current = self
+            .index
+            .range((bound, Bound::Unbounded))
+            .next()
+            .map(|(k, _)| k.clone());
+        CursorMut {
+            graph: self,
+            current,
+        }
+    }
+
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V>
+    where
+        K: Ord,
+    {
+        Entry {
+            graph: &mut self.graph,
+            entry: self.index.entry(key),
+            observer: &mut self.observer,
+        }
+    }
+
+    /// Get the value for the key, inserting `V::default()` if the key
+    /// is missing. A promised node is filled with the default value.
+    pub fn get_or_insert_default(&mut self, key: K) -> &mut V
+    where
+        K: Ord,
+        V: Default,
+    {
+        self.entry(key).or_default()
+    }
+
+    /// Get the value for the key, inserting `f()` if the key is
+    /// missing. A promised node is filled with `f()`.
+    pub fn get_or_insert_with<F>(&mut self, key: K, f: F) -> &mut V
+    where
+        K: Ord,
+        F: FnOnce() -> V,
+    {
+        self.entry(key).or_insert_with(f)
+    }
+}
+
+/// Check whether the ratio of removed slots to indexed nodes
+/// exceeds the threshold.
+pub(crate) fn fragmented(slots: usize, nodes: usize, threshold: f32) -> bool {
+    (slots - nodes) as f32 > threshold * nodes as f32
+}
+
+/// The current value for the key, if it holds one.
+fn existing<'a, K: Ord, V>(index: &BTreeMap<K, Ref<V>>, key: &K) -> Option<&'a V> {
+    unsafe { index.get(key)?.try_get_unchecked() }
+}
+
+impl<K, V> Default for BTreeGraph<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> AsRef<Graph<V>> for BTreeGraph<K, V> {
+    fn as_ref(&self) -> &Graph<V> {
+        &self.graph
+    }
+}
+
+impl<K, V> GraphMut<V> for BTreeGraph<K, V> {
+    fn borrow_mut(&mut self, node: &Ref<V>) -> &mut V {
+        BTreeGraph::borrow_mut(self, node)
+    }
+}
+
+impl<K> UnsortedError<K> {
+    /// The offending key.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+}
+
+impl<K: Debug> Display for UnsortedError<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "key out of order or duplicated: {:?}", self.key)
+    }
+}
+
+impl<K: Debug> core::error::Error for UnsortedError<K> {}
+
+impl<K: Ord, V> FromIterator<(K, V)> for BTreeGraph<K, V> {
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let iter = iter.into_iter();
+        let ((_, Some(size)) | (size, None)) = iter.size_hint();
+
+        let mut graph = Graph::with_capacity(size);
+        let mut index = BTreeMap::new(); // ::with_capacity(size)
+
+        iter.for_each(|(key, value)| {
+            index.insert(key, graph.insert(value));
+        });
+
+        Self {
+            graph,
+            index,
+            observer: None,
+            auto_compact: None,
+        }
+    }
+}
+
+impl<K: Ord, V> From<BTreeMap<K, V>> for BTreeGraph<K, V> {
+    fn from(map: BTreeMap<K, V>) -> Self {
+        let mut graph = Graph::with_capacity(map.len());
+        let index = map
+            .into_iter()
+            .map(|(key, value)| (key, graph.insert(value)))
+            .collect();
+
+        Self {
+            graph,
+            index,
+            observer: None,
+            auto_compact: None,
+        }
+    }
+}
+
+/// Extending a graph inserts each pair as with `insert`.
+impl<K: Ord, V> Extend<(K, V)> for BTreeGraph<K, V> {
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        iter.into_iter().for_each(|(key, value)| {
+            self.insert(key, value);
+        })
+    }
+}
+
+/* Safety: the references in the index only point into the graph's
+ * own nodes, so the auto trait bounds on Ref are stricter than
+ * needed. The bounds here are those of a map owning its values. The
+ * observer is Send, and is only used through a mutable reference. */
+
+unsafe impl<K: Send, V: Send> Send for BTreeGraph<K, V> {}
+unsafe impl<K: Sync, V: Sync> Sync for BTreeGraph<K, V> {}
+
+/// The index's references, shared with the worker threads of
+/// `par_values_mut` for the borrow checks, which only compare them and
+/// update their slots' atomic borrow marks.
+#[cfg(feature = "rayon")]
+struct ParValues<'a, K, V>(btree_map::Values<'a, K, Ref<V>>);
+
+/// A node handed to a single worker thread by `par_values_mut`.
+#[cfg(feature = "rayon")]
+struct ParNode<'a, V>(&'a Ref<V>);
+
+/* Safety: the values themselves are not accessed through ParValues,
+ * and each ParNode is mutably borrowed by the one thread it is sent
+ * to, as with the items of `BTreeMap::par_iter_mut`. */
+
+#[cfg(feature = "rayon")]
+unsafe impl<K: Sync, V: Send> Send for ParValues<'_, K, V> {}
+#[cfg(feature = "rayon")]
+unsafe impl<K: Sync, V: Send> Sync for ParValues<'_, K, V> {}
+#[cfg(feature = "rayon")]
+unsafe impl<V: Send> Send for ParNode<'_, V> {}
+
+#[cfg(feature = "rayon")]
+impl<K, V> Clone for ParValues<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, K, V> Iterator for ParValues<'a, K, V> {
+    type Item = &'a Ref<V>;
+
+    fn next(&mut self) -> Option<&'a Ref<V>> {
+        self.0.next()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K, V> Serialize for BTreeGraph<K, V>
+where
+    K: Serialize,
+    V: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut m = serializer.serialize_map(Some(self.index.len()))?;
+        self.iter()
+            .try_for_each(|(key, value)| m.serialize_entry(key, value))?;
+        m.end()
+    }
+}
+
+/// Deserialize a `BTreeGraph` using custom options.
+#[cfg(feature = "serde")]
+pub struct BTreeGraphSeed<K, V> {
+    options: GraphDeserializeOptions,
+    _marker: PhantomData<(K, V)>,
+}
+
+#[cfg(feature = "serde")]
+impl<K, V> BTreeGraphSeed<K, V> {
+    pub fn new(options: GraphDeserializeOptions) -> Self {
+        Self {
+            options,
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K, V> Default for BTreeGraphSeed<K, V> {
+    fn default() -> Self {
+        Self::new(GraphDeserializeOptions::default())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V> DeserializeSeed<'de> for BTreeGraphSeed<K, V>
+where
+    K: Deserialize<'de> + Ord,
+    V: Deserialize<'de>,
+{
+    type Value = BTreeGraph<K, V>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
     where
         D: Deserializer<'de>,
     {
-        struct GraphVisitor<K, V>(PhantomData<(K, V)>);
+        struct GraphVisitor<K, V>(GraphDeserializeOptions, PhantomData<(K, V)>);
 
         impl<'de, K, V> Visitor<'de> for GraphVisitor<K, V>
         where
@@ -332,7 +1557,7 @@ where
         {
             type Value = BTreeGraph<K, V>;
 
-            fn expecting(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+            fn expecting(&self, formatter: &mut Formatter<'_>) -> core::fmt::Result {
                 write!(formatter, "a map")
             }
 
@@ -340,41 +1565,1914 @@ where
             where
                 A: MapAccess<'de>,
             {
-                let (mut graph, mut index) = match map.size_hint() {
-                    Some(size) => (Graph::with_capacity(size), BTreeMap::new()),
-                    None => (Graph::new(), BTreeMap::new()),
-                };
+                let size_hint = map.size_hint();
+                let mut graph = self.0.graph(size_hint);
+                let mut index = BTreeMap::<K, Ref<V>>::new();
 
                 while let Some((key, value)) = map.next_entry()? {
-                    let node = graph.insert(value);
-                    index.insert(key, node);
+                    match index.entry(key) {
+                        btree_map::Entry::Occupied(entry) => unsafe {
+                            entry.get().try_replace_unchecked(value);
+                        },
+                        btree_map::Entry::Vacant(entry) => {
+                            entry.insert(graph.insert(value));
+                        }
+                    }
                 }
 
-                Ok(BTreeGraph { graph, index })
+                Ok(BTreeGraph {
+                    graph,
+                    index,
+                    observer: None,
+                    auto_compact: None,
+                })
             }
         }
 
-        deserializer.deserialize_map(GraphVisitor(PhantomData))
+        deserializer.deserialize_map(GraphVisitor(self.options, PhantomData))
     }
 }
 
-impl<'a, K, V> Entry<'a, K, V>
+/// Deserialize a graph and resolve the references in its nodes
+/// against the graph itself. This can be used to deserialize a graph
+/// embedded in a larger structure, using
+/// `#[serde(deserialize_with = "graph::deserialize_resolved")]`.
+#[cfg(feature = "serde")]
+pub fn deserialize_resolved<'de, D, K, V>(deserializer: D) -> Result<BTreeGraph<K, V>, D::Error>
 where
-    K: Ord,
+    D: Deserializer<'de>,
+    K: Deserialize<'de> + Ord + Debug,
+    V: Deserialize<'de> + Resolve<K, V>,
 {
-    pub fn or_insert_with<F>(self, default: F) -> &'a mut V
+    let mut graph = BTreeGraph::deserialize(deserializer)?;
+    graph.resolve().map_err(|missing| {
+        D::Error::custom(format_args!("unresolved reference(s): {missing:?}"))
+    })?;
+    Ok(graph)
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V> Deserialize<'de> for BTreeGraph<K, V>
+where
+    K: Deserialize<'de> + Ord,
+    V: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
-        F: FnOnce() -> V,
+        D: Deserializer<'de>,
     {
-        match self.entry {
-            btree_map::Entry::Vacant(ent) => unsafe {
-                ent.insert(self.graph.insert(default()))
-                    .try_get_unchecked_mut()
-                    .unwrap()
-            },
-            btree_map::Entry::Occupied(ent) => unsafe {
-                ent.get().try_get_unchecked_mut().unwrap()
-            },
-        }
+        BTreeGraphSeed::default().deserialize(deserializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K, V> BTreeGraph<K, V> {
+    /// Deserialize a map into an existing graph. Nodes for keys that
+    /// are already present are updated in place, so that existing
+    /// references to them remain valid. Nodes for keys that are not
+    /// present in the input are removed. On error, the graph may be
+    /// partially updated.
+    pub fn deserialize_into<'de, D>(&mut self, deserializer: D) -> Result<(), D::Error>
+    where
+        K: Deserialize<'de> + Ord,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(IntoVisitor {
+            graph: self,
+            remove: true,
+        })
+    }
+
+    /// Like `deserialize_into`, but keeps the nodes for keys that are
+    /// not present in the input. This can be used to apply partial
+    /// updates to the graph.
+    pub fn merge_from_deserializer<'de, D>(&mut self, deserializer: D) -> Result<(), D::Error>
+    where
+        K: Deserialize<'de> + Ord,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(IntoVisitor {
+            graph: self,
+            remove: false,
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+struct IntoVisitor<'a, K, V> {
+    graph: &'a mut BTreeGraph<K, V>,
+    remove: bool,
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V> Visitor<'de> for IntoVisitor<'_, K, V>
+where
+    K: Deserialize<'de> + Ord,
+    V: Deserialize<'de>,
+{
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(formatter, "a map")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let BTreeGraph {
+            graph,
+            index,
+            observer,
+            ..
+        } = self.graph;
+        let mut old = core::mem::take(index);
+
+        loop {
+            let (key, value) = match map.next_entry::<K, V>() {
+                Ok(Some(entry)) => entry,
+                Ok(None) => break,
+                Err(err) => {
+                    index.append(&mut old);
+                    return Err(err);
+                }
+            };
+
+            match index.get(&key) {
+                Some(node) => {
+                    replace_observed(observer, &key, node, value);
+                }
+                None => match old.remove(&key) {
+                    Some(node) => {
+                        replace_observed(observer, &key, &node, value);
+                        index.insert(key, node);
+                    }
+                    None => {
+                        let node = graph.insert(value);
+                        if let Some(observer) = observer.as_mut() {
+                            observer.on_insert(&key, unsafe { node.get_unchecked() });
+                        }
+                        index.insert(key, node);
+                    }
+                },
+            }
+        }
+
+        if self.remove {
+            old.into_iter().for_each(|(key, node)| {
+                let value = unsafe { node.try_remove_unchecked() };
+                if let Some(observer) = observer.as_mut() {
+                    observer.on_remove(&key, value.as_ref());
+                }
+            });
+        } else {
+            index.append(&mut old);
+        }
+
+        Ok(())
+    }
+}
+
+impl<K, V> GraphReadView<'_, K, V> {
+    /// The reference to the mutably borrowed node.
+    pub fn node_ref(&self) -> &Ref<V> {
+        self.node
+    }
+
+    pub fn get_ref<Q>(&self, key: &Q) -> Option<&Ref<V>>
+    where
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
+    {
+        self.graph.get_ref(key)
+    }
+
+    /// Get the value for `key`. Returns `None` for the mutably
+    /// borrowed node.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
+    {
+        let node = self.graph.get_ref(key)?;
+        if node == self.node {
+            return None;
+        }
+        unsafe { Some(node.get_unchecked()) }
+    }
+
+    /// Borrow a node from the graph. Panics if it is the mutably
+    /// borrowed node, or under the same conditions as
+    /// `BTreeGraph::borrow`.
+    pub fn borrow<R>(&self, node: &R) -> &V
+    where
+        R: AsRef<Ref<V>>,
+    {
+        assert!(
+            node.as_ref() != self.node,
+            "borrowed the node that is being mutated"
+        );
+        self.graph.borrow(node)
+    }
+
+    /// Iterate over the entries in ascending key order, skipping the
+    /// mutably borrowed node.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        let skipped = self.node;
+        unsafe {
+            self.graph
+                .iter_ref()
+                .filter(move |(_, node)| *node != skipped)
+                .map(|(key, node)| (key, node.get_unchecked()))
+        }
+    }
+}
+
+impl<'a, K, V> Cursor<'a, K, V>
+where
+    K: Ord,
+{
+    /// The key of the current node, or `None` at the ghost position.
+    pub fn key(&self) -> Option<&'a K> {
+        self.current.map(|(key, _)| key)
+    }
+
+    pub fn value_ref(&self) -> Option<&'a Ref<V>> {
+        self.current.map(|(_, node)| node)
+    }
+
+    /// The value of the current node, or `None` at the ghost position
+    /// or if the node was promised but not yet created.
+    pub fn value(&self) -> Option<&'a V> {
+        self.current
+            .and_then(|(_, node)| unsafe { node.try_get_unchecked() })
+    }
+
+    /// Move the cursor to the next node, or from the last node to
+    /// the ghost position. Returns false if the cursor ends up at
+    /// the ghost position.
+    pub fn move_next(&mut self) -> bool {
+        self.current = match self.current {
+            Some((key, _)) => self
+                .graph
+                .index
+                .range((Bound::Excluded(key), Bound::Unbounded))
+                .next(),
+            None => self.graph.index.first_key_value(),
+        };
+        self.current.is_some()
+    }
+
+    /// Move the cursor to the previous node, or from the first node
+    /// to the ghost position. Returns false if the cursor ends up at
+    /// the ghost position.
+    pub fn move_prev(&mut self) -> bool {
+        self.current = match self.current {
+            Some((key, _)) => self
+                .graph
+                .index
+                .range((Bound::Unbounded, Bound::Excluded(key)))
+                .next_back(),
+            None => self.graph.index.last_key_value(),
+        };
+        self.current.is_some()
+    }
+}
+
+impl<K, V> CursorMut<'_, K, V>
+where
+    K: Ord + Clone,
+{
+    /// The key of the current node, or `None` at the ghost position.
+    pub fn key(&self) -> Option<&K> {
+        self.current.as_ref()
+    }
+
+    /// The value of the current node, or `None` at the ghost position
+    /// or if the node was promised but not yet created.
+    pub fn value(&self) -> Option<&V> {
+        let node = self.graph.get_ref(self.current.as_ref()?)?;
+        unsafe { node.try_get_unchecked() }
+    }
+
+    /// Mutably borrow the value of the current node. See `value`.
+    pub fn value_mut(&mut self) -> Option<&mut V> {
+        let node = self.graph.get_ref(self.current.as_ref()?)?;
+        unsafe { node.try_get_unchecked_mut() }
+    }
+
+    /// Access the underlying graph, e.g. to look up other nodes.
+    pub fn graph(&mut self) -> &mut BTreeGraph<K, V> {
+        self.graph
+    }
+
+    /// Move the cursor to the next node. Returns false if there
+    /// are no more nodes. Unlike `move_next`, this does not move
+    /// away from the ghost position.
+    pub fn advance(&mut self) -> bool {
+        self.current.is_some() && self.move_next()
+    }
+
+    /// Move the cursor to the next node, or from the last node to
+    /// the ghost position. Returns false if the cursor ends up at
+    /// the ghost position.
+    pub fn move_next(&mut self) -> bool {
+        self.current = match &self.current {
+            Some(key) => self
+                .graph
+                .index
+                .range((Bound::Excluded(key), Bound::Unbounded))
+                .next()
+                .map(|(k, _)| k.clone()),
+            None => self.graph.index.keys().next().cloned(),
+        };
+        self.current.is_some()
+    }
+
+    /// Move the cursor to the previous node, or from the first node
+    /// to the ghost position. Returns false if the cursor ends up at
+    /// the ghost position.
+    pub fn move_prev(&mut self) -> bool {
+        self.current = match &self.current {
+            Some(key) => self
+                .graph
+                .index
+                .range((Bound::Unbounded, Bound::Excluded(key)))
+                .next_back()
+                .map(|(k, _)| k.clone()),
+            None => self.graph.index.keys().next_back().cloned(),
+        };
+        self.current.is_some()
+    }
+
+    /// Remove the current node from the graph and move the cursor to
+    /// the next node. As with `BTreeGraph::remove`, any remaining
+    /// references to the node will be dangling.
+    pub fn remove_current(&mut self) -> Option<(K, V)> {
+        let key = self.current.clone()?;
+        self.advance();
+        let value = self.graph.remove(&key)?;
+        Some((key, value))
+    }
+
+    /// Insert a node that will be visited later by `move_next`,
+    /// without moving the cursor. Panics if the key does not sort
+    /// after the current key or, at the ghost position, if it does
+    /// not sort before the first key.
+    pub fn insert_after(&mut self, key: K, value: V) -> Ref<V> {
+        let valid = match &self.current {
+            Some(current) => &key > current,
+            None => self
+                .graph
+                .index
+                .keys()
+                .next()
+                .is_none_or(|first| &key < first),
+        };
+        assert!(
+            valid,
+            "inserted key must sort after the cursor's current key"
+        );
+        self.graph.insert(key, value)
+    }
+
+    /// Insert a node that will be visited later by `move_prev`,
+    /// without moving the cursor. Panics if the key does not sort
+    /// before the current key or, at the ghost position, if it does
+    /// not sort after the last key.
+    pub fn insert_before(&mut self, key: K, value: V) -> Ref<V> {
+        let valid = match &self.current {
+            Some(current) => &key < current,
+            None => self
+                .graph
+                .index
+                .keys()
+                .next_back()
+                .is_none_or(|last| &key > last),
+        };
+        assert!(
+            valid,
+            "inserted key must sort before the cursor's current key"
+        );
+        self.graph.insert(key, value)
+    }
+}
+
+impl<'a, K, V> Entry<'a, K, V>
+where
+    K: Ord,
+{
+    /// Return the entry's value, inserting `default()` if the entry
+    /// is vacant. If the key was promised but not yet created, the
+    /// promised slot is filled with `default()`.
+    pub fn or_insert_with<F>(self, default: F) -> &'a mut V
+    where
+        F: FnOnce() -> V,
+    {
+        unsafe { self.or_fill_with(default).get_unchecked_mut() }
+    }
+
+    /// Modify the entry's value with `modify` if the node exists, or
+    /// insert `default()` otherwise. As with `or_insert_with`, a
+    /// promised node is filled with `default()`.
+    pub fn modify_or_insert_with<M, F>(self, modify: M, default: F) -> &'a mut V
+    where
+        M: FnOnce(&mut V),
+        F: FnOnce() -> V,
+    {
+        match self.entry {
+            btree_map::Entry::Occupied(ent) if !unsafe { ent.get().is_promised_unchecked() } => {
+                let value = unsafe { ent.into_mut().get_unchecked_mut() };
+                modify(value);
+                value
+            }
+            entry => Self { entry, ..self }.or_insert_with(default),
+        }
+    }
+
+    /// Alias for `or_insert_with`, making the filling of promised
+    /// nodes explicit.
+    pub fn or_create_with<F>(self, default: F) -> &'a mut V
+    where
+        F: FnOnce() -> V,
+    {
+        self.or_insert_with(default)
+    }
+
+    /// Return the entry's value, inserting `V::default()` if the entry
+    /// is vacant or filling the promised slot with it.
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Like `or_insert_with`, but additionally return the node's
+    /// reference, e.g. to register back-edges to the node without a
+    /// second lookup.
+    pub fn ref_handle<F>(self, default: F) -> (Ref<V>, &'a mut V)
+    where
+        F: FnOnce() -> V,
+    {
+        let node = self.or_fill_with(default);
+        unsafe { (node.clone(), node.get_unchecked_mut()) }
+    }
+
+    fn or_fill_with<F>(self, default: F) -> &'a Ref<V>
+    where
+        F: FnOnce() -> V,
+    {
+        match self.entry {
+            btree_map::Entry::Vacant(ent) => {
+                let node = self.graph.insert(default());
+                if let Some(observer) = self.observer {
+                    observer.on_insert(ent.key(), unsafe { node.get_unchecked() });
+                }
+                ent.insert(node)
+            }
+            btree_map::Entry::Occupied(ent) => {
+                unsafe {
+                    let node = ent.get();
+                    if node.is_promised_unchecked() {
+                        node.try_replace_unchecked(default());
+                        if let Some(observer) = self.observer {
+                            observer.on_insert(ent.key(), node.get_unchecked());
+                        }
+                    }
+                }
+                ent.into_mut()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::{
+        ops::Bound,
+        sync::{Arc, Mutex},
+    };
+
+    use crate::{BTreeGraph, CreateError, GraphObserver, RefMap};
+
+    #[test]
+    fn pop_first_last() {
+        let mut graph = [(3, "c"), (1, "a"), (4, "d"), (2, "b")]
+            .into_iter()
+            .collect::<BTreeGraph<_, _>>();
+
+        assert_eq!(graph.pop_last(), Some((4, "d")));
+
+        let mut drained = Vec::new();
+        while let Some(entry) = graph.pop_first() {
+            drained.push(entry);
+        }
+
+        assert_eq!(drained, vec![(1, "a"), (2, "b"), (3, "c")]);
+        assert!(graph.is_empty());
+        assert_eq!(graph.pop_first(), None);
+        assert_eq!(graph.pop_last(), None);
+
+        graph.promise(0);
+        graph.insert(1, "a");
+        graph.promise(2);
+        graph.promise(3);
+        assert_eq!(graph.pop_first(), Some((1, "a")));
+        assert_eq!(graph.keys().collect::<Vec<_>>(), [&2, &3]);
+        assert_eq!(graph.pop_last(), None);
+        assert!(graph.is_empty());
+    }
+
+    #[test]
+    fn resolve() {
+        use crate::{IndexBy, RefBy, Resolve};
+
+        struct Node(Vec<RefBy<u32, Node>>);
+
+        impl Resolve<u32, Node> for Node {
+            fn resolve<I>(&mut self, index: &I) -> Result<(), u32>
+            where
+                I: IndexBy<u32, Node>,
+            {
+                self.0.resolve(index)
+            }
+        }
+
+        let mut graph = BTreeGraph::new();
+        graph.insert(1, Node(vec![RefBy::dangling(2)]));
+        graph.insert(2, Node(vec![RefBy::dangling(1), RefBy::dangling(2)]));
+        graph.resolve().unwrap();
+
+        let node = graph.get(&2).unwrap();
+        assert_eq!(graph.borrow(&node.0[0]).0.len(), 1);
+        assert_eq!(graph.borrow(&node.0[1]).0.len(), 2);
+
+        graph.insert(3, Node(vec![RefBy::dangling(4)]));
+        assert_eq!(graph.resolve().err(), Some(vec![4]));
+    }
+
+    #[test]
+    fn merge() {
+        use crate::{IndexBy, MergeDecision, RefBy, Resolve};
+
+        struct Node {
+            name: &'static str,
+            links: Vec<RefBy<u32, Node>>,
+        }
+
+        impl Resolve<u32, Node> for Node {
+            fn resolve<I>(&mut self, index: &I) -> Result<(), u32>
+            where
+                I: IndexBy<u32, Node>,
+            {
+                self.links.resolve(index)
+            }
+        }
+
+        fn graph(nodes: &[(u32, &'static str, &[u32])]) -> BTreeGraph<u32, Node> {
+            let mut graph = nodes
+                .iter()
+                .map(|(key, name, links)| {
+                    let links = links.iter().copied().map(RefBy::dangling).collect();
+                    (*key, Node { name, links })
+                })
+                .collect::<BTreeGraph<_, _>>();
+            graph.resolve().unwrap();
+            graph
+        }
+
+        fn links(graph: &BTreeGraph<u32, Node>, key: u32) -> Vec<&'static str> {
+            let node = graph.get(&key).unwrap();
+            node.links.iter().map(|r| graph.borrow(r).name).collect()
+        }
+
+        let mut ours = graph(&[(1, "a", &[2]), (2, "b", &[]), (3, "c", &[1])]);
+        let theirs = graph(&[
+            (1, "A", &[4]),
+            (2, "B", &[5]),
+            (3, "C", &[]),
+            (4, "d", &[1, 5]),
+            (5, "e", &[4]),
+        ]);
+        let first = ours.get_ref(&1).unwrap().clone();
+        let second = ours.get_ref(&2).unwrap().clone();
+
+        impl GraphObserver<u32, Node> for Events {
+            fn on_replace(&mut self, key: &u32, _: &Node, new: &Node) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("replace {key} {}", new.name));
+            }
+
+            fn on_merge(&mut self, key: &u32, value: &Node) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("merge {key} {}", value.name));
+            }
+        }
+
+        let events = Events::default();
+        ours.set_observer(events.clone());
+
+        ours.merge(theirs, |key, existing, mut incoming| match key {
+            1 => MergeDecision::Replace(incoming),
+            2 => {
+                existing.links.append(&mut incoming.links);
+                MergeDecision::Merged
+            }
+            _ => MergeDecision::KeepExisting,
+        })
+        .unwrap();
+
+        assert_eq!(events.take(), vec!["replace 1 A", "merge 2 b"]);
+        assert_eq!(ours.borrow(&first).name, "A");
+        assert_eq!(ours.borrow(&second).name, "b");
+        assert_eq!(ours.get(&3).unwrap().name, "c");
+        assert_eq!(links(&ours, 1), vec!["d"]);
+        assert_eq!(links(&ours, 2), vec!["e"]);
+        assert_eq!(links(&ours, 3), vec!["A"]);
+        assert_eq!(links(&ours, 4), vec!["A", "e"]);
+        assert_eq!(links(&ours, 5), vec!["d"]);
+
+        let theirs = graph(&[(6, "f", &[])]);
+        let mut dangling = BTreeGraph::new();
+        dangling.insert(
+            7,
+            Node {
+                name: "g",
+                links: vec![RefBy::dangling(8)],
+            },
+        );
+        ours.merge(theirs, |_, _, incoming| MergeDecision::Replace(incoming))
+            .unwrap();
+        assert_eq!(
+            ours.merge(dangling, |_, _, incoming| MergeDecision::Replace(incoming)),
+            Err(vec![8])
+        );
+
+        let mut theirs = graph(&[(9, "i", &[10]), (10, "j", &[])]);
+        theirs.remove(&10);
+        assert_eq!(ours.merge(theirs, |_, _, _| unreachable!()), Err(vec![10]));
+        let link = &ours.get(&9).unwrap().links[0];
+        assert!(unsafe { link.value_ref().try_get_unchecked() }.is_none());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialize_into() {
+        use serde::de::value::{Error, MapDeserializer};
+
+        let input = |entries: Vec<(u32, &'static str)>| {
+            MapDeserializer::<_, Error>::new(entries.into_iter())
+        };
+
+        let mut graph = BTreeGraph::<u32, String>::new();
+        graph
+            .merge_from_deserializer(input(vec![(1, "a"), (2, "b"), (3, "c")]))
+            .unwrap();
+        let a = graph.get_ref(&1).unwrap().clone();
+        let b = graph.get_ref(&2).unwrap().clone();
+
+        graph
+            .merge_from_deserializer(input(vec![(1, "x"), (4, "d")]))
+            .unwrap();
+        assert_eq!(graph.borrow(&a), "x");
+        assert_eq!(graph.borrow(&b), "b");
+        assert_eq!(graph.keys().copied().collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+
+        graph
+            .deserialize_into(input(vec![(2, "y"), (4, "d"), (5, "e")]))
+            .unwrap();
+        assert_eq!(graph.get_ref(&2), Some(&b));
+        assert_eq!(graph.borrow(&b), "y");
+        assert_eq!(graph.keys().copied().collect::<Vec<_>>(), vec![2, 4, 5]);
+        assert_eq!(graph.get(&1), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialize_duplicate_keys() {
+        use serde::{
+            de::value::{Error, MapDeserializer},
+            Deserialize,
+        };
+
+        let input = MapDeserializer::<_, Error>::new([(1, "a"), (2, "b"), (1, "c")].into_iter());
+        let graph = BTreeGraph::<u32, String>::deserialize(input).unwrap();
+        assert_eq!(graph.as_ref().slot_count(), 2);
+        assert_eq!(
+            graph.iter().collect::<Vec<_>>(),
+            vec![(&1, &"c".to_string()), (&2, &"b".to_string())]
+        );
+
+        let graph = serde_json::from_str::<BTreeGraph<u32, String>>(r#"{"1":"a","2":"b","1":"c"}"#)
+            .unwrap();
+        assert_eq!(graph.as_ref().slot_count(), 2);
+        assert_eq!(
+            serde_json::to_string(&graph).unwrap(),
+            r#"{"1":"c","2":"b"}"#
+        );
+    }
+
+    #[test]
+    fn interior_mutation() {
+        let mut graph = (0..10).map(|i| (i, i)).collect::<BTreeGraph<_, _>>();
+        let refs = graph.values_ref().cloned().collect::<Vec<_>>();
+
+        graph.values_mut().for_each(|value| *value *= 2);
+        graph.iter_mut().for_each(|(_, value)| *value += 1);
+        graph.insert(10, 21);
+
+        let [a, b] = graph.borrow_many_mut([&refs[0], &refs[9]]);
+        std::mem::swap(a, b);
+
+        assert_eq!(
+            refs.iter()
+                .map(|node| *graph.borrow(node))
+                .collect::<Vec<_>>(),
+            vec![19, 3, 5, 7, 9, 11, 13, 15, 17, 1]
+        );
+        assert_eq!(graph.get(&10), Some(&21));
+    }
+
+    #[test]
+    fn std_map_conversions() {
+        use std::collections::BTreeMap;
+
+        let map = BTreeMap::from([(1, "a".to_string()), (2, "b".to_string())]);
+        let mut graph = BTreeGraph::from(map.clone());
+        assert_eq!(graph.get(&2).map(String::as_str), Some("b"));
+
+        graph.promise(3);
+        assert_eq!(graph.to_btree_map(), map.iter().collect::<BTreeMap<_, _>>());
+        assert_eq!(graph.into_btree_map(), map);
+    }
+
+    #[test]
+    fn ref_by_resolved() {
+        use crate::RefBy;
+
+        struct Node {
+            name: &'static str,
+            parent: Option<RefBy<u32, Node>>,
+        }
+
+        let mut graph = BTreeGraph::new();
+        graph.insert(
+            1,
+            Node {
+                name: "root",
+                parent: None,
+            },
+        );
+        let node = Node {
+            name: "child",
+            parent: RefBy::resolved(1, &graph),
+        };
+        assert!(RefBy::<_, Node>::resolved(3, &graph).is_none());
+        let child = graph.insert(2, node);
+
+        let parent = graph.borrow(&child).parent.as_ref().unwrap();
+        assert_eq!(parent.key(), &1);
+        assert_eq!(graph.borrow(parent).name, "root");
+        assert_eq!(graph.borrow(&child).name, "child");
+    }
+
+    #[test]
+    fn to_ref_map() {
+        use crate::RefBy;
+
+        let graph = [("a", 1), ("b", 2)]
+            .into_iter()
+            .collect::<BTreeGraph<_, _>>();
+        let map = graph.to_ref_map();
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec![&"a", &"b"]);
+
+        let mut node = RefBy::dangling("b");
+        node.resolve(&map).unwrap();
+        assert_eq!(graph.borrow(&node), &2);
+        assert_eq!(RefBy::<_, i32>::dangling("c").resolve(&map), Err("c"));
+    }
+
+    #[test]
+    fn auto_compact() {
+        use std::sync::{Arc, Mutex};
+
+        use crate::{IndexBy, RefBy, Resolve};
+
+        struct Node(Option<RefBy<u32, Node>>);
+
+        struct Failed(Arc<Mutex<Vec<u32>>>);
+
+        impl GraphObserver<u32, Node> for Failed {
+            fn on_compact_failed(&mut self, missing: &[u32]) {
+                self.0.lock().unwrap().extend_from_slice(missing);
+            }
+        }
+
+        impl Resolve<u32, Node> for Node {
+            fn resolve<I>(&mut self, index: &I) -> Result<(), u32>
+            where
+                I: IndexBy<u32, Node>,
+            {
+                self.0.resolve(index)
+            }
+        }
+
+        let mut graph = BTreeGraph::new();
+        graph.set_auto_compact(1.0);
+        (0..10).for_each(|key| {
+            graph.insert(key, Node(None));
+        });
+
+        for key in 0..1000 {
+            let prev = graph.get_ref_by(&(key + 9)).unwrap();
+            graph.insert(key + 10, Node(Some(prev)));
+            graph.get_mut(&(key + 1)).unwrap().0 = None;
+            graph.remove(&key);
+            assert!(graph.stats().arena_slots <= 21);
+        }
+
+        let last = graph.get(&1009).unwrap();
+        let prev = graph.borrow(last.0.as_ref().unwrap());
+        assert_eq!(prev.0.as_ref().unwrap().key(), &1007);
+        assert_eq!(graph.index.len(), 10);
+
+        let failed = Arc::new(Mutex::new(Vec::new()));
+        graph.set_observer(Failed(failed.clone()));
+        let prev = graph.get_ref_by(&1000).unwrap();
+        graph.insert(2000, Node(Some(prev)));
+        let slots = graph.stats().arena_slots;
+        (1000..1009).for_each(|key| {
+            graph.remove(&key);
+        });
+        assert_eq!(graph.stats().arena_slots, slots);
+        let failed = failed.lock().unwrap();
+        assert!(failed.contains(&1000) && failed.iter().all(|key| (1000..1009).contains(key)));
+
+        graph.disable_auto_compact();
+        let slots = graph.stats().arena_slots;
+        while graph.pop_first().is_some() {}
+        assert_eq!(graph.stats().arena_slots, slots);
+    }
+
+    #[test]
+    fn iter_ref_by_ref() {
+        /// A key that cannot be cloned.
+        #[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+        struct Key(u32);
+
+        let graph = [(Key(1), "a"), (Key(2), "b")]
+            .into_iter()
+            .collect::<BTreeGraph<_, _>>();
+        let refs = graph.iter_ref_by_ref().collect::<Vec<_>>();
+        assert_eq!(refs.len(), 2);
+        assert_eq!(refs[0].key(), &Key(1));
+        assert_eq!(graph.borrow(refs[1].value_ref()), &"b");
+        assert_eq!(graph.borrow(&refs[1]), &"b");
+        assert!(refs[0] < refs[1]);
+
+        let (key, node) = refs[0].pair();
+        assert_eq!(graph.get_entry(key), Some((key, node)));
+        assert!(graph.iter_ref_pairs().eq(graph.iter_ref()));
+    }
+
+    #[test]
+    fn with_node_mut() {
+        use crate::GraphReadView;
+
+        struct Node {
+            value: u32,
+            neighbors: Vec<u32>,
+            sum: u32,
+        }
+
+        let mut graph = BTreeGraph::new();
+        for (key, neighbors) in [(1, vec![2, 3]), (2, vec![1]), (3, vec![1, 2])] {
+            let node = Node {
+                value: key * 10,
+                neighbors,
+                sum: 0,
+            };
+            graph.insert(key, node);
+        }
+
+        let recompute = |node: &mut Node, view: &GraphReadView<'_, u32, Node>| {
+            node.sum = node
+                .neighbors
+                .iter()
+                .filter_map(|key| view.get(key))
+                .map(|neighbor| neighbor.value)
+                .sum();
+            view.iter().count()
+        };
+        assert_eq!(graph.with_node_mut(&1, recompute), Some(2));
+        assert_eq!(graph.with_node_mut(&3, recompute), Some(2));
+        assert_eq!(graph.get(&1).unwrap().sum, 50);
+        assert_eq!(graph.get(&3).unwrap().sum, 30);
+        assert_eq!(graph.with_node_mut(&4, recompute), None);
+        graph.promise(5);
+        assert_eq!(graph.with_node_mut(&5, recompute), None);
+
+        graph.with_node_mut(&2, |node, view| {
+            assert!(view.get(&2).is_none());
+            assert!(view.get_ref(&2) == Some(view.node_ref()));
+            node.sum = view.borrow(view.get_ref(&1).unwrap()).sum;
+        });
+        assert_eq!(graph.get(&2).unwrap().sum, 50);
+    }
+
+    #[test]
+    #[should_panic(expected = "borrowed the node that is being mutated")]
+    fn with_node_mut_self_borrow() {
+        let mut graph = (0..3).map(|i| (i, i)).collect::<BTreeGraph<_, _>>();
+        graph.with_node_mut(&1, |_, view| {
+            view.borrow(view.node_ref());
+        });
+    }
+
+    #[test]
+    fn iter_full() {
+        let mut graph = (0..5).map(|i| (i, i * 10)).collect::<BTreeGraph<_, _>>();
+
+        let (key, node, value) = graph.get_full(&3).unwrap();
+        assert_eq!((key, value), (&3, &30));
+        assert!(Some(node) == graph.get_ref(&3));
+        assert!(graph.get_full(&5).is_none());
+
+        assert!(graph
+            .iter_full()
+            .all(|(key, node, value)| graph.borrow(node) == value && *value == key * 10));
+        assert_eq!(graph.iter_full().len(), 5);
+        assert_eq!(
+            graph.iter_full().next_back().map(|(key, _, _)| key),
+            Some(&4)
+        );
+
+        let mut adjacent = Vec::new();
+        graph.iter_full_mut().for_each(|(key, node, value)| {
+            *value += key;
+            adjacent.push(node.clone());
+        });
+        assert_eq!(graph.borrow(&adjacent[4]), &44);
+    }
+
+    #[test]
+    fn double_ended_iterators() {
+        let mut graph = (0..10).map(|i| (i, i * 10)).collect::<BTreeGraph<_, _>>();
+
+        assert_eq!(graph.keys().len(), 10);
+        assert_eq!(graph.keys().rev().take(3).collect::<Vec<_>>(), [&9, &8, &7]);
+        assert_eq!(graph.iter().len(), 10);
+        assert_eq!(graph.iter().next_back(), Some((&9, &90)));
+        assert_eq!(graph.iter().rfind(|(_, v)| **v < 45), Some((&4, &40)));
+        assert_eq!(graph.values().len(), 10);
+        assert_eq!(graph.values().rev().nth(1), Some(&80));
+        assert_eq!(graph.values_ref().len(), 10);
+        assert_eq!(graph.values_ref().next_back(), graph.get_ref(&9));
+        assert_eq!(graph.iter_ref().len(), 10);
+        assert_eq!(graph.iter_ref().next_back().map(|(k, _)| k), Some(&9));
+        assert_eq!(graph.iter_ref_pairs().rev().count(), 10);
+        assert_eq!(graph.iter_ref_by_ref().len(), 10);
+        assert_eq!(graph.iter_ref_by_ref().next_back().unwrap().key(), &9);
+        assert_eq!(graph.iter_ref_by().len(), 10);
+        assert_eq!(graph.iter_ref_by().next_back().unwrap().key(), &9);
+
+        {
+            let mut iter = graph.keys().skip(8);
+            assert_eq!(iter.len(), 2);
+            assert_eq!(iter.next(), Some(&8));
+            assert_eq!(iter.next(), Some(&9));
+            assert_eq!(iter.next(), None);
+            assert_eq!(iter.next(), None);
+        }
+
+        assert_eq!(graph.iter_mut().len(), 10);
+        graph.iter_mut().rev().take(2).for_each(|(_, v)| *v += 1);
+        assert_eq!(graph.values_mut().len(), 10);
+        graph.values_mut().rev().take(1).for_each(|v| *v += 1);
+        assert_eq!(
+            graph.values().rev().take(3).collect::<Vec<_>>(),
+            [&92, &81, &70]
+        );
+    }
+
+    #[test]
+    fn cursor() {
+        let mut graph = (0..10).map(|i| (i, i * i)).collect::<BTreeGraph<_, _>>();
+        let mut cursor = graph.cursor(&2);
+        let mut visited = Vec::new();
+
+        while let Some(&key) = cursor.key() {
+            visited.push(key);
+            if key == 4 {
+                cursor.insert_after(20, 400);
+            }
+            if cursor.value().is_some_and(|v| v % 2 == 1) {
+                cursor.remove_current();
+            } else {
+                *cursor.value_mut().unwrap() += 1;
+                cursor.advance();
+            }
+        }
+
+        assert_eq!(visited, vec![2, 3, 4, 5, 6, 7, 8, 9, 20]);
+        assert_eq!(
+            graph.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(),
+            vec![(0, 0), (1, 1), (2, 5), (4, 17), (6, 37), (8, 65), (20, 401)]
+        );
+    }
+
+    #[test]
+    fn cursor_navigation() {
+        let mut graph = (1..=5).map(|i| (i * 10, i)).collect::<BTreeGraph<_, _>>();
+        let mut cursor = graph.lower_bound_mut(Bound::Excluded(&20));
+        assert_eq!(cursor.key(), Some(&30));
+
+        cursor.insert_before(25, 0);
+        assert!(cursor.move_prev());
+        assert_eq!(cursor.key(), Some(&25));
+        assert_eq!(cursor.remove_current(), Some((25, 0)));
+        assert_eq!(cursor.key(), Some(&30));
+
+        assert!(cursor.move_next() && cursor.move_next());
+        assert_eq!(cursor.key(), Some(&50));
+        assert!(!cursor.move_next());
+        cursor.insert_before(60, 6);
+        cursor.insert_after(0, 0);
+        assert!(cursor.move_prev());
+        assert_eq!(cursor.key(), Some(&60));
+        assert!(!cursor.move_next() && cursor.move_next());
+        assert_eq!(cursor.key(), Some(&0));
+        *cursor.value_mut().unwrap() = 7;
+        assert!(!cursor.move_prev());
+        assert!(!cursor.advance());
+
+        let keys = |graph: &BTreeGraph<i32, i32>, bound| {
+            let mut cursor = graph.lower_bound(bound);
+            let mut keys = Vec::new();
+            while let Some(&key) = cursor.key() {
+                keys.push((key, *cursor.value().unwrap()));
+                cursor.move_next();
+            }
+            keys
+        };
+        assert_eq!(
+            keys(&graph, Bound::Unbounded),
+            vec![(0, 7), (10, 1), (20, 2), (30, 3), (40, 4), (50, 5), (60, 6)]
+        );
+        assert_eq!(
+            keys(&graph, Bound::Included(&40)),
+            vec![(40, 4), (50, 5), (60, 6)]
+        );
+
+        let mut cursor = graph.lower_bound(Bound::Excluded(&60));
+        assert_eq!(cursor.key(), None);
+        assert!(cursor.move_prev());
+        assert_eq!(cursor.value(), Some(&6));
+
+        graph.promise(70);
+        let cursor = graph.lower_bound(Bound::Included(&70));
+        assert_eq!((cursor.key(), cursor.value()), (Some(&70), None));
+        let mut cursor = graph.cursor(&70);
+        assert_eq!(cursor.value(), None);
+        assert_eq!(cursor.value_mut(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "inserted key must sort before the cursor's current key")]
+    fn cursor_insert_out_of_order() {
+        let mut graph = (1..=5).map(|i| (i, i)).collect::<BTreeGraph<_, _>>();
+        graph
+            .lower_bound_mut(Bound::Included(&3))
+            .insert_before(4, 4);
+    }
+
+    #[derive(Clone, Default)]
+    struct Events(Arc<Mutex<Vec<String>>>);
+
+    impl Events {
+        fn take(&self) -> Vec<String> {
+            std::mem::take(&mut self.0.lock().unwrap())
+        }
+    }
+
+    impl GraphObserver<u32, &'static str> for Events {
+        fn on_insert(&mut self, key: &u32, value: &&str) {
+            self.0.lock().unwrap().push(format!("insert {key} {value}"));
+        }
+
+        fn on_replace(&mut self, key: &u32, old: &&str, new: &&str) {
+            let event = format!("replace {key} {old} {new}");
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn on_remove(&mut self, key: &u32, value: Option<&&str>) {
+            let event = format!("remove {key} {}", value.unwrap_or(&"-"));
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn on_clear(&mut self) {
+            self.0.lock().unwrap().push("clear".to_string());
+        }
+    }
+
+    #[test]
+    fn observer() {
+        let events = Events::default();
+        let mut graph = BTreeGraph::new();
+        graph.set_observer(events.clone());
+
+        graph.insert(1, "a");
+        graph.insert(1, "b");
+        assert_eq!(events.take(), vec!["insert 1 a", "replace 1 a b"]);
+
+        let node = graph.promise(2);
+        graph.create(&node, "c");
+        graph.create_or_update(&node, "d");
+        graph.promise(3);
+        graph.insert(3, "e");
+        graph.promise(4);
+        assert_eq!(graph.try_create(&4, "f"), Ok(()));
+        assert_eq!(
+            events.take(),
+            vec!["insert 2 c", "replace 2 c d", "insert 3 e", "insert 4 f"]
+        );
+
+        graph.entry(5).or_insert_with(|| "g");
+        graph.entry(5).or_insert_with(|| "x");
+        graph.promise(6);
+        graph.entry(6).or_insert_with(|| "h");
+        graph.promise(4);
+        assert_eq!(
+            events.take(),
+            vec!["insert 5 g", "insert 6 h", "remove 4 f"]
+        );
+
+        graph.remove(&1);
+        graph.retain_mut(|key, _| key % 2 == 1);
+        graph.extend([(7, "i"), (3, "j")]);
+        assert_eq!(
+            events.take(),
+            vec![
+                "remove 1 b",
+                "remove 2 d",
+                "remove 6 h",
+                "insert 7 i",
+                "replace 3 e j"
+            ]
+        );
+
+        graph.pop_first();
+        graph.remove_many([7]);
+        graph.cursor(&5).remove_current();
+        graph.clear();
+        assert_eq!(
+            events.take(),
+            vec!["remove 3 j", "remove 7 i", "remove 5 g", "clear"]
+        );
+        assert!(graph.take_observer().is_some());
+        graph.insert(8, "k");
+        assert!(events.take().is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn observer_deserialize_into() {
+        use serde::de::value::{Error, MapDeserializer};
+
+        let events = Events::default();
+        let mut graph = [(1, "a"), (2, "b")]
+            .into_iter()
+            .collect::<BTreeGraph<_, _>>();
+        graph.set_observer(events.clone());
+
+        let input = MapDeserializer::<_, Error>::new([(2, "c"), (3, "d")].into_iter());
+        graph.deserialize_into(input).unwrap();
+        assert_eq!(
+            events.take(),
+            vec!["replace 2 b c", "insert 3 d", "remove 1 a"]
+        );
+    }
+
+    #[test]
+    fn entry_ref_handle() {
+        use crate::Ref;
+
+        struct Node {
+            parent: Option<Ref<Node>>,
+            children: Vec<Ref<Node>>,
+        }
+
+        let new = || Node {
+            parent: None,
+            children: Vec::new(),
+        };
+
+        let mut graph = BTreeGraph::new();
+        let (root, _) = graph.entry("root").ref_handle(new);
+
+        for name in ["a", "b", "a"] {
+            let (child, node) = graph.entry(name).ref_handle(new);
+            if node.parent.is_none() {
+                node.parent = Some(root.clone());
+                graph.borrow_mut(&root).children.push(child);
+            }
+        }
+
+        let (_, root_node) = graph.entry("root").ref_handle(new);
+        assert_eq!(root_node.children.len(), 2);
+        assert!(root_node.parent.is_none());
+        assert!(graph
+            .values()
+            .filter(|node| node.parent.is_some())
+            .all(|node| node.parent.as_ref() == Some(&root)));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_values_mut() {
+        use core::cell::Cell;
+
+        use rayon::prelude::*;
+
+        let new = || (0..10_000u64).map(|i| (i, i)).collect::<BTreeGraph<_, _>>();
+        let mut graph = new();
+        let mut expected = new();
+
+        graph
+            .par_values_mut()
+            .for_each(|value| *value = *value * 3 + 1);
+        expected
+            .values_mut()
+            .for_each(|value| *value = *value * 3 + 1);
+
+        assert!(graph.iter().eq(expected.iter()));
+        assert_eq!(
+            graph.par_values().map(|value| *value).sum::<u64>(),
+            expected.values().sum::<u64>()
+        );
+        assert_eq!(graph.par_iter().count(), 10_000);
+
+        /* The values only need to be Send. */
+        let mut cells = (0..100u64)
+            .map(|i| (i, Cell::new(i)))
+            .collect::<BTreeGraph<_, _>>();
+        cells.par_values_mut().for_each(|cell| *cell.get_mut() += 1);
+        assert_eq!(cells.get(&99).map(Cell::get), Some(100));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialize_resolved() {
+        use serde::de::{
+            value::{Error, MapDeserializer, SeqDeserializer},
+            Deserialize, Deserializer, Error as _, MapAccess, Visitor,
+        };
+        use std::fmt::Formatter;
+
+        use crate::{RefBy, Resolve};
+
+        struct Node(Vec<RefBy<u32, Node>>);
+
+        impl<'de> Deserialize<'de> for Node {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                Vec::deserialize(deserializer).map(Node)
+            }
+        }
+
+        impl Resolve<u32, Node> for Node {
+            fn resolve<I: crate::IndexBy<u32, Node>>(&mut self, index: &I) -> Result<(), u32> {
+                self.0.resolve(index)
+            }
+        }
+
+        /// Equivalent to deriving `Deserialize` with
+        /// `#[serde(deserialize_with = "graph::deserialize_resolved")]`
+        /// on the graph field.
+        struct Parent {
+            graph: BTreeGraph<u32, Node>,
+        }
+
+        impl<'de> Deserialize<'de> for Parent {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                struct DeserializeWith(BTreeGraph<u32, Node>);
+
+                impl<'de> Deserialize<'de> for DeserializeWith {
+                    fn deserialize<D: Deserializer<'de>>(
+                        deserializer: D,
+                    ) -> Result<Self, D::Error> {
+                        super::deserialize_resolved(deserializer).map(DeserializeWith)
+                    }
+                }
+
+                struct ParentVisitor;
+
+                impl<'de> Visitor<'de> for ParentVisitor {
+                    type Value = Parent;
+
+                    fn expecting(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+                        write!(f, "a parent struct")
+                    }
+
+                    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Parent, A::Error> {
+                        let mut graph = None;
+                        while let Some(field) = map.next_key::<String>()? {
+                            match field.as_str() {
+                                "graph" => graph = Some(map.next_value::<DeserializeWith>()?.0),
+                                _ => return Err(A::Error::unknown_field(&field, &["graph"])),
+                            }
+                        }
+                        Ok(Parent {
+                            graph: graph.ok_or_else(|| A::Error::missing_field("graph"))?,
+                        })
+                    }
+                }
+
+                deserializer.deserialize_struct("Parent", &["graph"], ParentVisitor)
+            }
+        }
+
+        let input = |edges: Vec<(u32, Vec<u32>)>| {
+            let graph = edges
+                .into_iter()
+                .map(|(key, targets)| (key, SeqDeserializer::new(targets.into_iter())));
+            MapDeserializer::<_, Error>::new([("graph", MapDeserializer::new(graph))].into_iter())
+        };
+
+        let parent =
+            Parent::deserialize(input(vec![(1, vec![2]), (2, vec![1, 2]), (3, vec![])])).unwrap();
+        let one = parent.graph.get(&1).unwrap();
+        let two = parent.graph.borrow(&one.0[0]);
+        assert_eq!(parent.graph.borrow(&two.0[0]).0[0].key(), &2);
+        assert_eq!(parent.graph.borrow(&two.0[1]).0.len(), 2);
+
+        assert!(Parent::deserialize(input(vec![(1, vec![4])])).is_err());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn build_parallel() {
+        use crate::{IndexBy, RefBy, Resolve};
+
+        struct Node {
+            value: u32,
+            next: RefBy<u32, Node>,
+        }
+
+        impl Resolve<u32, Node> for Node {
+            fn resolve<I: IndexBy<u32, Node>>(&mut self, index: &I) -> Result<(), u32> {
+                self.next.resolve(index)
+            }
+        }
+
+        const SHARD: u32 = 100;
+        const SHARDS: u32 = 8;
+
+        let shards = (0..SHARDS)
+            .map(|shard| {
+                move || {
+                    let mut graph = BTreeGraph::new();
+                    (shard * SHARD..(shard + 1) * SHARD).for_each(|i| {
+                        let next = (i + 1) % (SHARD * SHARDS);
+                        let node = Node {
+                            value: i,
+                            next: RefBy::dangling(next),
+                        };
+                        graph.insert(i, node);
+                    });
+                    graph
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let graph = BTreeGraph::build_parallel(shards).unwrap();
+        assert_eq!(graph.index().len(), (SHARD * SHARDS) as usize);
+        graph.iter().for_each(|(key, node)| {
+            let next = graph.borrow(&node.next);
+            assert_eq!(next.value, (key + 1) % (SHARD * SHARDS));
+        });
+
+        let missing = BTreeGraph::build_parallel(vec![|| {
+            let mut graph = BTreeGraph::new();
+            graph.insert(
+                1,
+                Node {
+                    value: 1,
+                    next: RefBy::dangling(2),
+                },
+            );
+            graph
+        }])
+        .err()
+        .unwrap();
+        assert_eq!(missing, vec![2]);
+
+        let shards: Vec<Box<dyn FnOnce() -> BTreeGraph<u32, Node> + Send>> = vec![
+            Box::new(|| {
+                let mut graph = BTreeGraph::new();
+                let next = RefBy::dangling(2);
+                graph.insert(1, Node { value: 1, next });
+                graph
+            }),
+            Box::new(|| {
+                let mut graph = BTreeGraph::new();
+                let next = RefBy::new(1, graph.promise(1));
+                graph.insert(2, Node { value: 2, next });
+                graph
+            }),
+        ];
+        let graph = BTreeGraph::build_parallel(shards).unwrap();
+        assert_eq!(graph.borrow(&graph.get(&2).unwrap().next).value, 1);
+        assert_eq!(graph.validate(), Ok(()));
+    }
+
+    #[test]
+    fn remove_many() {
+        let mut graph = (0..10).map(|i| (i, i * 10)).collect::<BTreeGraph<_, _>>();
+        graph.promise(12);
+        let removed = graph.remove_many([7, 2, 11, 12, 5, 2]);
+        assert_eq!(removed, vec![(7, 70), (2, 20), (5, 50)]);
+        assert!(graph.get_ref(&12).is_none());
+        assert_eq!(
+            graph.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(),
+            [0, 1, 3, 4, 6, 8, 9]
+                .into_iter()
+                .map(|i| (i, i * 10))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn retain_mut() {
+        let mut graph = (0..10).map(|i| (i, i)).collect::<BTreeGraph<_, _>>();
+        let kept = graph.get_ref(&4).unwrap().clone();
+        graph.promise(10);
+        graph.retain_mut(|key, value| {
+            *value *= 10;
+            key % 2 == 0
+        });
+        assert_eq!(
+            graph.keys().copied().collect::<Vec<_>>(),
+            vec![0, 2, 4, 6, 8, 10]
+        );
+        assert_eq!(graph.borrow(&kept), &40);
+    }
+
+    #[test]
+    fn filter_map_values() {
+        use crate::{IndexBy, RefBy, Resolve};
+
+        struct Node {
+            value: u32,
+            next: RefBy<u32, Node>,
+        }
+
+        impl Resolve<u32, Node> for Node {
+            fn resolve<I: IndexBy<u32, Node>>(&mut self, index: &I) -> Result<(), u32> {
+                self.next.resolve(index)
+            }
+        }
+
+        let mut graph = (0..6).map(|i| (i, i * 10)).collect::<BTreeGraph<_, _>>();
+        graph.promise(6);
+        let mut filtered = graph.filter_map_values(|value| {
+            (value % 20 == 0).then(|| Node {
+                value: value + 1,
+                next: RefBy::dangling((value / 10 + 2) % 6),
+            })
+        });
+        assert_eq!(filtered.keys().copied().collect::<Vec<_>>(), [0, 2, 4]);
+        assert_eq!(
+            filtered.values().map(|node| node.value).collect::<Vec<_>>(),
+            [1, 21, 41]
+        );
+        filtered.resolve().unwrap();
+        let next = filtered.borrow(&filtered.get(&4).unwrap().next);
+        assert_eq!(next.value, 1);
+
+        let graph = (0..4).map(|i| (i, i)).collect::<BTreeGraph<_, _>>();
+        let mut filtered = graph.filter_map_values(|&value| {
+            (value != 1).then(|| Node {
+                value,
+                next: RefBy::dangling((value + 1) % 4),
+            })
+        });
+        assert_eq!(filtered.resolve(), Err(vec![1]));
+    }
+
+    #[test]
+    fn replace_ref() {
+        use crate::{Ref, RefBy, VisitRefs, VisitRefsBy};
+
+        struct Node(Vec<RefBy<char, Node>>);
+
+        impl VisitRefs<Node> for Node {
+            fn visit_refs(&self, f: &mut impl FnMut(&Ref<Node>)) {
+                self.0.visit_refs(f)
+            }
+
+            fn visit_refs_mut(&mut self, f: &mut impl FnMut(&mut Ref<Node>)) {
+                self.0.visit_refs_mut(f)
+            }
+        }
+
+        impl VisitRefsBy<char, Node> for Node {
+            fn visit_refs_by_mut(
+                &mut self,
+                f: &mut impl FnMut(&char, &mut Ref<Node>) -> Option<char>,
+            ) {
+                self.0.visit_refs_by_mut(f)
+            }
+        }
+
+        let mut graph = BTreeGraph::new();
+        let a = graph.insert('a', Node(Vec::new()));
+        let b = graph.insert('b', Node(Vec::new()));
+        let c = graph.insert('c', Node(Vec::new()));
+        graph.borrow_mut(&a).0 = vec![graph.get_ref_by(&'b').unwrap()];
+        graph.borrow_mut(&b).0 = vec![graph.get_ref_by(&'a').unwrap()];
+        graph.borrow_mut(&c).0 = vec![
+            graph.get_ref_by(&'a').unwrap(),
+            graph.get_ref_by(&'b').unwrap(),
+        ];
+
+        let (key, _) = graph.replace_ref_by(&b, &a).unwrap();
+        assert_eq!(key, 'b');
+        assert_eq!(graph.keys().collect::<Vec<_>>(), vec![&'a', &'c']);
+        for node in graph.values() {
+            assert!(node
+                .0
+                .iter()
+                .all(|r| r.value_ref() == &a && r.key() == &'a'));
+        }
+        assert_eq!(graph.borrow(&c).0.len(), 2);
+        assert!(graph.replace_ref(&a, &a).is_none());
+
+        let d = graph.insert('d', Node(Vec::new()));
+        graph.replace_ref(&a, &d);
+        assert!(graph
+            .borrow(&c)
+            .0
+            .iter()
+            .all(|r| r.value_ref() == &d && r.key() == &'a'));
+    }
+
+    #[test]
+    fn upsert() {
+        let mut graph = BTreeGraph::new();
+        let (a, old) = graph.upsert(1, "a");
+        assert_eq!(old, None);
+        graph.promise(2);
+        assert_eq!(graph.upsert(2, "b").1, None);
+        let (c, old) = graph.upsert(1, "c");
+        assert_eq!(old, Some("a"));
+        assert!(a == c);
+        assert_eq!(graph.get(&1), Some(&"c"));
+        assert_eq!(graph.keys().count(), 2);
+    }
+
+    #[cfg(feature = "debug-borrows")]
+    #[test]
+    #[should_panic(expected = "(index entries [0, 2]) is mutably borrowed twice")]
+    fn debug_borrows() {
+        let mut graph = (0..3).map(|i| (i, i)).collect::<BTreeGraph<_, _>>();
+        graph.values_mut().for_each(|value| *value += 1);
+        graph.values_mut().for_each(|value| *value += 1);
+
+        /* Simulate a bug resulting in two index entries referring to
+         * the same node. */
+        let node = graph.index[&0].clone();
+        graph.index.insert(2, node);
+        graph.iter_mut().for_each(|(_, value)| *value += 1);
+    }
+
+    #[test]
+    fn insert_reuses_slot() {
+        let mut graph = BTreeGraph::with_capacity(4);
+        let node = graph.insert(1, 0);
+        for i in 1..100 {
+            assert!(graph.insert(1, i) == node);
+        }
+        assert_eq!(graph.borrow(&node), &99);
+        assert_eq!(graph.stats().arena_slots, 1);
+        assert_eq!(graph.stats().arena_chunks, 1);
+    }
+
+    #[test]
+    fn promise_insert() {
+        let mut graph = BTreeGraph::new();
+        let promised = graph.promise(1);
+        assert!(graph.promise(1) == promised);
+
+        let node = graph.insert(1, "a");
+        assert!(node == promised);
+        assert_eq!(graph.borrow(&promised), &"a");
+
+        let replaced = graph.insert(1, "b");
+        assert!(replaced == promised);
+        assert_eq!(graph.borrow(&promised), &"b");
+
+        let promised = graph.promise(1);
+        assert!(promised != replaced);
+        assert_eq!(graph.get_ref(&1), Some(&promised));
+        graph.insert(1, "c");
+        assert_eq!(graph.borrow(&promised), &"c");
+    }
+
+    #[test]
+    fn memory_usage() {
+        let mut graph = BTreeGraph::new();
+        let empty = graph.memory_usage();
+        (0..100u64).for_each(|i| {
+            graph.insert(i, i);
+        });
+        assert!(graph.memory_usage() > empty);
+        assert!(graph.memory_usage() >= 100 * 3 * std::mem::size_of::<u64>());
+    }
+
+    #[test]
+    fn compact() {
+        use crate::{IndexBy, RefBy, Resolve};
+
+        struct Node(RefBy<u32, Node>);
+
+        impl Resolve<u32, Node> for Node {
+            fn resolve<I: IndexBy<u32, Node>>(&mut self, index: &I) -> Result<(), u32> {
+                self.0.resolve(index)
+            }
+        }
+
+        let mut graph = BTreeGraph::new();
+        for i in 0..100 {
+            graph.insert(i, Node(RefBy::dangling((i + 2) % 100)));
+        }
+        graph.resolve().unwrap();
+        graph.promise(100);
+        for i in 0..50 {
+            graph.remove(&(i * 2 + 1));
+        }
+        let before = graph.memory_usage();
+        let stale = graph.get_ref(&0).unwrap().clone();
+
+        assert_eq!(graph.compact(), Ok(50));
+        assert!(graph.memory_usage() < before);
+        assert_eq!(graph.compact(), Ok(0));
+
+        assert!(graph.get_ref(&0) != Some(&stale));
+        assert!(graph.get_ref(&100).is_some());
+        let next = &graph.get(&98).unwrap().0;
+        assert_eq!(next.key(), &0);
+        assert!(std::ptr::eq(graph.borrow(next), graph.get(&0).unwrap()));
+    }
+
+    #[test]
+    #[should_panic(expected = "borrowed node that was removed")]
+    fn compact_unresolved() {
+        use crate::{IndexBy, RefBy, Resolve};
+
+        struct Node(RefBy<u32, Node>);
+
+        impl Resolve<u32, Node> for Node {
+            fn resolve<I: IndexBy<u32, Node>>(&mut self, index: &I) -> Result<(), u32> {
+                self.0.resolve(index)
+            }
+        }
+
+        let mut graph = BTreeGraph::new();
+        for i in 0..4 {
+            graph.insert(i, Node(RefBy::dangling((i + 1) % 4)));
+        }
+        graph.resolve().unwrap();
+        graph.remove(&0);
+
+        assert_eq!(graph.compact(), Err(vec![0]));
+        let prev = &graph.get(&3).unwrap().0;
+        graph.borrow(prev);
+    }
+
+    #[test]
+    fn entry_promised() {
+        let mut graph = BTreeGraph::new();
+        graph.insert(1, "a");
+        let promised = graph.promise(2);
+
+        assert_eq!(graph.entry(1).or_insert_with(|| "x"), &mut "a");
+        assert_eq!(graph.entry(2).or_insert_with(|| "b"), &mut "b");
+        assert_eq!(graph.entry(3).or_create_with(|| "c"), &mut "c");
+
+        assert_eq!(graph.borrow(&promised), &"b");
+        assert_eq!(graph.get(&1), Some(&"a"));
+        assert_eq!(graph.get(&3), Some(&"c"));
+
+        let promised = graph.promise(4);
+        let (node, value) = graph.entry(4).ref_handle(|| "d");
+        assert_eq!(value, &mut "d");
+        assert!(node == promised);
+    }
+
+    #[test]
+    fn entry_modify_or_insert_with() {
+        let mut graph = BTreeGraph::new();
+        graph.promise(4);
+        for (from, to) in [(1, 2), (2, 3), (1, 3), (4, 1), (1, 4)] {
+            graph
+                .entry(from)
+                .modify_or_insert_with(|targets: &mut Vec<_>| targets.push(to), || vec![to]);
+        }
+        assert_eq!(
+            graph.iter().collect::<Vec<_>>(),
+            [(&1, &vec![2, 3, 4]), (&2, &vec![3]), (&4, &vec![1])]
+        );
+    }
+
+    #[test]
+    fn find_key() {
+        struct Node {
+            name: &'static str,
+            port: u16,
+        }
+
+        let graph = [
+            (
+                3,
+                Node {
+                    name: "c",
+                    port: 80,
+                },
+            ),
+            (
+                1,
+                Node {
+                    name: "a",
+                    port: 22,
+                },
+            ),
+            (
+                2,
+                Node {
+                    name: "b",
+                    port: 80,
+                },
+            ),
+        ]
+        .into_iter()
+        .collect::<BTreeGraph<_, _>>();
+
+        assert!(graph.any_value(|node| node.name == "b"));
+        assert!(!graph.any_value(|node| node.port == 443));
+        assert_eq!(graph.find_key(|node| node.name == "a"), Some(&1));
+        assert_eq!(graph.find_key(|node| node.port == 443), None);
+        assert_eq!(graph.find_key(|node| node.port == 80), Some(&2));
+    }
+
+    #[test]
+    fn get_or_insert_default() {
+        let mut graph = BTreeGraph::new();
+        let promised = graph.promise('b');
+        "abcab"
+            .chars()
+            .for_each(|c| *graph.get_or_insert_default(c) += 1);
+
+        assert_eq!(graph.get(&'a'), Some(&2));
+        assert_eq!(graph.borrow(&promised), &2);
+        assert_eq!(graph.get(&'c'), Some(&1));
+
+        let promised = graph.promise('d');
+        *graph.entry('d').or_default() += 5;
+        assert_eq!(graph.borrow(&promised), &5);
+        assert_eq!(*graph.get_or_insert_with('d', || 10), 5);
+        assert_eq!(*graph.get_or_insert_with('e', || 10), 10);
+    }
+
+    #[test]
+    fn try_create() {
+        let mut graph = BTreeGraph::new();
+        let node = graph.promise("a");
+        graph.insert("b", 2);
+        assert_eq!(graph.try_create("a", 1), Ok(()));
+        assert_eq!(graph.borrow(&node), &1);
+        assert_eq!(
+            graph.try_create("b", 3),
+            Err(CreateError::AlreadyFilled { value: 3 })
+        );
+        assert_eq!(
+            graph.try_create("c", 4).map_err(CreateError::into_value),
+            Err(4)
+        );
+        assert_eq!(graph.get("b"), Some(&2));
+    }
+
+    #[test]
+    fn rev_order() {
+        let mut graph = BTreeGraph::new();
+        for key in [2, 5, 1, 4, 3] {
+            graph.insert(key, key * 10);
+        }
+        assert_eq!(graph.keys().copied().collect::<Vec<_>>(), [1, 2, 3, 4, 5]);
+        assert_eq!(
+            graph.keys_rev().copied().collect::<Vec<_>>(),
+            [5, 4, 3, 2, 1]
+        );
+        assert_eq!(
+            graph.values_rev().copied().collect::<Vec<_>>(),
+            [50, 40, 30, 20, 10]
+        );
+        assert_eq!(
+            graph.iter_rev().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(),
+            [(5, 50), (4, 40), (3, 30), (2, 20), (1, 10)]
+        );
+    }
+
+    #[test]
+    fn from_sorted_iter() {
+        let graph = BTreeGraph::from_sorted_iter((0..100).map(|i| (i, i * 2))).unwrap();
+        assert_eq!(graph.keys().len(), 100);
+        assert_eq!(graph.get(&42), Some(&84));
+        assert_eq!(graph.iter().next_back(), Some((&99, &198)));
+
+        let err = BTreeGraph::from_sorted_iter([(1, "a"), (3, "c"), (2, "b")])
+            .err()
+            .unwrap();
+        assert_eq!(err.key(), &2);
+        assert_eq!(err.to_string(), "key out of order or duplicated: 2");
+        let err = BTreeGraph::from_sorted_iter([(1, "a"), (1, "b")])
+            .err()
+            .unwrap();
+        assert_eq!(err.into_key(), 1);
+        assert!(BTreeGraph::<u32, ()>::from_sorted_iter([])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn into_parts() {
+        let mut graph = BTreeGraph::new();
+        let a = graph.insert(1, "a");
+        graph.insert(2, "b");
+
+        let (nodes, index) = graph.into_parts();
+        assert!(index.values().all(|node| nodes.owns(node)));
+        let mut graph = unsafe { BTreeGraph::from_parts(nodes, index) };
+        assert_eq!(graph.borrow(&a), &"a");
+        *graph.borrow_mut(&a) = "c";
+        assert_eq!(graph.get(&1), Some(&"c"));
+        assert_eq!(graph.get(&2), Some(&"b"));
+    }
+
+    #[test]
+    fn into_graph() {
+        let mut graph = BTreeGraph::new();
+        let a = graph.insert(1, 10);
+        graph.insert(2, 20);
+        let refs = [("a", a.clone())].into_iter().collect::<RefMap<_, _>>();
+
+        let mut nodes = graph.into_graph();
+        let mut values = nodes.iter_mut().map(|value| *value).collect::<Vec<_>>();
+        values.sort();
+        assert_eq!(values, [10, 20]);
+        assert_eq!(nodes.borrow(&a), &10);
+        assert_eq!(refs.get(&"a", &nodes), Some(&10));
+    }
+
+    #[test]
+    fn map_keys() {
+        #[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+        struct Id(String);
+
+        let mut graph = BTreeGraph::new();
+        let a = graph.insert("a".to_string(), 1);
+        graph.insert("b".to_string(), 2);
+        let b = graph.get_ref_by(&"b".to_string()).unwrap();
+
+        let graph = graph.map_keys(|key| Id(key.to_uppercase()));
+        let b = b.map_key(|key| Id(key.to_uppercase()));
+        assert_eq!(b.key(), &Id("B".to_string()));
+        assert_eq!(graph.borrow(&a), &1);
+        assert_eq!(graph.borrow(&b), &2);
+        assert_eq!(graph.get(&Id("A".to_string())), Some(&1));
+
+        let graph = graph.try_map_keys(|Id(key)| key.len()).err();
+        assert_eq!(graph, Some(1));
+
+        let graph = (0..4).map(|i| (i, i * 10)).collect::<BTreeGraph<_, _>>();
+        let first = graph.get_ref(&0).unwrap().clone();
+        let graph = graph.map_keys(|i| i / 2);
+        assert_eq!(graph.iter().collect::<Vec<_>>(), [(&0, &10), (&1, &30)]);
+        assert_eq!(graph.as_ref().iter().count(), 2);
+        assert!(unsafe { first.is_removed_unchecked() });
+
+        let mut graph = BTreeGraph::new();
+        let promised = graph.promise(0);
+        graph.insert(1, 10);
+        let graph = graph.map_keys(|_| 0);
+        assert_eq!(graph.get(&0), Some(&10));
+        assert!(unsafe { promised.is_removed_unchecked() });
     }
 }
@@ -0,0 +1,174 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+use alloc::vec::Vec;
+use core::fmt::{Debug, Display};
+
+use crate::{BTreeGraph, CreateError, Ref, RefBy};
+
+/// A builder for a `BTreeGraph`, which checks that every node that
+/// was promised or referenced during construction was given a value.
+/// References handed out by the builder remain valid in the built
+/// graph: inserting a value for an existing key replaces the value in
+/// place.
+pub struct GraphBuilder<K, V> {
+    graph: BTreeGraph<K, V>,
+}
+
+/// Error returned by `GraphBuilder::build` when some nodes were
+/// promised or referenced, but never inserted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BuildError<K> {
+    missing: Vec<K>,
+}
+
+impl<K: Ord, V> GraphBuilder<K, V> {
+    pub fn new() -> Self {
+        Self {
+            graph: BTreeGraph::new(),
+        }
+    }
+
+    /// Get a reference to the node for `key`, reserving a slot if
+    /// the key was not seen before.
+    pub fn promise(&mut self, key: K) -> Ref<V> {
+        match self.graph.get_ref(&key) {
+            Some(node) => node.clone(),
+            None => self.graph.promise(key),
+        }
+    }
+
+    /// Like `promise`, but returns a keyed reference.
+    pub fn reference(&mut self, key: K) -> RefBy<K, V>
+    where
+        K: Clone,
+    {
+        let node = self.promise(key.clone());
+        RefBy::new(key, node)
+    }
+
+    /// Set the value for `key`, filling the promised slot if there is
+    /// one, or replacing a previously inserted value.
+    pub fn insert(&mut self, key: K, value: V) -> Ref<V> {
+        match self.graph.try_create(&key, value) {
+            Ok(()) => self.graph.get_ref(&key).unwrap().clone(),
+            Err(CreateError::AlreadyFilled { value }) => {
+                let node = self.graph.get_ref(&key).unwrap().clone();
+                *self.graph.borrow_mut(&node) = value;
+                node
+            }
+            Err(CreateError::UnknownKey { value }) => self.graph.insert(key, value),
+            Err(CreateError::WrongGraph { .. }) => unreachable!(),
+        }
+    }
+
+    /// Finish building, failing with the keys that were promised or
+    /// referenced but never inserted.
+    pub fn build(self) -> Result<BTreeGraph<K, V>, BuildError<K>>
+    where
+        K: Clone,
+    {
+        match self.graph.validate() {
+            Ok(()) => Ok(self.graph),
+            Err(report) => Err(BuildError {
+                missing: report.promised,
+            }),
+        }
+    }
+}
+
+impl<K: Ord, V> Default for GraphBuilder<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K> BuildError<K> {
+    /// The keys that were never inserted.
+    pub fn missing(&self) -> &[K] {
+        &self.missing
+    }
+
+    pub fn into_missing(self) -> Vec<K> {
+        self.missing
+    }
+}
+
+impl<K: Debug> Display for BuildError<K> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "promised node(s) never created: {:?}", self.missing)
+    }
+}
+
+impl<K: Debug> core::error::Error for BuildError<K> {}
+
+#[cfg(test)]
+mod test {
+
+    use crate::{GraphBuilder, RefBy};
+
+    struct Node {
+        value: u32,
+        next: Option<RefBy<&'static str, Node>>,
+    }
+
+    #[test]
+    fn build() {
+        let mut builder = GraphBuilder::new();
+        let next = builder.reference("b");
+        builder.insert(
+            "a",
+            Node {
+                value: 1,
+                next: Some(next),
+            },
+        );
+        let a = builder.reference("a");
+        builder.insert(
+            "b",
+            Node {
+                value: 0,
+                next: None,
+            },
+        );
+        builder.insert(
+            "b",
+            Node {
+                value: 2,
+                next: Some(a),
+            },
+        );
+
+        let graph = builder.build().unwrap();
+        let a = graph.get(&"a").unwrap();
+        let b = graph.borrow(a.next.as_ref().unwrap());
+        assert_eq!((a.value, b.value), (1, 2));
+        assert!(std::ptr::eq(graph.borrow(b.next.as_ref().unwrap()), a));
+    }
+
+    #[test]
+    fn missing() {
+        let mut builder = GraphBuilder::<_, u32>::new();
+        builder.insert("a", 1);
+        builder.reference("c");
+        builder.promise("b");
+        builder.promise("a");
+        let err = builder.build().err().unwrap();
+        assert_eq!(err.missing(), &["b", "c"]);
+        assert_eq!(
+            err.to_string(),
+            "promised node(s) never created: [\"b\", \"c\"]"
+        );
+    }
+}
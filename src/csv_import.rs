@@ -0,0 +1,264 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+use std::{
+    fmt::Display,
+    io::{self, BufRead},
+    str::FromStr,
+};
+
+use crate::{BTreeGraph, RefBy};
+
+/// Summary of an edge list import.
+#[derive(Clone, PartialEq, Eq, Default, Debug)]
+pub struct ImportReport {
+    /// The number of nodes that were created for previously unknown
+    /// keys.
+    pub nodes_created: usize,
+    /// The number of edges that were attached.
+    pub edges_created: usize,
+    /// The (one-based) line numbers of rows that could not be parsed.
+    pub malformed_lines: Vec<usize>,
+}
+
+/// Error returned when the edge list could not be read.
+#[derive(Debug)]
+pub struct ImportError(io::Error);
+
+/// Load an edge list of `src,dst` rows into the graph, creating any
+/// missing nodes with `V::default()`. See `load_edges_with`.
+pub fn load_edges<K, V, R, A>(
+    graph: &mut BTreeGraph<K, V>,
+    reader: R,
+    attach: A,
+) -> Result<ImportReport, ImportError>
+where
+    K: FromStr + Ord + Clone,
+    V: Default,
+    R: BufRead,
+    A: FnMut(&mut V, RefBy<K, V>),
+{
+    load_edges_with(graph, reader, |_| V::default(), attach)
+}
+
+/// Load an edge list of `src,dst` rows into the graph. Nodes that do
+/// not yet exist (either as source or as target) are created using
+/// `factory`, which also fulfills nodes that were only promised.
+/// Each edge is passed to `attach`, together with its source node.
+/// Empty lines are skipped; rows that do not consist of exactly two
+/// fields that can be parsed as keys are skipped and reported. Note
+/// that a header row will be imported as an edge if its fields can
+/// be parsed as keys.
+pub fn load_edges_with<K, V, R, F, A>(
+    graph: &mut BTreeGraph<K, V>,
+    reader: R,
+    mut factory: F,
+    mut attach: A,
+) -> Result<ImportReport, ImportError>
+where
+    K: FromStr + Ord + Clone,
+    R: BufRead,
+    F: FnMut(&K) -> V,
+    A: FnMut(&mut V, RefBy<K, V>),
+{
+    let mut report = ImportReport::default();
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_row(&line) {
+            Some((source, target)) => add_edge(
+                graph,
+                source,
+                target,
+                &mut factory,
+                &mut attach,
+                &mut report,
+            ),
+            None => report.malformed_lines.push(i + 1),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Load `(src, dst)` pairs into the graph. This performs the same
+/// node creation and attachment as `load_edges_with`, for callers
+/// that parse the input themselves.
+pub fn load_edge_pairs<K, V, I, F, A>(
+    graph: &mut BTreeGraph<K, V>,
+    pairs: I,
+    mut factory: F,
+    mut attach: A,
+) -> ImportReport
+where
+    K: Ord + Clone,
+    I: IntoIterator<Item = (K, K)>,
+    F: FnMut(&K) -> V,
+    A: FnMut(&mut V, RefBy<K, V>),
+{
+    let mut report = ImportReport::default();
+    pairs.into_iter().for_each(|(source, target)| {
+        add_edge(
+            graph,
+            source,
+            target,
+            &mut factory,
+            &mut attach,
+            &mut report,
+        )
+    });
+    report
+}
+
+fn parse_row<K: FromStr>(line: &str) -> Option<(K, K)> {
+    let mut fields = line.split(',');
+    let source = fields.next()?.trim().parse().ok()?;
+    let target = fields.next()?.trim().parse().ok()?;
+    match fields.next() {
+        None => Some((source, target)),
+        Some(_) => None,
+    }
+}
+
+fn add_edge<K, V, F, A>(
+    graph: &mut BTreeGraph<K, V>,
+    source: K,
+    target: K,
+    factory: &mut F,
+    attach: &mut A,
+    report: &mut ImportReport,
+) where
+    K: Ord + Clone,
+    F: FnMut(&K) -> V,
+    A: FnMut(&mut V, RefBy<K, V>),
+{
+    let source = get_or_create(graph, source, factory, report);
+    let target = get_or_create(graph, target, factory, report);
+    attach(graph.borrow_mut(&source), target);
+    report.edges_created += 1;
+}
+
+fn get_or_create<K, V, F>(
+    graph: &mut BTreeGraph<K, V>,
+    key: K,
+    factory: &mut F,
+    report: &mut ImportReport,
+) -> RefBy<K, V>
+where
+    K: Ord + Clone,
+    F: FnMut(&K) -> V,
+{
+    let mut created = false;
+    let (node, _) = graph.entry(key.clone()).ref_handle(|| {
+        created = true;
+        factory(&key)
+    });
+    report.nodes_created += usize::from(created);
+    RefBy::new(key, node)
+}
+
+impl ImportError {
+    pub fn io_error(&self) -> &io::Error {
+        &self.0
+    }
+}
+
+impl From<io::Error> for ImportError {
+    fn from(err: io::Error) -> Self {
+        Self(err)
+    }
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "failed to read edge list: {}", self.0)
+    }
+}
+
+impl std::error::Error for ImportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{load_edges, ImportReport};
+    use crate::{BTreeGraph, RefBy};
+
+    #[derive(Default)]
+    struct Node {
+        next: Vec<RefBy<String, Node>>,
+    }
+
+    #[test]
+    fn load() {
+        let input = "src,dst\na,b\nb, c\n\nb,c,d\nc,a\nd,d\n";
+        let mut graph = BTreeGraph::new();
+        graph.insert("a".to_string(), Node::default());
+
+        let report = load_edges(&mut graph, input.as_bytes(), |node: &mut Node, target| {
+            node.next.push(target)
+        })
+        .unwrap();
+
+        assert_eq!(
+            report,
+            ImportReport {
+                nodes_created: 5,
+                edges_created: 5,
+                malformed_lines: vec![5],
+            }
+        );
+
+        let next = |key: &str| {
+            graph
+                .get(key)
+                .unwrap()
+                .next
+                .iter()
+                .map(|target| target.key().as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(next("src"), vec!["dst"]);
+        assert_eq!(next("a"), vec!["b"]);
+        assert_eq!(next("b"), vec!["c"]);
+        assert_eq!(next("c"), vec!["a"]);
+        assert_eq!(next("d"), vec!["d"]);
+
+        let c = graph.get("b").unwrap().next[0].clone();
+        assert_eq!(graph.borrow(&graph.borrow(&c).next[0]).next.len(), 1);
+    }
+
+    #[test]
+    fn load_promised() {
+        let mut graph = BTreeGraph::new();
+        let promised = graph.promise("b".to_string());
+
+        let report = load_edges(
+            &mut graph,
+            "a,b\nb,a\n".as_bytes(),
+            |node: &mut Node, target| node.next.push(target),
+        )
+        .unwrap();
+
+        assert_eq!(report.nodes_created, 2);
+        assert_eq!(graph.borrow(&promised).next[0].key(), "a");
+        assert!(graph.get("a").unwrap().next[0].value_ref() == &promised);
+    }
+}
@@ -0,0 +1,541 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+#[cfg(feature = "serde")]
+use alloc::string::String;
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    vec::Vec,
+};
+use core::fmt::{Debug, Display};
+#[cfg(feature = "serde")]
+use core::{fmt::Formatter, marker::PhantomData};
+
+#[cfg(feature = "serde")]
+use serde::{
+    de::{Deserializer, Error, MapAccess, SeqAccess, Visitor},
+    ser::{SerializeStruct, Serializer},
+    Deserialize, Serialize,
+};
+
+use crate::BTreeGraph;
+
+/// The changes needed to turn one graph into another.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct GraphDiff<K, V> {
+    pub added: BTreeMap<K, V>,
+    pub removed: BTreeSet<K>,
+    pub changed: BTreeMap<K, V>,
+}
+
+/// The keys that differ between two graphs, as returned by
+/// [`diff_keys`] and [`diff_with`]. Modified keys carry the summary
+/// of their change (`()` for `diff_keys`).
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct KeyDiff<K, C = ()> {
+    pub added: BTreeSet<K>,
+    pub removed: BTreeSet<K>,
+    pub modified: BTreeMap<K, C>,
+}
+
+/// A single change in a `KeyDiff`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeyChange<'a, C> {
+    Added,
+    Removed,
+    Modified(&'a C),
+}
+
+/// Error returned when a diff does not apply to a graph.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ApplyError<K> {
+    /// Removed or changed keys that are not present in the graph, or
+    /// only promised.
+    pub missing: Vec<K>,
+    /// Added keys that are already present in the graph.
+    pub existing: Vec<K>,
+    /// Keys that appear in more than one of the diff's sets.
+    pub overlapping: Vec<K>,
+}
+
+impl<K, V> GraphDiff<K, V> {
+    pub fn new() -> Self {
+        Self {
+            added: BTreeMap::new(),
+            removed: BTreeSet::new(),
+            changed: BTreeMap::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl<K, V> Default for GraphDiff<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, C> KeyDiff<K, C> {
+    pub fn new() -> Self {
+        Self {
+            added: BTreeSet::new(),
+            removed: BTreeSet::new(),
+            modified: BTreeMap::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+
+    /// Iterate over the changes: first the added keys, then the
+    /// removed keys and finally the modified keys, each in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, KeyChange<'_, C>)> {
+        self.added
+            .iter()
+            .map(|key| (key, KeyChange::Added))
+            .chain(self.removed.iter().map(|key| (key, KeyChange::Removed)))
+            .chain(
+                self.modified
+                    .iter()
+                    .map(|(key, change)| (key, KeyChange::Modified(change))),
+            )
+    }
+}
+
+impl<K, C> Default for KeyDiff<K, C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Compare the keys of two snapshots of a graph, reporting the keys
+/// whose values differ as modified.
+pub fn diff_keys<K, V>(old: &BTreeGraph<K, V>, new: &BTreeGraph<K, V>) -> KeyDiff<K>
+where
+    K: Ord + Clone,
+    V: PartialEq,
+{
+    diff_with(old, new, |old, new| (old != new).then_some(()))
+}
+
+/// Compare two snapshots of a graph. For keys present in both, `cmp`
+/// is called with the old and new values and returns a summary of
+/// the change, or `None` if the values are considered equal.
+pub fn diff_with<K, V, C, F>(
+    old: &BTreeGraph<K, V>,
+    new: &BTreeGraph<K, V>,
+    mut cmp: F,
+) -> KeyDiff<K, C>
+where
+    K: Ord + Clone,
+    F: FnMut(&V, &V) -> Option<C>,
+{
+    let mut diff = KeyDiff::new();
+
+    created_entries(old).for_each(|(key, value)| match created(new, key) {
+        None => {
+            diff.removed.insert(key.clone());
+        }
+        Some(new_value) => {
+            if let Some(change) = cmp(value, new_value) {
+                diff.modified.insert(key.clone(), change);
+            }
+        }
+    });
+
+    created_entries(new).for_each(|(key, _)| {
+        if created(old, key).is_none() {
+            diff.added.insert(key.clone());
+        }
+    });
+
+    diff
+}
+
+impl<K, V> BTreeGraph<K, V> {
+    /// Calculate the changes needed to turn this graph into `other`.
+    pub fn diff(&self, other: &Self) -> GraphDiff<K, V>
+    where
+        K: Ord + Clone,
+        V: PartialEq + Clone,
+    {
+        let mut diff = GraphDiff::new();
+
+        created_entries(self).for_each(|(key, value)| match created(other, key) {
+            None => {
+                diff.removed.insert(key.clone());
+            }
+            Some(other_value) if other_value != value => {
+                diff.changed.insert(key.clone(), other_value.clone());
+            }
+            Some(_) => {}
+        });
+
+        created_entries(other).for_each(|(key, value)| {
+            if created(self, key).is_none() {
+                diff.added.insert(key.clone(), value.clone());
+            }
+        });
+
+        diff
+    }
+
+    /// Apply a diff to the graph. Changed nodes are updated in place,
+    /// so that existing references to them remain valid. Every change
+    /// is reported to the graph's observer, if any. Promised keys
+    /// count as missing: they can be added, filling the promised
+    /// node, but not removed or changed. The graph is left
+    /// unmodified if the diff does not apply, or if a key appears in
+    /// more than one of its sets.
+    pub fn apply(&mut self, diff: GraphDiff<K, V>) -> Result<(), ApplyError<K>>
+    where
+        K: Ord + Clone,
+    {
+        let missing = diff
+            .removed
+            .iter()
+            .chain(diff.changed.keys())
+            .filter(|key| created(self, key).is_none())
+            .cloned()
+            .collect::<Vec<_>>();
+        let existing = diff
+            .added
+            .keys()
+            .filter(|key| created(self, key).is_some())
+            .cloned()
+            .collect::<Vec<_>>();
+        let overlapping = diff
+            .changed
+            .keys()
+            .filter(|key| diff.removed.contains(*key))
+            .chain(
+                diff.added
+                    .keys()
+                    .filter(|key| diff.removed.contains(*key) || diff.changed.contains_key(*key)),
+            )
+            .cloned()
+            .collect::<BTreeSet<_>>();
+
+        if !missing.is_empty() || !existing.is_empty() || !overlapping.is_empty() {
+            return Err(ApplyError {
+                missing,
+                existing,
+                overlapping: overlapping.into_iter().collect(),
+            });
+        }
+
+        diff.removed.iter().for_each(|key| {
+            self.remove(key);
+        });
+        diff.changed.into_iter().for_each(|(key, value)| {
+            self.insert(key, value);
+        });
+        diff.added.into_iter().for_each(|(key, value)| {
+            self.insert(key, value);
+        });
+
+        Ok(())
+    }
+}
+
+/// The value for `key`, or `None` if the key is missing or was only
+/// promised.
+fn created<'a, K: Ord, V>(graph: &'a BTreeGraph<K, V>, key: &K) -> Option<&'a V> {
+    unsafe { graph.index().get(key)?.try_get_unchecked() }
+}
+
+/// The entries of the graph, skipping promised nodes.
+fn created_entries<K, V>(graph: &BTreeGraph<K, V>) -> impl Iterator<Item = (&K, &V)> {
+    graph
+        .index()
+        .iter()
+        .filter_map(|(key, node)| Some((key, unsafe { node.try_get_unchecked() }?)))
+}
+
+impl<K: Debug> Display for ApplyError<K> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "diff does not apply")?;
+        if !self.missing.is_empty() {
+            write!(f, "; missing keys: {:?}", self.missing)?;
+        }
+        if !self.existing.is_empty() {
+            write!(f, "; existing keys: {:?}", self.existing)?;
+        }
+        if !self.overlapping.is_empty() {
+            write!(f, "; overlapping keys: {:?}", self.overlapping)?;
+        }
+        Ok(())
+    }
+}
+
+impl<K: Debug> core::error::Error for ApplyError<K> {}
+
+#[cfg(feature = "serde")]
+impl<K, V> Serialize for GraphDiff<K, V>
+where
+    K: Serialize,
+    V: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_struct("GraphDiff", 3)?;
+        s.serialize_field("added", &self.added)?;
+        s.serialize_field("removed", &self.removed)?;
+        s.serialize_field("changed", &self.changed)?;
+        s.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K, C> Serialize for KeyDiff<K, C>
+where
+    K: Serialize,
+    C: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_struct("KeyDiff", 3)?;
+        s.serialize_field("added", &self.added)?;
+        s.serialize_field("removed", &self.removed)?;
+        s.serialize_field("modified", &self.modified)?;
+        s.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V> Deserialize<'de> for GraphDiff<K, V>
+where
+    K: Deserialize<'de> + Ord,
+    V: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct DiffVisitor<K, V>(PhantomData<(K, V)>);
+
+        impl<'de, K, V> Visitor<'de> for DiffVisitor<K, V>
+        where
+            K: Deserialize<'de> + Ord,
+            V: Deserialize<'de>,
+        {
+            type Value = GraphDiff<K, V>;
+
+            fn expecting(&self, formatter: &mut Formatter<'_>) -> core::fmt::Result {
+                write!(formatter, "a graph diff")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let added = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(0, &self))?;
+                let removed = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(1, &self))?;
+                let changed = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(2, &self))?;
+                Ok(GraphDiff {
+                    added,
+                    removed,
+                    changed,
+                })
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut diff = GraphDiff::new();
+
+                while let Some(field) = map.next_key::<String>()? {
+                    match field.as_str() {
+                        "added" => diff.added = map.next_value()?,
+                        "removed" => diff.removed = map.next_value()?,
+                        "changed" => diff.changed = map.next_value()?,
+                        _ => return Err(A::Error::unknown_field(&field, FIELDS)),
+                    }
+                }
+
+                Ok(diff)
+            }
+        }
+
+        deserializer.deserialize_struct("GraphDiff", FIELDS, DiffVisitor(PhantomData))
+    }
+}
+
+#[cfg(feature = "serde")]
+const FIELDS: &[&str] = &["added", "removed", "changed"];
+
+#[cfg(test)]
+mod test {
+
+    use std::sync::{Arc, Mutex};
+
+    use crate::{diff_keys, diff_with, BTreeGraph, GraphDiff, GraphObserver, KeyChange};
+
+    #[test]
+    fn diff_apply() {
+        let old = [(1, "a"), (2, "b"), (3, "c")]
+            .into_iter()
+            .collect::<BTreeGraph<_, _>>();
+        let new = [(2, "B"), (3, "c"), (4, "d")]
+            .into_iter()
+            .collect::<BTreeGraph<_, _>>();
+
+        let diff = old.diff(&new);
+        assert_eq!(diff.added.keys().collect::<Vec<_>>(), vec![&4]);
+        assert_eq!(diff.removed.iter().collect::<Vec<_>>(), vec![&1]);
+        assert_eq!(diff.changed.keys().collect::<Vec<_>>(), vec![&2]);
+
+        #[derive(Clone, Default)]
+        struct Events(Arc<Mutex<Vec<String>>>);
+
+        impl GraphObserver<u32, &'static str> for Events {
+            fn on_insert(&mut self, key: &u32, value: &&str) {
+                self.0.lock().unwrap().push(format!("insert {key} {value}"));
+            }
+
+            fn on_replace(&mut self, key: &u32, old: &&str, new: &&str) {
+                let event = format!("replace {key} {old} {new}");
+                self.0.lock().unwrap().push(event);
+            }
+
+            fn on_remove(&mut self, key: &u32, _value: Option<&&str>) {
+                self.0.lock().unwrap().push(format!("remove {key}"));
+            }
+        }
+
+        let mut graph = old;
+        let events = Events::default();
+        graph.set_observer(events.clone());
+        let changed = graph.get_ref_by(&2).unwrap();
+        let unchanged = graph.get_ref_by(&3).unwrap();
+
+        graph.apply(diff.clone()).unwrap();
+        assert!(graph.diff(&new).is_empty());
+        assert_eq!(
+            *events.0.lock().unwrap(),
+            vec!["remove 1", "replace 2 b B", "insert 4 d"]
+        );
+        assert_eq!(graph.get_ref(&2), Some(changed.value_ref()));
+        assert_eq!(graph.borrow(&changed), &"B");
+        assert_eq!(graph.borrow(&unchanged), &"c");
+
+        let err = graph.apply(diff).unwrap_err();
+        assert_eq!(err.missing, vec![1]);
+        assert_eq!(err.existing, vec![4]);
+        assert!(err.overlapping.is_empty());
+        assert!(graph.diff(&new).is_empty());
+
+        let mut diff = GraphDiff::new();
+        diff.removed.insert(2);
+        diff.changed.insert(2, "x");
+        diff.changed.insert(3, "y");
+        diff.added.insert(3, "z");
+        let err = graph.apply(diff).unwrap_err();
+        assert_eq!(err.overlapping, vec![2, 3]);
+        assert!(graph.diff(&new).is_empty());
+
+        let promised = graph.promise(5);
+        let mut diff = GraphDiff::new();
+        diff.removed.insert(5);
+        assert_eq!(graph.apply(diff).unwrap_err().missing, vec![5]);
+        let diff = graph.diff(&[(5, "e")].into_iter().collect());
+        assert_eq!(diff.added.keys().collect::<Vec<_>>(), vec![&5]);
+        graph.apply(diff).unwrap();
+        assert_eq!(graph.borrow(&promised), &"e");
+    }
+
+    #[derive(PartialEq, Debug)]
+    struct Node {
+        name: &'static str,
+        port: u16,
+    }
+
+    #[derive(PartialEq, Debug)]
+    enum Summary {
+        Renamed,
+        PortChanged(u16),
+    }
+
+    fn snapshots() -> (BTreeGraph<u32, Node>, BTreeGraph<u32, Node>) {
+        let node = |name, port| Node { name, port };
+        let old = [(1, node("a", 80)), (2, node("b", 80)), (3, node("c", 22))]
+            .into_iter()
+            .collect();
+        let new = [(2, node("B", 80)), (3, node("c", 2222)), (4, node("d", 80))]
+            .into_iter()
+            .collect();
+        (old, new)
+    }
+
+    #[test]
+    fn diff_keys_snapshots() {
+        let (old, new) = snapshots();
+        let diff = diff_keys(&old, &new);
+        assert_eq!(diff.added.iter().collect::<Vec<_>>(), vec![&4]);
+        assert_eq!(diff.removed.iter().collect::<Vec<_>>(), vec![&1]);
+        assert_eq!(diff.modified.keys().collect::<Vec<_>>(), vec![&2, &3]);
+        assert!(diff_keys(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn diff_with_summary() {
+        let (old, new) = snapshots();
+        let diff = diff_with(&old, &new, |old, new| {
+            if old.name != new.name {
+                Some(Summary::Renamed)
+            } else if old.port != new.port {
+                Some(Summary::PortChanged(new.port))
+            } else {
+                None
+            }
+        });
+        assert_eq!(
+            diff.iter().collect::<Vec<_>>(),
+            vec![
+                (&4, KeyChange::Added),
+                (&1, KeyChange::Removed),
+                (&2, KeyChange::Modified(&Summary::Renamed)),
+                (&3, KeyChange::Modified(&Summary::PortChanged(2222))),
+            ]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize_key_diff() {
+        let (old, new) = snapshots();
+        let diff = diff_with(&old, &new, |old, new| {
+            (old.port != new.port).then_some(new.port)
+        });
+        assert_eq!(
+            serde_json::to_string(&diff).unwrap(),
+            r#"{"added":[4],"removed":[1],"modified":{"3":2222}}"#
+        );
+    }
+}
@@ -0,0 +1,490 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+use std::{
+    borrow::Borrow,
+    collections::{HashMap, HashSet},
+};
+#[cfg(feature = "serde")]
+use std::{fmt::Formatter, marker::PhantomData};
+
+#[cfg(feature = "serde")]
+use serde::{
+    de::{Deserializer, Error, MapAccess, SeqAccess, Visitor},
+    ser::{SerializeStruct, Serializer},
+    Deserialize, Serialize,
+};
+
+use crate::{BTreeGraph, Graph, Ref, ResolveError};
+
+/// A `BTreeGraph` with first-class directed edges carrying a payload
+/// of type `E`. Edges are kept outside of the node values, in forward
+/// and reverse adjacency maps keyed by node identity, so that
+/// `remove_node` can detach all incident edges. There is at most one
+/// edge from one node to another.
+///
+/// Serialized, the graph is a struct of `nodes` and `edges`, the
+/// latter as a sequence of `(from, to, payload)` key triples, which
+/// are resolved on deserialization.
+pub struct EdgeGraph<K, V, E = ()> {
+    graph: BTreeGraph<K, V>,
+    forward: HashMap<Ref<V>, HashMap<Ref<V>, E>>,
+    reverse: HashMap<Ref<V>, HashSet<Ref<V>>>,
+}
+
+impl<K, V, E> EdgeGraph<K, V, E> {
+    pub fn new() -> Self {
+        Self {
+            graph: BTreeGraph::new(),
+            forward: HashMap::new(),
+            reverse: HashMap::new(),
+        }
+    }
+
+    /// Combine a graph with a list of edges between its keys. Fails
+    /// with the keys that were not found in the graph. Auto-compaction
+    /// is disabled, since the edges refer to the nodes by reference.
+    pub fn from_parts<I>(mut graph: BTreeGraph<K, V>, edges: I) -> Result<Self, ResolveError<K>>
+    where
+        K: Ord,
+        I: IntoIterator<Item = (K, K, E)>,
+    {
+        graph.disable_auto_compact();
+        let mut this = Self {
+            graph,
+            forward: HashMap::new(),
+            reverse: HashMap::new(),
+        };
+        let mut missing = Vec::new();
+        for (from, to, edge) in edges {
+            match (this.graph.get_ref(&from), this.graph.get_ref(&to)) {
+                (Some(a), Some(b)) => {
+                    let (a, b) = (a.clone(), b.clone());
+                    this.add_edge(&a, &b, edge);
+                }
+                (a, b) => {
+                    let (a, b) = (a.is_none(), b.is_none());
+                    if a {
+                        missing.push(from);
+                    }
+                    if b {
+                        missing.push(to);
+                    }
+                }
+            }
+        }
+        if missing.is_empty() {
+            Ok(this)
+        } else {
+            Err(ResolveError::new(missing))
+        }
+    }
+
+    /// The underlying graph. Nodes can not be removed through it,
+    /// since that would leave their edges dangling.
+    pub fn nodes(&self) -> &BTreeGraph<K, V> {
+        &self.graph
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.graph.is_empty()
+    }
+
+    /// Insert a node. If the key is already present or promised, the
+    /// value is stored in place, keeping the node's identity and
+    /// edges.
+    pub fn insert(&mut self, key: K, value: V) -> Ref<V>
+    where
+        K: Ord,
+    {
+        self.graph.insert(key, value)
+    }
+
+    /// Remove a node and detach all of its incoming and outgoing edges.
+    pub fn remove_node<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
+    {
+        let node = self.graph.get_ref(key)?.clone();
+        if let Some(targets) = self.forward.remove(&node) {
+            for target in targets.keys().filter(|target| **target != node) {
+                if let Some(sources) = self.reverse.get_mut(target) {
+                    sources.remove(&node);
+                    if sources.is_empty() {
+                        self.reverse.remove(target);
+                    }
+                }
+            }
+        }
+        if let Some(sources) = self.reverse.remove(&node) {
+            for source in sources.iter().filter(|source| **source != node) {
+                if let Some(targets) = self.forward.get_mut(source) {
+                    targets.remove(&node);
+                    if targets.is_empty() {
+                        self.forward.remove(source);
+                    }
+                }
+            }
+        }
+        self.graph.remove(key)
+    }
+
+    pub fn get_ref<Q>(&self, key: &Q) -> Option<&Ref<V>>
+    where
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
+    {
+        self.graph.get_ref(key)
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
+    {
+        self.graph.get(key)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
+    {
+        self.graph.get_mut(key)
+    }
+
+    pub fn borrow(&self, node: &Ref<V>) -> &V {
+        self.graph.borrow(node)
+    }
+
+    pub fn borrow_mut(&mut self, node: &Ref<V>) -> &mut V {
+        self.graph.borrow_mut(node)
+    }
+
+    /// Add an edge from `a` to `b`, returning the payload of the
+    /// edge it replaces, if any. Panics if either node does not
+    /// belong to this graph (e.g. a stale reference) or was removed.
+    pub fn add_edge(&mut self, a: &Ref<V>, b: &Ref<V>, edge: E) -> Option<E> {
+        let graph: &Graph<V> = self.graph.as_ref();
+        assert!(
+            graph.owns(a) && graph.owns(b),
+            "edge endpoint does not belong to this graph"
+        );
+        assert!(
+            unsafe { !a.is_removed_unchecked() && !b.is_removed_unchecked() },
+            "edge endpoint was removed"
+        );
+        self.reverse.entry(b.clone()).or_default().insert(a.clone());
+        self.forward
+            .entry(a.clone())
+            .or_default()
+            .insert(b.clone(), edge)
+    }
+
+    /// Remove the edge from `a` to `b`, returning its payload.
+    pub fn remove_edge(&mut self, a: &Ref<V>, b: &Ref<V>) -> Option<E> {
+        let targets = self.forward.get_mut(a)?;
+        let edge = targets.remove(b)?;
+        if targets.is_empty() {
+            self.forward.remove(a);
+        }
+        if let Some(sources) = self.reverse.get_mut(b) {
+            sources.remove(a);
+            if sources.is_empty() {
+                self.reverse.remove(b);
+            }
+        }
+        Some(edge)
+    }
+
+    pub fn edge(&self, a: &Ref<V>, b: &Ref<V>) -> Option<&E> {
+        self.forward.get(a)?.get(b)
+    }
+
+    pub fn edge_mut(&mut self, a: &Ref<V>, b: &Ref<V>) -> Option<&mut E> {
+        self.forward.get_mut(a)?.get_mut(b)
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.forward.values().map(|targets| targets.len()).sum()
+    }
+
+    /// The targets of the outgoing edges of `node`, with their payload,
+    /// in unspecified order.
+    pub fn neighbors(&self, node: &Ref<V>) -> impl Iterator<Item = (&Ref<V>, &E)> {
+        self.forward.get(node).into_iter().flatten()
+    }
+
+    /// The sources of the incoming edges of `node`, in unspecified order.
+    pub fn incoming(&self, node: &Ref<V>) -> impl Iterator<Item = &Ref<V>> {
+        self.reverse.get(node).into_iter().flatten()
+    }
+
+    /// All edges, with their payload, in unspecified order.
+    pub fn edges(&self) -> impl Iterator<Item = (&Ref<V>, &Ref<V>, &E)> {
+        self.forward
+            .iter()
+            .flat_map(|(a, targets)| targets.iter().map(move |(b, e)| (a, b, e)))
+    }
+}
+
+impl<K, V, E> Default for EdgeGraph<K, V, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, E> AsRef<BTreeGraph<K, V>> for EdgeGraph<K, V, E> {
+    fn as_ref(&self) -> &BTreeGraph<K, V> {
+        &self.graph
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K, V, E> Serialize for EdgeGraph<K, V, E>
+where
+    K: Serialize + Ord,
+    V: Serialize,
+    E: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let keys = self
+            .graph
+            .iter_ref()
+            .map(|(key, node)| (node, key))
+            .collect::<HashMap<_, _>>();
+        let edges = self
+            .graph
+            .iter_ref()
+            .filter_map(|(key, node)| Some((key, self.forward.get(node)?)))
+            .flat_map(|(key, targets)| {
+                let mut targets = targets
+                    .iter()
+                    .map(|(target, edge)| (key, keys[target], edge))
+                    .collect::<Vec<_>>();
+                targets.sort_by_key(|(_, target, _)| *target);
+                targets
+            })
+            .collect::<Vec<_>>();
+        let mut s = serializer.serialize_struct("EdgeGraph", 2)?;
+        s.serialize_field("nodes", &self.graph)?;
+        s.serialize_field("edges", &edges)?;
+        s.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V, E> Deserialize<'de> for EdgeGraph<K, V, E>
+where
+    K: Deserialize<'de> + Ord,
+    V: Deserialize<'de>,
+    E: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct EdgeGraphVisitor<K, V, E>(PhantomData<(K, V, E)>);
+
+        impl<'de, K, V, E> Visitor<'de> for EdgeGraphVisitor<K, V, E>
+        where
+            K: Deserialize<'de> + Ord,
+            V: Deserialize<'de>,
+            E: Deserialize<'de>,
+        {
+            type Value = EdgeGraph<K, V, E>;
+
+            fn expecting(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+                write!(formatter, "an edge graph")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let nodes = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(0, &self))?;
+                let edges: Vec<(K, K, E)> = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(1, &self))?;
+                EdgeGraph::from_parts(nodes, edges).map_err(A::Error::custom)
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut nodes = None;
+                let mut edges: Option<Vec<(K, K, E)>> = None;
+
+                while let Some(field) = map.next_key::<String>()? {
+                    match field.as_str() {
+                        "nodes" if nodes.is_some() => {
+                            return Err(A::Error::duplicate_field("nodes"))
+                        }
+                        "edges" if edges.is_some() => {
+                            return Err(A::Error::duplicate_field("edges"))
+                        }
+                        "nodes" => nodes = Some(map.next_value()?),
+                        "edges" => edges = Some(map.next_value()?),
+                        _ => return Err(A::Error::unknown_field(&field, FIELDS)),
+                    }
+                }
+
+                EdgeGraph::from_parts(
+                    nodes.ok_or_else(|| A::Error::missing_field("nodes"))?,
+                    edges.unwrap_or_default(),
+                )
+                .map_err(A::Error::custom)
+            }
+        }
+
+        deserializer.deserialize_struct("EdgeGraph", FIELDS, EdgeGraphVisitor(PhantomData))
+    }
+}
+
+#[cfg(feature = "serde")]
+const FIELDS: &[&str] = &["nodes", "edges"];
+
+#[cfg(test)]
+mod test {
+
+    use crate::{BTreeGraph, EdgeGraph};
+
+    #[test]
+    fn edges() {
+        let mut graph = EdgeGraph::new();
+        let a = graph.insert("a", 1);
+        let b = graph.insert("b", 2);
+        let c = graph.insert("c", 3);
+
+        assert_eq!(graph.add_edge(&a, &b, "ab"), None);
+        assert_eq!(graph.add_edge(&a, &c, "ac"), None);
+        assert_eq!(graph.add_edge(&c, &b, "cb"), None);
+        assert_eq!(graph.add_edge(&a, &b, "ab2"), Some("ab"));
+        assert_eq!(graph.edge_count(), 3);
+        assert_eq!(graph.edge(&a, &b), Some(&"ab2"));
+        assert_eq!(graph.neighbors(&a).count(), 2);
+        assert_eq!(graph.incoming(&b).count(), 2);
+
+        assert_eq!(graph.remove_edge(&a, &b), Some("ab2"));
+        assert_eq!(graph.remove_edge(&a, &b), None);
+        assert_eq!(graph.incoming(&b).collect::<Vec<_>>(), vec![&c]);
+
+        let a2 = graph.insert("a", 10);
+        assert!(a2 == a);
+        assert_eq!(graph.neighbors(&a2).collect::<Vec<_>>(), vec![(&c, &"ac")]);
+    }
+
+    #[test]
+    fn remove_node() {
+        let mut graph = EdgeGraph::new();
+        let a = graph.insert("a", 1);
+        let b = graph.insert("b", 2);
+        let c = graph.insert("c", 3);
+        graph.add_edge(&a, &b, ());
+        graph.add_edge(&b, &c, ());
+        graph.add_edge(&c, &b, ());
+        graph.add_edge(&b, &b, ());
+
+        assert_eq!(graph.remove_node("b"), Some(2));
+        assert_eq!(graph.edge_count(), 0);
+        assert_eq!(graph.neighbors(&a).count(), 0);
+        assert_eq!(graph.incoming(&c).count(), 0);
+        assert_eq!(graph.remove_node("b"), None);
+    }
+
+    #[test]
+    fn from_parts() {
+        let mut nodes = BTreeGraph::new();
+        nodes.insert("a", 1);
+        nodes.insert("b", 2);
+
+        let graph = EdgeGraph::from_parts(nodes, vec![("a", "b", 5)]).unwrap();
+        let (a, b) = (graph.get_ref("a").unwrap(), graph.get_ref("b").unwrap());
+        assert_eq!(graph.edge(a, b), Some(&5));
+
+        let mut nodes = BTreeGraph::new();
+        nodes.insert("a", 1);
+        let err = EdgeGraph::from_parts(nodes, vec![("a", "b", 5), ("c", "a", 6)])
+            .err()
+            .unwrap();
+        assert_eq!(err.missing(), &["b", "c"]);
+
+        let mut nodes = BTreeGraph::new();
+        nodes.insert("a", 1);
+        nodes.promise("b");
+        let mut graph = EdgeGraph::from_parts(nodes, vec![("a", "b", 5)]).unwrap();
+        let b = graph.insert("b", 2);
+        assert_eq!(graph.get("b"), Some(&2));
+        assert_eq!(graph.incoming(&b).count(), 1);
+    }
+
+    #[test]
+    fn from_parts_auto_compact() {
+        use crate::{IndexBy, Resolve};
+
+        struct Node(u32);
+
+        impl Resolve<u32, Node> for Node {
+            fn resolve<I>(&mut self, _: &I) -> Result<(), u32>
+            where
+                I: IndexBy<u32, Node>,
+            {
+                Ok(())
+            }
+        }
+
+        let mut nodes = BTreeGraph::new();
+        nodes.set_auto_compact(1.0);
+        (0..10).for_each(|key| {
+            nodes.insert(key, Node(key));
+        });
+
+        let edges = (0..9).map(|key| (key, key + 1, ()));
+        let mut graph = EdgeGraph::from_parts(nodes, edges).unwrap();
+        (0..8).for_each(|key| {
+            graph.remove_node(&key);
+        });
+
+        let (a, b) = (graph.get_ref(&8).unwrap(), graph.get_ref(&9).unwrap());
+        assert_eq!(graph.edge(a, b), Some(&()));
+        assert_eq!(graph.get(&9).unwrap().0, 9);
+        assert_eq!(graph.edge_count(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "edge endpoint was removed")]
+    fn add_edge_removed() {
+        let mut graph = EdgeGraph::new();
+        let a = graph.insert("a", 1);
+        let b = graph.insert("b", 2);
+        graph.remove_node("b");
+        graph.add_edge(&a, &b, ());
+    }
+
+    #[test]
+    #[should_panic(expected = "edge endpoint does not belong to this graph")]
+    fn add_edge_stale() {
+        let mut graph = EdgeGraph::new();
+        let a = graph.insert("a", 1);
+        let b = EdgeGraph::<_, _, ()>::new().insert("b", 2);
+        graph.add_edge(&a, &b, ());
+    }
+}
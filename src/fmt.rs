@@ -0,0 +1,305 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+//! Pretty-printing of tree-shaped graphs.
+//!
+//! Nodes expose their children through the `TreeNode` trait. Each
+//! node is printed on its own line, indented by two spaces per level:
+//!
+//! ```
+//! use core::fmt::Write;
+//!
+//! use graph::{fmt::{self, TreeFormat, TreeNode}, BTreeGraph, Ref, RefBy};
+//!
+//! struct Node {
+//!     parent: Option<String>,
+//!     children: Vec<Ref<Node>>,
+//! }
+//!
+//! impl TreeNode<Node> for Node {
+//!     fn visit_children(&self, f: &mut impl FnMut(&Ref<Node>)) {
+//!         self.children.iter().for_each(f)
+//!     }
+//! }
+//!
+//! let mut graph = BTreeGraph::new();
+//! let mut add = |key: &str, parent: Option<&str>| {
+//!     let node = graph.insert(key.to_string(), Node {
+//!         parent: parent.map(str::to_string),
+//!         children: Vec::new(),
+//!     });
+//!     if let Some(parent) = parent {
+//!         graph.get_mut(parent).unwrap().children.push(node.clone());
+//!     }
+//!     RefBy::new(key.to_string(), node)
+//! };
+//! let root = add("root", None);
+//! add("child 1", Some("root"));
+//! add("child 1.1", Some("child 1"));
+//! add("child 1.1.1", Some("child 1.1"));
+//! add("child 1.2", Some("child 1"));
+//! add("child 2", Some("root"));
+//!
+//! let format = TreeFormat::new().node(|out, key, node: &Node| {
+//!     write!(out, "{key}")?;
+//!     match &node.parent {
+//!         Some(parent) => write!(out, " (parent: {parent})"),
+//!         None => Ok(()),
+//!     }
+//! });
+//!
+//! assert_eq!(
+//!     fmt::tree_with(&graph, &root, &format),
+//!     r#"root
+//!   child 1 (parent: root)
+//!     child 1.1 (parent: child 1)
+//!       child 1.1.1 (parent: child 1.1)
+//!     child 1.2 (parent: child 1)
+//!   child 2 (parent: root)
+//! "#
+//! );
+//! ```
+//!
+//! Children beyond the maximum depth, and nodes that already appear
+//! on the path from the root (cycles), are printed as `…`. Nodes that
+//! were promised but not yet created are printed as their key,
+//! followed by `(promised)`.
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    string::String,
+    vec::{self, Vec},
+};
+use core::fmt::{Display, Error, Result, Write};
+
+use crate::{graph::Slot, BTreeGraph, Ref, RefBy};
+
+/// A node that has children in a graph of `V` nodes.
+pub trait TreeNode<V> {
+    /// Call `f` for every child of the node, in display order.
+    fn visit_children(&self, f: &mut impl FnMut(&Ref<V>));
+}
+
+type NodeFormatter<'a, K, V> = dyn Fn(&mut dyn Write, &K, &V) -> Result + 'a;
+
+/// Options for printing a tree.
+pub struct TreeFormat<'a, K, V> {
+    max_depth: Option<usize>,
+    node: Option<Box<NodeFormatter<'a, K, V>>>,
+}
+
+impl<'a, K, V> TreeFormat<'a, K, V> {
+    pub fn new() -> Self {
+        Self {
+            max_depth: None,
+            node: None,
+        }
+    }
+
+    /// Limit the depth of the printed tree. The root is at depth zero.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Format nodes using `f` instead of printing their key. The
+    /// indentation and newline are written by the printer.
+    pub fn node<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&mut dyn Write, &K, &V) -> Result + 'a,
+    {
+        self.node = Some(Box::new(f));
+        self
+    }
+}
+
+impl<K, V> Default for TreeFormat<'_, K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Print the tree below `root`, showing the key of every node.
+pub fn tree<K, V>(graph: &BTreeGraph<K, V>, root: &RefBy<K, V>) -> String
+where
+    K: Display,
+    V: TreeNode<V>,
+{
+    tree_with(graph, root, &TreeFormat::new())
+}
+
+/// Print the tree below `root` using custom options.
+pub fn tree_with<K, V>(
+    graph: &BTreeGraph<K, V>,
+    root: &RefBy<K, V>,
+    format: &TreeFormat<K, V>,
+) -> String
+where
+    K: Display,
+    V: TreeNode<V>,
+{
+    let mut out = String::new();
+    write_tree(&mut out, graph, root, format).expect("writing to a string does not fail");
+    out
+}
+
+/// Write the tree below `root` to `out`.
+pub fn write_tree<W, K, V>(
+    out: &mut W,
+    graph: &BTreeGraph<K, V>,
+    root: &RefBy<K, V>,
+    format: &TreeFormat<K, V>,
+) -> Result
+where
+    W: Write,
+    K: Display,
+    V: TreeNode<V>,
+{
+    let keys = graph
+        .iter_ref()
+        .map(|(key, node)| (node.as_ptr(), key))
+        .collect::<BTreeMap<_, _>>();
+    let mut printer = Printer { out, keys, format };
+    printer.write(root.key(), root.value_ref())
+}
+
+struct Printer<'a, W, K, V> {
+    out: &'a mut W,
+    keys: BTreeMap<*const Slot<V>, &'a K>,
+    format: &'a TreeFormat<'a, K, V>,
+}
+
+impl<W, K, V> Printer<'_, W, K, V>
+where
+    W: Write,
+    K: Display,
+    V: TreeNode<V>,
+{
+    /// Print the tree depth-first, keeping the children that remain
+    /// to be printed for every node on the path from the root.
+    fn write(&mut self, key: &K, root: &Ref<V>) -> Result {
+        let mut stack = Vec::from([(root.as_ptr(), self.line(key, root, 0)?)]);
+        let mut path = BTreeSet::from([root.as_ptr()]);
+        while let Some((_, children)) = stack.last_mut() {
+            let Some(child) = children.next() else {
+                if let Some((node, _)) = stack.pop() {
+                    path.remove(&node);
+                }
+                continue;
+            };
+            let depth = stack.len();
+            match self.keys.get(&child.as_ptr()).copied() {
+                Some(key) if !path.contains(&child.as_ptr()) => {
+                    let children = self.line(key, &child, depth)?;
+                    path.insert(child.as_ptr());
+                    stack.push((child.as_ptr(), children));
+                }
+                _ => self.ellipsis(depth)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Print a single node, returning the children to print below
+    /// it.
+    fn line(
+        &mut self,
+        key: &K,
+        node: &Ref<V>,
+        depth: usize,
+    ) -> core::result::Result<vec::IntoIter<Ref<V>>, Error> {
+        self.indent(depth)?;
+        let Some(value) = (unsafe { node.try_get_unchecked() }) else {
+            writeln!(self.out, "{key} (promised)")?;
+            return Ok(Vec::new().into_iter());
+        };
+        match &self.format.node {
+            Some(f) => f(self.out, key, value)?,
+            None => write!(self.out, "{key}")?,
+        }
+        writeln!(self.out)?;
+
+        let mut children = Vec::new();
+        value.visit_children(&mut |child| children.push(child.clone()));
+        if !children.is_empty() && self.format.max_depth.is_some_and(|max| depth >= max) {
+            self.ellipsis(depth + 1)?;
+            children.clear();
+        }
+        Ok(children.into_iter())
+    }
+
+    fn ellipsis(&mut self, depth: usize) -> Result {
+        self.indent(depth)?;
+        writeln!(self.out, "…")
+    }
+
+    fn indent(&mut self, depth: usize) -> Result {
+        (0..depth).try_for_each(|_| write!(self.out, "  "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use crate::{
+        fmt::{tree, tree_with, TreeFormat, TreeNode},
+        BTreeGraph, Ref, RefBy,
+    };
+
+    struct Node(Vec<Ref<Node>>);
+
+    impl TreeNode<Node> for Node {
+        fn visit_children(&self, f: &mut impl FnMut(&Ref<Node>)) {
+            self.0.iter().for_each(f)
+        }
+    }
+
+    fn chain() -> (BTreeGraph<u32, Node>, RefBy<u32, Node>) {
+        let mut graph = BTreeGraph::new();
+        let a = graph.insert(1, Node(Vec::new()));
+        let b = graph.insert(2, Node(Vec::new()));
+        let c = graph.insert(3, Node(Vec::new()));
+        graph.borrow_mut(&a).0.push(b.clone());
+        graph.borrow_mut(&b).0.push(c.clone());
+        graph.borrow_mut(&c).0.push(a.clone());
+        (graph, RefBy::new(1, a))
+    }
+
+    #[test]
+    fn cycle() {
+        let (graph, root) = chain();
+        assert_eq!(tree(&graph, &root), "1\n  2\n    3\n      …\n");
+    }
+
+    #[test]
+    fn max_depth() {
+        let (graph, root) = chain();
+        let format = TreeFormat::new().max_depth(1);
+        assert_eq!(tree_with(&graph, &root, &format), "1\n  2\n    …\n");
+    }
+
+    #[test]
+    fn shared_and_promised() {
+        let mut graph = BTreeGraph::new();
+        let promised = graph.promise(4);
+        let c = graph.insert(3, Node(vec![promised]));
+        let b = graph.insert(2, Node(vec![c.clone()]));
+        let a = graph.insert(1, Node(vec![b, c]));
+        assert_eq!(
+            tree(&graph, &RefBy::new(1, a)),
+            "1\n  2\n    3\n      4 (promised)\n  3\n    4 (promised)\n"
+        );
+    }
+}
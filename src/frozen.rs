@@ -0,0 +1,181 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+use alloc::{collections::BTreeMap, sync::Arc};
+use core::borrow::Borrow;
+
+use crate::{BTreeGraph, ProjectedRef, Ref, RefBy};
+
+/// An immutable, shared graph. This only exposes the read API of
+/// `BTreeGraph`, and can be cloned cheaply to share the graph between
+/// (e.g. async) readers. Use `thaw` to get back the mutable graph
+/// once all other clones have been dropped.
+pub struct FrozenGraph<K, V>(Arc<BTreeGraph<K, V>>);
+
+impl<K, V> BTreeGraph<K, V> {
+    /// Make the graph immutable and shareable.
+    pub fn freeze(self) -> FrozenGraph<K, V> {
+        FrozenGraph(Arc::new(self))
+    }
+}
+
+impl<K, V> FrozenGraph<K, V> {
+    /// Get back the mutable graph. Returns `None` (dropping this
+    /// handle) if the graph is still shared by other clones.
+    pub fn thaw(self) -> Option<BTreeGraph<K, V>> {
+        Arc::into_inner(self.0)
+    }
+
+    pub fn index(&self) -> &BTreeMap<K, Ref<V>> {
+        self.0.index()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get_ref<Q>(&self, key: &Q) -> Option<&Ref<V>>
+    where
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
+    {
+        self.0.get_ref(key)
+    }
+
+    pub fn get_ref_by<Q>(&self, key: &Q) -> Option<RefBy<K, V>>
+    where
+        K: Borrow<Q> + Ord + Clone,
+        Q: Ord + ?Sized,
+    {
+        self.0.get_ref_by(key)
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
+    {
+        self.0.get(key)
+    }
+
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
+    {
+        self.0.get_key_value(key)
+    }
+
+    /// Borrow the value from the graph. Panics under the same
+    /// conditions as `BTreeGraph::borrow`.
+    pub fn borrow<R>(&self, node: &R) -> &V
+    where
+        R: AsRef<Ref<V>>,
+    {
+        BTreeGraph::borrow(&self.0, node)
+    }
+
+    pub fn borrow_projected<U: ?Sized, F, G>(&self, node: &ProjectedRef<V, U, F, G>) -> &U
+    where
+        F: Fn(&V) -> &U,
+    {
+        self.0.borrow_projected(node)
+    }
+
+    pub fn iter_ref(&self) -> impl Iterator<Item = (&K, &Ref<V>)> {
+        self.0.iter_ref()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.0.iter()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.0.keys()
+    }
+
+    pub fn values_ref(&self) -> impl Iterator<Item = &Ref<V>> {
+        self.0.values_ref()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.0.values()
+    }
+}
+
+impl<K, V> Clone for FrozenGraph<K, V> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<K, V> AsRef<BTreeGraph<K, V>> for FrozenGraph<K, V> {
+    fn as_ref(&self) -> &BTreeGraph<K, V> {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::thread;
+
+    use crate::{BTreeGraph, Ref};
+
+    #[test]
+    fn freeze_thaw() {
+        struct Node {
+            value: u32,
+            next: Option<Ref<Node>>,
+        }
+
+        let mut graph = BTreeGraph::new();
+        let last = graph.insert(
+            2,
+            Node {
+                value: 2,
+                next: None,
+            },
+        );
+        graph.insert(
+            1,
+            Node {
+                value: 1,
+                next: Some(last),
+            },
+        );
+
+        let frozen = graph.freeze();
+        let readers = (0..4)
+            .map(|_| {
+                let frozen = frozen.clone();
+                thread::spawn(move || {
+                    let first = frozen.get(&1).unwrap();
+                    frozen.borrow(first.next.as_ref().unwrap()).value
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let clone = frozen.clone();
+        assert!(clone.thaw().is_none());
+        readers
+            .into_iter()
+            .for_each(|reader| assert_eq!(reader.join().unwrap(), 2));
+
+        let mut graph = frozen.thaw().unwrap();
+        graph.get_mut(&2).unwrap().value = 3;
+        let first = graph.get(&1).unwrap();
+        assert_eq!(graph.borrow(first.next.as_ref().unwrap()).value, 3);
+    }
+}
@@ -12,18 +12,32 @@
  * under the License.                                                         * 
  ******************************************************************************/
 
-#[cfg(any(not(feature = "unsafe"), debug_assertions))]
-use std::sync::atomic::{AtomicU64, Ordering};
+use core::sync::atomic::Ordering;
 
-#[cfg(any(not(feature = "unsafe"), debug_assertions))]
-static GENERATION: AtomicU64 = AtomicU64::new(1);
+/* Targets without 64-bit atomics fall back to a pointer-sized
+ * counter, which only wraps after 2^32 graphs on 32-bit targets. */
+
+#[cfg(target_has_atomic = "64")]
+use core::sync::atomic::AtomicU64 as AtomicGen;
+#[cfg(target_has_atomic = "64")]
+type GenValue = u64;
+
+#[cfg(not(target_has_atomic = "64"))]
+use core::sync::atomic::AtomicUsize as AtomicGen;
+#[cfg(not(target_has_atomic = "64"))]
+type GenValue = usize;
+
+static GENERATION: AtomicGen = AtomicGen::new(1);
+
+/* The generation is kept in every build, including the optimized
+ * "unsafe" one, which only skips the checks on the panicking borrow
+ * paths: the fallible methods (`try_remove`, `validate_many`, ...),
+ * `owns` and reference equality rely on it. */
 
-#[cfg(any(not(feature = "unsafe"), debug_assertions))]
 #[allow(clippy::derived_hash_with_manual_eq)]
 #[derive(Clone, Copy, Hash, Debug)]
-pub struct Gen(u64);
+pub struct Gen(GenValue);
 
-#[cfg(any(not(feature = "unsafe"), debug_assertions))]
 impl Gen {
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
@@ -37,33 +51,16 @@ impl Gen {
     pub fn is_invalid(&self) -> bool {
         self.0 == 0
     }
+
+    /// `GenValue` is `usize` on targets without 64-bit atomics.
+    #[allow(clippy::unnecessary_cast)]
+    pub(crate) fn value(&self) -> u64 {
+        self.0 as u64
+    }
 }
 
-#[cfg(any(not(feature = "unsafe"), debug_assertions))]
 impl PartialEq for Gen {
     fn eq(&self, other: &Self) -> bool {
         self.0 != 0 && self.0 == other.0
     }
 }
-
-/* Disable gen checking in optimized "unsafe" build. */
-
-#[cfg(all(feature = "unsafe", not(debug_assertions)))]
-#[derive(Clone, Copy, PartialEq, Hash, Debug)]
-pub struct Gen;
-
-#[cfg(all(feature = "unsafe", not(debug_assertions)))]
-impl Gen {
-    #[allow(clippy::new_without_default)]
-    pub fn new() -> Self {
-        Self
-    }
-
-    pub fn invalid() -> Self {
-        Self
-    }
-
-    pub fn is_invalid(&self) -> bool {
-        false
-    }
-}
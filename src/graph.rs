@@ -12,62 +12,237 @@
  * under the License.                                                         * 
  ******************************************************************************/
 
-use std::ptr::NonNull;
+use alloc::{boxed::Box, string::String, vec::Vec};
+#[cfg(feature = "runtime-borrow-check")]
+use core::sync::atomic::AtomicIsize;
+#[cfg(feature = "debug-borrows")]
+use core::sync::atomic::AtomicU64;
+use core::{
+    cell::UnsafeCell,
+    fmt::{Debug, Display},
+    ptr,
+};
 #[cfg(feature = "serde")]
-use std::{fmt::Formatter, marker::PhantomData};
+use core::{fmt::Formatter, marker::PhantomData};
 
 #[cfg(feature = "serde")]
 use serde::{
-    de::{Deserializer, SeqAccess, Visitor},
-    Deserialize,
+    de::{DeserializeSeed, Deserializer, SeqAccess, Visitor},
+    ser::{SerializeSeq, Serializer},
+    Deserialize, Serialize,
 };
 #[cfg(feature = "tsify")]
 use tsify::Tsify;
-use typed_arena::Arena;
 
-use crate::{Gen, Ref};
+use crate::{
+    borrow_check::{check_unguarded, BorrowGuard, BorrowGuardMut, BorrowScope},
+    storage::{self, Heap, NodeStorage, Nodes},
+    Gen, ProjectedRef, Ref,
+};
+
+/// A graph node's storage slot. Nodes are wrapped in an `UnsafeCell`
+/// because they are mutated through `Ref` pointers obtained through a
+/// shared borrow (e.g. while iterating over a keyed graph's index).
+/// With the `debug-borrows` feature, the slot also records the live
+/// borrow scope in which it is mutably borrowed, if any. With the
+/// `runtime-borrow-check` feature, it counts the outstanding borrow
+/// guards (-1 for a mutable one). Slots of types with an identical
+/// layout must have an identical layout themselves, for `Ref::cast`,
+/// hence `repr(C)` here and on `SlotState`.
+#[repr(C)]
+pub(crate) struct Slot<T> {
+    value: UnsafeCell<SlotState<T>>,
+    #[cfg(feature = "debug-borrows")]
+    pub(crate) borrow: AtomicU64,
+    #[cfg(feature = "runtime-borrow-check")]
+    pub(crate) guards: AtomicIsize,
+}
+
+impl<T> Slot<T> {
+    fn new(value: SlotState<T>) -> Self {
+        Self {
+            value: UnsafeCell::new(value),
+            #[cfg(feature = "debug-borrows")]
+            borrow: AtomicU64::new(0),
+            #[cfg(feature = "runtime-borrow-check")]
+            guards: AtomicIsize::new(0),
+        }
+    }
+
+    /// Get a pointer to the slot's contents, without creating a
+    /// reference to the slot.
+    ///
+    /// Safety: the pointer must point to a live slot.
+    pub(crate) unsafe fn raw_get(this: *const Self) -> *mut SlotState<T> {
+        UnsafeCell::raw_get(ptr::addr_of!((*this).value))
+    }
+
+    fn get_mut(&mut self) -> &mut SlotState<T> {
+        self.value.get_mut()
+    }
+
+    fn into_inner(self) -> SlotState<T> {
+        self.value.into_inner()
+    }
+}
+
+/// The contents of a slot. An empty slot remembers whether the node
+/// was promised or removed, to provide a meaningful panic message
+/// when it is borrowed.
+#[repr(C)]
+pub(crate) enum SlotState<T> {
+    Promised,
+    Created(T),
+    Removed,
+}
+
+impl<T> SlotState<T> {
+    pub(crate) fn as_ref(&self) -> Option<&T> {
+        match self {
+            Self::Created(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_mut(&mut self) -> Option<&mut T> {
+        match self {
+            Self::Created(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn into_option(self) -> Option<T> {
+        match self {
+            Self::Created(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn is_promised(&self) -> bool {
+        matches!(self, Self::Promised)
+    }
+
+    /// Panic with a message describing why the slot is empty.
+    #[track_caller]
+    pub(crate) fn empty(&self) -> ! {
+        match self {
+            Self::Promised => panic!("borrowed node that was promised but not yet created"),
+            Self::Removed => panic!("borrowed node that was removed"),
+            Self::Created(_) => unreachable!(),
+        }
+    }
+}
 
 #[cfg_attr(feature = "tsify", derive(Tsify))]
-#[cfg_attr(feature = "tsify", tsify(from_wasm_abi, into_wasm_abi, type = "[T]"))]
-pub struct Graph<T> {
-    nodes: Arena<Option<T>>,
+#[cfg_attr(feature = "tsify", tsify(from_wasm_abi, into_wasm_abi, type = "T[]"))]
+pub struct Graph<T, S: NodeStorage = Heap> {
+    nodes: Nodes<Slot<T>, S>,
     gen: Gen,
 }
 
 impl<T> Graph<T> {
     /// Create a new empty graph.
     pub fn new() -> Self {
+        Self::with_storage(Heap)
+    }
+
+    /// Create an empty graph with capacity for ''n'' nodes.
+    pub fn with_capacity(n: usize) -> Self {
         Self {
-            nodes: Arena::new(),
+            nodes: Nodes::with_capacity(Heap, n),
             gen: Gen::new(),
         }
     }
 
-    /// Create an empty graph with capacity for ''n'' nodes.
-    pub fn with_capacity(n: usize) -> Self {
+    /// Create an empty graph whose arena allocates nodes in chunks of
+    /// ''n'' nodes. By default, each chunk is twice as large as the
+    /// previous one; fixed-size chunks bound the unused capacity to
+    /// that of a single chunk, at the cost of more allocations for
+    /// large graphs. `reserve` and `insert_batch` may still allocate
+    /// a larger chunk.
+    pub fn with_chunk_capacity(n: usize) -> Self {
+        let mut graph = Self::new();
+        graph.nodes.set_chunk_capacity(n);
+        graph
+    }
+}
+
+impl<T, S: NodeStorage> Graph<T, S> {
+    /// Create an empty graph allocating the memory for its nodes
+    /// from a custom storage instead of the heap.
+    pub fn with_storage(storage: S) -> Self {
         Self {
-            nodes: Arena::with_capacity(n),
+            nodes: Nodes::new(storage),
             gen: Gen::new(),
         }
     }
 
+    /// Estimate the memory used by the graph, in bytes. This counts
+    /// every allocated slot, including those of removed nodes, but
+    /// not the arena's spare capacity nor any heap memory owned by
+    /// the nodes.
+    pub fn memory_usage(&self) -> usize {
+        self.nodes.len() * core::mem::size_of::<Slot<T>>()
+    }
+
+    /// Check whether the reference belongs to this graph. Unlike the
+    /// assertions in `borrow` and friends, this check is performed
+    /// with the `unsafe` feature as well.
+    pub fn owns(&self, node: &Ref<T>) -> bool {
+        self.gen == node.gen
+    }
+
+    /// The number of allocated slots, including those of promised
+    /// and removed nodes.
+    pub(crate) fn slot_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// The number of contiguous chunks of slots in the storage.
+    pub(crate) fn chunk_count(&self) -> usize {
+        self.nodes.chunks()
+    }
+
+    /// Remove all nodes from the graph. The graph receives a new
+    /// generation, so that references to the old nodes become
+    /// invalid. The memory allocated for the old nodes is kept and
+    /// reused for new ones.
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.gen = Gen::new();
+    }
+
     /// Insert a node into the graph. The returned reference can be used
     /// to access this node.
     pub fn insert(&mut self, value: T) -> Ref<T> {
-        unsafe {
-            let node = self.nodes.alloc(Some(value));
-            Ref::new(NonNull::new_unchecked(node), self.gen)
-        }
+        let node = self.nodes.alloc(Slot::new(SlotState::Created(value)));
+        Ref::new(node, self.gen)
+    }
+
+    /// Insert multiple nodes into the graph, returning a reference to
+    /// each of them in iteration order.
+    pub fn insert_batch<I>(&mut self, values: I) -> Vec<Ref<T>>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let values = values.into_iter();
+        self.nodes.reserve(values.size_hint().0);
+        values.map(|value| self.insert(value)).collect()
+    }
+
+    /// Prepare the storage for the allocation of (at least)
+    /// `additional` more nodes.
+    pub fn reserve(&mut self, additional: usize) {
+        self.nodes.reserve(additional);
     }
 
     /// Reserve an empty slot in the graph. This can be used when
-    /// initializing the graph or to create cycles. Trying to access
-    /// the node before it's value is set, will cause a panic.
+    /// initializing the graph or to create cycles. Trying to borrow
+    /// the node before it's value is set, will cause a panic with a
+    /// message distinguishing it from a removed node.
     pub fn promise(&mut self) -> Ref<T> {
-        unsafe {
-            let node = self.nodes.alloc(None);
-            Ref::new(NonNull::new_unchecked(node), self.gen)
-        }
+        let node = self.nodes.alloc(Slot::new(SlotState::Promised));
+        Ref::new(node, self.gen)
     }
 
     /// Create a node that has previously been promised or
@@ -80,6 +255,30 @@ impl<T> Graph<T> {
         assert!(r.is_none());
     }
 
+    /// Set the value of a node, whether it was promised, removed or
+    /// already created, returning the previous value if there was
+    /// one. Panics if the node belongs to a different graph.
+    pub fn create_or_update(&mut self, node: &Ref<T>, value: T) -> Option<T> {
+        #[cfg(any(not(feature = "unsafe"), debug_assertions))]
+        assert!(self.gen == node.gen);
+        unsafe { node.try_replace_unchecked(value) }
+    }
+
+    /// Create a node that has previously been promised or removed,
+    /// without panicking. If the node belongs to a different graph or
+    /// already holds a value, the given value is handed back in the
+    /// error and the existing value is left untouched.
+    pub fn try_create(&mut self, node: &Ref<T>, value: T) -> Result<(), CreateError<T>> {
+        if self.gen != node.gen {
+            return Err(CreateError::WrongGraph { value });
+        }
+        if unsafe { node.try_get_unchecked() }.is_some() {
+            return Err(CreateError::AlreadyFilled { value });
+        }
+        unsafe { node.try_replace_unchecked(value) };
+        Ok(())
+    }
+
     /// Remove the value from the graph. Panics if you try to remove
     /// the node from a different graph or if the node was previously
     /// removed.
@@ -89,28 +288,89 @@ impl<T> Graph<T> {
         unsafe { node.try_remove_unchecked().unwrap() }
     }
 
+    /// Remove the value from the graph without panicking. Returns
+    /// `None` if the node belongs to a different graph, was already
+    /// removed or was promised but not yet created.
+    pub fn try_remove(&mut self, node: &Ref<T>) -> Option<T> {
+        if self.gen != node.gen {
+            return None;
+        }
+        unsafe { node.try_remove_unchecked() }
+    }
+
     /// Borrow the value from the graph. Panics if you try to borrow
-    /// the node from a different graph or if the node was previously
-    /// removed.
+    /// the node from a different graph, if the node was previously
+    /// removed or if it was promised but not yet created.
     pub fn borrow<R>(&self, node: &R) -> &T
     where
         R: AsRef<Ref<T>>,
     {
         #[cfg(any(not(feature = "unsafe"), debug_assertions))]
         assert!(self.gen == node.as_ref().gen);
-        unsafe { node.as_ref().try_get_unchecked().unwrap() }
+        unsafe {
+            check_unguarded(node.as_ref(), false);
+            node.as_ref().get_unchecked()
+        }
     }
 
     /// Mutably borrow the value from the graph. Panics if you try to
-    /// borrow the node from a different graph or if the node was
-    /// previously removed.
+    /// borrow the node from a different graph, if the node was
+    /// previously removed or if it was promised but not yet created.
     pub fn borrow_mut<R>(&mut self, node: &R) -> &mut T
     where
         R: AsRef<Ref<T>>,
     {
         #[cfg(any(not(feature = "unsafe"), debug_assertions))]
         assert!(self.gen == node.as_ref().gen);
-        unsafe { node.as_ref().try_get_unchecked_mut().unwrap() }
+        unsafe {
+            check_unguarded(node.as_ref(), true);
+            node.as_ref().get_unchecked_mut()
+        }
+    }
+
+    /// Like `borrow`, but returns a guard. With the
+    /// `runtime-borrow-check` feature, the borrow is registered with
+    /// the node until the guard is dropped, and a conflicting borrow
+    /// (e.g. through a smuggled reference) panics. Without the
+    /// feature, the guard is a plain reference.
+    pub fn borrow_guard<R>(&self, node: &R) -> BorrowGuard<'_, T>
+    where
+        R: AsRef<Ref<T>>,
+    {
+        #[cfg(any(not(feature = "unsafe"), debug_assertions))]
+        assert!(self.gen == node.as_ref().gen);
+        unsafe { BorrowGuard::new(node.as_ref()) }
+    }
+
+    /// Like `borrow_mut`, but returns a guard. See `borrow_guard`.
+    pub fn borrow_guard_mut<R>(&mut self, node: &R) -> BorrowGuardMut<'_, T>
+    where
+        R: AsRef<Ref<T>>,
+    {
+        #[cfg(any(not(feature = "unsafe"), debug_assertions))]
+        assert!(self.gen == node.as_ref().gen);
+        unsafe { BorrowGuardMut::new(node.as_ref()) }
+    }
+
+    /// Borrow the projected part of a node from the graph. Panics
+    /// under the same conditions as `borrow`.
+    pub fn borrow_projected<U: ?Sized, F, G>(&self, node: &ProjectedRef<T, U, F, G>) -> &U
+    where
+        F: Fn(&T) -> &U,
+    {
+        node.project(self.borrow(node))
+    }
+
+    /// Mutably borrow the projected part of a node from the
+    /// graph. Panics under the same conditions as `borrow_mut`.
+    pub fn borrow_projected_mut<U: ?Sized, F, G>(
+        &mut self,
+        node: &ProjectedRef<T, U, F, G>,
+    ) -> &mut U
+    where
+        G: Fn(&mut T) -> &mut U,
+    {
+        node.project_mut(self.borrow_mut(node))
     }
 
     /// Get mutable references to multiple nodes in the graph. This
@@ -127,55 +387,237 @@ impl<T> Graph<T> {
                 && nodes[..i]
                     .iter()
                     .all(|other| other.as_ref() != node.as_ref())));
-        unsafe { nodes.map(|node| node.as_ref().try_get_unchecked_mut().unwrap()) }
-    }
-
-    /* Disabled because this needs invalid_reference_casting due to
-     * Arena's lack of immutable iteration method. */
-    // pub fn iter(&self) -> impl Iterator<Item = &T> {
-    //     unsafe {
-    //         // Arena provides an ``iter_mut``, but not an ``iter``
-    //         // method, probably because it is !Sync and allows
-    //         // allocation (mutation) through &self. Since our public
-    //         // api only allows modification through methods taking
-    //         // &mut self (enforcing exclusive access), this is
-    //         // presumably fine.
-    //         #[allow(invalid_reference_casting)]
-    //         let self_mut = &mut *(self as *const Self as *mut Self);
-    //         self_mut.nodes.iter_mut().flat_map(|node| &*node)
-    //     }
-    // }
+        let scope = BorrowScope::new(nodes.iter().map(|node| node.as_ref()));
+        unsafe {
+            core::array::from_fn(|i| {
+                let node = nodes[i].as_ref();
+                scope.check(node, String::new);
+                node.get_unchecked_mut()
+            })
+        }
+    }
+
+    /// Get mutable references to multiple nodes in the graph,
+    /// always checking that the references belong to this graph and
+    /// point to distinct, filled nodes. Unlike
+    /// [`borrow_many_mut`](Self::borrow_many_mut), these checks are
+    /// not compiled out with the `unsafe` feature.
+    pub fn try_borrow_many_mut<const N: usize, R>(
+        &mut self,
+        nodes: [R; N],
+    ) -> Result<[&mut T; N], BorrowManyError>
+    where
+        R: AsRef<Ref<T>>,
+    {
+        if let Some(index) = nodes.iter().position(|node| node.as_ref().gen != self.gen) {
+            return Err(BorrowManyError::WrongGraph { index });
+        }
+        let mut order = core::array::from_fn::<usize, N, _>(|i| i);
+        order.sort_unstable_by_key(|&i| (nodes[i].as_ref().as_ptr(), i));
+        if let Some(w) = order
+            .windows(2)
+            .find(|w| nodes[w[0]].as_ref() == nodes[w[1]].as_ref())
+        {
+            return Err(BorrowManyError::Duplicate {
+                index: w[1],
+                first: w[0],
+            });
+        }
+        if let Some(index) = nodes
+            .iter()
+            .position(|node| unsafe { node.as_ref().try_get_unchecked() }.is_none())
+        {
+            return Err(BorrowManyError::Empty { index });
+        }
+        unsafe { Ok(nodes.map(|node| node.as_ref().get_unchecked_mut())) }
+    }
+
+    /// Check a batch of references before borrowing them. Returns
+    /// the indices of all references that belong to a different
+    /// graph, point to a removed node or to a node that was promised
+    /// but not yet created. Like the other fallible methods, this is
+    /// checked with the `unsafe` feature as well.
+    pub fn validate_many(&self, refs: &[Ref<T>]) -> Result<(), Vec<usize>> {
+        let invalid = refs
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| {
+                node.gen != self.gen || unsafe { node.try_get_unchecked() }.is_none()
+            })
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        if invalid.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid)
+        }
+    }
+
+    /// Iterate over the nodes in the graph, skipping the slots of
+    /// promised and removed nodes.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.iter_with_refs().map(|(_, value)| value)
+    }
 
     pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
-        self.nodes.iter_mut().flatten()
+        self.nodes
+            .iter_mut()
+            .filter_map(|node| node.get_mut().as_mut())
+    }
+
+    /// Iterate over the nodes in the graph, paired with a reference
+    /// to each of them.
+    pub fn iter_with_refs(&self) -> impl Iterator<Item = (Ref<T>, &T)> {
+        let gen = self.gen;
+        self.nodes.ptrs().filter_map(move |node| {
+            let value = unsafe { (*Slot::raw_get(node.as_ptr())).as_ref()? };
+            Some((Ref::new(node, gen), value))
+        })
+    }
+
+    /// A reference to every live node in the graph, skipping the
+    /// slots of promised and removed nodes.
+    pub fn iter_refs(&self) -> impl Iterator<Item = Ref<T>> + '_ {
+        self.iter_with_refs().map(|(node, _)| node)
+    }
+
+    /// Iterate mutably over the nodes in the graph, paired with a
+    /// reference to each of them, e.g. to update references to the
+    /// node itself.
+    pub fn iter_mut_with_refs(&mut self) -> impl Iterator<Item = (Ref<T>, &mut T)> {
+        let gen = self.gen;
+        self.nodes.ptrs().filter_map(move |node| {
+            let value = unsafe { (*Slot::raw_get(node.as_ptr())).as_mut()? };
+            Some((Ref::new(node, gen), value))
+        })
+    }
+
+    /// Iterate over a reference to every slot in the graph, including
+    /// those of promised and removed nodes. The position of a
+    /// reference in the iteration is its stable id, as used by
+    /// `serialize_stable` and `StableGraph`.
+    pub fn slot_refs(&self) -> impl Iterator<Item = Ref<T>> + '_ {
+        let gen = self.gen;
+        self.nodes.ptrs().map(move |node| Ref::new(node, gen))
+    }
+
+    /// Serialize the graph as a sequence with one entry per slot,
+    /// so that a node's position (its stable id, see `slot_refs`)
+    /// is not affected by the removal of other nodes. Promised and
+    /// removed nodes are serialized as `None` tombstones. Use
+    /// `StableGraph` to deserialize the result.
+    #[cfg(feature = "serde")]
+    pub fn serialize_stable(&self) -> SerializeStable<'_, T, S> {
+        SerializeStable(self)
     }
 }
 
-impl<T> Default for Graph<T> {
+/// Helpers for graphs of boxed (e.g. trait object) nodes.
+impl<T: ?Sized, S: NodeStorage> Graph<Box<T>, S> {
+    /// Borrow a boxed node as `&T`, e.g. `&dyn Trait` rather than
+    /// `&Box<dyn Trait>`. Panics as `borrow`.
+    pub fn borrow_dyn<R>(&self, node: &R) -> &T
+    where
+        R: AsRef<Ref<Box<T>>>,
+    {
+        self.borrow(node)
+    }
+
+    /// Mutably borrow a boxed node as `&mut T`. Panics as
+    /// `borrow_mut`.
+    pub fn borrow_dyn_mut<R>(&mut self, node: &R) -> &mut T
+    where
+        R: AsRef<Ref<Box<T>>>,
+    {
+        self.borrow_mut(node)
+    }
+
+    /// Iterate over the boxed nodes as `&mut T`.
+    pub fn iter_dyn_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.iter_mut().map(|node| &mut **node)
+    }
+}
+
+impl<T, S: NodeStorage + Default> Default for Graph<T, S> {
     fn default() -> Self {
-        Self::new()
+        Self::with_storage(S::default())
+    }
+}
+
+impl<T, S: NodeStorage> AsRef<Graph<T, S>> for Graph<T, S> {
+    fn as_ref(&self) -> &Graph<T, S> {
+        self
+    }
+}
+
+/// Mutable access to the nodes of a graph, implemented by `Graph`
+/// and the keyed graphs. This is the mutable counterpart of
+/// `AsRef<Graph<T>>`; the keyed graphs do not implement
+/// `AsMut<Graph<T>>`, since replacing their graph would invalidate
+/// the index.
+pub trait GraphMut<T> {
+    /// Mutably borrow the value from the graph. Panics if the node
+    /// belongs to a different graph or was removed.
+    fn borrow_mut(&mut self, node: &Ref<T>) -> &mut T;
+}
+
+impl<T, S: NodeStorage> GraphMut<T> for Graph<T, S> {
+    fn borrow_mut(&mut self, node: &Ref<T>) -> &mut T {
+        Graph::borrow_mut(self, node)
     }
 }
 
-impl<T> IntoIterator for Graph<T> {
+/// Extending a graph inserts each value as a new node. Since the
+/// references to the new nodes are discarded, this is only useful if
+/// the nodes are reachable otherwise (e.g. through `iter_mut`). Use
+/// `insert_batch` to retrieve the references.
+impl<T, S: NodeStorage> Extend<T> for Graph<T, S> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.nodes.reserve(iter.size_hint().0);
+        iter.for_each(|value| {
+            self.insert(value);
+        });
+    }
+}
+
+impl<T, S: NodeStorage> IntoIterator for Graph<T, S> {
     type Item = T;
-    type IntoIter = std::iter::Flatten<std::vec::IntoIter<Option<T>>>;
+    type IntoIter = IntoIter<T, S>;
     fn into_iter(self) -> Self::IntoIter {
-        self.nodes.into_vec().into_iter().flatten()
+        IntoIter(self.nodes.into_iter())
     }
 }
 
-/* Safety: Graph is Send + Sync because the public interface only
- * allows modifying state when given a mutable reference. */
+/// An iterator over the nodes of a graph, by value.
+pub struct IntoIter<T, S: NodeStorage = Heap>(storage::IntoIter<Slot<T>, S>);
+
+impl<T, S: NodeStorage> Iterator for IntoIter<T, S> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        self.0
+            .by_ref()
+            .find_map(|node| node.into_inner().into_option())
+    }
+}
 
-unsafe impl<T> Send for Graph<T> {}
-unsafe impl<T> Sync for Graph<T> {}
+/* Safety: Graph owns its nodes, so it can be sent to another thread
+ * when the nodes and the storage can. The public interface only
+ * allows modifying nodes when given a mutable reference, so a shared
+ * reference only gives out shared references to the nodes, and
+ * never touches the storage. */
 
-/* Safety: Ref is Send + Sync because it cannot be modified using the
- * public interface. */
+unsafe impl<T: Send, S: NodeStorage + Send> Send for Graph<T, S> {}
+unsafe impl<T: Sync, S: NodeStorage> Sync for Graph<T, S> {}
 
-unsafe impl<T> Send for Ref<T> {}
-unsafe impl<T> Sync for Ref<T> {}
+/* Safety: Ref cannot be used to access its node without the graph,
+ * which enforces the bounds above. However, a Ref (or a shared
+ * reference to one) sent to another thread can be combined there
+ * with either a shared or a mutable reference to the graph, so both
+ * Send and Sync require the node to be Send + Sync. */
+
+unsafe impl<T: Send + Sync> Send for Ref<T> {}
+unsafe impl<T: Send + Sync> Sync for Ref<T> {}
 
 /* This needs Graph::iter which is unsound due to Arena's lack of
  * immutable iteration method.  */
@@ -194,16 +636,145 @@ unsafe impl<T> Sync for Ref<T> {}
 //     }
 // }
 
+/// Error returned by [`Graph::try_borrow_many_mut`]. The indices
+/// refer to positions in the array of nodes passed in.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BorrowManyError {
+    /// The node at `index` belongs to a different graph.
+    WrongGraph { index: usize },
+    /// The node at `index` is the same node as the one at `first`.
+    Duplicate { index: usize, first: usize },
+    /// The node at `index` was promised but not yet created, or
+    /// removed.
+    Empty { index: usize },
+}
+
+impl Display for BorrowManyError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::WrongGraph { index } => {
+                write!(f, "node {index} belongs to a different graph")
+            }
+            Self::Duplicate { index, first } => {
+                write!(f, "node {index} is the same as node {first}")
+            }
+            Self::Empty { index } => write!(f, "node {index} holds no value"),
+        }
+    }
+}
+
+impl core::error::Error for BorrowManyError {}
+
+/// Error returned by [`Graph::try_create`] and its keyed variants.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum CreateError<T> {
+    /// The reference belongs to a different graph.
+    WrongGraph { value: T },
+    /// The key was not found in the graph's index.
+    UnknownKey { value: T },
+    /// The node already holds a value; the unconsumed value is
+    /// handed back.
+    AlreadyFilled { value: T },
+}
+
+impl<T> CreateError<T> {
+    /// Recover the value that was not inserted.
+    pub fn into_value(self) -> T {
+        match self {
+            Self::WrongGraph { value }
+            | Self::UnknownKey { value }
+            | Self::AlreadyFilled { value } => value,
+        }
+    }
+}
+
+impl<T> Display for CreateError<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::WrongGraph { .. } => write!(f, "node belongs to a different graph"),
+            Self::UnknownKey { .. } => write!(f, "key not found in graph"),
+            Self::AlreadyFilled { .. } => write!(f, "node already holds a value"),
+        }
+    }
+}
+
+impl<T: Debug> core::error::Error for CreateError<T> {}
+
+/// Options for deserializing graphs through the seed types
+/// (`GraphSeed`, `BTreeGraphSeed` and `HashGraphSeed`).
 #[cfg(feature = "serde")]
-impl<'de, T> Deserialize<'de> for Graph<T>
+#[derive(Clone, Copy, Debug)]
+pub struct GraphDeserializeOptions {
+    /// The number of nodes to allocate for if the deserializer
+    /// does not provide a size hint. Defaults to 256.
+    pub initial_capacity: usize,
+    /// If non-zero, the number of nodes allocated at once when the
+    /// initial capacity is exhausted, as in `Graph::with_chunk_capacity`.
+    /// Defaults to 0, growing the arena geometrically.
+    pub chunk_capacity: usize,
+}
+
+#[cfg(feature = "serde")]
+impl GraphDeserializeOptions {
+    pub(crate) fn capacity(&self, size_hint: Option<usize>) -> usize {
+        size_hint.unwrap_or(self.initial_capacity)
+    }
+
+    pub(crate) fn graph<T>(&self, size_hint: Option<usize>) -> Graph<T> {
+        let mut graph = Graph::with_capacity(self.capacity(size_hint));
+        if self.chunk_capacity > 0 {
+            graph.nodes.set_chunk_capacity(self.chunk_capacity);
+        }
+        graph
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Default for GraphDeserializeOptions {
+    fn default() -> Self {
+        Self {
+            initial_capacity: 256,
+            chunk_capacity: 0,
+        }
+    }
+}
+
+/// Deserialize a `Graph` using custom options.
+#[cfg(feature = "serde")]
+pub struct GraphSeed<T> {
+    options: GraphDeserializeOptions,
+    _marker: PhantomData<T>,
+}
+
+#[cfg(feature = "serde")]
+impl<T> GraphSeed<T> {
+    pub fn new(options: GraphDeserializeOptions) -> Self {
+        Self {
+            options,
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T> Default for GraphSeed<T> {
+    fn default() -> Self {
+        Self::new(GraphDeserializeOptions::default())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T> DeserializeSeed<'de> for GraphSeed<T>
 where
     T: Deserialize<'de>,
 {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    type Value = Graph<T>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
     where
         D: Deserializer<'de>,
     {
-        struct GraphVisitor<T>(PhantomData<T>);
+        struct GraphVisitor<T>(GraphDeserializeOptions, PhantomData<T>);
 
         impl<'de, T> Visitor<'de> for GraphVisitor<T>
         where
@@ -211,7 +782,7 @@ where
         {
             type Value = Graph<T>;
 
-            fn expecting(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+            fn expecting(&self, formatter: &mut Formatter<'_>) -> core::fmt::Result {
                 write!(formatter, "a sequence of nodes")
             }
 
@@ -219,10 +790,7 @@ where
             where
                 A: SeqAccess<'de>,
             {
-                let mut graph = match seq.size_hint() {
-                    Some(size) => Graph::with_capacity(size),
-                    None => Graph::new(),
-                };
+                let mut graph = self.0.graph(seq.size_hint());
 
                 while let Some(node) = seq.next_element()? {
                     graph.insert(node);
@@ -232,14 +800,136 @@ where
             }
         }
 
-        deserializer.deserialize_seq(GraphVisitor(PhantomData))
+        deserializer.deserialize_seq(GraphVisitor(self.options, PhantomData))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T> Deserialize<'de> for Graph<T>
+where
+    T: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        GraphSeed::default().deserialize(deserializer)
+    }
+}
+
+/// Serialize a graph with stable ids. See `Graph::serialize_stable`.
+#[cfg(feature = "serde")]
+pub struct SerializeStable<'a, T, N: NodeStorage = Heap>(&'a Graph<T, N>);
+
+#[cfg(feature = "serde")]
+impl<T, N: NodeStorage> Serialize for SerializeStable<'_, T, N>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_seq(Some(self.0.nodes.len()))?;
+        self.0.nodes.ptrs().try_for_each(|node| {
+            s.serialize_element(&unsafe { (*Slot::raw_get(node.as_ptr())).as_ref() })
+        })?;
+        s.end()
+    }
+}
+
+/// A graph deserialized from the output of `Graph::serialize_stable`.
+/// Tombstones are restored as removed nodes, so that every node keeps
+/// its slot position. `refs` holds a reference to every slot, indexed
+/// by stable id.
+#[cfg(feature = "serde")]
+pub struct StableGraph<T> {
+    pub graph: Graph<T>,
+    pub refs: Vec<Ref<T>>,
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T> Deserialize<'de> for StableGraph<T>
+where
+    T: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct StableVisitor<T>(PhantomData<T>);
+
+        impl<'de, T> Visitor<'de> for StableVisitor<T>
+        where
+            T: Deserialize<'de>,
+        {
+            type Value = StableGraph<T>;
+
+            fn expecting(&self, formatter: &mut Formatter<'_>) -> core::fmt::Result {
+                write!(formatter, "a sequence of nodes or tombstones")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let options = GraphDeserializeOptions::default();
+                let mut graph = options.graph(seq.size_hint());
+                let mut refs = Vec::with_capacity(options.capacity(seq.size_hint()));
+
+                while let Some(node) = seq.next_element::<Option<T>>()? {
+                    refs.push(match node {
+                        Some(value) => graph.insert(value),
+                        None => {
+                            let node = graph.nodes.alloc(Slot::new(SlotState::Removed));
+                            Ref::new(node, graph.gen)
+                        }
+                    });
+                }
+
+                Ok(StableGraph { graph, refs })
+            }
+        }
+
+        deserializer.deserialize_seq(StableVisitor(PhantomData))
     }
 }
 
 #[cfg(test)]
 mod test {
 
-    use crate::{Graph, Ref};
+    use std::collections::HashMap;
+
+    use crate::{BorrowManyError, CreateError, Graph, Ref};
+
+    #[test]
+    fn identity_key() {
+        let mut graph = Graph::new();
+        let a = graph.insert(1);
+        let b = graph.insert(2);
+        let mut other = Graph::new();
+        let c = other.insert(3);
+        assert_ne!(a.identity_key().1, 0);
+        assert_ne!(a.identity_key().1, c.identity_key().1);
+
+        let refs = [&a, &b, &c, &a.clone()];
+        for x in refs {
+            for y in refs {
+                assert_eq!(x.identity_key() == y.identity_key(), x == y);
+            }
+        }
+
+        let keys = HashMap::from([(a.identity_key(), 1), (b.identity_key(), 2)]);
+        assert_eq!(keys[&a.clone().identity_key()], 1);
+        assert!(!keys.contains_key(&c.identity_key()));
+    }
+
+    #[test]
+    fn dangling_eq() {
+        let a = Ref::<u32>::dangling();
+        assert!(a == a && a == Ref::dangling());
+        assert!(a != Graph::new().insert(1));
+    }
 
     #[test]
     fn cycle() {
@@ -284,4 +974,361 @@ mod test {
         assert_eq!(graph.borrow(&b).prev, a);
         assert_eq!(graph.borrow(&a).prev, c);
     }
+
+    #[test]
+    fn projection() {
+        struct Node {
+            name: String,
+            count: usize,
+            ports: Vec<u16>,
+        }
+
+        let mut graph = Graph::new();
+        let node = graph.insert(Node {
+            name: "a".to_string(),
+            count: 0,
+            ports: vec![22, 80],
+        });
+
+        let count = node.project(|n| &n.count, |n| &mut n.count);
+        let name = node.project(|n| n.name.as_str(), |n| n.name.as_mut_str());
+
+        *graph.borrow_projected_mut(&count) += 2;
+        graph.borrow_projected_mut(&name).make_ascii_uppercase();
+
+        assert_eq!(*graph.borrow_projected(&count), 2);
+        assert_eq!(graph.borrow_projected(&name), "A");
+        assert_eq!(graph.borrow(&count).count, 2);
+
+        let i = 1;
+        let port = node.project(move |n| &n.ports[i], move |n| &mut n.ports[i]);
+        *graph.borrow_projected_mut(&port) = 443;
+        assert_eq!(graph.borrow(&port).ports, [22, 443]);
+    }
+
+    #[test]
+    fn extend() {
+        let mut graph = Graph::new();
+        graph.insert(0);
+        graph.extend(1..5);
+        graph.extend(vec![5, 6]);
+
+        let values = graph.iter_mut().map(|v| *v).collect::<Vec<_>>();
+        assert_eq!(values, (0..7).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn clear() {
+        let mut graph = Graph::with_capacity(4);
+        let old = graph.insert_batch(0..4);
+        graph.clear();
+        assert_eq!(graph.memory_usage(), 0);
+        assert!(!graph.owns(&old[0]));
+
+        let new = graph.insert_batch(4..8);
+        assert_eq!(graph.chunk_count(), 1);
+        assert_eq!(new[0].as_ptr(), old[0].as_ptr());
+        assert_eq!(*graph.borrow(&new[3]), 7);
+    }
+
+    #[test]
+    fn chunk_capacity() {
+        let mut graph = Graph::with_chunk_capacity(4);
+        (0..10).for_each(|i| {
+            graph.insert(i);
+        });
+        assert_eq!(graph.chunk_count(), 3);
+        graph.insert_batch(10..16);
+        assert_eq!(graph.chunk_count(), 4);
+        graph.insert(16);
+        assert_eq!(graph.chunk_count(), 5);
+
+        let mut graph = Graph::with_capacity(4);
+        (0..16).for_each(|i| {
+            graph.insert(i);
+        });
+        assert_eq!(graph.chunk_count(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "borrowed node that was promised but not yet created")]
+    fn borrow_promised() {
+        let mut graph = Graph::<u32>::new();
+        let node = graph.promise();
+        graph.borrow(&node);
+    }
+
+    #[test]
+    #[should_panic(expected = "borrowed node that was removed")]
+    fn borrow_removed() {
+        let mut graph = Graph::new();
+        let node = graph.insert(1);
+        graph.remove(node.clone());
+        graph.borrow(&node);
+    }
+
+    #[test]
+    fn create_promised() {
+        let mut graph = Graph::new();
+        let node = graph.promise();
+        graph.create(&node, 1);
+        assert_eq!(graph.remove(node.clone()), 1);
+        graph.create(&node, 2);
+        assert_eq!(graph.into_iter().collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[test]
+    fn try_remove() {
+        let mut graph = Graph::new();
+        let node = graph.insert(1);
+        assert_eq!(graph.try_remove(&node), Some(1));
+        assert_eq!(graph.try_remove(&node), None);
+        let promised = graph.promise();
+        assert_eq!(graph.try_remove(&promised), None);
+
+        let other = Graph::new().insert(2);
+        let node = graph.insert(2);
+        assert_eq!(graph.try_remove(&other), None);
+        assert_eq!(graph.try_remove(&Ref::dangling()), None);
+        assert_eq!(*graph.borrow(&node), 2);
+    }
+
+    #[test]
+    fn try_create() {
+        let mut graph = Graph::new();
+        let node = graph.promise();
+        assert_eq!(graph.try_create(&node, 1), Ok(()));
+        assert_eq!(
+            graph.try_create(&node, 2),
+            Err(CreateError::AlreadyFilled { value: 2 })
+        );
+        assert_eq!(*graph.borrow(&node), 1);
+        let other = Graph::new().promise();
+        assert_eq!(
+            graph.try_create(&other, 3),
+            Err(CreateError::WrongGraph { value: 3 })
+        );
+    }
+
+    #[test]
+    fn try_borrow_many_mut() {
+        let mut graph = Graph::new();
+        let a = graph.insert(1);
+        let b = graph.insert(2);
+        let c = graph.promise();
+        let [x, y] = graph.try_borrow_many_mut([&b, &a]).unwrap();
+        std::mem::swap(x, y);
+        assert_eq!((*graph.borrow(&a), *graph.borrow(&b)), (2, 1));
+        assert_eq!(
+            graph.try_borrow_many_mut([&a, &b, &a]).map(|_| ()),
+            Err(BorrowManyError::Duplicate { index: 2, first: 0 })
+        );
+        assert_eq!(
+            graph.try_borrow_many_mut([&a, &c]).map(|_| ()),
+            Err(BorrowManyError::Empty { index: 1 })
+        );
+        let other = Graph::new().insert(3);
+        assert_eq!(
+            graph.try_borrow_many_mut([&a, &other]).map(|_| ()),
+            Err(BorrowManyError::WrongGraph { index: 1 })
+        );
+    }
+
+    #[test]
+    fn validate_many() {
+        let mut graph = Graph::new();
+        let a = graph.insert(1);
+        let b = graph.insert(2);
+        let c = graph.promise();
+        let mut other_graph = Graph::new();
+        let other = other_graph.insert(3);
+        assert_eq!(graph.validate_many(&[a.clone(), b.clone()]), Ok(()));
+        assert_eq!(graph.validate_many(&[]), Ok(()));
+
+        graph.remove(b.clone());
+        let refs = [a.clone(), b, other, a.clone(), c.clone()];
+        assert_eq!(graph.validate_many(&refs), Err(vec![1, 2, 4]));
+        graph.create(&c, 4);
+        assert_eq!(graph.validate_many(&[a, c]), Ok(()));
+    }
+
+    #[test]
+    fn cast() {
+        #[repr(transparent)]
+        struct Wrapper(u64);
+
+        let mut graph = Graph::new();
+        let node = graph.insert(Wrapper(42));
+        let inner = unsafe { node.clone().cast::<u64>() };
+        let wrapped = unsafe { inner.cast::<Wrapper>() };
+        assert!(wrapped == node);
+        assert_eq!(graph.borrow(&wrapped).0, 42);
+        graph.borrow_mut(&wrapped).0 += 1;
+        assert_eq!(graph.borrow(&node).0, 43);
+    }
+
+    #[test]
+    fn iter_with_refs() {
+        let mut graph = Graph::new();
+        let refs = graph.insert_batch(0..10usize);
+        graph.remove(refs[3].clone());
+        let indices = graph
+            .iter_with_refs()
+            .map(|(node, value)| (node, *value))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(indices.len(), 9);
+        assert!(!indices.contains_key(&refs[3]));
+        for (i, node) in refs.iter().enumerate().filter(|(i, _)| *i != 3) {
+            assert_eq!(indices[node], i);
+            assert_eq!(*graph.borrow(node), i);
+        }
+    }
+
+    #[test]
+    fn iter_refs() {
+        let mut graph = Graph::new();
+        let a = graph.insert(1);
+        let b = graph.insert(2);
+        let promised = graph.promise();
+        graph.remove(b.clone());
+        let value = graph.borrow(&a);
+        let refs = graph.iter_refs().collect::<Vec<_>>();
+        assert_eq!(refs.len(), 1);
+        assert!(refs[0] == a);
+        assert_eq!(*value, 1);
+        graph.create(&promised, 3);
+        assert_eq!(graph.iter_refs().count(), 2);
+        assert_eq!(graph.iter().copied().collect::<Vec<_>>(), vec![1, 3]);
+    }
+
+    #[test]
+    fn iter_mut_with_refs() {
+        struct Node {
+            value: usize,
+            this: Ref<Node>,
+        }
+
+        let mut graph = Graph::new();
+        let refs = graph.insert_batch((0..10).map(|value| Node {
+            value,
+            this: Ref::dangling(),
+        }));
+        graph.remove(refs[3].clone());
+        graph
+            .iter_mut_with_refs()
+            .for_each(|(node, value)| value.this = node);
+        for (i, node) in refs.iter().enumerate().filter(|(i, _)| *i != 3) {
+            let this = &graph.borrow(node).this;
+            assert!(this == node);
+            assert_eq!(graph.borrow(this).value, i);
+        }
+    }
+
+    #[test]
+    fn create_or_update() {
+        let mut graph = Graph::new();
+        let node = graph.promise();
+        assert_eq!(graph.create_or_update(&node, 1), None);
+        assert_eq!(graph.create_or_update(&node, 2), Some(1));
+        assert_eq!(*graph.borrow(&node), 2);
+    }
+
+    #[test]
+    fn memory_usage() {
+        let mut graph = Graph::new();
+        let empty = graph.memory_usage();
+        graph.insert_batch(0..100u64);
+        assert!(graph.memory_usage() > empty);
+        assert!(graph.memory_usage() >= 100 * std::mem::size_of::<u64>());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize_stable() {
+        use super::StableGraph;
+
+        let mut graph = Graph::new();
+        let a = graph.insert("a".to_string());
+        let b = graph.insert("b".to_string());
+        let c = graph.insert("c".to_string());
+        assert_eq!(graph.slot_refs().collect::<Vec<_>>(), vec![a, b.clone(), c]);
+
+        graph.remove(b);
+        let json = serde_json::to_string(&graph.serialize_stable()).unwrap();
+        assert_eq!(json, r#"["a",null,"c"]"#);
+
+        let StableGraph { mut graph, refs } =
+            serde_json::from_str::<StableGraph<String>>(&json).unwrap();
+        assert_eq!(graph.borrow(&refs[0]), "a");
+        assert_eq!(graph.try_remove(&refs[1]), None);
+        assert_eq!(graph.borrow(&refs[2]), "c");
+        assert_eq!(graph.slot_refs().collect::<Vec<_>>(), refs);
+        assert_eq!(
+            serde_json::to_string(&graph.serialize_stable()).unwrap(),
+            json
+        );
+    }
+
+    #[test]
+    fn trait_objects() {
+        trait Node {
+            fn weight(&self) -> u32;
+            fn next(&self) -> Option<&Ref<Box<dyn Node>>>;
+            fn grow(&mut self);
+        }
+
+        struct Leaf(u32);
+
+        struct Link(u32, Ref<Box<dyn Node>>);
+
+        impl Node for Leaf {
+            fn weight(&self) -> u32 {
+                self.0
+            }
+            fn next(&self) -> Option<&Ref<Box<dyn Node>>> {
+                None
+            }
+            fn grow(&mut self) {
+                self.0 += 1;
+            }
+        }
+
+        impl Node for Link {
+            fn weight(&self) -> u32 {
+                self.0
+            }
+            fn next(&self) -> Option<&Ref<Box<dyn Node>>> {
+                Some(&self.1)
+            }
+            fn grow(&mut self) {
+                self.0 *= 2;
+            }
+        }
+
+        let mut graph = Graph::<Box<dyn Node>>::new();
+        let leaf = graph.insert(Box::new(Leaf(1)));
+        let middle = graph.insert(Box::new(Link(2, leaf.clone())));
+        let head = graph.insert(Box::new(Link(3, middle.clone())));
+
+        let total = |graph: &Graph<Box<dyn Node>>| {
+            let mut node = Some(&head);
+            let mut total = 0;
+            while let Some(r) = node {
+                let value: &dyn Node = graph.borrow_dyn(r);
+                total += value.weight();
+                node = value.next();
+            }
+            total
+        };
+        assert_eq!(total(&graph), 6);
+
+        graph.borrow_dyn_mut(&leaf).grow();
+        let [a, b] = graph.borrow_many_mut([&head, &middle]);
+        a.grow();
+        b.grow();
+        assert_eq!(total(&graph), 2 + 4 + 6);
+
+        graph.iter_dyn_mut().for_each(|node| node.grow());
+        assert_eq!(total(&graph), 3 + 8 + 12);
+    }
 }
@@ -0,0 +1,426 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    io::{self, Write},
+};
+
+use crate::BTreeGraph;
+
+/// A GraphML attribute value.
+#[derive(Clone, PartialEq, Debug)]
+pub enum AttrValue {
+    String(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+/// A list of named attribute values for a node or an edge.
+pub type Attributes = Vec<(String, AttrValue)>;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum AttrType {
+    String,
+    Long,
+    Double,
+    Boolean,
+}
+
+/// Export a graph as a GraphML document. See `write` for a
+/// description of the arguments.
+pub fn export<K, V, E, N, A>(
+    graph: &BTreeGraph<K, V>,
+    edges: E,
+    node_attrs: N,
+    edge_attrs: A,
+) -> String
+where
+    K: Ord + Display,
+    E: FnMut(&V) -> Vec<K>,
+    N: FnMut(&K, &V) -> Attributes,
+    A: FnMut(&K, &K) -> Attributes,
+{
+    let mut out = Vec::new();
+    write(&mut out, graph, edges, node_attrs, edge_attrs).expect("writing to a vec cannot fail");
+    String::from_utf8(out).expect("GraphML output is valid UTF-8")
+}
+
+/// Write a graph as a GraphML document. Node ids are taken from the
+/// graph's keys. For each node, `edges` returns the keys of the
+/// targets of its outgoing edges, `node_attrs` returns the node's
+/// attributes and `edge_attrs` returns the attributes for an edge
+/// given its source and target keys. Edges to keys that are not in
+/// the graph are skipped.
+///
+/// The closures are called once for every node or edge. If an
+/// attribute name occurs more than once for the same node or edge,
+/// the last value is written. The type of an attribute is derived
+/// from its values; attributes with mixed types are declared as
+/// `string`, except for a mix of integers and floats, which is
+/// declared as `double`.
+pub fn write<W, K, V, E, N, A>(
+    mut writer: W,
+    graph: &BTreeGraph<K, V>,
+    mut edges: E,
+    mut node_attrs: N,
+    mut edge_attrs: A,
+) -> io::Result<()>
+where
+    W: Write,
+    K: Ord + Display,
+    E: FnMut(&V) -> Vec<K>,
+    N: FnMut(&K, &V) -> Attributes,
+    A: FnMut(&K, &K) -> Attributes,
+{
+    let mut node_keys = BTreeMap::new();
+    let mut edge_keys = BTreeMap::new();
+    let mut nodes = Vec::new();
+    let mut links = Vec::new();
+
+    for (key, value) in graph.iter() {
+        let attrs = dedup(node_attrs(key, value));
+        declare(&mut node_keys, &attrs);
+        nodes.push((key, attrs));
+        for target in edges(value) {
+            if graph.get(&target).is_none() {
+                continue;
+            }
+            let attrs = dedup(edge_attrs(key, &target));
+            declare(&mut edge_keys, &attrs);
+            links.push((key, target, attrs));
+        }
+    }
+
+    let node_ids = ids("n", &node_keys);
+    let edge_ids = ids("e", &edge_keys);
+
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        writer,
+        concat!(
+            r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns""#,
+            r#" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance""#,
+            r#" xsi:schemaLocation="http://graphml.graphdrawing.org/xmlns"#,
+            r#" http://graphml.graphdrawing.org/xmlns/1.0/graphml.xsd">"#
+        )
+    )?;
+    write_keys(&mut writer, "node", &node_keys, &node_ids)?;
+    write_keys(&mut writer, "edge", &edge_keys, &edge_ids)?;
+    writeln!(writer, r#"  <graph id="G" edgedefault="directed">"#)?;
+
+    for (key, attrs) in nodes {
+        let id = escape(&key.to_string());
+        if attrs.is_empty() {
+            writeln!(writer, r#"    <node id="{id}"/>"#)?;
+        } else {
+            writeln!(writer, r#"    <node id="{id}">"#)?;
+            write_data(&mut writer, &attrs, &node_ids)?;
+            writeln!(writer, "    </node>")?;
+        }
+    }
+
+    for (source, target, attrs) in links {
+        let source = escape(&source.to_string());
+        let target = escape(&target.to_string());
+        if attrs.is_empty() {
+            writeln!(writer, r#"    <edge source="{source}" target="{target}"/>"#)?;
+        } else {
+            writeln!(writer, r#"    <edge source="{source}" target="{target}">"#)?;
+            write_data(&mut writer, &attrs, &edge_ids)?;
+            writeln!(writer, "    </edge>")?;
+        }
+    }
+
+    writeln!(writer, "  </graph>")?;
+    writeln!(writer, "</graphml>")
+}
+
+/// Keep only the last value for every attribute name, at the
+/// position of its first occurrence.
+fn dedup(attrs: Attributes) -> Attributes {
+    let mut out: Attributes = Vec::with_capacity(attrs.len());
+    attrs.into_iter().for_each(|(name, value)| {
+        match out.iter_mut().find(|(other, _)| *other == name) {
+            Some((_, prev)) => *prev = value,
+            None => out.push((name, value)),
+        }
+    });
+    out
+}
+
+fn declare(keys: &mut BTreeMap<String, AttrType>, attrs: &Attributes) {
+    attrs.iter().for_each(|(name, value)| {
+        let typ = value.attr_type();
+        keys.entry(name.clone())
+            .and_modify(|t| *t = t.merge(typ))
+            .or_insert(typ);
+    })
+}
+
+fn ids(prefix: &str, keys: &BTreeMap<String, AttrType>) -> BTreeMap<String, String> {
+    keys.keys()
+        .enumerate()
+        .map(|(i, name)| (name.clone(), format!("{prefix}{i}")))
+        .collect()
+}
+
+fn write_keys<W: Write>(
+    writer: &mut W,
+    domain: &str,
+    keys: &BTreeMap<String, AttrType>,
+    ids: &BTreeMap<String, String>,
+) -> io::Result<()> {
+    keys.iter().try_for_each(|(name, typ)| {
+        writeln!(
+            writer,
+            r#"  <key id="{}" for="{domain}" attr.name="{}" attr.type="{}"/>"#,
+            ids[name],
+            escape(name),
+            typ.name()
+        )
+    })
+}
+
+fn write_data<W: Write>(
+    writer: &mut W,
+    attrs: &Attributes,
+    ids: &BTreeMap<String, String>,
+) -> io::Result<()> {
+    attrs.iter().try_for_each(|(name, value)| {
+        writeln!(
+            writer,
+            r#"      <data key="{}">{}</data>"#,
+            ids[name],
+            escape(&value.to_string())
+        )
+    })
+}
+
+/// Escape text for use in XML attribute values and character data.
+/// Characters that cannot be represented in XML 1.0 are replaced by
+/// U+FFFD.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    text.chars().for_each(|c| match c {
+        '&' => out.push_str("&amp;"),
+        '<' => out.push_str("&lt;"),
+        '>' => out.push_str("&gt;"),
+        '"' => out.push_str("&quot;"),
+        '\'' => out.push_str("&apos;"),
+        '\t' => out.push_str("&#9;"),
+        '\n' => out.push_str("&#10;"),
+        '\r' => out.push_str("&#13;"),
+        '\u{0}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => out.push('\u{fffd}'),
+        c => out.push(c),
+    });
+    out
+}
+
+impl AttrValue {
+    fn attr_type(&self) -> AttrType {
+        match self {
+            Self::String(_) => AttrType::String,
+            Self::Int(_) => AttrType::Long,
+            Self::Float(_) => AttrType::Double,
+            Self::Bool(_) => AttrType::Boolean,
+        }
+    }
+}
+
+impl Display for AttrValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::String(v) => write!(f, "{v}"),
+            Self::Int(v) => write!(f, "{v}"),
+            Self::Float(v) if v.is_nan() => write!(f, "NaN"),
+            Self::Float(v) if v.is_infinite() && *v > 0.0 => write!(f, "INF"),
+            Self::Float(v) if v.is_infinite() => write!(f, "-INF"),
+            Self::Float(v) => write!(f, "{v}"),
+            Self::Bool(v) => write!(f, "{v}"),
+        }
+    }
+}
+
+impl From<String> for AttrValue {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<&str> for AttrValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+impl From<i64> for AttrValue {
+    fn from(value: i64) -> Self {
+        Self::Int(value)
+    }
+}
+
+impl From<i32> for AttrValue {
+    fn from(value: i32) -> Self {
+        Self::Int(value.into())
+    }
+}
+
+impl From<u32> for AttrValue {
+    fn from(value: u32) -> Self {
+        Self::Int(value.into())
+    }
+}
+
+impl From<f64> for AttrValue {
+    fn from(value: f64) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl From<bool> for AttrValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl AttrType {
+    fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (Self::Long, Self::Double) | (Self::Double, Self::Long) => Self::Double,
+            _ => Self::String,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::String => "string",
+            Self::Long => "long",
+            Self::Double => "double",
+            Self::Boolean => "boolean",
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{export, AttrValue};
+    use crate::BTreeGraph;
+
+    #[test]
+    fn golden() {
+        let graph = [
+            ("a", (vec!["b"], 1.5)),
+            ("b", (vec!["a", "c & <d>"], 2.0)),
+            ("c & <d>", (vec![], f64::INFINITY)),
+        ]
+        .into_iter()
+        .collect::<BTreeGraph<_, _>>();
+
+        let output = export(
+            &graph,
+            |(targets, _)| targets.clone(),
+            |key, (_, weight)| {
+                let mut attrs = vec![("weight".to_string(), AttrValue::from(*weight))];
+                if *key == "a" {
+                    attrs.push(("label".to_string(), "\"first\"\n".into()));
+                    attrs.push(("root".to_string(), true.into()));
+                }
+                attrs
+            },
+            |source, target| vec![("back".to_string(), (target < source).into())],
+        );
+
+        assert_eq!(
+            output,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<graphml xmlns="http://graphml.graphdrawing.org/xmlns" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:schemaLocation="http://graphml.graphdrawing.org/xmlns http://graphml.graphdrawing.org/xmlns/1.0/graphml.xsd">
+  <key id="n0" for="node" attr.name="label" attr.type="string"/>
+  <key id="n1" for="node" attr.name="root" attr.type="boolean"/>
+  <key id="n2" for="node" attr.name="weight" attr.type="double"/>
+  <key id="e0" for="edge" attr.name="back" attr.type="boolean"/>
+  <graph id="G" edgedefault="directed">
+    <node id="a">
+      <data key="n2">1.5</data>
+      <data key="n0">&quot;first&quot;&#10;</data>
+      <data key="n1">true</data>
+    </node>
+    <node id="b">
+      <data key="n2">2</data>
+    </node>
+    <node id="c &amp; &lt;d&gt;">
+      <data key="n2">INF</data>
+    </node>
+    <edge source="a" target="b">
+      <data key="e0">false</data>
+    </edge>
+    <edge source="b" target="a">
+      <data key="e0">true</data>
+    </edge>
+    <edge source="b" target="c &amp; &lt;d&gt;">
+      <data key="e0">false</data>
+    </edge>
+  </graph>
+</graphml>
+"#
+        );
+    }
+
+    #[test]
+    fn single_pass() {
+        let graph = [(1, vec![2, 3]), (2, vec![])]
+            .into_iter()
+            .collect::<BTreeGraph<_, _>>();
+
+        let mut calls = 0;
+        let output = export(
+            &graph,
+            |targets| targets.clone(),
+            |key, _| {
+                calls += 1;
+                if calls > 2 {
+                    panic!("node attributes requested twice");
+                }
+                let first = vec![("x".to_string(), AttrValue::from(*key))];
+                match key {
+                    1 => vec![("x".to_string(), "one".into()), first[0].clone()],
+                    _ => first,
+                }
+            },
+            |_, _| Vec::new(),
+        );
+
+        assert_eq!(
+            output,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<graphml xmlns="http://graphml.graphdrawing.org/xmlns" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:schemaLocation="http://graphml.graphdrawing.org/xmlns http://graphml.graphdrawing.org/xmlns/1.0/graphml.xsd">
+  <key id="n0" for="node" attr.name="x" attr.type="long"/>
+  <graph id="G" edgedefault="directed">
+    <node id="1">
+      <data key="n0">1</data>
+    </node>
+    <node id="2">
+      <data key="n0">2</data>
+    </node>
+    <edge source="1" target="2"/>
+  </graph>
+</graphml>
+"#
+        );
+    }
+}
@@ -19,37 +19,64 @@ use std::{
         HashMap,
     },
     hash::{BuildHasher, Hash},
+    iter::FusedIterator,
 };
 #[cfg(feature = "serde")]
 use std::{fmt::Formatter, marker::PhantomData};
 
 #[cfg(feature = "serde")]
 use serde::{
-    de::{Deserializer, MapAccess, Visitor},
+    de::{DeserializeSeed, Deserializer, MapAccess, Visitor},
     ser::{SerializeMap, Serializer},
     Deserialize, Serialize,
 };
 #[cfg(feature = "tsify")]
 use tsify::Tsify;
 
+use crate::borrow_check::{index_entries, BorrowScope};
+use crate::btree_graph::{fragmented, AutoCompact};
+#[cfg(feature = "serde")]
+use crate::graph::GraphDeserializeOptions;
+use crate::observer::{replace_observed, Observer};
 use crate::reference::Ref;
-use crate::{graph::Graph, RefBy};
+use crate::{
+    graph::{BorrowManyError, CreateError, Graph},
+    BTreeGraph, GraphMut, GraphObserver, KeysView, ProjectedRef, RefBy, RefByRef, RefMap, Resolve,
+    VisitRefs, VisitRefsBy,
+};
 
 /// A graph structure that allows pointer-based references between
 /// nodes.
 #[cfg_attr(feature = "tsify", derive(Tsify))]
 #[cfg_attr(
     feature = "tsify",
-    tsify(from_wasm_abi, into_wasm_abi, type = "{ [key: K]: V }")
+    tsify(
+        from_wasm_abi,
+        into_wasm_abi,
+        type = "K extends string | number ? Record<K, V> : [K, V][]"
+    )
 )]
 pub struct HashGraph<K, V, S = RandomState> {
     graph: Graph<V>,
     index: HashMap<K, Ref<V>, S>,
+    observer: Observer<K, V>,
+    auto_compact: AutoCompact<HashGraph<K, V, S>>,
 }
 
 pub struct Entry<'a, K, V> {
     graph: &'a mut Graph<V>,
     entry: hash_map::Entry<'a, K, Ref<V>>,
+    observer: &'a mut Observer<K, V>,
+}
+
+/// An entry looked up by a borrowed key, which is only converted to an
+/// owned key if the entry is vacant and a value is inserted.
+pub struct EntryRef<'a, 'q, K, V, Q: ?Sized, S = RandomState> {
+    graph: &'a mut Graph<V>,
+    index: &'a mut HashMap<K, Ref<V>, S>,
+    key: &'q Q,
+    node: Option<Ref<V>>,
+    observer: &'a mut Observer<K, V>,
 }
 
 impl<K, V> HashGraph<K, V, RandomState> {
@@ -58,6 +85,8 @@ impl<K, V> HashGraph<K, V, RandomState> {
         Self {
             graph: Graph::new(),
             index: HashMap::new(),
+            observer: None,
+            auto_compact: None,
         }
     }
 
@@ -65,15 +94,102 @@ impl<K, V> HashGraph<K, V, RandomState> {
         Self {
             graph: Graph::with_capacity(n),
             index: HashMap::with_capacity(n),
+            observer: None,
+            auto_compact: None,
         }
     }
 }
 
 impl<K, V, S> HashGraph<K, V, S> {
+    /// Decompose the graph into its node arena and key index.
+    pub fn into_parts(self) -> (Graph<V>, HashMap<K, Ref<V>, S>) {
+        (self.graph, self.index)
+    }
+
+    /// Discard the key index and return the node arena. References
+    /// to the nodes remain valid for the returned graph.
+    pub fn into_graph(self) -> Graph<V> {
+        self.graph
+    }
+
+    /// Reassemble a graph from parts obtained through `into_parts`
+    /// (or built by hand).
+    ///
+    /// # Safety
+    ///
+    /// Every reference in the index must belong to `graph` (as checked
+    /// by `Graph::owns`) and no two keys may refer to the same node.
+    pub unsafe fn from_parts(graph: Graph<V>, index: HashMap<K, Ref<V>, S>) -> Self {
+        Self {
+            graph,
+            index,
+            observer: None,
+            auto_compact: None,
+        }
+    }
+
+    /// Convert the graph into a `BTreeGraph` by rebuilding only the
+    /// index. The nodes stay in place, so all references to them
+    /// remain valid. The observer and auto-compaction setting, if
+    /// any, are dropped.
+    pub fn into_btree_graph(self) -> BTreeGraph<K, V>
+    where
+        K: Ord,
+    {
+        let (graph, index) = self.into_parts();
+        unsafe { BTreeGraph::from_parts(graph, index.into_iter().collect()) }
+    }
+
+    /// Transform the graph's keys by rebuilding only the index, with
+    /// the same hasher. The nodes stay in place, so all references
+    /// to them remain valid. If `f` maps multiple keys to the same
+    /// key, an arbitrary one of their nodes is indexed and the others
+    /// are removed. Use `try_map_keys` to detect this instead. The
+    /// observer and auto-compaction setting, if any, are dropped.
+    /// See `BTreeGraph::map_keys` for re-keying references.
+    pub fn map_keys<K2, F>(self, mut f: F) -> HashGraph<K2, V, S>
+    where
+        K2: Hash + Eq,
+        S: BuildHasher + Clone,
+        F: FnMut(K) -> K2,
+    {
+        let mut index =
+            HashMap::with_capacity_and_hasher(self.index.len(), self.index.hasher().clone());
+        for (key, node) in self.index {
+            if let Some(shadowed) = index.insert(f(key), node) {
+                unsafe { shadowed.discard_unchecked() };
+            }
+        }
+        unsafe { HashGraph::from_parts(self.graph, index) }
+    }
+
+    /// Transform the graph's keys, like `map_keys`. Fails with the
+    /// first transformed key that occurs more than once.
+    pub fn try_map_keys<K2, F>(self, mut f: F) -> Result<HashGraph<K2, V, S>, K2>
+    where
+        K2: Hash + Eq,
+        S: BuildHasher + Clone,
+        F: FnMut(K) -> K2,
+    {
+        let mut index =
+            HashMap::with_capacity_and_hasher(self.index.len(), self.index.hasher().clone());
+        for (key, node) in self.index {
+            match index.entry(f(key)) {
+                hash_map::Entry::Vacant(ent) => {
+                    ent.insert(node);
+                }
+                hash_map::Entry::Occupied(ent) => return Err(ent.remove_entry().0),
+            }
+        }
+        Ok(unsafe { HashGraph::from_parts(self.graph, index) })
+    }
+
     pub fn with_hasher(hasher: S) -> Self {
         Self {
             graph: Graph::new(),
             index: HashMap::with_hasher(hasher),
+            observer: None,
+            auto_compact: None,
         }
     }
 
@@ -81,43 +197,124 @@ impl<K, V, S> HashGraph<K, V, S> {
         Self {
             graph: Graph::with_capacity(capacity),
             index: HashMap::with_capacity_and_hasher(capacity, hasher),
+            observer: None,
+            auto_compact: None,
         }
     }
 
+    /// Prepare the arena and the index for (at least) `additional`
+    /// more nodes.
+    pub fn reserve(&mut self, additional: usize)
+    where
+        K: Hash + Eq,
+        S: BuildHasher,
+    {
+        self.graph.reserve(additional);
+        self.index.reserve(additional);
+    }
+
     pub fn index(&self) -> &HashMap<K, Ref<V>, S> {
         &self.index
     }
 
+    /// Notify `observer` of every subsequent insertion, replacement
+    /// and removal of a node. See `BTreeGraph::set_observer`.
+    pub fn set_observer<O>(&mut self, observer: O)
+    where
+        O: GraphObserver<K, V> + Send + 'static,
+    {
+        self.observer = Some(Box::new(observer));
+    }
+
+    /// Remove the observer, returning it.
+    pub fn take_observer(&mut self) -> Option<Box<dyn GraphObserver<K, V> + Send>> {
+        self.observer.take()
+    }
+
     /// Insert a node into the graph. The returned NodePtr can be used
-    /// to reference this node.
+    /// to reference this node. If the key was promised or already
+    /// exists, the value is stored in the key's slot, so that existing
+    /// references to the node remain valid (and see the new value)
+    /// and no slot is left behind.
     pub fn insert(&mut self, key: K, value: V) -> Ref<V>
     where
         K: Hash + Eq,
         S: BuildHasher,
     {
+        self.upsert(key, value).0
+    }
+
+    /// Like `insert`, but also returns the value of the node that
+    /// was displaced, if the key existed and was not merely promised.
+    pub fn upsert(&mut self, key: K, value: V) -> (Ref<V>, Option<V>)
+    where
+        K: Hash + Eq,
+        S: BuildHasher,
+    {
+        if let Some(node) = self.index.get(&key).cloned() {
+            let old = replace_observed(&mut self.observer, &key, &node, value);
+            return (node, old);
+        }
         let node = self.graph.insert(value);
-        if let Some(old_node) = self.index.insert(key, node.clone()) {
-            unsafe {
-                old_node.try_remove_unchecked().unwrap();
-            }
+        if let Some(observer) = &mut self.observer {
+            observer.on_insert(&key, unsafe { node.get_unchecked() });
         }
-        node
+        self.index.insert(key, node.clone());
+        (node, None)
     }
 
+    /// Reserve an empty slot for the key, to be filled by `insert`
+    /// or `create`. If the key was already promised, the existing
+    /// slot is returned. Otherwise, an existing node with the same
+    /// key is removed, leaving any references to it dangling.
     pub fn promise(&mut self, key: K) -> Ref<V>
     where
         K: Hash + Eq,
         S: BuildHasher,
     {
+        if let Some(node) = self.promised(&key) {
+            return node;
+        }
         let node = self.graph.promise();
-        if let Some(old_node) = self.index.insert(key, node.clone()) {
-            unsafe {
-                old_node.try_remove_unchecked().unwrap();
-            }
+        if let Some(old_node) = self.index.get(&key) {
+            let old = unsafe { old_node.try_remove_unchecked() };
+            self.notify_remove(&key, old.as_ref());
         }
+        self.index.insert(key, node.clone());
         node
     }
 
+    fn promised(&self, key: &K) -> Option<Ref<V>>
+    where
+        K: Hash + Eq,
+        S: BuildHasher,
+    {
+        self.index
+            .get(key)
+            .filter(|node| unsafe { node.is_promised_unchecked() })
+            .cloned()
+    }
+
+    fn notify_remove(&mut self, key: &K, value: Option<&V>) {
+        if let Some(observer) = &mut self.observer {
+            observer.on_remove(key, value);
+        }
+    }
+
+    /// Notify the observer that the node was filled, looking up its
+    /// key. Nodes that are not in the index are not reported.
+    fn notify_create(&mut self, node: &Ref<V>, old: Option<&V>) {
+        if let Some(observer) = &mut self.observer {
+            if let Some((key, _)) = self.index.iter().find(|(_, other)| *other == node) {
+                let new = unsafe { node.get_unchecked() };
+                match old {
+                    Some(old) => observer.on_replace(key, old, new),
+                    None => observer.on_insert(key, new),
+                }
+            }
+        }
+    }
+
     /// Remove a node from the graph. You are responsible to make sure
     /// no pointers to the node will be dereferenced from this point
     /// on.
@@ -127,9 +324,169 @@ impl<K, V, S> HashGraph<K, V, S> {
         Q: Hash + Eq + ?Sized,
         S: BuildHasher,
     {
-        unsafe {
-            let node = self.index.remove(key)?;
-            Some(node.try_remove_unchecked().unwrap())
+        let (key, node) = self.index.remove_entry(key)?;
+        let value = unsafe { node.try_remove_unchecked().unwrap() };
+        self.notify_remove(&key, Some(&value));
+        self.maybe_compact();
+        Some(value)
+    }
+
+    /// Redirect every reference to `from` stored in the graph's
+    /// nodes to `to`, then remove `from`, returning its key and
+    /// value. This coalesces two nodes into one. Note that the keys
+    /// of redirected keyed references (e.g. `RefBy`) are left
+    /// unchanged; use `replace_ref_by` to update them as well. Panics
+    /// if either reference belongs to a different graph.
+    pub fn replace_ref(&mut self, from: &Ref<V>, to: &Ref<V>) -> Option<(K, V)>
+    where
+        K: Hash + Eq + Clone,
+        S: BuildHasher,
+        V: VisitRefs<V>,
+    {
+        self.replace_ref_with(from, to, |_, _, _| {})
+    }
+
+    /// Like `replace_ref`, but also replace the keys of redirected
+    /// keyed references by the key of `to`.
+    pub fn replace_ref_by(&mut self, from: &Ref<V>, to: &Ref<V>) -> Option<(K, V)>
+    where
+        K: Hash + Eq + Clone,
+        S: BuildHasher,
+        V: VisitRefs<V> + VisitRefsBy<K, V>,
+    {
+        self.replace_ref_with(from, to, |value, key, to_key| {
+            value.visit_refs_by_mut(&mut |k, r| (r == to && k == key).then(|| to_key.clone()))
+        })
+    }
+
+    /// Redirect the references to `from`, calling `rekey` with each
+    /// node value and the keys of `from` and `to`, and remove `from`.
+    fn replace_ref_with<F>(&mut self, from: &Ref<V>, to: &Ref<V>, mut rekey: F) -> Option<(K, V)>
+    where
+        K: Hash + Eq + Clone,
+        S: BuildHasher,
+        V: VisitRefs<V>,
+        F: FnMut(&mut V, &K, &K),
+    {
+        assert!(self.graph.owns(from) && self.graph.owns(to));
+        if from == to {
+            return None;
+        }
+        let (mut key, mut to_key) = (None, None);
+        for (k, node) in &self.index {
+            if node == from {
+                key = Some(k.clone());
+            } else if node == to {
+                to_key = Some(k.clone());
+            }
+        }
+        for node in self.index.values() {
+            if let Some(value) = unsafe { node.try_get_unchecked_mut() } {
+                value.visit_refs_mut(&mut |r| {
+                    if r == from {
+                        *r = to.clone();
+                    }
+                });
+                if let (Some(key), Some(to_key)) = (&key, &to_key) {
+                    rekey(value, key, to_key);
+                }
+            }
+        }
+        let (key, node) = self.index.remove_entry(&key?)?;
+        let value = unsafe { node.try_remove_unchecked() };
+        self.notify_remove(&key, value.as_ref());
+        self.maybe_compact();
+        value.map(|value| (key, value))
+    }
+
+    /// Remove the nodes for which the predicate returns false, giving
+    /// it mutable access to the values. Promised nodes are kept. As
+    /// with `remove`, any remaining references to the removed nodes
+    /// will be dangling.
+    pub fn retain_mut<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        let observer = &mut self.observer;
+        self.index.retain(|key, node| {
+            let keep = unsafe { node.try_get_unchecked_mut() }.is_none_or(|value| f(key, value));
+            if !keep {
+                let value = unsafe { node.try_remove_unchecked() };
+                if let Some(observer) = observer {
+                    observer.on_remove(key, value.as_ref());
+                }
+            }
+            keep
+        });
+        self.maybe_compact();
+    }
+
+    /// Build a new graph from the values for which `f` returns
+    /// `Some`, under the same keys. Promised nodes are dropped, as
+    /// are the observer and auto-compaction setting. See
+    /// `BTreeGraph::filter_map_values` for values holding references.
+    pub fn filter_map_values<U, F>(self, mut f: F) -> HashGraph<K, U, S>
+    where
+        K: Hash + Eq,
+        S: BuildHasher + Clone,
+        F: FnMut(&V) -> Option<U>,
+    {
+        let mut graph = HashGraph::with_hasher(self.index.hasher().clone());
+        for (key, node) in self.index {
+            if let Some(value) = unsafe { node.try_get_unchecked() }.and_then(&mut f) {
+                graph.insert(key, value);
+            }
+        }
+        graph
+    }
+
+    /// Remove the nodes with the given keys from the graph, returning
+    /// the removed keys and values in the order of `keys`. Keys that
+    /// are not present, or repeated, are skipped. Promised nodes are
+    /// removed as well, but have no value to return. As with `remove`,
+    /// any remaining references to the removed nodes will be dangling.
+    pub fn remove_many<Q, I>(&mut self, keys: I) -> Vec<(K, V)>
+    where
+        I: IntoIterator<Item = Q>,
+        K: Borrow<Q> + Hash + Eq,
+        Q: Hash + Eq,
+        S: BuildHasher,
+    {
+        let removed = keys
+            .into_iter()
+            .filter_map(|key| {
+                let (key, node) = self.index.remove_entry(&key)?;
+                let value = unsafe { node.try_remove_unchecked() };
+                self.notify_remove(&key, value.as_ref());
+                Some((key, value?))
+            })
+            .collect();
+        self.maybe_compact();
+        removed
+    }
+
+    /// Remove all nodes from the graph, keeping the allocated
+    /// capacity. References to the old nodes become invalid.
+    pub fn clear(&mut self) {
+        self.graph.clear();
+        self.index.clear();
+        if let Some(observer) = &mut self.observer {
+            observer.on_clear();
+        }
+    }
+
+    /// Remove all nodes from the graph and release the allocated
+    /// memory. References to the old nodes become invalid.
+    pub fn clear_and_shrink(&mut self)
+    where
+        K: Hash + Eq,
+        S: BuildHasher,
+    {
+        self.graph = Graph::new();
+        self.index.clear();
+        self.index.shrink_to_fit();
+        if let Some(observer) = &mut self.observer {
+            observer.on_clear();
         }
     }
 
@@ -137,6 +494,104 @@ impl<K, V, S> HashGraph<K, V, S> {
         self.index.is_empty()
     }
 
+    /// Estimate the memory used by the graph, in bytes. In addition to
+    /// the arena (see `Graph::memory_usage`), this counts the index's
+    /// allocated buckets and their control bytes, but not any heap
+    /// memory owned by the keys.
+    pub fn memory_usage(&self) -> usize {
+        self.graph.memory_usage() + self.index.capacity() * (std::mem::size_of::<(K, Ref<V>)>() + 1)
+    }
+
+    /// Rebuild the graph's arena with only the live and promised
+    /// nodes, returning the number of reclaimed slots. The graph
+    /// receives a new generation, and the references inside the
+    /// nodes are re-resolved against the index. References held
+    /// outside the graph must be re-resolved as well; stale ones fail
+    /// validation, as do references to keys no longer in the graph.
+    ///
+    /// The references inside the nodes are resolved before anything
+    /// is moved: if some cannot be resolved (e.g. because their key
+    /// was removed), their keys are returned and the arena is left
+    /// as is, since they would otherwise point into freed memory.
+    pub fn compact(&mut self) -> Result<usize, Vec<K>>
+    where
+        V: Resolve<K, V>,
+        K: Hash + Eq,
+        S: BuildHasher,
+    {
+        self.resolve_nodes()?;
+        let old = std::mem::replace(&mut self.graph, Graph::with_capacity(self.index.len()));
+        for node in self.index.values_mut() {
+            *node = match unsafe { node.try_remove_unchecked() } {
+                Some(value) => self.graph.insert(value),
+                None => self.graph.promise(),
+            };
+        }
+        match self.resolve_nodes() {
+            Ok(()) => Ok(old.slot_count() - self.index.len()),
+            Err(missing) => {
+                /* Only possible if resolution is not deterministic:
+                 * keep the old nodes allocated. */
+                std::mem::forget(old);
+                Err(missing)
+            }
+        }
+    }
+
+    /// Resolve the references in all nodes against the index,
+    /// returning the keys that could not be resolved.
+    fn resolve_nodes(&mut self) -> Result<(), Vec<K>>
+    where
+        V: Resolve<K, V>,
+        K: Hash + Eq,
+        S: BuildHasher,
+    {
+        let missing = self
+            .index
+            .values()
+            .filter_map(|node| {
+                unsafe { node.try_get_unchecked_mut() }?
+                    .resolve(&self.index)
+                    .err()
+            })
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(missing)
+        }
+    }
+
+    /// Automatically `compact` the graph after a removal. See
+    /// `BTreeGraph::set_auto_compact`.
+    pub fn set_auto_compact(&mut self, threshold: f32)
+    where
+        V: Resolve<K, V>,
+        K: Hash + Eq,
+        S: BuildHasher,
+    {
+        self.auto_compact = Some((threshold, |graph| {
+            if let Err(missing) = graph.compact() {
+                if let Some(observer) = &mut graph.observer {
+                    observer.on_compact_failed(&missing);
+                }
+            }
+        }));
+        self.maybe_compact();
+    }
+
+    pub fn disable_auto_compact(&mut self) {
+        self.auto_compact = None;
+    }
+
+    fn maybe_compact(&mut self) {
+        if let Some((threshold, compact)) = self.auto_compact {
+            if fragmented(self.graph.slot_count(), self.index.len(), threshold) {
+                compact(self);
+            }
+        }
+    }
+
     pub fn get_ref<Q>(&self, key: &Q) -> Option<&Ref<V>>
     where
         K: Borrow<Q> + Hash + Eq,
@@ -171,7 +626,7 @@ impl<K, V, S> HashGraph<K, V, S> {
         Q: Hash + Eq + ?Sized,
         S: BuildHasher,
     {
-        unsafe { Some(self.get_ref(key)?.try_get_unchecked().unwrap()) }
+        unsafe { Some(self.get_ref(key)?.get_unchecked()) }
     }
 
     pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
@@ -181,7 +636,18 @@ impl<K, V, S> HashGraph<K, V, S> {
         S: BuildHasher,
     {
         let (key, value) = self.get_entry(key)?;
-        unsafe { Some((key, value.try_get_unchecked().unwrap())) }
+        unsafe { Some((key, value.get_unchecked())) }
+    }
+
+    /// Get the key, reference and value for a key in a single lookup.
+    pub fn get_full<Q>(&self, key: &Q) -> Option<(&K, &Ref<V>, &V)>
+    where
+        K: Borrow<Q> + Hash + Eq,
+        Q: Hash + Eq + ?Sized,
+        S: BuildHasher,
+    {
+        let (key, node) = self.get_entry(key)?;
+        unsafe { Some((key, node, node.get_unchecked())) }
     }
 
     pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
@@ -190,7 +656,7 @@ impl<K, V, S> HashGraph<K, V, S> {
         Q: Hash + Eq + ?Sized,
         S: BuildHasher,
     {
-        unsafe { Some(self.get_ref(key)?.try_get_unchecked_mut().unwrap()) }
+        unsafe { Some(self.get_ref(key)?.get_unchecked_mut()) }
     }
 
     pub fn get_key_value_mut<Q>(&mut self, key: &Q) -> Option<(&K, &mut V)>
@@ -200,11 +666,39 @@ impl<K, V, S> HashGraph<K, V, S> {
         S: BuildHasher,
     {
         let (key, value) = self.get_entry(key)?;
-        unsafe { Some((key, value.try_get_unchecked_mut().unwrap())) }
+        unsafe { Some((key, value.get_unchecked_mut())) }
     }
 
     pub fn create(&mut self, node: &Ref<V>, value: V) {
-        self.graph.create(node, value)
+        self.graph.create(node, value);
+        self.notify_create(node, None);
+    }
+
+    /// Set the value of a promised, removed or existing node,
+    /// returning the previous value. See `Graph::create_or_update`.
+    pub fn create_or_update(&mut self, node: &Ref<V>, value: V) -> Option<V> {
+        let old = self.graph.create_or_update(node, value);
+        self.notify_create(node, old.as_ref());
+        old
+    }
+
+    /// Create the promised (or removed) node for `key`. Unlike
+    /// [`create`](Self::create), this never panics: an unknown key or
+    /// an already filled node hands the value back in the error.
+    pub fn try_create<Q>(&mut self, key: &Q, value: V) -> Result<(), CreateError<V>>
+    where
+        K: Borrow<Q> + Hash + Eq,
+        Q: Hash + Eq + ?Sized,
+        S: BuildHasher,
+    {
+        let Some((key, node)) = self.index.get_key_value(key) else {
+            return Err(CreateError::UnknownKey { value });
+        };
+        self.graph.try_create(node, value)?;
+        if let Some(observer) = &mut self.observer {
+            observer.on_insert(key, unsafe { node.get_unchecked() });
+        }
+        Ok(())
     }
 
     /// Borrow the value from the graph. Panics if you try to borrow
@@ -227,6 +721,27 @@ impl<K, V, S> HashGraph<K, V, S> {
         self.graph.borrow_mut(node)
     }
 
+    /// Borrow the projected part of a node from the graph. Panics
+    /// under the same conditions as `borrow`.
+    pub fn borrow_projected<U: ?Sized, F, G>(&self, node: &ProjectedRef<V, U, F, G>) -> &U
+    where
+        F: Fn(&V) -> &U,
+    {
+        self.graph.borrow_projected(node)
+    }
+
+    /// Mutably borrow the projected part of a node from the
+    /// graph. Panics under the same conditions as `borrow_mut`.
+    pub fn borrow_projected_mut<U: ?Sized, F, G>(
+        &mut self,
+        node: &ProjectedRef<V, U, F, G>,
+    ) -> &mut U
+    where
+        G: Fn(&mut V) -> &mut U,
+    {
+        self.graph.borrow_projected_mut(node)
+    }
+
     /// Get mutable references to multiple nodes in the graph. This
     /// may be necessary to create cycles.
     pub fn borrow_many_mut<const N: usize, R>(&mut self, nodes: [R; N]) -> [&mut V; N]
@@ -237,11 +752,41 @@ impl<K, V, S> HashGraph<K, V, S> {
         self.graph.borrow_many_mut(nodes)
     }
 
-    pub fn iter_ref(&self) -> impl Iterator<Item = (&K, &Ref<V>)> {
+    /// Get mutable references to multiple nodes in the graph, with
+    /// checks that are never compiled out. See
+    /// [`Graph::try_borrow_many_mut`].
+    pub fn try_borrow_many_mut<const N: usize, R>(
+        &mut self,
+        nodes: [R; N],
+    ) -> Result<[&mut V; N], BorrowManyError>
+    where
+        R: AsRef<Ref<V>>,
+    {
+        self.graph.try_borrow_many_mut(nodes)
+    }
+
+    pub fn iter_ref(&self) -> impl ExactSizeIterator<Item = (&K, &Ref<V>)> + FusedIterator {
         self.index.iter()
     }
 
-    pub fn iter_ref_by(&self) -> impl Iterator<Item = RefBy<K, V>> + '_
+    /// Iterate over the keys and references without cloning them.
+    /// This is the same as `iter_ref`; prefer it over `iter_ref_by`
+    /// when the keys are only read.
+    pub fn iter_ref_pairs(&self) -> impl ExactSizeIterator<Item = (&K, &Ref<V>)> + FusedIterator {
+        self.iter_ref()
+    }
+
+    /// Like `iter_ref_by`, but yields borrowed `RefByRef` views
+    /// instead of cloning the keys into `RefBy`s.
+    pub fn iter_ref_by_ref(
+        &self,
+    ) -> impl ExactSizeIterator<Item = RefByRef<'_, K, V>> + FusedIterator {
+        self.iter_ref().map(|(k, v)| RefByRef::new(k, v))
+    }
+
+    /// Iterate over owned `RefBy`s, cloning every key. See
+    /// `iter_ref_by_ref` for a clone-free alternative.
+    pub fn iter_ref_by(&self) -> impl ExactSizeIterator<Item = RefBy<K, V>> + FusedIterator + '_
     where
         K: Clone,
     {
@@ -249,45 +794,147 @@ impl<K, V, S> HashGraph<K, V, S> {
             .map(|(k, v)| RefBy::new(k.clone(), v.clone()))
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+    /// Copy the graph's index into a `RefMap`. The references in the
+    /// map remain valid as long as the nodes are not removed from the
+    /// graph.
+    pub fn to_ref_map(&self) -> RefMap<K, V>
+    where
+        K: Ord + Clone,
+    {
+        self.iter_ref()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    /// Borrow the keys and values as a plain map using the graph's
+    /// hasher. Promised nodes are left out.
+    pub fn to_hash_map(&self) -> HashMap<&K, &V, S>
+    where
+        K: Hash + Eq,
+        S: BuildHasher + Clone,
+    {
+        let mut map =
+            HashMap::with_capacity_and_hasher(self.index.len(), self.index.hasher().clone());
+        map.extend(
+            self.index
+                .iter()
+                .filter_map(|(key, node)| Some((key, unsafe { node.try_get_unchecked() }?))),
+        );
+        map
+    }
+
+    /// Move the values out of their slots into a plain map using the
+    /// graph's hasher, without cloning them. Promised nodes are left
+    /// out.
+    pub fn into_hash_map(self) -> HashMap<K, V, S>
+    where
+        K: Hash + Eq,
+        S: BuildHasher + Clone,
+    {
+        let mut map =
+            HashMap::with_capacity_and_hasher(self.index.len(), self.index.hasher().clone());
+        map.extend(
+            self.index
+                .into_iter()
+                .filter_map(|(key, node)| Some((key, unsafe { node.try_remove_unchecked() }?))),
+        );
+        map
+    }
+
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = (&K, &V)> + FusedIterator {
         unsafe {
             self.iter_ref()
-                .map(|(key, value)| (key, value.try_get_unchecked().unwrap()))
+                .map(|(key, value)| (key, value.get_unchecked()))
         }
     }
 
-    pub fn iter_mut(&self) -> impl Iterator<Item = (&K, &mut V)> {
+    pub fn iter_mut(&mut self) -> impl ExactSizeIterator<Item = (&K, &mut V)> + FusedIterator {
+        let index = &self.index;
+        let scope = BorrowScope::new(index.values());
+        unsafe {
+            self.iter_ref().map(move |(key, value)| {
+                scope.check(value, || index_entries(index.values(), value));
+                (key, value.get_unchecked_mut())
+            })
+        }
+    }
+
+    /// Iterate over the entries, yielding the key, reference and
+    /// value of each node.
+    pub fn iter_full(&self) -> impl ExactSizeIterator<Item = (&K, &Ref<V>, &V)> + FusedIterator {
         unsafe {
             self.iter_ref()
-                .map(|(key, value)| (key, value.try_get_unchecked_mut().unwrap()))
+                .map(|(key, node)| (key, node, node.get_unchecked()))
+        }
+    }
+
+    /// Iterate mutably over the entries, yielding the key, reference
+    /// and value of each node.
+    pub fn iter_full_mut(
+        &mut self,
+    ) -> impl ExactSizeIterator<Item = (&K, &Ref<V>, &mut V)> + FusedIterator {
+        let index = &self.index;
+        let scope = BorrowScope::new(index.values());
+        unsafe {
+            self.iter_ref().map(move |(key, node)| {
+                scope.check(node, || index_entries(index.values(), node));
+                (key, node, node.get_unchecked_mut())
+            })
         }
     }
 
-    pub fn keys(&self) -> impl Iterator<Item = &K> {
+    pub fn keys(&self) -> impl ExactSizeIterator<Item = &K> + FusedIterator {
         self.index.keys()
     }
 
-    pub fn values_ref(&self) -> impl Iterator<Item = &Ref<V>> {
+    /// A borrowed view of the graph's keys. See
+    /// `BTreeGraph::keys_view`.
+    pub fn keys_view(&self) -> KeysView<'_, HashMap<K, Ref<V>, S>>
+    where
+        K: Hash + Eq,
+        S: BuildHasher,
+    {
+        KeysView::new(&self.index)
+    }
+
+    pub fn values_ref(&self) -> impl ExactSizeIterator<Item = &Ref<V>> + FusedIterator {
         self.index.values()
     }
 
-    pub fn values(&self) -> impl Iterator<Item = &V> {
+    pub fn values(&self) -> impl ExactSizeIterator<Item = &V> + FusedIterator {
         //self.graph.iter_mut()
-        unsafe {
-            self.values_ref()
-                .map(|value| value.try_get_unchecked().unwrap())
-        }
+        unsafe { self.values_ref().map(|value| value.get_unchecked()) }
     }
 
-    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
-        //self.graph.iter_mut()
+    /// Check whether any value matches the predicate.
+    pub fn any_value<F>(&self, f: F) -> bool
+    where
+        F: FnMut(&V) -> bool,
+    {
+        self.values().any(f)
+    }
+
+    /// Find a key whose value matches the predicate. If several
+    /// values match, which key is returned is unspecified.
+    pub fn find_key<F>(&self, mut f: F) -> Option<&K>
+    where
+        F: FnMut(&V) -> bool,
+    {
+        self.iter().find(|(_, value)| f(value)).map(|(key, _)| key)
+    }
+
+    pub fn values_mut(&mut self) -> impl ExactSizeIterator<Item = &mut V> + FusedIterator {
+        let index = &self.index;
+        let scope = BorrowScope::new(index.values());
         unsafe {
-            self.values_ref()
-                .map(|value| value.try_get_unchecked_mut().unwrap())
+            self.values_ref().map(move |value| {
+                scope.check(value, || index_entries(index.values(), value));
+                value.get_unchecked_mut()
+            })
         }
     }
 
-    pub fn entry(&mut self, key: K) -> Entry<K, V>
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V>
     where
         K: Hash + Eq,
         S: BuildHasher,
@@ -295,10 +942,77 @@ impl<K, V, S> HashGraph<K, V, S> {
         Entry {
             graph: &mut self.graph,
             entry: self.index.entry(key),
+            observer: &mut self.observer,
+        }
+    }
+
+    /// Get the value for the key, inserting `V::default()` if the key
+    /// is missing. A promised node is filled with the default value.
+    pub fn get_or_insert_default(&mut self, key: K) -> &mut V
+    where
+        K: Hash + Eq,
+        S: BuildHasher,
+        V: Default,
+    {
+        self.entry(key).or_default()
+    }
+
+    /// Get the value for the key, inserting `f()` if the key is
+    /// missing. A promised node is filled with `f()`.
+    pub fn get_or_insert_with<F>(&mut self, key: K, f: F) -> &mut V
+    where
+        K: Hash + Eq,
+        S: BuildHasher,
+        F: FnOnce() -> V,
+    {
+        self.entry(key).or_insert_with(f)
+    }
+
+    /// Like `entry`, but takes a borrowed key. The key is only cloned
+    /// into an owned `K` when a value is inserted for a vacant entry.
+    pub fn entry_ref<'q, Q>(&mut self, key: &'q Q) -> EntryRef<'_, 'q, K, V, Q, S>
+    where
+        K: Borrow<Q> + Hash + Eq,
+        Q: Hash + Eq + ?Sized,
+        S: BuildHasher,
+    {
+        EntryRef {
+            node: self.index.get(key).cloned(),
+            graph: &mut self.graph,
+            index: &mut self.index,
+            key,
+            observer: &mut self.observer,
         }
     }
 }
 
+/// Graphs are equal if they have the same keys, with equal values
+/// for each key. Since each of the keys is looked up in the other
+/// graph's index, this does not depend on iteration order.
+impl<K, V, S> PartialEq for HashGraph<K, V, S>
+where
+    K: Hash + Eq,
+    V: PartialEq,
+    S: BuildHasher,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.index.len() == other.index.len()
+            && self.index.iter().all(|(key, node)| {
+                other.index.get(key).is_some_and(|other_node| unsafe {
+                    node.try_get_unchecked() == other_node.try_get_unchecked()
+                })
+            })
+    }
+}
+
+impl<K, V, S> Eq for HashGraph<K, V, S>
+where
+    K: Hash + Eq,
+    V: Eq,
+    S: BuildHasher,
+{
+}
+
 impl<K, V> Default for HashGraph<K, V> {
     fn default() -> Self {
         Self::new()
@@ -311,6 +1025,12 @@ impl<K, V, S> AsRef<Graph<V>> for HashGraph<K, V, S> {
     }
 }
 
+impl<K, V, S> GraphMut<V> for HashGraph<K, V, S> {
+    fn borrow_mut(&mut self, node: &Ref<V>) -> &mut V {
+        HashGraph::borrow_mut(self, node)
+    }
+}
+
 impl<K: Hash + Eq, V, S: BuildHasher + Default> FromIterator<(K, V)> for HashGraph<K, V, S> {
     fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
         let iter = iter.into_iter();
@@ -323,10 +1043,39 @@ impl<K: Hash + Eq, V, S: BuildHasher + Default> FromIterator<(K, V)> for HashGra
             index.insert(key, graph.insert(value));
         });
 
-        Self { graph, index }
+        Self {
+            graph,
+            index,
+            observer: None,
+            auto_compact: None,
+        }
     }
 }
 
+/// The index uses a clone of the map's hasher. Since `HashMap` cannot
+/// change its value type in place, every key is hashed again.
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> From<HashMap<K, V, S>> for HashGraph<K, V, S> {
+    fn from(map: HashMap<K, V, S>) -> Self {
+        let mut graph = Graph::with_capacity(map.len());
+        let mut index = HashMap::with_capacity_and_hasher(map.len(), map.hasher().clone());
+        map.into_iter().for_each(|(key, value)| {
+            index.insert(key, graph.insert(value));
+        });
+
+        Self {
+            graph,
+            index,
+            observer: None,
+            auto_compact: None,
+        }
+    }
+}
+
+/* Safety: see the corresponding impls for BTreeGraph. */
+
+unsafe impl<K: Send, V: Send, S: Send> Send for HashGraph<K, V, S> {}
+unsafe impl<K: Sync, V: Sync, S: Sync> Sync for HashGraph<K, V, S> {}
+
 #[cfg(feature = "serde")]
 impl<K, V, R> Serialize for HashGraph<K, V, R>
 where
@@ -344,18 +1093,44 @@ where
     }
 }
 
+/// Deserialize a `HashGraph` using custom options.
 #[cfg(feature = "serde")]
-impl<'de, K, V, S> Deserialize<'de> for HashGraph<K, V, S>
+pub struct HashGraphSeed<K, V, S = RandomState> {
+    options: GraphDeserializeOptions,
+    _marker: PhantomData<(K, V, S)>,
+}
+
+#[cfg(feature = "serde")]
+impl<K, V, S> HashGraphSeed<K, V, S> {
+    pub fn new(options: GraphDeserializeOptions) -> Self {
+        Self {
+            options,
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K, V, S> Default for HashGraphSeed<K, V, S> {
+    fn default() -> Self {
+        Self::new(GraphDeserializeOptions::default())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V, S> DeserializeSeed<'de> for HashGraphSeed<K, V, S>
 where
     K: Deserialize<'de> + Hash + Eq,
     V: Deserialize<'de>,
     S: BuildHasher + Default,
 {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    type Value = HashGraph<K, V, S>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
     where
         D: Deserializer<'de>,
     {
-        struct GraphVisitor<K, V, S>(PhantomData<(K, V, S)>);
+        struct GraphVisitor<K, V, S>(GraphDeserializeOptions, PhantomData<(K, V, S)>);
 
         impl<'de, K, V, S> Visitor<'de> for GraphVisitor<K, V, S>
         where
@@ -373,42 +1148,701 @@ where
             where
                 A: MapAccess<'de>,
             {
-                let (mut graph, mut index) = match map.size_hint() {
-                    Some(size) => (
-                        Graph::with_capacity(size),
-                        HashMap::with_capacity_and_hasher(size, S::default()),
-                    ),
-                    None => (Graph::new(), HashMap::with_hasher(S::default())),
-                };
+                let size_hint = map.size_hint();
+                let mut graph = self.0.graph(size_hint);
+                let mut index = HashMap::<K, Ref<V>, S>::with_capacity_and_hasher(
+                    self.0.capacity(size_hint),
+                    S::default(),
+                );
 
                 while let Some((key, value)) = map.next_entry()? {
-                    let node = graph.insert(value);
-                    index.insert(key, node);
+                    match index.entry(key) {
+                        hash_map::Entry::Occupied(entry) => unsafe {
+                            entry.get().try_replace_unchecked(value);
+                        },
+                        hash_map::Entry::Vacant(entry) => {
+                            entry.insert(graph.insert(value));
+                        }
+                    }
                 }
 
-                Ok(HashGraph { graph, index })
+                Ok(HashGraph {
+                    graph,
+                    index,
+                    observer: None,
+                    auto_compact: None,
+                })
             }
         }
 
-        deserializer.deserialize_map(GraphVisitor(PhantomData))
+        deserializer.deserialize_map(GraphVisitor(self.options, PhantomData))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V, S> Deserialize<'de> for HashGraph<K, V, S>
+where
+    K: Deserialize<'de> + Hash + Eq,
+    V: Deserialize<'de>,
+    S: BuildHasher + Default,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        HashGraphSeed::default().deserialize(deserializer)
     }
 }
 
 impl<'a, K, V> Entry<'a, K, V>
 where
-    K: Ord,
+    K: Hash + Eq,
 {
+    /// Return the entry's value, inserting `default()` if the entry
+    /// is vacant. If the key was promised but not yet created, the
+    /// promised slot is filled with `default()`.
     pub fn or_insert_with<F>(self, default: F) -> &'a mut V
     where
         F: FnOnce() -> V,
+    {
+        unsafe { self.or_fill_with(default).get_unchecked_mut() }
+    }
+
+    /// Modify the entry's value with `modify` if the node exists, or
+    /// insert `default()` otherwise. As with `or_insert_with`, a
+    /// promised node is filled with `default()`.
+    pub fn modify_or_insert_with<M, F>(self, modify: M, default: F) -> &'a mut V
+    where
+        M: FnOnce(&mut V),
+        F: FnOnce() -> V,
     {
         match self.entry {
-            hash_map::Entry::Vacant(ent) => unsafe {
-                ent.insert(self.graph.insert(default()))
-                    .try_get_unchecked_mut()
-                    .unwrap()
-            },
-            hash_map::Entry::Occupied(ent) => unsafe { ent.get().try_get_unchecked_mut().unwrap() },
+            hash_map::Entry::Occupied(ent) if !unsafe { ent.get().is_promised_unchecked() } => {
+                let value = unsafe { ent.into_mut().get_unchecked_mut() };
+                modify(value);
+                value
+            }
+            entry => Self { entry, ..self }.or_insert_with(default),
         }
     }
+
+    /// Alias for `or_insert_with`, making the filling of promised
+    /// nodes explicit.
+    pub fn or_create_with<F>(self, default: F) -> &'a mut V
+    where
+        F: FnOnce() -> V,
+    {
+        self.or_insert_with(default)
+    }
+
+    /// Return the entry's value, inserting `V::default()` if the entry
+    /// is vacant or filling the promised slot with it.
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Like `or_insert_with`, but additionally return the node's
+    /// reference, e.g. to register back-edges to the node without a
+    /// second lookup.
+    pub fn ref_handle<F>(self, default: F) -> (Ref<V>, &'a mut V)
+    where
+        F: FnOnce() -> V,
+    {
+        let node = self.or_fill_with(default);
+        unsafe { (node.clone(), node.get_unchecked_mut()) }
+    }
+
+    fn or_fill_with<F>(self, default: F) -> &'a Ref<V>
+    where
+        F: FnOnce() -> V,
+    {
+        match self.entry {
+            hash_map::Entry::Vacant(ent) => {
+                let node = self.graph.insert(default());
+                if let Some(observer) = self.observer {
+                    observer.on_insert(ent.key(), unsafe { node.get_unchecked() });
+                }
+                ent.insert(node)
+            }
+            hash_map::Entry::Occupied(ent) => {
+                unsafe {
+                    let node = ent.get();
+                    if node.is_promised_unchecked() {
+                        node.try_replace_unchecked(default());
+                        if let Some(observer) = self.observer {
+                            observer.on_insert(ent.key(), node.get_unchecked());
+                        }
+                    }
+                }
+                ent.into_mut()
+            }
+        }
+    }
+}
+
+impl<'a, K, V, Q, S> EntryRef<'a, '_, K, V, Q, S>
+where
+    K: Borrow<Q> + Hash + Eq,
+    Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    S: BuildHasher,
+{
+    pub fn key(&self) -> &Q {
+        self.key
+    }
+
+    /// Whether the key is present in the index (created or promised).
+    pub fn is_occupied(&self) -> bool {
+        self.node.is_some()
+    }
+
+    /// Return the entry's value, inserting `default()` under an owned
+    /// copy of the key if the entry is vacant. If the key was promised
+    /// but not yet created, the promised slot is filled with `default()`.
+    pub fn or_insert_with<F>(self, default: F) -> &'a mut V
+    where
+        F: FnOnce() -> V,
+    {
+        let node = self.or_fill_with(default);
+        unsafe { node.get_unchecked_mut() }
+    }
+
+    /// Alias for `or_insert_with`, making the filling of promised
+    /// nodes explicit.
+    pub fn or_create_with<F>(self, default: F) -> &'a mut V
+    where
+        F: FnOnce() -> V,
+    {
+        self.or_insert_with(default)
+    }
+
+    /// Return the entry's value, inserting `V::default()` if the entry
+    /// is vacant or filling the promised slot with it.
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Like `or_insert_with`, but additionally return the node's
+    /// reference.
+    pub fn ref_handle<F>(self, default: F) -> (Ref<V>, &'a mut V)
+    where
+        F: FnOnce() -> V,
+    {
+        let node = self.or_fill_with(default);
+        let value = unsafe { node.get_unchecked_mut() };
+        (node, value)
+    }
+
+    fn or_fill_with<F>(self, default: F) -> Ref<V>
+    where
+        F: FnOnce() -> V,
+    {
+        match self.node {
+            Some(node) => {
+                unsafe {
+                    if node.is_promised_unchecked() {
+                        node.try_replace_unchecked(default());
+                        if let Some(observer) = self.observer {
+                            let key = self.index.get_key_value(self.key).unwrap().0;
+                            observer.on_insert(key, node.get_unchecked());
+                        }
+                    }
+                }
+                node
+            }
+            None => {
+                let node = self.graph.insert(default());
+                let key = self.key.to_owned();
+                if let Some(observer) = self.observer {
+                    observer.on_insert(&key, unsafe { node.get_unchecked() });
+                }
+                self.index.insert(key, node.clone());
+                node
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::collections::HashSet;
+
+    use crate::HashGraph;
+
+    #[test]
+    fn clear() {
+        let mut graph = (0..100).map(|i| (i, i)).collect::<HashGraph<_, _>>();
+        let capacity = graph.index().capacity();
+
+        graph.clear();
+        assert!(graph.is_empty());
+        assert_eq!(graph.index().capacity(), capacity);
+
+        graph.insert(1, 1);
+        graph.clear_and_shrink();
+        assert!(graph.is_empty());
+        assert!(graph.index().capacity() < capacity);
+    }
+
+    #[test]
+    fn eq() {
+        let entries = (0..100).map(|i| (i, i * 2)).collect::<Vec<_>>();
+        let mut graph = HashGraph::new();
+        let mut reversed = HashGraph::new();
+        entries.iter().for_each(|&(k, v)| {
+            graph.insert(k, v);
+        });
+        entries.iter().rev().for_each(|&(k, v)| {
+            reversed.insert(k, v);
+        });
+        assert!(graph == reversed);
+
+        reversed.insert(0, 1);
+        assert!(graph != reversed);
+        reversed.insert(0, 0);
+        reversed.insert(100, 200);
+        assert!(graph != reversed);
+        reversed.remove(&100);
+        assert!(graph == reversed);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialize_duplicate_keys() {
+        use serde::{
+            de::value::{Error, MapDeserializer},
+            Deserialize,
+        };
+
+        let input = MapDeserializer::<_, Error>::new([(1, "a"), (2, "b"), (1, "c")].into_iter());
+        let graph = HashGraph::<u32, String>::deserialize(input).unwrap();
+        assert_eq!(graph.as_ref().slot_count(), 2);
+        assert_eq!(graph.get(&1).map(String::as_str), Some("c"));
+        assert_eq!(graph.get(&2).map(String::as_str), Some("b"));
+
+        let graph =
+            serde_json::from_str::<HashGraph<u32, String>>(r#"{"1":"a","2":"b","1":"c"}"#).unwrap();
+        assert_eq!(graph.as_ref().slot_count(), 2);
+        let json = serde_json::to_string(&graph).unwrap();
+        assert!(json == r#"{"1":"c","2":"b"}"# || json == r#"{"2":"b","1":"c"}"#);
+    }
+
+    #[test]
+    fn remove_many() {
+        let mut graph = (0..4).map(|i| (i, i * 10)).collect::<HashGraph<_, _>>();
+        assert_eq!(graph.remove_many([3, 5, 1, 3]), vec![(3, 30), (1, 10)]);
+        assert_eq!(
+            graph.keys().copied().collect::<HashSet<_>>(),
+            HashSet::from([0, 2])
+        );
+    }
+
+    #[test]
+    fn retain_mut() {
+        let mut graph = (0..6).map(|i| (i, i)).collect::<HashGraph<_, _>>();
+        graph.retain_mut(|key, value| {
+            *value *= 10;
+            key % 2 == 0
+        });
+        assert_eq!(
+            graph.iter().map(|(k, v)| (*k, *v)).collect::<HashSet<_>>(),
+            HashSet::from([(0, 0), (2, 20), (4, 40)])
+        );
+    }
+
+    #[test]
+    fn filter_map_values() {
+        let mut graph = (0..10).map(|i| (i, i)).collect::<HashGraph<_, _>>();
+        graph.promise(10);
+        let filtered = graph.filter_map_values(|value| (value % 3 == 0).then(|| value.to_string()));
+        let mut values = filtered.iter().collect::<Vec<_>>();
+        values.sort();
+        assert_eq!(
+            values,
+            [
+                (&0, &"0".to_string()),
+                (&3, &"3".to_string()),
+                (&6, &"6".to_string()),
+                (&9, &"9".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn fill_promised() {
+        let mut graph = HashGraph::new();
+        let a = graph.promise('a');
+        assert_eq!(graph.upsert('a', 1).1, None);
+        let b = graph.promise('b');
+        *graph.get_or_insert_default('b') += 2;
+        let c = graph.promise('c');
+        assert!(graph.insert('c', 3) == c);
+        assert_eq!([&a, &b, &c].map(|node| *graph.borrow(node)), [1, 2, 3]);
+    }
+
+    #[test]
+    fn memory_usage() {
+        let mut graph = HashGraph::new();
+        let empty = graph.memory_usage();
+        graph.insert(0u64, 0u64);
+        assert!(graph.memory_usage() > empty);
+    }
+
+    #[test]
+    fn compact() {
+        use crate::{IndexBy, RefBy, Resolve};
+
+        struct Node(RefBy<u32, Node>);
+
+        impl Resolve<u32, Node> for Node {
+            fn resolve<I: IndexBy<u32, Node>>(&mut self, index: &I) -> Result<(), u32> {
+                self.0.resolve(index)
+            }
+        }
+
+        let mut graph = HashGraph::new();
+        let nodes = (0..100)
+            .map(|i| graph.insert(i, Node(RefBy::dangling(0))))
+            .collect::<Vec<_>>();
+        for node in &nodes {
+            graph.borrow_mut(node).0 = graph.get_ref_by(&0).unwrap();
+        }
+        for i in 1..100 {
+            graph.remove(&i);
+        }
+        let before = graph.memory_usage();
+        assert_eq!(graph.compact(), Ok(99));
+        assert!(graph.memory_usage() < before);
+        let node = graph.get_ref(&0).unwrap();
+        assert!(graph.borrow(&graph.borrow(node).0) as *const _ == graph.borrow(node));
+
+        let node = Node(graph.get_ref_by(&0).unwrap());
+        graph.insert(1, node);
+        graph.remove(&0);
+        assert_eq!(graph.compact(), Err(vec![0]));
+    }
+
+    #[test]
+    fn entry_promised() {
+        let mut graph = HashGraph::new();
+        graph.insert(1, "a");
+        let promised = graph.promise(2);
+
+        assert_eq!(graph.entry(1).or_insert_with(|| "x"), &mut "a");
+        assert_eq!(graph.entry(2).or_insert_with(|| "b"), &mut "b");
+        assert_eq!(graph.entry(3).or_create_with(|| "c"), &mut "c");
+
+        assert_eq!(graph.borrow(&promised), &"b");
+        assert_eq!(graph.get(&1), Some(&"a"));
+        assert_eq!(graph.get(&3), Some(&"c"));
+
+        let promised = graph.promise(4);
+        let (node, value) = graph.entry(4).ref_handle(|| "d");
+        assert_eq!(value, &mut "d");
+        assert!(node == promised);
+    }
+
+    #[test]
+    fn entry_modify_or_insert_with() {
+        let mut graph = HashGraph::new();
+        graph.promise(4);
+        for (from, to) in [(1, 2), (2, 3), (1, 3), (4, 1), (1, 4)] {
+            graph
+                .entry(from)
+                .modify_or_insert_with(|targets: &mut Vec<_>| targets.push(to), || vec![to]);
+        }
+        assert_eq!(graph.get(&1), Some(&vec![2, 3, 4]));
+        assert_eq!(graph.get(&2), Some(&vec![3]));
+        assert_eq!(graph.get(&4), Some(&vec![1]));
+        assert_eq!(graph.keys().len(), 3);
+    }
+
+    #[test]
+    fn find_key() {
+        let graph = [(3, 80), (1, 22), (2, 80)]
+            .into_iter()
+            .collect::<HashGraph<_, u16>>();
+        assert_eq!(graph.find_key(|port| *port == 22), Some(&1));
+        assert!(matches!(graph.find_key(|port| *port == 80), Some(2 | 3)));
+    }
+
+    #[test]
+    fn entry_unordered_key() {
+        #[derive(PartialEq, Eq, Hash)]
+        struct Key(u32);
+
+        let mut graph = HashGraph::new();
+        *graph.entry(Key(1)).or_default() += 1;
+        *graph.entry(Key(1)).or_insert_with(|| 10) += 1;
+        assert_eq!(graph.get(&Key(1)), Some(&2));
+    }
+
+    #[test]
+    fn entry_ref() {
+        let mut graph = HashGraph::<String, usize>::new();
+        graph.insert("a".to_string(), 1);
+        graph.promise("b".to_string());
+
+        assert!(graph.entry_ref("a").is_occupied());
+        assert!(!graph.entry_ref("c").is_occupied());
+        assert_eq!(graph.entry_ref("a").or_insert_with(|| 10), &mut 1);
+        assert_eq!(graph.entry_ref("b").or_insert_with(|| 2), &mut 2);
+        let (node, value) = graph.entry_ref("c").ref_handle(|| 3);
+        *value += 1;
+        assert_eq!(graph.borrow(&node), &4);
+        assert_eq!(graph.get("c"), Some(&4));
+        assert_eq!(graph.entry_ref("d").or_default(), &mut 0);
+        assert_eq!(graph.keys().count(), 4);
+    }
+
+    #[test]
+    fn std_map_conversions() {
+        use std::collections::HashMap;
+
+        let map = HashMap::from([(1, "a".to_string()), (2, "b".to_string())]);
+        let mut graph = HashGraph::from(map.clone());
+        assert_eq!(graph.get(&2).map(String::as_str), Some("b"));
+
+        graph.promise(3);
+        assert_eq!(graph.to_hash_map(), map.iter().collect::<HashMap<_, _>>());
+        assert_eq!(graph.into_hash_map(), map);
+    }
+
+    #[test]
+    fn btree_graph_conversion() {
+        use crate::{IndexBy, RefBy, Resolve};
+
+        struct Node(Option<RefBy<u32, Node>>);
+
+        impl Resolve<u32, Node> for Node {
+            fn resolve<I>(&mut self, index: &I) -> Result<(), u32>
+            where
+                I: IndexBy<u32, Node>,
+            {
+                self.0.resolve(index)
+            }
+        }
+
+        let mut graph = HashGraph::<_, _>::new();
+        graph.insert(2, Node(None));
+        graph.insert(1, Node(RefBy::resolved(2, graph.index())));
+        let first = graph.get_ref_by(&1).unwrap();
+
+        let graph = graph.into_btree_graph();
+        assert_eq!(graph.keys().collect::<Vec<_>>(), vec![&1, &2]);
+        let next = graph.borrow(&first).0.as_ref().unwrap();
+        assert!(graph.borrow(next).0.is_none());
+
+        let graph = graph.into_hash_graph::<std::collections::hash_map::RandomState>();
+        assert!(graph.borrow(&first).0.is_some());
+    }
+
+    #[test]
+    fn iter_full() {
+        let mut graph = (0..5).map(|i| (i, i * 10)).collect::<HashGraph<_, _>>();
+
+        let (key, node, value) = graph.get_full(&3).unwrap();
+        assert_eq!((key, value), (&3, &30));
+        assert!(Some(node) == graph.get_ref(&3));
+        assert!(graph.get_full(&5).is_none());
+
+        assert_eq!(graph.iter_full().len(), 5);
+        assert!(graph
+            .iter_full()
+            .all(|(key, node, value)| graph.borrow(node) == value && *value == key * 10));
+
+        graph
+            .iter_full_mut()
+            .for_each(|(key, _, value)| *value += key);
+        assert_eq!(graph.get(&4), Some(&44));
+    }
+
+    #[test]
+    fn exact_size_iterators() {
+        let mut graph = (0..10).map(|i| (i, i * 10)).collect::<HashGraph<_, _>>();
+
+        assert_eq!(graph.keys().len(), 10);
+        assert_eq!(graph.iter().len(), 10);
+        assert_eq!(graph.values().len(), 10);
+        assert_eq!(graph.values_ref().len(), 10);
+        assert_eq!(graph.iter_ref().len(), 10);
+        assert_eq!(graph.iter_ref_pairs().len(), 10);
+        assert_eq!(graph.iter_ref_by_ref().len(), 10);
+        assert_eq!(graph.iter_ref_by().len(), 10);
+        assert_eq!(graph.iter_mut().len(), 10);
+        assert_eq!(graph.values_mut().len(), 10);
+
+        let mut iter = graph.keys().skip(9);
+        assert_eq!(iter.len(), 1);
+        assert!(iter.next().is_some());
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn map_keys() {
+        let graph = (0..4).map(|i| (i, i * 10)).collect::<HashGraph<_, _>>();
+        let node = graph.get_ref_by(&3).unwrap();
+
+        let graph = graph.map_keys(|i| i.to_string());
+        let node = node.map_key(|i| i.to_string());
+        assert_eq!(graph.index().len(), 4);
+        assert_eq!(graph.get(node.key()), Some(&30));
+        assert_eq!(graph.borrow(&node), &30);
+
+        let graph = graph.try_map_keys(|key| key.len()).err();
+        assert_eq!(graph, Some(1));
+
+        let mut graph = HashGraph::new();
+        let promised = graph.promise(0);
+        let first = graph.insert(1, 10);
+        let graph = graph.map_keys(|_| 0);
+        assert_eq!(graph.index().len(), 1);
+        assert_eq!(graph.as_ref().iter().count(), graph.stats().nodes);
+        assert!(unsafe { promised.is_removed_unchecked() || first.is_removed_unchecked() });
+    }
+
+    #[test]
+    fn replace_ref() {
+        use crate::{Ref, RefMap, VisitRefs, VisitRefsBy};
+
+        #[derive(Default)]
+        struct Node(RefMap<char, Node>);
+
+        impl VisitRefs<Node> for Node {
+            fn visit_refs(&self, f: &mut impl FnMut(&Ref<Node>)) {
+                self.0.visit_refs(f)
+            }
+
+            fn visit_refs_mut(&mut self, f: &mut impl FnMut(&mut Ref<Node>)) {
+                self.0.visit_refs_mut(f)
+            }
+        }
+
+        impl VisitRefsBy<char, Node> for Node {
+            fn visit_refs_by_mut(
+                &mut self,
+                f: &mut impl FnMut(&char, &mut Ref<Node>) -> Option<char>,
+            ) {
+                self.0.visit_refs_by_mut(f)
+            }
+        }
+
+        let mut graph = HashGraph::new();
+        let a = graph.insert('a', Node::default());
+        let b = graph.insert('b', Node::default());
+        let c = graph.insert('c', Node::default());
+        graph.borrow_mut(&c).0 = [('a', a.clone()), ('b', b.clone())].into_iter().collect();
+        graph.borrow_mut(&a).0 = [('b', b.clone())].into_iter().collect();
+
+        assert_eq!(graph.replace_ref_by(&b, &a).map(|(key, _)| key), Some('b'));
+        let refs = |node| {
+            graph
+                .borrow(node)
+                .0
+                .iter_ref()
+                .map(|(key, node)| (*key, node.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert!(refs(&c) == [('a', a.clone())]);
+        assert!(refs(&a) == [('a', a.clone())]);
+
+        let d = graph.insert('d', Node::default());
+        assert_eq!(graph.replace_ref(&a, &d).map(|(key, _)| key), Some('a'));
+        let refs = |node| {
+            graph
+                .borrow(node)
+                .0
+                .iter_ref()
+                .map(|(key, node)| (*key, node.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert!(refs(&c) == [('a', d.clone())]);
+    }
+
+    #[test]
+    fn observer() {
+        use std::sync::{Arc, Mutex};
+
+        use crate::GraphObserver;
+
+        #[derive(Clone, Default)]
+        struct Events(Arc<Mutex<Vec<String>>>);
+
+        impl Events {
+            fn take(&self) -> Vec<String> {
+                std::mem::take(&mut self.0.lock().unwrap())
+            }
+        }
+
+        impl GraphObserver<u32, &'static str> for Events {
+            fn on_insert(&mut self, key: &u32, value: &&str) {
+                self.0.lock().unwrap().push(format!("insert {key} {value}"));
+            }
+
+            fn on_replace(&mut self, key: &u32, old: &&str, new: &&str) {
+                let event = format!("replace {key} {old} {new}");
+                self.0.lock().unwrap().push(event);
+            }
+
+            fn on_remove(&mut self, key: &u32, value: Option<&&str>) {
+                let event = format!("remove {key} {}", value.unwrap_or(&"-"));
+                self.0.lock().unwrap().push(event);
+            }
+
+            fn on_clear(&mut self) {
+                self.0.lock().unwrap().push("clear".to_string());
+            }
+        }
+
+        let events = Events::default();
+        let mut graph = HashGraph::new();
+        graph.set_observer(events.clone());
+
+        graph.insert(1, "a");
+        graph.insert(1, "b");
+        assert_eq!(events.take(), vec!["insert 1 a", "replace 1 a b"]);
+
+        let node = graph.promise(2);
+        graph.create(&node, "c");
+        graph.create_or_update(&node, "d");
+        graph.promise(3);
+        graph.insert(3, "e");
+        graph.promise(4);
+        assert_eq!(graph.try_create(&4, "f"), Ok(()));
+        assert_eq!(
+            events.take(),
+            vec!["insert 2 c", "replace 2 c d", "insert 3 e", "insert 4 f"]
+        );
+
+        graph.entry(5).or_insert_with(|| "g");
+        graph.entry(5).or_insert_with(|| "x");
+        graph.promise(6);
+        graph.entry_ref(&6).or_insert_with(|| "h");
+        graph.entry_ref(&7).or_insert_with(|| "i");
+        graph.promise(4);
+        assert_eq!(
+            events.take(),
+            vec!["insert 5 g", "insert 6 h", "insert 7 i", "remove 4 f"]
+        );
+
+        graph.remove(&1);
+        graph.remove_many([7]);
+        assert_eq!(events.take(), vec!["remove 1 b", "remove 7 i"]);
+
+        graph.retain_mut(|key, _| *key != 6);
+        assert_eq!(events.take(), vec!["remove 6 h"]);
+
+        graph.clear();
+        assert_eq!(events.take(), vec!["clear"]);
+        assert!(graph.take_observer().is_some());
+        graph.insert(8, "k");
+        assert!(events.take().is_empty());
+    }
 }
@@ -12,10 +12,14 @@
  * under the License.                                                         * 
  ******************************************************************************/
 
-use std::collections::{BTreeMap, HashMap};
-use std::hash::{BuildHasher, Hash};
+use alloc::collections::BTreeMap;
+#[cfg(feature = "std")]
+use std::{
+    collections::HashMap,
+    hash::{BuildHasher, Hash},
+};
 
-use crate::{BTreeGraph, Ref};
+use crate::{BTreeGraph, Ref, RefMap};
 
 pub trait IndexBy<K, V> {
     fn get(&self, key: &K) -> Option<&Ref<V>>;
@@ -30,6 +34,15 @@ where
     }
 }
 
+impl<K, V> IndexBy<K, V> for RefMap<K, V>
+where
+    K: Ord,
+{
+    fn get(&self, key: &K) -> Option<&Ref<V>> {
+        self.get_ref(key)
+    }
+}
+
 impl<K, V> IndexBy<K, V> for BTreeMap<K, Ref<V>>
 where
     K: Ord,
@@ -39,6 +52,7 @@ where
     }
 }
 
+#[cfg(feature = "std")]
 impl<K, V, S> IndexBy<K, V> for HashMap<K, Ref<V>, S>
 where
     K: Hash + Eq,
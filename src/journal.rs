@@ -0,0 +1,366 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+use alloc::{collections::VecDeque, vec::Vec};
+
+use crate::{BTreeGraph, Ref};
+
+/// A wrapper around `BTreeGraph` that records every change, so that
+/// it can be undone and redone. Changes are reverted in place: a
+/// replaced value is put back into the same node, and a removed node
+/// is restored into its old slot, so references to the node (e.g.
+/// held in `RefBy`s outside the graph) remain valid across undo and
+/// redo.
+///
+/// The graph can only be modified through the wrapper. Filling a
+/// node that was promised before the graph was wrapped is recorded
+/// as an insertion, and undoing it removes the key.
+pub struct JournaledGraph<K, V> {
+    graph: BTreeGraph<K, V>,
+    undo: VecDeque<(u64, Change<K, V>)>,
+    redo: Vec<(u64, Change<K, V>)>,
+    limit: Option<usize>,
+    /// The id of the last entry dropped from the journal, or zero.
+    base: u64,
+    next_id: u64,
+}
+
+/// A position in the journal, to return to with `rollback_to`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Checkpoint(u64);
+
+/// A recorded change, holding what is needed to revert it.
+enum Change<K, V> {
+    /// The key was inserted.
+    Insert { key: K, node: Ref<V> },
+    /// The key was removed, with its value.
+    Remove { key: K, node: Ref<V>, value: V },
+    /// The key's value was replaced; this holds the old value.
+    Replace { key: K, node: Ref<V>, value: V },
+}
+
+impl<K, V> JournaledGraph<K, V> {
+    /// Create a new empty graph with an empty journal.
+    pub fn new() -> Self {
+        Self::from_graph(BTreeGraph::new())
+    }
+
+    /// Start recording changes to an existing graph. Auto-compaction
+    /// is disabled, since the journal refers to removed nodes by
+    /// reference.
+    pub fn from_graph(mut graph: BTreeGraph<K, V>) -> Self {
+        graph.disable_auto_compact();
+        Self {
+            graph,
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            limit: None,
+            base: 0,
+            next_id: 1,
+        }
+    }
+
+    /// Keep at most `n` changes in the journal, forgetting the oldest
+    /// ones first.
+    pub fn limit(mut self, n: usize) -> Self {
+        self.limit = Some(n);
+        self.truncate();
+        self
+    }
+
+    pub fn graph(&self) -> &BTreeGraph<K, V> {
+        &self.graph
+    }
+
+    /// Stop recording changes and return the graph.
+    pub fn into_graph(self) -> BTreeGraph<K, V> {
+        self.graph
+    }
+
+    /// The number of changes that can be undone.
+    pub fn undo_len(&self) -> usize {
+        self.undo.len()
+    }
+
+    /// The number of changes that can be redone.
+    pub fn redo_len(&self) -> usize {
+        self.redo.len()
+    }
+
+    /// Get the current position in the journal.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.position())
+    }
+
+    fn position(&self) -> u64 {
+        self.undo.back().map_or(self.base, |(id, _)| *id)
+    }
+
+    fn record(&mut self, change: Change<K, V>) {
+        self.redo.clear();
+        self.undo.push_back((self.next_id, change));
+        self.next_id += 1;
+        self.truncate();
+    }
+
+    fn truncate(&mut self) {
+        while self.limit.is_some_and(|limit| self.undo.len() > limit) {
+            if let Some((id, _)) = self.undo.pop_front() {
+                self.base = id;
+            }
+        }
+    }
+}
+
+impl<K, V> JournaledGraph<K, V>
+where
+    K: Ord + Clone,
+{
+    /// Insert a node, or replace the value of an existing node in
+    /// place, as `BTreeGraph::insert` does, and record the change.
+    pub fn insert(&mut self, key: K, value: V) -> Ref<V> {
+        match self.graph.get_ref(&key).cloned() {
+            Some(node) => {
+                if let Some(value) = self.graph.create_or_update(&node, value) {
+                    self.record(Change::Replace {
+                        key,
+                        node: node.clone(),
+                        value,
+                    });
+                } else {
+                    self.record(Change::Insert {
+                        key,
+                        node: node.clone(),
+                    });
+                }
+                node
+            }
+            None => {
+                let node = self.graph.insert(key.clone(), value);
+                self.record(Change::Insert {
+                    key,
+                    node: node.clone(),
+                });
+                node
+            }
+        }
+    }
+
+    /// Remove a node, returning a copy of its value. References to
+    /// the node become valid again if the removal is undone.
+    pub fn remove(&mut self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        let (key, node) = self.entry(key)?;
+        let value = self.graph.remove(&key)?;
+        self.record(Change::Remove {
+            key,
+            node,
+            value: value.clone(),
+        });
+        Some(value)
+    }
+
+    /// Modify the value of a node in place, recording a copy of the
+    /// old value. Returns false if the key has no value.
+    pub fn update<F>(&mut self, key: &K, f: F) -> bool
+    where
+        V: Clone,
+        F: FnOnce(&mut V),
+    {
+        let Some((key, node)) = self.entry(key) else {
+            return false;
+        };
+        let value = self.graph.borrow_mut(&node);
+        let old = value.clone();
+        f(value);
+        self.record(Change::Replace {
+            key,
+            node,
+            value: old,
+        });
+        true
+    }
+
+    /// Look up the key and node for a key that holds a value.
+    fn entry(&self, key: &K) -> Option<(K, Ref<V>)> {
+        let (key, node) = self.graph.get_entry(key)?;
+        unsafe { node.try_get_unchecked() }?;
+        Some((key.clone(), node.clone()))
+    }
+
+    /// Undo the last change. Returns false if there is nothing to
+    /// undo.
+    pub fn undo(&mut self) -> bool {
+        match self.undo.pop_back() {
+            Some((id, change)) => {
+                let change = self.revert(change);
+                self.redo.push((id, change));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Redo the last undone change. Returns false if there is nothing
+    /// to redo.
+    pub fn redo(&mut self) -> bool {
+        match self.redo.pop() {
+            Some((id, change)) => {
+                let change = self.revert(change);
+                self.undo.push_back((id, change));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Undo (or redo) changes until the graph is back at the
+    /// checkpoint. Returns false, leaving the graph unchanged, if the
+    /// checkpoint is no longer reachable: its changes were dropped
+    /// from the journal or discarded by a change after an undo.
+    pub fn rollback_to(&mut self, checkpoint: Checkpoint) -> bool {
+        let Checkpoint(target) = checkpoint;
+        if target == self.base || self.undo.iter().any(|(id, _)| *id == target) {
+            while self.position() != target {
+                self.undo();
+            }
+            true
+        } else if self.redo.iter().any(|(id, _)| *id == target) {
+            while self.position() != target {
+                self.redo();
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Apply the inverse of a change, returning the change that
+    /// reverts it in turn.
+    fn revert(&mut self, change: Change<K, V>) -> Change<K, V> {
+        match change {
+            Change::Insert { key, node } => {
+                let value = self.graph.remove(&key).expect("journaled node was removed");
+                Change::Remove { key, node, value }
+            }
+            Change::Remove { key, node, value } => {
+                self.graph.restore(key.clone(), &node, value);
+                Change::Insert { key, node }
+            }
+            Change::Replace { key, node, value } => {
+                let value = self
+                    .graph
+                    .create_or_update(&node, value)
+                    .expect("journaled node was removed");
+                Change::Replace { key, node, value }
+            }
+        }
+    }
+}
+
+impl<K, V> Default for JournaledGraph<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use crate::{JournaledGraph, RefBy};
+
+    #[test]
+    fn undo_redo() {
+        let mut graph = JournaledGraph::new();
+        let a = RefBy::new("a", graph.insert("a", 1));
+        let b = RefBy::new("b", graph.insert("b", 2));
+        graph.insert("a", 3);
+        graph.update(&"b", |v| *v += 10);
+        assert_eq!(graph.remove(&"a"), Some(3));
+        assert!(graph.graph().get(&"a").is_none());
+
+        assert!(graph.undo());
+        assert_eq!(graph.graph().borrow(&a), &3);
+        assert!(graph.undo());
+        assert_eq!(graph.graph().borrow(&b), &2);
+        assert!(graph.undo());
+        assert_eq!(graph.graph().borrow(&a), &1);
+        assert!(graph.undo() && graph.undo());
+        assert!(graph.graph().is_empty());
+        assert!(!graph.undo());
+
+        assert!(graph.redo() && graph.redo() && graph.redo());
+        assert_eq!(graph.graph().borrow(&a), &3);
+        assert_eq!(graph.graph().borrow(&b), &2);
+        assert!(graph.graph().get_ref(&"a") == Some(a.value_ref()));
+        assert!(graph.graph().get_ref(&"b") == Some(b.value_ref()));
+        assert!(graph.redo() && graph.redo());
+        assert!(!graph.redo());
+        assert_eq!(graph.graph().borrow(&b), &12);
+        assert!(graph.graph().get(&"a").is_none());
+
+        assert!(graph.undo());
+        graph.insert("c", 4);
+        assert!(!graph.redo());
+        assert_eq!(graph.graph().borrow(&a), &3);
+    }
+
+    #[test]
+    fn checkpoint() {
+        let mut graph = JournaledGraph::new();
+        let start = graph.checkpoint();
+        let a = graph.insert(1, "a");
+        let checkpoint = graph.checkpoint();
+        graph.insert(1, "b");
+        graph.insert(2, "c");
+        graph.remove(&1);
+
+        assert!(graph.rollback_to(checkpoint));
+        assert_eq!(graph.graph().borrow(&a), &"a");
+        assert_eq!(graph.graph().get(&2), None);
+        assert_eq!(graph.redo_len(), 3);
+
+        assert!(graph.rollback_to(start));
+        assert!(graph.graph().is_empty());
+        assert!(graph.rollback_to(checkpoint));
+        assert_eq!(graph.graph().borrow(&a), &"a");
+
+        graph.insert(3, "d");
+        let diverged = graph.checkpoint();
+        assert!(graph.undo());
+        graph.insert(4, "e");
+        assert!(!graph.rollback_to(diverged));
+        assert_eq!(graph.graph().keys().collect::<Vec<_>>(), vec![&1, &4]);
+    }
+
+    #[test]
+    fn limit() {
+        let mut graph = JournaledGraph::new().limit(2);
+        let start = graph.checkpoint();
+        let node = graph.insert(1, 1);
+        graph.update(&1, |v| *v = 2);
+        let checkpoint = graph.checkpoint();
+        graph.update(&1, |v| *v = 3);
+        assert_eq!(graph.undo_len(), 2);
+
+        assert!(!graph.rollback_to(start));
+        assert!(graph.undo() && graph.undo());
+        assert!(!graph.undo());
+        assert_eq!(graph.graph().borrow(&node), &1);
+        assert!(graph.rollback_to(checkpoint));
+        assert_eq!(graph.graph().borrow(&node), &2);
+    }
+}
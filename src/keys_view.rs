@@ -0,0 +1,222 @@
+/******************************************************************************
+ * Copyright 2025 ContinuousC                                                 *
+ *                                                                            *
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may *
+ * not use this file except in compliance with the License. You may  obtain a *
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          *
+ *                                                                            *
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software *
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT *
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the *
+ * License for the  specific language  governing permissions  and limitations *
+ * under the License.                                                         *
+ ******************************************************************************/
+
+use alloc::collections::{btree_map, BTreeMap};
+use core::{
+    fmt::{self, Debug, Formatter},
+    iter::FusedIterator,
+};
+#[cfg(feature = "std")]
+use std::{
+    collections::{hash_map, HashMap},
+    hash::{BuildHasher, Hash},
+};
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer};
+
+/// A borrowed view of the keys of a keyed graph or reference map,
+/// returned by `keys_view`. It borrows the index, so it is cheap to
+/// create and copy. Views of different kinds of maps with the same
+/// key type can be compared with each other.
+pub struct KeysView<'a, M: ?Sized> {
+    keys: &'a M,
+}
+
+/// The index types a `KeysView` can borrow. The values (references)
+/// are not exposed, so that views only need to agree on the key
+/// type.
+pub trait KeySet {
+    type Key;
+    type Iter<'a>: ExactSizeIterator<Item = &'a Self::Key> + FusedIterator
+    where
+        Self: 'a;
+
+    fn len(&self) -> usize;
+    fn contains(&self, key: &Self::Key) -> bool;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn keys(&self) -> Self::Iter<'_>;
+}
+
+impl<K: Ord, R> KeySet for BTreeMap<K, R> {
+    type Key = K;
+    type Iter<'a>
+        = btree_map::Keys<'a, K, R>
+    where
+        Self: 'a;
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn contains(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn keys(&self) -> Self::Iter<'_> {
+        self.keys()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, R, S> KeySet for HashMap<K, R, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    type Key = K;
+    type Iter<'a>
+        = hash_map::Keys<'a, K, R>
+    where
+        Self: 'a;
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn contains(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn keys(&self) -> Self::Iter<'_> {
+        self.keys()
+    }
+}
+
+impl<'a, M: KeySet + ?Sized> KeysView<'a, M> {
+    pub(crate) fn new(keys: &'a M) -> Self {
+        Self { keys }
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn contains(&self, key: &M::Key) -> bool {
+        self.keys.contains(key)
+    }
+
+    /// Iterate over the keys. For views of a `BTreeGraph` or
+    /// reference map, the keys are returned in order.
+    pub fn iter(&self) -> M::Iter<'a> {
+        self.keys.keys()
+    }
+
+    /// Check whether every key in this view is also in `other`.
+    pub fn is_subset<N>(&self, other: &KeysView<'_, N>) -> bool
+    where
+        N: KeySet<Key = M::Key> + ?Sized,
+    {
+        self.len() <= other.len() && self.iter().all(|key| other.contains(key))
+    }
+
+    /// Check whether every key in `other` is also in this view.
+    pub fn is_superset<N>(&self, other: &KeysView<'_, N>) -> bool
+    where
+        N: KeySet<Key = M::Key> + ?Sized,
+    {
+        other.is_subset(self)
+    }
+}
+
+impl<M: ?Sized> Clone for KeysView<'_, M> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<M: ?Sized> Copy for KeysView<'_, M> {}
+
+impl<M> Debug for KeysView<'_, M>
+where
+    M: KeySet + ?Sized,
+    M::Key: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Serialized as a sequence of keys, like `RefMap`.
+#[cfg(feature = "serde")]
+impl<M> Serialize for KeysView<'_, M>
+where
+    M: KeySet + ?Sized,
+    M::Key: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    #[cfg(feature = "std")]
+    use crate::HashGraph;
+    use crate::{BTreeGraph, Graph, RefMap};
+
+    #[test]
+    fn keys_view() {
+        let graph = (0..5).map(|i| (i, i)).collect::<BTreeGraph<_, _>>();
+        let mut nodes = Graph::new();
+        let map = [(1, nodes.insert(())), (3, nodes.insert(()))]
+            .into_iter()
+            .collect::<RefMap<_, _>>();
+
+        let view = graph.keys_view();
+        assert_eq!(view.len(), 5);
+        assert!(view.contains(&4));
+        assert!(!view.contains(&5));
+        assert_eq!(view.iter().copied().collect::<Vec<_>>(), [0, 1, 2, 3, 4]);
+        assert_eq!(view.iter().skip(2).len(), 3);
+        assert_eq!(view.iter().next_back(), Some(&4));
+        assert_eq!(format!("{:?}", map.keys_view()), "{1, 3}");
+
+        assert!(map.keys_view().is_subset(&view));
+        assert!(view.is_superset(&map.keys_view()));
+        assert!(view.is_subset(&view));
+        assert!(!view.is_subset(&map.keys_view()));
+        assert!(RefMap::<u32, ()>::new().keys_view().is_empty());
+
+        #[cfg(feature = "std")]
+        {
+            let hashed = (0..3).map(|i| (i, i)).collect::<HashGraph<_, _>>();
+            assert!(hashed.keys_view().is_subset(&view));
+            assert!(view.is_superset(&hashed.keys_view()));
+            assert!(!map.keys_view().is_subset(&hashed.keys_view()));
+            assert!(hashed.keys_view().contains(&2));
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize() {
+        let graph = [(2, "b"), (1, "a")]
+            .into_iter()
+            .collect::<BTreeGraph<_, _>>();
+        assert_eq!(serde_json::to_string(&graph.keys_view()).unwrap(), "[1,2]");
+    }
+}
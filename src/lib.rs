@@ -12,18 +12,92 @@
  * under the License.                                                         * 
  ******************************************************************************/
 
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+mod borrow_check;
+#[cfg(feature = "branded")]
+mod branded;
 mod btree_graph;
+mod builder;
+#[cfg(feature = "std")]
+pub mod csv_import;
+mod diff;
+#[cfg(feature = "std")]
+mod edge_graph;
+pub mod fmt;
+mod frozen;
 mod gen;
 mod graph;
+#[cfg(feature = "std")]
+pub mod graphml;
+#[cfg(feature = "std")]
 mod hash_graph;
 mod index;
+mod journal;
+mod keys_view;
+mod macros;
+mod observer;
+mod rc_graph;
 mod reference;
-mod refmap;
+pub mod refmap;
+mod resolve;
+#[cfg(feature = "schemars")]
+mod schema;
+mod spec;
+mod stats;
+mod storage;
+mod validate;
+mod visit;
+#[cfg(feature = "tsify")]
+mod wasm;
 
-pub use crate::btree_graph::BTreeGraph;
+pub use crate::borrow_check::{BorrowGuard, BorrowGuardMut};
+#[cfg(feature = "branded")]
+pub use crate::branded::{BrandedGraph, BrandedNodes, BrandedRef};
+#[cfg(feature = "serde")]
+pub use crate::btree_graph::{deserialize_resolved, BTreeGraphSeed};
+pub use crate::btree_graph::{BTreeGraph, GraphReadView, MergeDecision, UnsortedError};
+pub use crate::builder::{BuildError, GraphBuilder};
+pub use crate::diff::{diff_keys, diff_with, ApplyError, GraphDiff, KeyChange, KeyDiff};
+#[cfg(feature = "std")]
+pub use crate::edge_graph::EdgeGraph;
+pub use crate::frozen::FrozenGraph;
 pub use crate::gen::Gen;
-pub use crate::graph::Graph;
+pub use crate::graph::{BorrowManyError, CreateError, Graph, GraphMut};
+#[cfg(feature = "serde")]
+pub use crate::graph::{GraphDeserializeOptions, GraphSeed, SerializeStable, StableGraph};
+#[cfg(feature = "std")]
 pub use crate::hash_graph::HashGraph;
+#[cfg(all(feature = "std", feature = "serde"))]
+pub use crate::hash_graph::HashGraphSeed;
 pub use crate::index::IndexBy;
-pub use crate::reference::{OptRefBy, Ref, RefBy};
-pub use crate::refmap::{OptRefMap, RefMap};
+pub use crate::journal::{Checkpoint, JournaledGraph};
+pub use crate::keys_view::{KeySet, KeysView};
+pub use crate::observer::GraphObserver;
+pub use crate::rc_graph::{RcGraph, RcRef};
+pub use crate::reference::{OptRefBy, ProjectedRef, Ref, RefBy, RefByRef};
+#[cfg(feature = "serde")]
+pub use crate::refmap::{AsObject, OptRefMapSeed, RefMapSeed};
+pub use crate::refmap::{MergePolicy, OptRefMap, RefMap};
+pub use crate::resolve::{resolve_all, Resolve, ResolveError};
+pub use crate::spec::GraphSpec;
+pub use crate::stats::GraphStats;
+pub use crate::storage::{Heap, NodeStorage};
+pub use crate::validate::{IntegrityError, ValidationReport};
+pub use crate::visit::{VisitRefs, VisitRefsBy};
+#[cfg(feature = "tsify")]
+pub use crate::wasm::JsResolveError;
+#[cfg(feature = "js-ref-helpers")]
+pub use crate::wasm::{js_as_ref, js_ref_key};
+#[cfg(feature = "derive")]
+pub use graph_derive::VisitRefs;
+
+/* Used by the macros, which cannot refer to this crate's
+ * dependencies directly. */
+#[doc(hidden)]
+pub mod __private {
+    #[cfg(feature = "serde")]
+    pub use serde;
+}
@@ -0,0 +1,368 @@
+/******************************************************************************
+ * Copyright 2025 ContinuousC                                                 *
+ *                                                                            *
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may *
+ * not use this file except in compliance with the License. You may  obtain a *
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          *
+ *                                                                            *
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software *
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT *
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the *
+ * License for the  specific language  governing permissions  and limitations *
+ * under the License.                                                         *
+ ******************************************************************************/
+
+/// Declare a newtype around `Ref<Node>`, for type clarity and to
+/// hang methods off. The wrapper works with `borrow` and friends
+/// through `AsRef<Ref<Node>>`, converts from and into `Ref<Node>`
+/// (e.g. for use with `RefMap`) and from `RefBy<K, Node>`, and
+/// implements `Clone`, `PartialEq`, `Eq`, `Hash` and `VisitRefs`.
+///
+/// ```
+/// use graph::{declare_ref, Graph};
+///
+/// struct Host {
+///     name: String,
+/// }
+///
+/// declare_ref!(pub struct HostRef for Host);
+///
+/// let mut graph = Graph::new();
+/// let host = HostRef::from(graph.insert(Host { name: "a".to_string() }));
+/// assert_eq!(graph.borrow(&host).name, "a");
+/// ```
+///
+/// Since a `Ref` cannot be serialized, neither can the wrapper; use
+/// `declare_ref_by!` for references that are (de)serialized.
+#[macro_export]
+macro_rules! declare_ref {
+    ($(#[$attr:meta])* $vis:vis struct $name:ident for $node:ty $(;)?) => {
+        $(#[$attr])*
+        #[repr(transparent)]
+        $vis struct $name($crate::Ref<$node>);
+
+        #[allow(dead_code)]
+        impl $name {
+            pub fn into_inner(self) -> $crate::Ref<$node> {
+                self.0
+            }
+        }
+
+        impl ::core::clone::Clone for $name {
+            fn clone(&self) -> Self {
+                Self(self.0.clone())
+            }
+        }
+
+        impl ::core::cmp::PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                self.0 == other.0
+            }
+        }
+
+        impl ::core::cmp::Eq for $name {}
+
+        impl ::core::hash::Hash for $name {
+            fn hash<H: ::core::hash::Hasher>(&self, state: &mut H) {
+                self.0.hash(state)
+            }
+        }
+
+        impl ::core::convert::AsRef<$crate::Ref<$node>> for $name {
+            fn as_ref(&self) -> &$crate::Ref<$node> {
+                &self.0
+            }
+        }
+
+        impl ::core::convert::From<$crate::Ref<$node>> for $name {
+            fn from(node: $crate::Ref<$node>) -> Self {
+                Self(node)
+            }
+        }
+
+        impl ::core::convert::From<$name> for $crate::Ref<$node> {
+            fn from(node: $name) -> Self {
+                node.0
+            }
+        }
+
+        impl<K> ::core::convert::From<$crate::RefBy<K, $node>> for $name {
+            fn from(node: $crate::RefBy<K, $node>) -> Self {
+                Self(node.value_ref().clone())
+            }
+        }
+
+        impl $crate::VisitRefs<$node> for $name {
+            fn visit_refs(&self, f: &mut impl FnMut(&$crate::Ref<$node>)) {
+                f(&self.0)
+            }
+
+            fn visit_refs_mut(&mut self, f: &mut impl FnMut(&mut $crate::Ref<$node>)) {
+                f(&mut self.0)
+            }
+        }
+    };
+}
+
+/// Declare a newtype around `RefBy<Key, Node>`. In addition to the
+/// traits implemented by `declare_ref!`, the wrapper implements
+/// `PartialOrd`, `Ord`, `VisitRefsBy` and `Resolve`, all forwarded to
+/// the `RefBy`.
+/// The key type must thus implement `Ord`, `Clone` and `Hash`.
+///
+/// Ending the declaration with `, serde` (e.g.
+/// `declare_ref_by!(pub struct HostRef(String) for Host, serde)`)
+/// also forwards `Serialize` and `Deserialize`, which requires the
+/// `serde` feature.
+///
+/// ```
+/// use graph::{declare_ref_by, BTreeGraph, Resolve};
+///
+/// struct Host {
+///     parent: Option<HostRef>,
+/// }
+///
+/// declare_ref_by!(pub struct HostRef(String) for Host);
+///
+/// let mut graph = BTreeGraph::new();
+/// graph.insert("a".to_string(), Host { parent: None });
+/// let mut parent = HostRef::dangling("a".to_string());
+/// parent.resolve(graph.index()).unwrap();
+/// graph.insert("b".to_string(), Host { parent: Some(parent) });
+/// let parent = graph.get(&"b".to_string()).unwrap().parent.as_ref();
+/// assert_eq!(parent.map(|parent| parent.key().as_str()), Some("a"));
+/// ```
+#[macro_export]
+macro_rules! declare_ref_by {
+    ($(#[$attr:meta])* $vis:vis struct $name:ident($key:ty) for $node:ty, serde $(;)?) => {
+        $crate::declare_ref_by!($(#[$attr])* $vis struct $name($key) for $node);
+        $crate::__declare_ref_by_serde!($name, $key, $node);
+    };
+    ($(#[$attr:meta])* $vis:vis struct $name:ident($key:ty) for $node:ty $(;)?) => {
+        $(#[$attr])*
+        #[repr(transparent)]
+        $vis struct $name($crate::RefBy<$key, $node>);
+
+        #[allow(dead_code)]
+        impl $name {
+            pub fn new(key: $key, node: $crate::Ref<$node>) -> Self {
+                Self($crate::RefBy::new(key, node))
+            }
+
+            pub fn dangling(key: $key) -> Self {
+                Self($crate::RefBy::dangling(key))
+            }
+
+            pub fn key(&self) -> &$key {
+                self.0.key()
+            }
+
+            pub fn value_ref(&self) -> &$crate::Ref<$node> {
+                self.0.value_ref()
+            }
+
+            pub fn into_inner(self) -> $crate::RefBy<$key, $node> {
+                self.0
+            }
+        }
+
+        impl ::core::clone::Clone for $name {
+            fn clone(&self) -> Self {
+                Self(self.0.clone())
+            }
+        }
+
+        impl ::core::cmp::PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                self.0 == other.0
+            }
+        }
+
+        impl ::core::cmp::Eq for $name {}
+
+        impl ::core::cmp::PartialOrd for $name {
+            fn partial_cmp(&self, other: &Self) -> ::core::option::Option<::core::cmp::Ordering> {
+                ::core::option::Option::Some(self.cmp(other))
+            }
+        }
+
+        impl ::core::cmp::Ord for $name {
+            fn cmp(&self, other: &Self) -> ::core::cmp::Ordering {
+                self.0.cmp(&other.0)
+            }
+        }
+
+        impl ::core::hash::Hash for $name {
+            fn hash<H: ::core::hash::Hasher>(&self, state: &mut H) {
+                self.0.hash(state)
+            }
+        }
+
+        impl ::core::convert::AsRef<$crate::Ref<$node>> for $name {
+            fn as_ref(&self) -> &$crate::Ref<$node> {
+                self.0.value_ref()
+            }
+        }
+
+        impl ::core::convert::From<$crate::RefBy<$key, $node>> for $name {
+            fn from(node: $crate::RefBy<$key, $node>) -> Self {
+                Self(node)
+            }
+        }
+
+        impl ::core::convert::From<$name> for $crate::RefBy<$key, $node> {
+            fn from(node: $name) -> Self {
+                node.0
+            }
+        }
+
+        impl ::core::convert::From<$name> for $crate::Ref<$node> {
+            fn from(node: $name) -> Self {
+                node.0.value_ref().clone()
+            }
+        }
+
+        impl $crate::VisitRefs<$node> for $name {
+            fn visit_refs(&self, f: &mut impl FnMut(&$crate::Ref<$node>)) {
+                $crate::VisitRefs::visit_refs(&self.0, f)
+            }
+
+            fn visit_refs_mut(&mut self, f: &mut impl FnMut(&mut $crate::Ref<$node>)) {
+                $crate::VisitRefs::visit_refs_mut(&mut self.0, f)
+            }
+        }
+
+        impl $crate::VisitRefsBy<$key, $node> for $name {
+            fn visit_refs_by_mut(
+                &mut self,
+                f: &mut impl FnMut(&$key, &mut $crate::Ref<$node>) -> ::core::option::Option<$key>,
+            ) {
+                $crate::VisitRefsBy::visit_refs_by_mut(&mut self.0, f)
+            }
+        }
+
+        impl $crate::Resolve<$key, $node> for $name {
+            fn resolve<I>(&mut self, index: &I) -> ::core::result::Result<(), $key>
+            where
+                I: $crate::IndexBy<$key, $node>,
+            {
+                self.0.resolve(index)
+            }
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __declare_ref_by_serde {
+    ($name:ident, $key:ty, $node:ty) => {
+        impl $crate::__private::serde::Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> ::core::result::Result<S::Ok, S::Error>
+            where
+                S: $crate::__private::serde::Serializer,
+            {
+                $crate::__private::serde::Serialize::serialize(&self.0, serializer)
+            }
+        }
+
+        impl<'de> $crate::__private::serde::Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> ::core::result::Result<Self, D::Error>
+            where
+                D: $crate::__private::serde::Deserializer<'de>,
+            {
+                let node: $crate::RefBy<$key, $node> =
+                    $crate::__private::serde::Deserialize::deserialize(deserializer)?;
+                ::core::result::Result::Ok(Self(node))
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::collections::HashSet;
+
+    use crate::{BTreeGraph, Graph, RefBy, RefMap, Resolve, VisitRefs};
+
+    struct Host {
+        name: &'static str,
+        uplink: Option<UplinkRef>,
+    }
+
+    declare_ref!(struct HostRef for Host);
+    declare_ref_by!(struct UplinkRef(u32) for Host;);
+    #[cfg(feature = "serde")]
+    declare_ref_by!(struct SerdeRef(u32) for Host, serde);
+
+    #[test]
+    fn declare_ref() {
+        let mut graph = Graph::new();
+        let a = HostRef::from(graph.insert(Host {
+            name: "a",
+            uplink: None,
+        }));
+        let b = HostRef::from(graph.insert(Host {
+            name: "b",
+            uplink: None,
+        }));
+        assert_eq!(graph.borrow(&a).name, "a");
+        assert!(a != b && a == a.clone());
+        assert_eq!([&a, &b, &a].into_iter().collect::<HashSet<_>>().len(), 2);
+
+        let map = [(1, a.clone().into()), (2, b.into_inner())]
+            .into_iter()
+            .collect::<RefMap<_, _>>();
+        assert_eq!(map.get(&1, &graph).unwrap().name, "a");
+
+        let c = HostRef::from(RefBy::new("c", a.as_ref().clone()));
+        let mut count = 0;
+        c.visit_refs(&mut |node| {
+            assert!(node == a.as_ref());
+            count += 1;
+        });
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn declare_ref_by() {
+        let mut graph = BTreeGraph::new();
+        graph.insert(
+            1,
+            Host {
+                name: "a",
+                uplink: None,
+            },
+        );
+        graph.insert(
+            2,
+            Host {
+                name: "b",
+                uplink: Some(UplinkRef::dangling(1)),
+            },
+        );
+
+        let mut uplink = graph.get(&2).unwrap().uplink.clone().unwrap();
+        uplink.resolve(graph.index()).unwrap();
+        assert_eq!(graph.borrow(&uplink).name, "a");
+        assert_eq!(uplink.key(), &1);
+        assert!(uplink.value_ref() == graph.get_ref(&1).unwrap());
+        assert!(UplinkRef::dangling(0) < uplink);
+        assert_eq!(UplinkRef::dangling(3).resolve(graph.index()), Err(3));
+
+        let map = [(1, uplink.clone().into())]
+            .into_iter()
+            .collect::<RefMap<_, _>>();
+        assert_eq!(map.get(&1, &graph).unwrap().name, "a");
+        let uplink = RefBy::from(uplink);
+        assert_eq!(UplinkRef::from(uplink).into_inner().key(), &1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn declare_ref_by_serde() {
+        let uplink = serde_json::from_str::<SerdeRef>("5").unwrap();
+        assert_eq!(uplink.key(), &5);
+        assert_eq!(serde_json::to_string(&uplink).unwrap(), "5");
+    }
+}
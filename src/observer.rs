@@ -0,0 +1,69 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+use alloc::boxed::Box;
+
+use crate::Ref;
+
+/// Receives notifications of changes to the nodes of a keyed graph
+/// (see `BTreeGraph::set_observer` and `HashGraph::set_observer`).
+/// All methods default to doing nothing. Changes made in place
+/// through mutable borrows of the values (`get_mut`, `borrow_mut`,
+/// `iter_mut`, ...) are not reported.
+pub trait GraphObserver<K, V> {
+    /// A value was stored for a key that had no value, either a new
+    /// key or a promised one.
+    fn on_insert(&mut self, _key: &K, _value: &V) {}
+
+    /// The value for a key was replaced.
+    fn on_replace(&mut self, _key: &K, _old: &V, _new: &V) {}
+
+    /// The value for a key was updated in place with an incoming
+    /// value by `BTreeGraph::merge`.
+    fn on_merge(&mut self, _key: &K, _value: &V) {}
+
+    /// A key was removed from the graph (or its value was discarded
+    /// by promising the key again). The value is `None` if the key
+    /// was only promised.
+    fn on_remove(&mut self, _key: &K, _value: Option<&V>) {}
+
+    /// All nodes were removed from the graph.
+    fn on_clear(&mut self) {}
+
+    /// Automatic compaction (see `BTreeGraph::set_auto_compact`) was
+    /// skipped because the references to `missing` could not be
+    /// resolved.
+    fn on_compact_failed(&mut self, _missing: &[K]) {}
+}
+
+pub(crate) type Observer<K, V> = Option<Box<dyn GraphObserver<K, V> + Send>>;
+
+/// Replace the value of a node that is in the index, notifying the
+/// observer.
+pub(crate) fn replace_observed<K, V>(
+    observer: &mut Observer<K, V>,
+    key: &K,
+    node: &Ref<V>,
+    value: V,
+) -> Option<V> {
+    let old = unsafe { node.try_replace_unchecked(value) };
+    if let Some(observer) = observer {
+        let new = unsafe { node.get_unchecked() };
+        match &old {
+            Some(old) => observer.on_replace(key, old, new),
+            None => observer.on_insert(key, new),
+        }
+    }
+    old
+}
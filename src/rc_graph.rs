@@ -0,0 +1,330 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+use alloc::{
+    collections::BTreeMap,
+    rc::{Rc, Weak},
+    vec::Vec,
+};
+use core::{
+    borrow::Borrow,
+    cell::{self, Cell, RefCell},
+    mem::ManuallyDrop,
+};
+
+use crate::{BTreeGraph, Ref};
+
+/// An indexed graph whose nodes are reference counted. Inserting a
+/// node returns a counted handle (`RcRef`), and the node is removed
+/// from the graph as soon as its last handle is dropped. Handles may
+/// be stored inside the nodes themselves; removing a node drops the
+/// handles it holds, which may in turn remove other nodes.
+///
+/// If the graph is borrowed when the last handle to a node is
+/// dropped, the removal is deferred until the borrow ends and the
+/// graph is next modified (or `flush` is called).
+pub struct RcGraph<K: Ord, V>(Rc<Shared<K, V>>);
+
+/// A counted handle to a node in an `RcGraph`.
+pub struct RcRef<K: Ord, V>(Rc<Handle<K, V>>);
+
+struct Shared<K: Ord, V> {
+    graph: RefCell<BTreeGraph<K, V>>,
+    handles: RefCell<BTreeMap<K, Weak<Handle<K, V>>>>,
+    released: RefCell<Vec<K>>,
+    flushing: Cell<bool>,
+}
+
+struct Handle<K: Ord, V> {
+    key: ManuallyDrop<K>,
+    node: Ref<V>,
+    graph: Weak<Shared<K, V>>,
+}
+
+impl<K: Ord, V> RcGraph<K, V> {
+    pub fn new() -> Self {
+        Self(Rc::new(Shared {
+            graph: RefCell::new(BTreeGraph::new()),
+            handles: RefCell::new(BTreeMap::new()),
+            released: RefCell::new(Vec::new()),
+            flushing: Cell::new(false),
+        }))
+    }
+
+    /// Insert a node into the graph and return a counted handle to
+    /// it. If a node with the same key is still alive, its value is
+    /// replaced and a new handle to the existing node is returned.
+    pub fn insert(&self, key: K, value: V) -> RcRef<K, V>
+    where
+        K: Clone,
+    {
+        self.flush();
+        if let Some(handle) = self.get_handle(&key) {
+            let old = core::mem::replace(&mut *self.borrow_mut(&handle), value);
+            drop(old);
+            return handle;
+        }
+        let node = self.0.graph.borrow_mut().insert(key.clone(), value);
+        let handle = Rc::new(Handle {
+            key: ManuallyDrop::new(key.clone()),
+            node,
+            graph: Rc::downgrade(&self.0),
+        });
+        self.0
+            .handles
+            .borrow_mut()
+            .insert(key, Rc::downgrade(&handle));
+        RcRef(handle)
+    }
+
+    /// Get a new handle to the node with the given key, if it is
+    /// still alive.
+    pub fn get_handle<Q>(&self, key: &Q) -> Option<RcRef<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.0.handles.borrow().get(key)?.upgrade().map(RcRef)
+    }
+
+    /// Borrow a node's value. Panics if the handle belongs to a
+    /// different graph, or if the graph is mutably borrowed.
+    pub fn borrow(&self, node: &RcRef<K, V>) -> cell::Ref<'_, V> {
+        assert!(self.owns(node), "handle belongs to a different graph");
+        cell::Ref::map(self.0.graph.borrow(), |graph| graph.borrow(&node.0.node))
+    }
+
+    /// Mutably borrow a node's value. Panics if the handle belongs
+    /// to a different graph, or if the graph is already borrowed.
+    pub fn borrow_mut(&self, node: &RcRef<K, V>) -> cell::RefMut<'_, V> {
+        assert!(self.owns(node), "handle belongs to a different graph");
+        cell::RefMut::map(self.0.graph.borrow_mut(), |graph| {
+            graph.borrow_mut(&node.0.node)
+        })
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<cell::Ref<'_, V>>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        cell::Ref::filter_map(self.0.graph.borrow(), |graph| graph.get(key)).ok()
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.0.graph.borrow().index().contains_key(key)
+    }
+
+    /// The number of nodes in the graph, including those whose
+    /// removal was deferred.
+    pub fn len(&self) -> usize {
+        self.0.graph.borrow().index().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove the nodes whose last handle was dropped while the graph
+    /// was borrowed.
+    pub fn flush(&self) {
+        self.0.flush()
+    }
+
+    fn owns(&self, node: &RcRef<K, V>) -> bool {
+        core::ptr::eq(node.0.graph.as_ptr(), Rc::as_ptr(&self.0))
+    }
+}
+
+impl<K: Ord, V> Default for RcGraph<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord, V> RcRef<K, V> {
+    pub fn key(&self) -> &K {
+        &self.0.key
+    }
+
+    /// The number of handles to this node.
+    pub fn count(this: &Self) -> usize {
+        Rc::strong_count(&this.0)
+    }
+}
+
+impl<K: Ord, V> Clone for RcRef<K, V> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<K: Ord, V> PartialEq for RcRef<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<K: Ord, V> Eq for RcRef<K, V> {}
+
+impl<K: Ord, V> Shared<K, V> {
+    /// Remove released nodes whose handles are (still) dead. Values
+    /// are dropped outside of the borrows, so that handles they hold
+    /// can be released in turn. These are processed iteratively by
+    /// the outermost call, to avoid deep recursion on long chains.
+    fn flush(&self) {
+        if self.flushing.replace(true) {
+            return;
+        }
+        loop {
+            let removed = {
+                let (Ok(mut graph), Ok(mut handles), Ok(mut released)) = (
+                    self.graph.try_borrow_mut(),
+                    self.handles.try_borrow_mut(),
+                    self.released.try_borrow_mut(),
+                ) else {
+                    break;
+                };
+                released
+                    .drain(..)
+                    .filter_map(|key| {
+                        if handles
+                            .get(&key)
+                            .is_some_and(|handle| handle.strong_count() == 0)
+                        {
+                            handles.remove(&key);
+                            graph.remove(&key)
+                        } else {
+                            None
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            };
+            if removed.is_empty() {
+                break;
+            }
+            drop(removed);
+        }
+        self.flushing.set(false);
+    }
+}
+
+impl<K: Ord, V> Drop for Handle<K, V> {
+    fn drop(&mut self) {
+        let key = unsafe { ManuallyDrop::take(&mut self.key) };
+        if let Some(shared) = self.graph.upgrade() {
+            shared.released.borrow_mut().push(key);
+            shared.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::{cell::Cell, rc::Rc};
+
+    use crate::{RcGraph, RcRef};
+
+    struct Node {
+        dropped: Rc<Cell<usize>>,
+        next: Option<RcRef<u32, Node>>,
+    }
+
+    impl Drop for Node {
+        fn drop(&mut self) {
+            self.dropped.set(self.dropped.get() + 1);
+        }
+    }
+
+    fn node(dropped: &Rc<Cell<usize>>, next: Option<RcRef<u32, Node>>) -> Node {
+        Node {
+            dropped: dropped.clone(),
+            next,
+        }
+    }
+
+    #[test]
+    fn last_drop() {
+        let dropped = Rc::new(Cell::new(0));
+        let graph = RcGraph::new();
+        let a = graph.insert(1, node(&dropped, None));
+        let b = a.clone();
+        assert_eq!(RcRef::count(&a), 2);
+        drop(a);
+        assert!(graph.contains_key(&1));
+        assert_eq!(dropped.get(), 0);
+        drop(b);
+        assert!(!graph.contains_key(&1));
+        assert_eq!(dropped.get(), 1);
+        assert!(graph.is_empty());
+    }
+
+    #[test]
+    fn reinsert() {
+        let dropped = Rc::new(Cell::new(0));
+        let graph = RcGraph::new();
+        let a = graph.insert(1, node(&dropped, None));
+        let b = graph.insert(1, node(&dropped, None));
+        assert!(a == b);
+        assert_eq!(dropped.get(), 1);
+        assert!(graph.borrow(&a).next.is_none());
+        drop((a, b));
+        assert_eq!(dropped.get(), 2);
+        let c = graph.insert(1, node(&dropped, None));
+        assert!(graph.get_handle(&1) == Some(c));
+    }
+
+    #[test]
+    fn cascade() {
+        let dropped = Rc::new(Cell::new(0));
+        let graph = RcGraph::new();
+        let mut next = None;
+        for i in 0..10000 {
+            next = Some(graph.insert(i, node(&dropped, next)));
+        }
+        assert_eq!(graph.len(), 10000);
+        let mid = graph.get_handle(&4999).unwrap();
+        drop(next);
+        assert_eq!(graph.len(), 5000);
+        assert_eq!(
+            graph.borrow(&mid).next.as_ref().map(RcRef::key),
+            Some(&4998)
+        );
+        drop(mid);
+        assert!(graph.is_empty());
+        assert_eq!(dropped.get(), 10000);
+    }
+
+    #[test]
+    fn deferred() {
+        let dropped = Rc::new(Cell::new(0));
+        let graph = RcGraph::new();
+        let a = graph.insert(1, node(&dropped, None));
+        let b = graph.insert(2, node(&dropped, None));
+        {
+            let _value = graph.borrow(&b);
+            drop(a);
+            assert_eq!(dropped.get(), 0);
+        }
+        assert!(graph.contains_key(&1));
+        graph.flush();
+        assert!(!graph.contains_key(&1));
+        assert_eq!(dropped.get(), 1);
+    }
+}
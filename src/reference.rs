@@ -12,43 +12,81 @@
  * under the License.                                                         * 
  ******************************************************************************/
 
-use std::{hash::Hash, ptr::NonNull};
+use core::{
+    hash::Hash,
+    marker::PhantomData,
+    ptr::{self, NonNull},
+};
 
 #[cfg(feature = "serde")]
 use serde::{de::Deserializer, ser::Serializer, Deserialize, Serialize};
 #[cfg(feature = "tsify")]
 use tsify::Tsify;
 
-use crate::{Gen, IndexBy};
+use crate::{
+    graph::{Slot, SlotState},
+    Gen, IndexBy, VisitRefs, VisitRefsBy,
+};
 
 /// A reference to a graph node.
+///
+/// The pointer is derived from the mutable reference returned by the
+/// arena on allocation and thus retains the provenance of the node's
+/// slot. Since the slot is an `UnsafeCell`, the node may be mutated
+/// through this pointer while the graph is only borrowed immutably.
 #[derive(Debug)]
 pub struct Ref<T> {
-    value: NonNull<Option<T>>,
+    value: NonNull<Slot<T>>,
     pub(crate) gen: Gen,
 }
 
+/// A reference to a part (e.g. a field) of a graph node. The
+/// projection functions select the part from a shared or mutable
+/// borrow of the node. They may be closures capturing their
+/// environment, e.g. the index of an element in a list.
+pub struct ProjectedRef<T, U: ?Sized, F = fn(&T) -> &U, G = fn(&mut T) -> &mut U> {
+    node: Ref<T>,
+    project: F,
+    project_mut: G,
+    _part: PhantomData<fn() -> *const U>,
+}
+
 /// A reference with an associated key. This can be used to make a
 /// structure serializable.
 #[derive(Debug)]
 #[cfg_attr(feature = "tsify", derive(Tsify))]
-#[cfg_attr(feature = "tsify", tsify(from_wasm_abi, into_wasm_abi, type = "K"))]
+#[cfg_attr(
+    feature = "tsify",
+    tsify(from_wasm_abi, into_wasm_abi, type = "Ref<K>")
+)]
 pub struct RefBy<K, V> {
     key: K,
     value: Ref<V>,
 }
 
+/// A borrowed view of a reference and its key, as yielded by
+/// `iter_ref_by_ref` on the keyed graphs. It offers the accessors of
+/// `RefBy` without cloning the key.
+#[derive(Debug)]
+pub struct RefByRef<'a, K, V> {
+    key: &'a K,
+    value: &'a Ref<V>,
+}
+
 /// A reference that may or may not be resolvable.
 #[derive(Debug)]
 #[cfg_attr(feature = "tsify", derive(Tsify))]
-#[cfg_attr(feature = "tsify", tsify(from_wasm_abi, into_wasm_abi, type = "K"))]
+#[cfg_attr(
+    feature = "tsify",
+    tsify(from_wasm_abi, into_wasm_abi, type = "Ref<K>")
+)]
 pub struct OptRefBy<K, V> {
     key: K,
     value: Option<Ref<V>>,
 }
 
 impl<T> Ref<T> {
-    pub(crate) fn new(value: NonNull<Option<T>>, gen: Gen) -> Self {
+    pub(crate) fn new(value: NonNull<Slot<T>>, gen: Gen) -> Self {
         Self { value, gen }
     }
 
@@ -67,32 +105,130 @@ impl<T> Ref<T> {
         self.gen.is_invalid()
     }
 
+    /// A key identifying the referenced node: its address and the
+    /// generation of its graph. Two valid references have the same
+    /// key exactly when they compare equal, so the key can stand in
+    /// for the reference as a map key.
+    pub fn identity_key(&self) -> (usize, u64) {
+        (self.value.as_ptr() as usize, self.gen.value())
+    }
+
+    /// Create a reference to a part of the node, selected by the
+    /// given projection functions.
+    pub fn project<U: ?Sized, F, G>(&self, project: F, project_mut: G) -> ProjectedRef<T, U, F, G>
+    where
+        F: Fn(&T) -> &U,
+        G: Fn(&mut T) -> &mut U,
+    {
+        ProjectedRef {
+            node: self.clone(),
+            project,
+            project_mut,
+            _part: PhantomData,
+        }
+    }
+
+    /// Reinterpret the reference as a reference to a node of type
+    /// `U`, e.g. a `#[repr(transparent)]` wrapper around `T`.
+    ///
+    /// # Safety
+    ///
+    /// `T` and `U` must have an identical layout and every valid `T`
+    /// must be a valid `U`. The resulting reference may only be used
+    /// with the graph that owns the original node.
+    pub unsafe fn cast<U>(self) -> Ref<U> {
+        Ref {
+            value: self.value.cast(),
+            gen: self.gen,
+        }
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const Slot<T> {
+        self.value.as_ptr()
+    }
+
     /// Safety: when using this method, take (at least) a shared
     /// reference to the container and check the Ref's validity
     /// (ref.gen == container.gen).
     pub(crate) unsafe fn try_get_unchecked<'a>(&self) -> Option<&'a T> {
-        (*self.value.as_ptr()).as_ref()
+        (*Slot::raw_get(self.value.as_ptr())).as_ref()
     }
 
     /// Safety: when using this method, take a mutable reference to
     /// the container and check the Ref's validity (ref.gen ==
     /// container.gen).
     pub(crate) unsafe fn try_get_unchecked_mut<'a>(&self) -> Option<&'a mut T> {
-        (*self.value.as_ptr()).as_mut()
+        (*Slot::raw_get(self.value.as_ptr())).as_mut()
+    }
+
+    /// Like `try_get_unchecked`, but panics if the node was promised
+    /// but not yet created, or if it was removed.
+    #[track_caller]
+    pub(crate) unsafe fn get_unchecked<'a>(&self) -> &'a T {
+        let slot = &*Slot::raw_get(self.value.as_ptr());
+        match slot.as_ref() {
+            Some(value) => value,
+            None => slot.empty(),
+        }
+    }
+
+    /// Like `try_get_unchecked_mut`, but panics if the node was
+    /// promised but not yet created, or if it was removed.
+    #[track_caller]
+    pub(crate) unsafe fn get_unchecked_mut<'a>(&self) -> &'a mut T {
+        match self.try_get_unchecked_mut() {
+            Some(value) => value,
+            None => (*Slot::raw_get(self.value.as_ptr())).empty(),
+        }
+    }
+
+    /// Returns true if the node was promised but not yet created.
+    ///
+    /// Safety: when using this method, take (at least) a shared
+    /// reference to the container and check the Ref's validity
+    /// (ref.gen == container.gen).
+    pub(crate) unsafe fn is_promised_unchecked(&self) -> bool {
+        (*Slot::raw_get(self.value.as_ptr())).is_promised()
+    }
+
+    /// Returns true if the node was removed. Same safety
+    /// requirements as `is_promised_unchecked`.
+    #[cfg(any(feature = "std", test))]
+    pub(crate) unsafe fn is_removed_unchecked(&self) -> bool {
+        matches!(*Slot::raw_get(self.value.as_ptr()), SlotState::Removed)
     }
 
     /// Safety: when using this method, take a mutable reference to
     /// the container and check the Ref's validity (ref.gen ==
     /// container.gen).
     pub(crate) unsafe fn try_remove_unchecked(&self) -> Option<T> {
-        (*self.value.as_ptr()).take()
+        let slot = Slot::raw_get(self.value.as_ptr());
+        match ptr::replace(slot, SlotState::Removed) {
+            SlotState::Created(value) => Some(value),
+            state => {
+                ptr::write(slot, state);
+                None
+            }
+        }
+    }
+
+    /// Like `try_remove_unchecked`, but also removes a node that was
+    /// promised but not yet created.
+    ///
+    /// Safety: see `try_remove_unchecked`.
+    pub(crate) unsafe fn discard_unchecked(&self) -> Option<T> {
+        ptr::replace(Slot::raw_get(self.value.as_ptr()), SlotState::Removed).into_option()
     }
 
     /// Safety: when using this method, take a mutable reference to
     /// the container and check the Ref's validity (ref.gen ==
     /// container.gen).
     pub(crate) unsafe fn try_replace_unchecked(&self, value: T) -> Option<T> {
-        (*self.value.as_ptr()).replace(value)
+        ptr::replace(
+            Slot::raw_get(self.value.as_ptr()),
+            SlotState::Created(value),
+        )
+        .into_option()
     }
 }
 
@@ -105,9 +241,21 @@ impl<T> Clone for Ref<T> {
     }
 }
 
+/// References are equal if they point to the same node of the same
+/// graph.
 impl<T> PartialEq for Ref<T> {
     fn eq(&self, other: &Self) -> bool {
-        self.gen == other.gen && self.value == other.value
+        self.value == other.value
+            && (self.gen == other.gen || self.gen.is_invalid() && other.gen.is_invalid())
+    }
+}
+
+/// All invalid (dangling) references compare equal.
+impl<T> Eq for Ref<T> {}
+
+impl<T> Hash for Ref<T> {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.value.hash(state)
     }
 }
 
@@ -117,6 +265,44 @@ impl<T> AsRef<Ref<T>> for Ref<T> {
     }
 }
 
+impl<T, U: ?Sized, F, G> ProjectedRef<T, U, F, G> {
+    /// The reference to the node containing the projected part.
+    pub fn node(&self) -> &Ref<T> {
+        &self.node
+    }
+
+    pub(crate) fn project<'a>(&self, value: &'a T) -> &'a U
+    where
+        F: Fn(&T) -> &U,
+    {
+        (self.project)(value)
+    }
+
+    pub(crate) fn project_mut<'a>(&self, value: &'a mut T) -> &'a mut U
+    where
+        G: Fn(&mut T) -> &mut U,
+    {
+        (self.project_mut)(value)
+    }
+}
+
+impl<T, U: ?Sized, F: Clone, G: Clone> Clone for ProjectedRef<T, U, F, G> {
+    fn clone(&self) -> Self {
+        Self {
+            node: self.node.clone(),
+            project: self.project.clone(),
+            project_mut: self.project_mut.clone(),
+            _part: PhantomData,
+        }
+    }
+}
+
+impl<T, U: ?Sized, F, G> AsRef<Ref<T>> for ProjectedRef<T, U, F, G> {
+    fn as_ref(&self) -> &Ref<T> {
+        &self.node
+    }
+}
+
 impl<K, V> RefBy<K, V> {
     pub fn new(key: K, value: Ref<V>) -> Self {
         Self { key, value }
@@ -126,6 +312,16 @@ impl<K, V> RefBy<K, V> {
         Self::new(key, Ref::dangling())
     }
 
+    /// Create a reference that is resolved right away against `index`
+    /// (e.g. a `BTreeGraph`). Returns `None` if the key is not found.
+    pub fn resolved<I>(key: K, index: &I) -> Option<Self>
+    where
+        I: IndexBy<K, V>,
+    {
+        let value = index.get(&key)?.clone();
+        Some(Self::new(key, value))
+    }
+
     pub fn key(&self) -> &K {
         &self.key
     }
@@ -138,6 +334,16 @@ impl<K, V> RefBy<K, V> {
         (&self.key, &self.value)
     }
 
+    /// Transform the key, keeping the reference. This re-keys
+    /// references to a graph whose keys were transformed with
+    /// `map_keys`, without resolving them again.
+    pub fn map_key<K2, F>(self, f: F) -> RefBy<K2, V>
+    where
+        F: FnOnce(K) -> K2,
+    {
+        RefBy::new(f(self.key), self.value)
+    }
+
     pub fn resolve<I>(&mut self, index: &I) -> Result<(), K>
     where
         K: Ord + Clone,
@@ -171,19 +377,19 @@ impl<K: PartialEq, V> PartialEq for RefBy<K, V> {
 impl<K: Eq, V> Eq for RefBy<K, V> {}
 
 impl<K: PartialOrd, V> PartialOrd for RefBy<K, V> {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
         self.key.partial_cmp(&other.key)
     }
 }
 
 impl<K: Ord, V> Ord for RefBy<K, V> {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
         self.key.cmp(&other.key)
     }
 }
 
 impl<K: Hash, V> Hash for RefBy<K, V> {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
         self.key.hash(state);
     }
 }
@@ -228,6 +434,103 @@ where
     }
 }
 
+impl<K, V> VisitRefs<V> for RefBy<K, V> {
+    fn visit_refs(&self, f: &mut impl FnMut(&Ref<V>)) {
+        f(&self.value)
+    }
+
+    fn visit_refs_mut(&mut self, f: &mut impl FnMut(&mut Ref<V>)) {
+        f(&mut self.value)
+    }
+}
+
+impl<K, V> VisitRefsBy<K, V> for RefBy<K, V> {
+    fn visit_refs_by_mut(&mut self, f: &mut impl FnMut(&K, &mut Ref<V>) -> Option<K>) {
+        if let Some(key) = f(&self.key, &mut self.value) {
+            self.key = key;
+        }
+    }
+}
+
+impl<'a, K, V> RefByRef<'a, K, V> {
+    pub fn new(key: &'a K, value: &'a Ref<V>) -> Self {
+        Self { key, value }
+    }
+
+    pub fn key(&self) -> &'a K {
+        self.key
+    }
+
+    pub fn value_ref(&self) -> &'a Ref<V> {
+        self.value
+    }
+
+    pub fn pair(&self) -> (&'a K, &'a Ref<V>) {
+        (self.key, self.value)
+    }
+
+    /// Clone the key and reference into an owned `RefBy`.
+    pub fn to_ref_by(&self) -> RefBy<K, V>
+    where
+        K: Clone,
+    {
+        RefBy::new(self.key.clone(), self.value.clone())
+    }
+}
+
+impl<K, V> Clone for RefByRef<'_, K, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K, V> Copy for RefByRef<'_, K, V> {}
+
+impl<K: PartialEq, V> PartialEq for RefByRef<'_, K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<K: Eq, V> Eq for RefByRef<'_, K, V> {}
+
+impl<K: PartialOrd, V> PartialOrd for RefByRef<'_, K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        self.key.partial_cmp(other.key)
+    }
+}
+
+impl<K: Ord, V> Ord for RefByRef<'_, K, V> {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.key.cmp(other.key)
+    }
+}
+
+impl<K: Hash, V> Hash for RefByRef<'_, K, V> {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.key.hash(state);
+    }
+}
+
+impl<K, V> AsRef<Ref<V>> for RefByRef<'_, K, V> {
+    fn as_ref(&self) -> &Ref<V> {
+        self.value
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K, V> Serialize for RefByRef<'_, K, V>
+where
+    K: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.key.serialize(serializer)
+    }
+}
+
 impl<K, V> OptRefBy<K, V> {
     pub fn new(key: K, value: Option<Ref<V>>) -> Self {
         Self { key, value }
@@ -248,6 +551,15 @@ impl<K, V> OptRefBy<K, V> {
         self.value.as_ref()
     }
 
+    /// Transform the key, keeping the reference. See
+    /// `RefBy::map_key`.
+    pub fn map_key<K2, F>(self, f: F) -> OptRefBy<K2, V>
+    where
+        F: FnOnce(K) -> K2,
+    {
+        OptRefBy::new(f(self.key), self.value)
+    }
+
     pub fn resolve<I>(&mut self, index: &I)
     where
         K: Ord + Clone,
@@ -257,6 +569,26 @@ impl<K, V> OptRefBy<K, V> {
     }
 }
 
+impl<K, V> VisitRefs<V> for OptRefBy<K, V> {
+    fn visit_refs(&self, f: &mut impl FnMut(&Ref<V>)) {
+        self.value.visit_refs(f)
+    }
+
+    fn visit_refs_mut(&mut self, f: &mut impl FnMut(&mut Ref<V>)) {
+        self.value.visit_refs_mut(f)
+    }
+}
+
+/// An unresolved reference is not visited, as there is no
+/// reference to go with its key.
+impl<K, V> VisitRefsBy<K, V> for OptRefBy<K, V> {
+    fn visit_refs_by_mut(&mut self, f: &mut impl FnMut(&K, &mut Ref<V>) -> Option<K>) {
+        if let Some(key) = self.value.as_mut().and_then(|value| f(&self.key, value)) {
+            self.key = key;
+        }
+    }
+}
+
 impl<K: Clone, V> Clone for OptRefBy<K, V> {
     fn clone(&self) -> Self {
         Self {
@@ -12,29 +12,88 @@
  * under the License.                                                         * 
  ******************************************************************************/
 
-use std::{borrow::Borrow, cmp::Ordering, collections::BTreeMap};
+use alloc::{
+    boxed::Box,
+    collections::{btree_map, BTreeMap},
+    vec::Vec,
+};
+use core::{borrow::Borrow, cmp::Ordering, iter::FusedIterator};
 #[cfg(feature = "serde")]
-use std::{fmt::Formatter, marker::PhantomData};
+use core::{
+    fmt::{Debug, Formatter},
+    marker::PhantomData,
+};
 
 #[cfg(feature = "serde")]
 use serde::{
-    de::{Deserializer, SeqAccess, Visitor},
-    ser::{SerializeSeq, Serializer},
+    de::{DeserializeSeed, Deserializer, Error, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    ser::{SerializeMap, SerializeSeq, Serializer},
     Deserialize, Serialize,
 };
 #[cfg(feature = "tsify")]
 use tsify::Tsify;
 
-use crate::{BTreeGraph, Graph, IndexBy, OptRefBy, Ref, RefBy};
+use crate::{Graph, GraphMut, IndexBy, KeysView, OptRefBy, Ref, RefBy, VisitRefs, VisitRefsBy};
 
 #[cfg_attr(feature = "tsify", derive(Tsify))]
-#[cfg_attr(feature = "tsify", tsify(from_wasm_abi, into_wasm_abi, type = "[K]"))]
+#[cfg_attr(
+    feature = "tsify",
+    tsify(from_wasm_abi, into_wasm_abi, type = "Ref<K>[]")
+)]
 pub struct RefMap<K, V>(BTreeMap<K, Ref<V>>);
 
 #[cfg_attr(feature = "tsify", derive(Tsify))]
-#[cfg_attr(feature = "tsify", tsify(from_wasm_abi, into_wasm_abi, type = "[K]"))]
+#[cfg_attr(
+    feature = "tsify",
+    tsify(from_wasm_abi, into_wasm_abi, type = "Ref<K>[]")
+)]
 pub struct OptRefMap<K, V>(BTreeMap<K, Option<Ref<V>>>);
 
+/// How `RefMap::merge` and `OptRefMap::merge` resolve a key that is
+/// present in both maps with different references. `R` is the
+/// stored reference type (`Ref<V>` or `Option<Ref<V>>`).
+pub enum MergePolicy<'a, K, R> {
+    /// Keep the reference already in the map.
+    PreferSelf,
+    /// Replace it with the reference from the other map.
+    PreferOther,
+    /// Call the function with the key and both references (ours
+    /// first) and store the returned reference.
+    WithFn(Box<MergeFn<'a, K, R>>),
+}
+
+type MergeFn<'a, K, R> = dyn FnMut(&K, R, R) -> R + 'a;
+
+impl<K: Ord, R: PartialEq> MergePolicy<'_, K, R> {
+    /// Merge `other` into `map`, returning the number of conflicts.
+    fn merge(&mut self, map: &mut BTreeMap<K, R>, other: BTreeMap<K, R>) -> usize {
+        let mut conflicts = 0;
+        for (key, theirs) in other {
+            match map.entry(key) {
+                btree_map::Entry::Vacant(ent) => {
+                    ent.insert(theirs);
+                }
+                btree_map::Entry::Occupied(ent) if *ent.get() == theirs => {}
+                btree_map::Entry::Occupied(mut ent) => {
+                    conflicts += 1;
+                    match self {
+                        Self::PreferSelf => {}
+                        Self::PreferOther => {
+                            ent.insert(theirs);
+                        }
+                        Self::WithFn(f) => {
+                            let (key, ours) = ent.remove_entry();
+                            let merged = f(&key, ours, theirs);
+                            map.insert(key, merged);
+                        }
+                    }
+                }
+            }
+        }
+        conflicts
+    }
+}
+
 impl<K, V> RefMap<K, V> {
     pub fn new() -> Self {
         Self(BTreeMap::new())
@@ -70,22 +129,38 @@ impl<K, V> RefMap<K, V> {
         Some(RefBy::new(key.clone(), value.clone()))
     }
 
-    pub fn get<'a, Q>(&self, key: &Q, graph: &'a BTreeGraph<K, V>) -> Option<&'a V>
+    pub fn get<'a, Q, G>(&self, key: &Q, graph: &'a G) -> Option<&'a V>
     where
         K: Borrow<Q> + Ord,
         Q: Ord,
+        G: AsRef<Graph<V>>,
     {
-        Some(graph.borrow(self.get_ref(key)?))
+        Some(graph.as_ref().borrow(self.get_ref(key)?))
     }
 
-    pub fn get_mut<'a, Q>(&self, key: &Q, graph: &'a mut BTreeGraph<K, V>) -> Option<&'a V>
+    /// Mutably borrow the value for `key` from the graph. The graph's
+    /// keys need not be the same as the map's keys.
+    pub fn get_mut<'a, Q, G>(&self, key: &Q, graph: &'a mut G) -> Option<&'a mut V>
     where
         K: Borrow<Q> + Ord,
         Q: Ord,
+        G: GraphMut<V>,
     {
         Some(graph.borrow_mut(self.get_ref(key)?))
     }
 
+    /// Keep only the entries for which the predicate returns true,
+    /// giving it mutable access to the referenced values. Only the
+    /// map entries are removed, not the nodes.
+    pub fn retain_resolved<G, F>(&mut self, graph: &mut G, mut f: F)
+    where
+        K: Ord,
+        G: GraphMut<V>,
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        self.0.retain(|key, node| f(key, graph.borrow_mut(node)))
+    }
+
     pub fn contains_key<Q>(&self, key: &Q) -> bool
     where
         K: Borrow<Q> + Ord,
@@ -94,32 +169,37 @@ impl<K, V> RefMap<K, V> {
         self.0.contains_key(key)
     }
 
-    pub fn iter_ref(&self) -> impl Iterator<Item = (&K, &Ref<V>)> {
+    pub fn iter_ref(&self) -> btree_map::Iter<'_, K, Ref<V>> {
         self.0.iter()
     }
 
-    pub fn iter_ref_by(&self) -> impl Iterator<Item = RefBy<K, V>> + '_
+    pub fn iter_ref_by(&self) -> IterRefBy<'_, K, V>
     where
         K: Clone,
     {
-        self.iter_ref()
-            .map(|(k, v)| RefBy::new(k.clone(), v.clone()))
+        IterRefBy(self.0.iter())
     }
 
-    pub fn keys(&self) -> impl Iterator<Item = &K> {
-        self.0.keys()
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys(self.0.keys())
     }
 
-    pub fn value_refs(&self) -> impl Iterator<Item = &Ref<V>> {
+    pub fn keys_view(&self) -> KeysView<'_, BTreeMap<K, Ref<V>>>
+    where
+        K: Ord,
+    {
+        KeysView::new(&self.0)
+    }
+
+    pub fn value_refs(&self) -> btree_map::Values<'_, K, Ref<V>> {
         self.0.values()
     }
 
-    pub fn iter<'a, G: AsRef<Graph<V>>>(
-        &'a self,
-        graph: &'a G,
-    ) -> impl Iterator<Item = (&'a K, &'a V)> {
-        let graph = graph.as_ref();
-        self.iter_ref().map(|(k, v)| (k, graph.borrow(v)))
+    pub fn iter<'m, 'g, G: AsRef<Graph<V>>>(&'m self, graph: &'g G) -> Iter<'m, 'g, K, V> {
+        Iter {
+            refs: self.0.iter(),
+            graph: graph.as_ref(),
+        }
     }
 
     // pub fn iter_mut<'a, Q>(
@@ -129,12 +209,11 @@ impl<K, V> RefMap<K, V> {
     //     self.iter_ref().map(|(k, v)| (k, graph.borrow_mut(v)))
     // }
 
-    pub fn values<'a, G: AsRef<Graph<V>>>(
-        &'a self,
-        graph: &'a G,
-    ) -> impl Iterator<Item = &'a V> + 'a {
-        let graph = graph.as_ref();
-        self.value_refs().map(|v| graph.borrow(v))
+    pub fn values<'m, 'g, G: AsRef<Graph<V>>>(&'m self, graph: &'g G) -> Values<'m, 'g, K, V> {
+        Values {
+            refs: self.0.values(),
+            graph: graph.as_ref(),
+        }
     }
 
     pub fn insert(&mut self, key: K, value: Ref<V>) -> Option<Ref<V>>
@@ -152,6 +231,10 @@ impl<K, V> RefMap<K, V> {
         self.0.remove(key)
     }
 
+    /// Move all references from `other` into this map, leaving
+    /// `other` empty. For keys present in both maps, the reference
+    /// from `other` wins. Use `merge` to choose otherwise or to
+    /// detect such conflicts.
     pub fn append(&mut self, other: &mut Self)
     where
         K: Ord,
@@ -159,6 +242,17 @@ impl<K, V> RefMap<K, V> {
         self.0.append(&mut other.0)
     }
 
+    /// Move all references from `other` into this map. Keys present
+    /// in both maps with different references are resolved according
+    /// to `policy`. Returns the number of such conflicts; keys
+    /// referring to the same node in both maps are not counted.
+    pub fn merge(&mut self, other: RefMap<K, V>, mut policy: MergePolicy<'_, K, Ref<V>>) -> usize
+    where
+        K: Ord,
+    {
+        policy.merge(&mut self.0, other.0)
+    }
+
     pub fn resolve<I>(&mut self, index: &I) -> Result<(), K>
     where
         K: Ord + Clone,
@@ -174,6 +268,38 @@ impl<K, V> RefMap<K, V> {
                 None => Err(key.clone()),
             })
     }
+
+    /// Collect the map from an iterator, failing with the first key
+    /// that occurs more than once. Use `FromIterator` to silently
+    /// keep the last value instead.
+    pub fn try_from_iter<T>(iter: T) -> Result<Self, K>
+    where
+        K: Ord,
+        T: IntoIterator<Item = (K, Ref<V>)>,
+    {
+        Ok(Self(collect_unique(iter)?))
+    }
+
+    /// Transform the map's keys, keeping the references. If `f` maps
+    /// multiple keys to the same key, the reference for the largest
+    /// original key wins. Use `try_map_keys` to detect this instead.
+    pub fn map_keys<K2, F>(self, mut f: F) -> RefMap<K2, V>
+    where
+        K2: Ord,
+        F: FnMut(K) -> K2,
+    {
+        RefMap(self.0.into_iter().map(|(k, v)| (f(k), v)).collect())
+    }
+
+    /// Transform the map's keys, keeping the references. Fails with
+    /// the first transformed key that occurs more than once.
+    pub fn try_map_keys<K2, F>(self, mut f: F) -> Result<RefMap<K2, V>, K2>
+    where
+        K2: Ord,
+        F: FnMut(K) -> K2,
+    {
+        RefMap::try_from_iter(self.0.into_iter().map(|(k, v)| (f(k), v)))
+    }
 }
 
 impl<K, V> Default for RefMap<K, V> {
@@ -218,12 +344,35 @@ impl<K: Ord, V> Ord for RefMap<K, V> {
 
 impl<K, V> IntoIterator for RefMap<K, V> {
     type Item = (K, Ref<V>);
-    type IntoIter = std::collections::btree_map::IntoIter<K, Ref<V>>;
+    type IntoIter = alloc::collections::btree_map::IntoIter<K, Ref<V>>;
     fn into_iter(self) -> Self::IntoIter {
         self.0.into_iter()
     }
 }
 
+impl<K, V> VisitRefs<V> for RefMap<K, V> {
+    fn visit_refs(&self, f: &mut impl FnMut(&Ref<V>)) {
+        self.0.values().for_each(f)
+    }
+
+    fn visit_refs_mut(&mut self, f: &mut impl FnMut(&mut Ref<V>)) {
+        self.0.values_mut().for_each(f)
+    }
+}
+
+/// Entries whose keys are replaced move to their new position,
+/// replacing any entry that is already there.
+impl<K: Ord + Clone, V> VisitRefsBy<K, V> for RefMap<K, V> {
+    fn visit_refs_by_mut(&mut self, f: &mut impl FnMut(&K, &mut Ref<V>) -> Option<K>) {
+        let moved = self
+            .0
+            .iter_mut()
+            .filter_map(|(key, value)| Some((key.clone(), f(key, value)?)))
+            .collect::<Vec<_>>();
+        rekey(&mut self.0, moved);
+    }
+}
+
 impl<K, V> FromIterator<(K, Ref<V>)> for RefMap<K, V>
 where
     K: Ord,
@@ -265,7 +414,7 @@ where
         {
             type Value = RefMap<K, V>;
 
-            fn expecting(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+            fn expecting(&self, formatter: &mut Formatter<'_>) -> core::fmt::Result {
                 write!(formatter, "a sequence of keys")
             }
 
@@ -328,18 +477,22 @@ impl<K, V> OptRefMap<K, V> {
         Some(RefBy::new(key.clone(), value.clone()))
     }
 
-    pub fn get<'a, Q>(&self, key: &Q, graph: &'a BTreeGraph<K, V>) -> Option<&'a V>
+    pub fn get<'a, Q, G>(&self, key: &Q, graph: &'a G) -> Option<&'a V>
     where
         K: Borrow<Q> + Ord,
         Q: Ord,
+        G: AsRef<Graph<V>>,
     {
-        Some(graph.borrow(self.get_ref(key)?))
+        Some(graph.as_ref().borrow(self.get_ref(key)?))
     }
 
-    pub fn get_mut<'a, Q>(&self, key: &Q, graph: &'a mut BTreeGraph<K, V>) -> Option<&'a V>
+    /// Mutably borrow the value for `key` from the graph. The graph's
+    /// keys need not be the same as the map's keys.
+    pub fn get_mut<'a, Q, G>(&self, key: &Q, graph: &'a mut G) -> Option<&'a mut V>
     where
         K: Borrow<Q> + Ord,
         Q: Ord,
+        G: GraphMut<V>,
     {
         Some(graph.borrow_mut(self.get_ref(key)?))
     }
@@ -352,32 +505,37 @@ impl<K, V> OptRefMap<K, V> {
         self.0.contains_key(key)
     }
 
-    pub fn iter_ref(&self) -> impl Iterator<Item = (&K, &Option<Ref<V>>)> {
+    pub fn iter_ref(&self) -> btree_map::Iter<'_, K, Option<Ref<V>>> {
         self.0.iter()
     }
 
-    pub fn iter_ref_by(&self) -> impl Iterator<Item = OptRefBy<K, V>> + '_
+    pub fn iter_ref_by(&self) -> IterOptRefBy<'_, K, V>
     where
         K: Clone,
     {
-        self.iter_ref()
-            .map(|(k, v)| OptRefBy::new(k.clone(), v.clone()))
+        IterOptRefBy(self.0.iter())
+    }
+
+    pub fn keys(&self) -> OptKeys<'_, K, V> {
+        OptKeys(self.0.keys())
     }
 
-    pub fn keys(&self) -> impl Iterator<Item = &K> {
-        self.0.keys()
+    pub fn keys_view(&self) -> KeysView<'_, BTreeMap<K, Option<Ref<V>>>>
+    where
+        K: Ord,
+    {
+        KeysView::new(&self.0)
     }
 
-    pub fn value_refs(&self) -> impl Iterator<Item = &Option<Ref<V>>> {
+    pub fn value_refs(&self) -> btree_map::Values<'_, K, Option<Ref<V>>> {
         self.0.values()
     }
 
-    pub fn iter<'a, Q>(
-        &'a self,
-        graph: &'a BTreeGraph<Q, V>,
-    ) -> impl Iterator<Item = (&'a K, Option<&'a V>)> {
-        self.iter_ref()
-            .map(|(k, v)| (k, v.as_ref().map(|v| graph.borrow(v))))
+    pub fn iter<'m, 'g, G: AsRef<Graph<V>>>(&'m self, graph: &'g G) -> OptIter<'m, 'g, K, V> {
+        OptIter {
+            refs: self.0.iter(),
+            graph: graph.as_ref(),
+        }
     }
 
     // pub fn iter_mut<'a, Q>(
@@ -387,12 +545,11 @@ impl<K, V> OptRefMap<K, V> {
     //     self.iter_ref().map(|(k, v)| (k, graph.borrow_mut(v)))
     // }
 
-    pub fn values<'a, Q>(
-        &'a self,
-        graph: &'a BTreeGraph<Q, V>,
-    ) -> impl Iterator<Item = &'a V> + 'a {
-        self.value_refs()
-            .filter_map(|v| Some(graph.borrow(v.as_ref()?)))
+    pub fn values<'m, 'g, G: AsRef<Graph<V>>>(&'m self, graph: &'g G) -> OptValues<'m, 'g, K, V> {
+        OptValues {
+            refs: self.0.values(),
+            graph: graph.as_ref(),
+        }
     }
 
     pub fn insert(&mut self, key: K, value: Option<Ref<V>>)
@@ -410,6 +567,32 @@ impl<K, V> OptRefMap<K, V> {
         self.0.remove(key);
     }
 
+    /// Move all references from `other` into this map, like
+    /// `RefMap::merge`. An unresolved key conflicts with a resolved
+    /// one for the same key.
+    pub fn merge(
+        &mut self,
+        other: OptRefMap<K, V>,
+        mut policy: MergePolicy<'_, K, Option<Ref<V>>>,
+    ) -> usize
+    where
+        K: Ord,
+    {
+        policy.merge(&mut self.0, other.0)
+    }
+
+    /// Get the entry for `key`, to insert or update its reference
+    /// with a single lookup.
+    pub fn entry(&mut self, key: K) -> OptEntry<'_, K, V>
+    where
+        K: Ord,
+    {
+        match self.0.entry(key) {
+            btree_map::Entry::Occupied(ent) => OptEntry::Occupied(OptOccupiedEntry(ent)),
+            btree_map::Entry::Vacant(ent) => OptEntry::Vacant(OptVacantEntry(ent)),
+        }
+    }
+
     pub fn resolve<I>(&mut self, index: &I)
     where
         K: Ord + Clone,
@@ -419,6 +602,29 @@ impl<K, V> OptRefMap<K, V> {
             .iter_mut()
             .for_each(|(key, value)| *value = index.get(key).cloned())
     }
+
+    /// Transform the map's keys, keeping the references. If `f` maps
+    /// multiple keys to the same key, the reference for the largest
+    /// original key wins. Use `try_map_keys` to detect this instead.
+    pub fn map_keys<K2, F>(self, mut f: F) -> OptRefMap<K2, V>
+    where
+        K2: Ord,
+        F: FnMut(K) -> K2,
+    {
+        OptRefMap(self.0.into_iter().map(|(k, v)| (f(k), v)).collect())
+    }
+
+    /// Transform the map's keys, keeping the references. Fails with
+    /// the first transformed key that occurs more than once.
+    pub fn try_map_keys<K2, F>(self, mut f: F) -> Result<OptRefMap<K2, V>, K2>
+    where
+        K2: Ord,
+        F: FnMut(K) -> K2,
+    {
+        Ok(OptRefMap(collect_unique(
+            self.0.into_iter().map(|(k, v)| (f(k), v)),
+        )?))
+    }
 }
 
 impl<K, V> Default for OptRefMap<K, V> {
@@ -429,12 +635,34 @@ impl<K, V> Default for OptRefMap<K, V> {
 
 impl<K, V> IntoIterator for OptRefMap<K, V> {
     type Item = (K, Option<Ref<V>>);
-    type IntoIter = std::collections::btree_map::IntoIter<K, Option<Ref<V>>>;
+    type IntoIter = alloc::collections::btree_map::IntoIter<K, Option<Ref<V>>>;
     fn into_iter(self) -> Self::IntoIter {
         self.0.into_iter()
     }
 }
 
+impl<K, V> VisitRefs<V> for OptRefMap<K, V> {
+    fn visit_refs(&self, f: &mut impl FnMut(&Ref<V>)) {
+        self.0.values().flatten().for_each(f)
+    }
+
+    fn visit_refs_mut(&mut self, f: &mut impl FnMut(&mut Ref<V>)) {
+        self.0.values_mut().flatten().for_each(f)
+    }
+}
+
+/// See `RefMap`. Unresolved entries are not visited.
+impl<K: Ord + Clone, V> VisitRefsBy<K, V> for OptRefMap<K, V> {
+    fn visit_refs_by_mut(&mut self, f: &mut impl FnMut(&K, &mut Ref<V>) -> Option<K>) {
+        let moved = self
+            .0
+            .iter_mut()
+            .filter_map(|(key, value)| Some((key.clone(), f(key, value.as_mut()?)?)))
+            .collect::<Vec<_>>();
+        rekey(&mut self.0, moved);
+    }
+}
+
 impl<K, V> FromIterator<(K, Option<Ref<V>>)> for OptRefMap<K, V>
 where
     K: Ord,
@@ -476,7 +704,7 @@ where
         {
             type Value = OptRefMap<K, V>;
 
-            fn expecting(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+            fn expecting(&self, formatter: &mut Formatter<'_>) -> core::fmt::Result {
                 write!(formatter, "a sequence of keys")
             }
 
@@ -506,3 +734,944 @@ impl<K: Clone, V> Clone for OptRefMap<K, V> {
         Self(self.0.clone())
     }
 }
+
+/// An iterator over the keys of a `RefMap`.
+pub struct Keys<'a, K, V>(btree_map::Keys<'a, K, Ref<V>>);
+
+impl<'a, K, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Keys<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back()
+    }
+}
+
+impl<K, V> ExactSizeIterator for Keys<'_, K, V> {}
+impl<K, V> FusedIterator for Keys<'_, K, V> {}
+
+/// An iterator over the keys and referenced values of a `RefMap`.
+pub struct Iter<'m, 'g, K, V> {
+    refs: btree_map::Iter<'m, K, Ref<V>>,
+    graph: &'g Graph<V>,
+}
+
+impl<'m, 'g, K, V> Iterator for Iter<'m, 'g, K, V> {
+    type Item = (&'m K, &'g V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, node) = self.refs.next()?;
+        Some((key, self.graph.borrow(node)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.refs.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, '_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (key, node) = self.refs.next_back()?;
+        Some((key, self.graph.borrow(node)))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, '_, K, V> {}
+impl<K, V> FusedIterator for Iter<'_, '_, K, V> {}
+
+/// An iterator over the referenced values of a `RefMap`.
+pub struct Values<'m, 'g, K, V> {
+    refs: btree_map::Values<'m, K, Ref<V>>,
+    graph: &'g Graph<V>,
+}
+
+impl<'m, 'g, K, V> Iterator for Values<'m, 'g, K, V> {
+    type Item = &'g V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.graph.borrow(self.refs.next()?))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.refs.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Values<'_, '_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        Some(self.graph.borrow(self.refs.next_back()?))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Values<'_, '_, K, V> {}
+impl<K, V> FusedIterator for Values<'_, '_, K, V> {}
+
+/// An iterator over the entries of a `RefMap` as `RefBy`s, cloning
+/// the keys.
+pub struct IterRefBy<'a, K, V>(btree_map::Iter<'a, K, Ref<V>>);
+
+impl<K: Clone, V> Iterator for IterRefBy<'_, K, V> {
+    type Item = RefBy<K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, node) = self.0.next()?;
+        Some(RefBy::new(key.clone(), node.clone()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<K: Clone, V> DoubleEndedIterator for IterRefBy<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (key, node) = self.0.next_back()?;
+        Some(RefBy::new(key.clone(), node.clone()))
+    }
+}
+
+impl<K: Clone, V> ExactSizeIterator for IterRefBy<'_, K, V> {}
+impl<K: Clone, V> FusedIterator for IterRefBy<'_, K, V> {}
+
+/// An iterator over the keys of an `OptRefMap`.
+pub struct OptKeys<'a, K, V>(btree_map::Keys<'a, K, Option<Ref<V>>>);
+
+impl<'a, K, V> Iterator for OptKeys<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for OptKeys<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back()
+    }
+}
+
+impl<K, V> ExactSizeIterator for OptKeys<'_, K, V> {}
+impl<K, V> FusedIterator for OptKeys<'_, K, V> {}
+
+/// An iterator over the keys and (if resolved) referenced values of
+/// an `OptRefMap`.
+pub struct OptIter<'m, 'g, K, V> {
+    refs: btree_map::Iter<'m, K, Option<Ref<V>>>,
+    graph: &'g Graph<V>,
+}
+
+impl<'m, 'g, K, V> Iterator for OptIter<'m, 'g, K, V> {
+    type Item = (&'m K, Option<&'g V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, node) = self.refs.next()?;
+        Some((key, node.as_ref().map(|node| self.graph.borrow(node))))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.refs.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for OptIter<'_, '_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (key, node) = self.refs.next_back()?;
+        Some((key, node.as_ref().map(|node| self.graph.borrow(node))))
+    }
+}
+
+impl<K, V> ExactSizeIterator for OptIter<'_, '_, K, V> {}
+impl<K, V> FusedIterator for OptIter<'_, '_, K, V> {}
+
+/// An iterator over the resolved values of an `OptRefMap`. Since
+/// unresolved references are skipped, only an upper bound on the
+/// length is known.
+pub struct OptValues<'m, 'g, K, V> {
+    refs: btree_map::Values<'m, K, Option<Ref<V>>>,
+    graph: &'g Graph<V>,
+}
+
+impl<'m, 'g, K, V> Iterator for OptValues<'m, 'g, K, V> {
+    type Item = &'g V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let graph = self.graph;
+        self.refs
+            .find_map(|node| Some(graph.borrow(node.as_ref()?)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.refs.size_hint().1)
+    }
+}
+
+impl<K, V> DoubleEndedIterator for OptValues<'_, '_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let graph = self.graph;
+        self.refs
+            .by_ref()
+            .rev()
+            .find_map(|node| Some(graph.borrow(node.as_ref()?)))
+    }
+}
+
+impl<K, V> FusedIterator for OptValues<'_, '_, K, V> {}
+
+/// An iterator over the entries of an `OptRefMap` as `OptRefBy`s,
+/// cloning the keys.
+pub struct IterOptRefBy<'a, K, V>(btree_map::Iter<'a, K, Option<Ref<V>>>);
+
+impl<K: Clone, V> Iterator for IterOptRefBy<'_, K, V> {
+    type Item = OptRefBy<K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, node) = self.0.next()?;
+        Some(OptRefBy::new(key.clone(), node.clone()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<K: Clone, V> DoubleEndedIterator for IterOptRefBy<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (key, node) = self.0.next_back()?;
+        Some(OptRefBy::new(key.clone(), node.clone()))
+    }
+}
+
+impl<K: Clone, V> ExactSizeIterator for IterOptRefBy<'_, K, V> {}
+impl<K: Clone, V> FusedIterator for IterOptRefBy<'_, K, V> {}
+
+/// An entry in an `OptRefMap`, returned by `OptRefMap::entry`.
+pub enum OptEntry<'a, K, V> {
+    Occupied(OptOccupiedEntry<'a, K, V>),
+    Vacant(OptVacantEntry<'a, K, V>),
+}
+
+/// An entry for a key that is present in an `OptRefMap`, whether or
+/// not its reference is resolved.
+pub struct OptOccupiedEntry<'a, K, V>(btree_map::OccupiedEntry<'a, K, Option<Ref<V>>>);
+
+/// An entry for a key that is missing from an `OptRefMap`.
+pub struct OptVacantEntry<'a, K, V>(btree_map::VacantEntry<'a, K, Option<Ref<V>>>);
+
+impl<'a, K: Ord, V> OptEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        match self {
+            Self::Occupied(ent) => ent.key(),
+            Self::Vacant(ent) => ent.key(),
+        }
+    }
+
+    /// Return the entry's reference, inserting an unresolved one if
+    /// the key is missing.
+    pub fn or_none(self) -> &'a mut Option<Ref<V>> {
+        self.or_insert(None)
+    }
+
+    /// Return the entry's reference, inserting `default` if the key
+    /// is missing. An existing unresolved reference is kept.
+    pub fn or_insert(self, default: Option<Ref<V>>) -> &'a mut Option<Ref<V>> {
+        match self {
+            Self::Occupied(ent) => ent.into_mut(),
+            Self::Vacant(ent) => ent.insert(default),
+        }
+    }
+
+    /// Modify the reference if the key is present.
+    pub fn and_modify<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut Option<Ref<V>>),
+    {
+        if let Self::Occupied(ent) = &mut self {
+            f(ent.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K: Ord, V> OptOccupiedEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        self.0.key()
+    }
+
+    pub fn get(&self) -> &Option<Ref<V>> {
+        self.0.get()
+    }
+
+    pub fn get_mut(&mut self) -> &mut Option<Ref<V>> {
+        self.0.get_mut()
+    }
+
+    pub fn into_mut(self) -> &'a mut Option<Ref<V>> {
+        self.0.into_mut()
+    }
+
+    /// Replace the reference, returning the previous one.
+    pub fn insert(&mut self, value: Option<Ref<V>>) -> Option<Ref<V>> {
+        self.0.insert(value)
+    }
+
+    /// Remove the key from the map, returning its reference.
+    pub fn remove(self) -> Option<Ref<V>> {
+        self.0.remove()
+    }
+}
+
+impl<'a, K: Ord, V> OptVacantEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        self.0.key()
+    }
+
+    pub fn into_key(self) -> K {
+        self.0.into_key()
+    }
+
+    pub fn insert(self, value: Option<Ref<V>>) -> &'a mut Option<Ref<V>> {
+        self.0.insert(value)
+    }
+}
+
+/// Wrapper to (de)serialize a `RefMap` or `OptRefMap` as an object
+/// with null values (`{"key": null, ...}`) instead of a sequence of
+/// keys. As with the sequence form, deserialized references are
+/// dangling (`RefMap`) or unset (`OptRefMap`) until resolved.
+#[cfg(feature = "serde")]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AsObject<T>(pub T);
+
+#[cfg(feature = "serde")]
+impl<K, V> Serialize for AsObject<RefMap<K, V>>
+where
+    K: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_map(Some(self.0.len()))?;
+        self.0
+            .keys()
+            .try_for_each(|key| s.serialize_entry(key, &()))?;
+        s.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V> Deserialize<'de> for AsObject<RefMap<K, V>>
+where
+    K: Deserialize<'de> + Ord,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct ObjectVisitor<K, V>(PhantomData<(K, V)>);
+
+        impl<'de, K, V> Visitor<'de> for ObjectVisitor<K, V>
+        where
+            K: Deserialize<'de> + Ord,
+        {
+            type Value = RefMap<K, V>;
+
+            fn expecting(&self, formatter: &mut Formatter<'_>) -> core::fmt::Result {
+                write!(formatter, "an object of keys with null values")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut refs = RefMap::new();
+                while let Some((key, IgnoredAny)) = map.next_entry()? {
+                    refs.insert(key, Ref::dangling());
+                }
+                Ok(refs)
+            }
+        }
+
+        deserializer
+            .deserialize_map(ObjectVisitor(PhantomData))
+            .map(AsObject)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K, V> Serialize for AsObject<OptRefMap<K, V>>
+where
+    K: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_map(Some(self.0 .0.len()))?;
+        self.0
+            .keys()
+            .try_for_each(|key| s.serialize_entry(key, &()))?;
+        s.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V> Deserialize<'de> for AsObject<OptRefMap<K, V>>
+where
+    K: Deserialize<'de> + Ord,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct ObjectVisitor<K, V>(PhantomData<(K, V)>);
+
+        impl<'de, K, V> Visitor<'de> for ObjectVisitor<K, V>
+        where
+            K: Deserialize<'de> + Ord,
+        {
+            type Value = OptRefMap<K, V>;
+
+            fn expecting(&self, formatter: &mut Formatter<'_>) -> core::fmt::Result {
+                write!(formatter, "an object of keys with null values")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut refs = OptRefMap::new();
+                while let Some((key, IgnoredAny)) = map.next_entry()? {
+                    refs.insert(key, None);
+                }
+                Ok(refs)
+            }
+        }
+
+        deserializer
+            .deserialize_map(ObjectVisitor(PhantomData))
+            .map(AsObject)
+    }
+}
+
+/// Deserialize a `RefMap`, resolving each key against an index. Keys
+/// missing from the index fail deserialization, so the map is never
+/// observed with dangling references.
+#[cfg(feature = "serde")]
+pub struct RefMapSeed<'a, K, V, I> {
+    index: &'a I,
+    _marker: PhantomData<(K, V)>,
+}
+
+#[cfg(feature = "serde")]
+impl<'a, K, V, I: IndexBy<K, V>> RefMapSeed<'a, K, V, I> {
+    pub fn new(index: &'a I) -> Self {
+        Self {
+            index,
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V, I> DeserializeSeed<'de> for RefMapSeed<'_, K, V, I>
+where
+    K: Deserialize<'de> + Ord + Debug,
+    I: IndexBy<K, V>,
+{
+    type Value = RefMap<K, V>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct SeqVisitor<'a, K, V, I>(&'a I, PhantomData<(K, V)>);
+
+        impl<'de, K, V, I> Visitor<'de> for SeqVisitor<'_, K, V, I>
+        where
+            K: Deserialize<'de> + Ord + Debug,
+            I: IndexBy<K, V>,
+        {
+            type Value = RefMap<K, V>;
+
+            fn expecting(&self, formatter: &mut Formatter<'_>) -> core::fmt::Result {
+                write!(formatter, "a sequence of keys")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let mut map = RefMap::new();
+                let mut missing = Vec::new();
+
+                while let Some(key) = seq.next_element::<K>()? {
+                    match self.0.get(&key) {
+                        Some(node) => {
+                            let node = node.clone();
+                            map.insert(key, node);
+                        }
+                        None => missing.push(key),
+                    }
+                }
+
+                if missing.is_empty() {
+                    Ok(map)
+                } else {
+                    Err(A::Error::custom(format_args!(
+                        "unresolved reference(s): {missing:?}"
+                    )))
+                }
+            }
+        }
+
+        deserializer.deserialize_seq(SeqVisitor(self.index, PhantomData))
+    }
+}
+
+/// Deserialize an `OptRefMap`, resolving each key against an index.
+/// Keys missing from the index are kept without a reference.
+#[cfg(feature = "serde")]
+pub struct OptRefMapSeed<'a, K, V, I> {
+    index: &'a I,
+    _marker: PhantomData<(K, V)>,
+}
+
+#[cfg(feature = "serde")]
+impl<'a, K, V, I: IndexBy<K, V>> OptRefMapSeed<'a, K, V, I> {
+    pub fn new(index: &'a I) -> Self {
+        Self {
+            index,
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V, I> DeserializeSeed<'de> for OptRefMapSeed<'_, K, V, I>
+where
+    K: Deserialize<'de> + Ord,
+    I: IndexBy<K, V>,
+{
+    type Value = OptRefMap<K, V>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct SeqVisitor<'a, K, V, I>(&'a I, PhantomData<(K, V)>);
+
+        impl<'de, K, V, I> Visitor<'de> for SeqVisitor<'_, K, V, I>
+        where
+            K: Deserialize<'de> + Ord,
+            I: IndexBy<K, V>,
+        {
+            type Value = OptRefMap<K, V>;
+
+            fn expecting(&self, formatter: &mut Formatter<'_>) -> core::fmt::Result {
+                write!(formatter, "a sequence of keys")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let mut map = OptRefMap::new();
+                while let Some(key) = seq.next_element::<K>()? {
+                    let node = self.0.get(&key).cloned();
+                    map.insert(key, node);
+                }
+                Ok(map)
+            }
+        }
+
+        deserializer.deserialize_seq(SeqVisitor(self.index, PhantomData))
+    }
+}
+
+/// Move the entries in `moved` from their old to their new keys.
+/// The entries are removed before any is reinserted, so that keys
+/// can be swapped.
+fn rekey<K: Ord, T>(map: &mut BTreeMap<K, T>, moved: Vec<(K, K)>) {
+    let moved = moved
+        .into_iter()
+        .filter_map(|(old, new)| Some((new, map.remove(&old)?)))
+        .collect::<Vec<_>>();
+    map.extend(moved);
+}
+
+/// Collect key-value pairs into a map, failing with the first key
+/// that occurs more than once.
+pub(crate) fn collect_unique<K, T, I>(iter: I) -> Result<BTreeMap<K, T>, K>
+where
+    K: Ord,
+    I: IntoIterator<Item = (K, T)>,
+{
+    let mut map = BTreeMap::new();
+    for (key, value) in iter {
+        match map.entry(key) {
+            btree_map::Entry::Vacant(ent) => {
+                ent.insert(value);
+            }
+            btree_map::Entry::Occupied(ent) => return Err(ent.remove_entry().0),
+        }
+    }
+    Ok(map)
+}
+
+#[cfg(test)]
+mod test {
+
+    use crate::{BTreeGraph, Graph, MergePolicy, OptRefMap, RefMap};
+
+    #[test]
+    fn try_from_iter() {
+        let mut graph = Graph::new();
+        let a = graph.insert("a");
+        let b = graph.insert("b");
+
+        let map = RefMap::try_from_iter([(1, a.clone()), (2, b.clone())]).unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(graph.borrow(map.get_ref(&2).unwrap()), &"b");
+
+        let err = RefMap::try_from_iter([(1, a.clone()), (2, b), (1, a.clone()), (2, a)]);
+        assert_eq!(err.err(), Some(1));
+    }
+
+    #[test]
+    fn map_keys() {
+        #[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+        struct Id(String);
+
+        let mut graph = Graph::new();
+        let a = graph.insert("a");
+        let b = graph.insert("b");
+
+        let map = [("a".to_string(), a.clone()), ("B".to_string(), b.clone())]
+            .into_iter()
+            .collect::<RefMap<_, _>>();
+        let map = map.map_keys(|k| Id(k.to_lowercase()));
+        assert_eq!(
+            map.keys().collect::<Vec<_>>(),
+            vec![&Id("a".to_string()), &Id("b".to_string())]
+        );
+        assert_eq!(
+            graph.borrow(map.get_ref(&Id("b".to_string())).unwrap()),
+            &"b"
+        );
+
+        let err = map.try_map_keys(|_| Id("x".to_string())).err();
+        assert_eq!(err, Some(Id("x".to_string())));
+
+        let map = [("a".to_string(), Some(a)), ("b".to_string(), None)]
+            .into_iter()
+            .collect::<OptRefMap<_, _>>();
+        let map = map.try_map_keys(Id).unwrap();
+        assert!(map.get_ref(&Id("a".to_string())).is_some());
+        assert!(map.contains_key(&Id("b".to_string())));
+        let map = map.map_keys(|_| 0);
+        assert!(map.contains_key(&0) && map.get_ref(&0).is_none());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn as_object() {
+        use crate::AsObject;
+
+        let mut graph = BTreeGraph::new();
+        let a = graph.insert("a".to_string(), 1);
+        let b = graph.insert("b".to_string(), 2);
+
+        let map = [("a".to_string(), a.clone()), ("b".to_string(), b)]
+            .into_iter()
+            .collect::<RefMap<_, _>>();
+        let json = serde_json::to_string(&AsObject(map)).unwrap();
+        assert_eq!(json, r#"{"a":null,"b":null}"#);
+
+        let AsObject(mut map) =
+            serde_json::from_str::<AsObject<RefMap<String, u32>>>(&json).unwrap();
+        map.resolve(graph.index()).unwrap();
+        assert!(map.get_ref(&"a".to_string()) == Some(&a));
+        assert_eq!(map.get(&"b".to_string(), &graph), Some(&2));
+
+        let AsObject(map) =
+            serde_json::from_str::<AsObject<OptRefMap<String, u32>>>(r#"{"c":null}"#).unwrap();
+        assert_eq!(
+            serde_json::to_string(&AsObject(map)).unwrap(),
+            r#"{"c":null}"#
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialize_seed() {
+        use serde::de::DeserializeSeed;
+
+        use crate::{OptRefMapSeed, RefMapSeed};
+
+        let mut graph = BTreeGraph::new();
+        let a = graph.insert("a".to_string(), 1);
+        graph.insert("b".to_string(), 2);
+
+        let mut de = serde_json::Deserializer::from_str(r#"["a","b"]"#);
+        let map = RefMapSeed::new(&graph).deserialize(&mut de).unwrap();
+        assert!(map.get_ref(&"a".to_string()) == Some(&a));
+        assert_eq!(map.values(&graph).collect::<Vec<_>>(), [&1, &2]);
+
+        let mut de = serde_json::Deserializer::from_str(r#"["a","c","d"]"#);
+        let err = RefMapSeed::<String, u32, _>::new(&graph)
+            .deserialize(&mut de)
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with(r#"unresolved reference(s): ["c", "d"]"#));
+
+        let mut de = serde_json::Deserializer::from_str(r#"["a","c"]"#);
+        let map = OptRefMapSeed::new(&graph).deserialize(&mut de).unwrap();
+        assert_eq!(map.values(&graph).collect::<Vec<_>>(), [&1]);
+        assert!(map.contains_key(&"c".to_string()));
+    }
+
+    #[test]
+    fn get_mut() {
+        let mut graph = BTreeGraph::new();
+        let a = graph.insert(1, "a".to_string());
+        let b = graph.insert(2, "b".to_string());
+
+        let map = [("a", a.clone())].into_iter().collect::<RefMap<_, _>>();
+        map.get_mut(&"a", &mut graph).unwrap().push('!');
+        assert!(map.get_mut(&"b", &mut graph).is_none());
+        assert_eq!(graph.get(&1).map(String::as_str), Some("a!"));
+        assert_eq!(map.get(&"a", &graph).map(String::as_str), Some("a!"));
+
+        let map = [("a", Some(a)), ("b", Some(b)), ("c", None)]
+            .into_iter()
+            .collect::<OptRefMap<_, _>>();
+        map.get_mut(&"b", &mut graph).unwrap().push('?');
+        assert!(map.get_mut(&"c", &mut graph).is_none());
+        assert_eq!(graph.get(&2).map(String::as_str), Some("b?"));
+        assert_eq!(map.get(&"b", &graph).map(String::as_str), Some("b?"));
+    }
+
+    #[test]
+    fn get_from_any_graph() {
+        let mut graph = Graph::new();
+        let a = graph.insert(1);
+        let map = [("a", a)].into_iter().collect::<RefMap<_, _>>();
+        *map.get_mut(&"a", &mut graph).unwrap() += 1;
+        assert_eq!(map.get(&"a", &graph), Some(&2));
+        assert_eq!(map.values(&graph).collect::<Vec<_>>(), [&2]);
+    }
+
+    #[test]
+    fn opt_entry() {
+        use crate::refmap::OptEntry;
+
+        let mut graph = BTreeGraph::new();
+        let a = graph.insert("a", 1);
+        let mut map = OptRefMap::new();
+
+        assert!(map.entry("a").or_none().is_none());
+        assert!(map.entry("b").or_insert(Some(a.clone())).is_some());
+        map.entry("a")
+            .and_modify(|node| *node = Some(a.clone()))
+            .or_none();
+        map.entry("c").and_modify(|_| panic!("vacant")).or_none();
+        assert_eq!(map.get(&"a", &graph), Some(&1));
+        assert_eq!(map.get(&"b", &graph), Some(&1));
+        assert!(map.get_ref(&"c").is_none());
+        assert!(map.contains_key(&"c"));
+
+        match map.entry("b") {
+            OptEntry::Occupied(mut ent) => {
+                assert_eq!(ent.key(), &"b");
+                assert!(ent.get().is_some());
+                assert!(ent.insert(None).is_some());
+                assert!(ent.get_mut().is_none());
+                assert!(ent.remove().is_none());
+            }
+            OptEntry::Vacant(_) => panic!("expected an occupied entry"),
+        }
+        match map.entry("b") {
+            OptEntry::Vacant(ent) => {
+                assert_eq!(ent.key(), &"b");
+                *ent.insert(None) = Some(a);
+            }
+            OptEntry::Occupied(_) => panic!("expected a vacant entry"),
+        }
+        assert_eq!(map.keys().copied().collect::<Vec<_>>(), ["a", "b", "c"]);
+        assert_eq!(map.values(&graph).count(), 2);
+    }
+
+    #[test]
+    fn merge() {
+        let mut graph = BTreeGraph::new();
+        let a = graph.insert("a", 1);
+        let b = graph.insert("b", 2);
+        let ours = [(1, a.clone()), (2, a.clone()), (3, a.clone())]
+            .into_iter()
+            .collect::<RefMap<_, _>>();
+        let theirs = [(2, a.clone()), (3, b.clone()), (4, b.clone())]
+            .into_iter()
+            .collect::<RefMap<_, _>>();
+
+        let mut map = ours.clone();
+        assert_eq!(map.merge(theirs.clone(), MergePolicy::PreferSelf), 1);
+        assert_eq!(map.values(&graph).collect::<Vec<_>>(), [&1, &1, &1, &2]);
+
+        let mut map = ours.clone();
+        assert_eq!(map.merge(theirs.clone(), MergePolicy::PreferOther), 1);
+        assert_eq!(map.values(&graph).collect::<Vec<_>>(), [&1, &1, &2, &2]);
+
+        let mut conflicts = Vec::new();
+        let mut map = ours;
+        let policy = MergePolicy::WithFn(Box::new(|key: &i32, ours, _| {
+            conflicts.push(*key);
+            ours
+        }));
+        assert_eq!(map.merge(theirs, policy), 1);
+        assert_eq!(conflicts, [3]);
+        assert_eq!(map.len(), 4);
+
+        let mut map = [(1, Some(a.clone())), (2, None), (3, None)]
+            .into_iter()
+            .collect::<OptRefMap<_, _>>();
+        let theirs = [(1, None), (2, Some(b.clone())), (3, None)]
+            .into_iter()
+            .collect::<OptRefMap<_, _>>();
+        let policy =
+            MergePolicy::WithFn(Box::new(|_: &i32, ours: Option<_>, theirs| ours.or(theirs)));
+        assert_eq!(map.merge(theirs, policy), 2);
+        assert_eq!(map.values(&graph).collect::<Vec<_>>(), [&1, &2]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn get_from_hash_graph() {
+        use crate::HashGraph;
+
+        let mut graph = HashGraph::<_, _>::new();
+        let a = graph.insert("x", 1);
+        let b = graph.insert("y", 2);
+
+        let map = [("a", a.clone())].into_iter().collect::<RefMap<_, _>>();
+        assert_eq!(map.get(&"a", &graph), Some(&1));
+        *map.get_mut(&"a", &mut graph).unwrap() += 10;
+        assert_eq!(graph.get(&"x"), Some(&11));
+
+        let mut map = [("a", Some(a)), ("b", Some(b)), ("c", None)]
+            .into_iter()
+            .collect::<OptRefMap<_, _>>();
+        *map.get_mut(&"b", &mut graph).unwrap() += 10;
+        assert_eq!(map.get(&"b", &graph), Some(&12));
+        assert_eq!(map.values(&graph).collect::<Vec<_>>(), [&11, &12]);
+        map.remove(&"c");
+        assert_eq!(map.iter(&graph).len(), 2);
+    }
+
+    #[test]
+    fn retain_resolved() {
+        struct Task {
+            done: bool,
+            visits: u32,
+        }
+
+        let mut graph = BTreeGraph::new();
+        let map = (0..6)
+            .map(|i| {
+                let task = Task {
+                    done: i % 3 == 0,
+                    visits: 0,
+                };
+                (i, graph.insert(i, task))
+            })
+            .collect::<RefMap<_, _>>();
+        let mut pending = map.clone();
+        pending.retain_resolved(&mut graph, |_, task| {
+            task.visits += 1;
+            !task.done
+        });
+        assert_eq!(
+            pending.keys().copied().collect::<Vec<_>>(),
+            vec![1, 2, 4, 5]
+        );
+        assert_eq!(map.len(), 6);
+        assert!(graph.values().all(|task| task.visits == 1));
+    }
+
+    #[test]
+    fn iterators() {
+        let mut graph = BTreeGraph::new();
+        let map = (0..5)
+            .map(|i| (i, graph.insert(i, i * 10)))
+            .collect::<RefMap<_, _>>();
+
+        assert_eq!(map.keys().len(), 5);
+        assert_eq!(map.keys().rev().take(2).collect::<Vec<_>>(), [&4, &3]);
+        assert_eq!(map.iter(&graph).len(), 5);
+        assert_eq!(map.iter(&graph).next_back(), Some((&4, &40)));
+        assert_eq!(map.values(&graph).len(), 5);
+        assert_eq!(map.values(&graph).rev().nth(1), Some(&30));
+        assert_eq!(map.iter_ref_by().len(), 5);
+        assert_eq!(map.iter_ref_by().next_back().unwrap().key(), &4);
+        assert_eq!(map.iter_ref().len(), 5);
+        assert_eq!(map.value_refs().len(), 5);
+
+        let mut iter = map.values(&graph).skip(3);
+        assert_eq!(iter.size_hint(), (2, Some(2)));
+        assert_eq!(iter.next(), Some(&30));
+        assert_eq!(iter.next(), Some(&40));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next(), None);
+
+        let map = (0..5)
+            .map(|i| (i, (i % 2 == 0).then(|| map.get_ref(&i).unwrap().clone())))
+            .collect::<OptRefMap<_, _>>();
+        assert_eq!(map.keys().len(), 5);
+        assert_eq!(map.keys().next_back(), Some(&4));
+        assert_eq!(map.iter(&graph).len(), 5);
+        assert_eq!(map.iter(&graph).rev().nth(1), Some((&3, None)));
+        assert_eq!(map.iter_ref_by().len(), 5);
+        assert_eq!(map.values(&graph).size_hint(), (0, Some(5)));
+        assert_eq!(map.values(&graph).rev().collect::<Vec<_>>(), [&40, &20, &0]);
+    }
+
+    #[test]
+    fn iter_split_borrow() {
+        let mut graph = BTreeGraph::new();
+        let refs = (0..4)
+            .map(|i| (i, graph.insert(i, i * 10)))
+            .collect::<Vec<_>>();
+
+        /* The values borrow the graph only, so they outlive the map. */
+        let values = {
+            let map = refs.iter().cloned().collect::<RefMap<_, _>>();
+            map.values(&graph).collect::<Vec<_>>()
+        };
+        assert_eq!(values, [&0, &10, &20, &30]);
+
+        let map = refs
+            .into_iter()
+            .filter(|(i, _)| i % 2 == 1)
+            .map(|(i, node)| (i, Some(node)))
+            .collect::<OptRefMap<_, _>>();
+        let pairs = map
+            .iter(&graph)
+            .map(|(key, value)| (key, value.zip(graph.get(&(key - 1)))))
+            .collect::<Vec<_>>();
+        assert_eq!(pairs, [(&1, Some((&10, &0))), (&3, Some((&30, &20)))]);
+    }
+}
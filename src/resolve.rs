@@ -0,0 +1,150 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+use alloc::vec::Vec;
+use core::fmt::{Debug, Display};
+
+use crate::{IndexBy, OptRefBy, OptRefMap, RefBy, RefMap};
+
+/// Structures containing keyed references that can be resolved
+/// against an index, e.g. after deserialization.
+pub trait Resolve<K, V> {
+    /// Resolve all references in the structure, returning the first
+    /// key that could not be found in the index.
+    fn resolve<I>(&mut self, index: &I) -> Result<(), K>
+    where
+        I: IndexBy<K, V>;
+}
+
+impl<K: Ord + Clone, V> Resolve<K, V> for RefBy<K, V> {
+    fn resolve<I>(&mut self, index: &I) -> Result<(), K>
+    where
+        I: IndexBy<K, V>,
+    {
+        RefBy::resolve(self, index)
+    }
+}
+
+impl<K: Ord + Clone, V> Resolve<K, V> for OptRefBy<K, V> {
+    fn resolve<I>(&mut self, index: &I) -> Result<(), K>
+    where
+        I: IndexBy<K, V>,
+    {
+        OptRefBy::resolve(self, index);
+        Ok(())
+    }
+}
+
+impl<K: Ord + Clone, V> Resolve<K, V> for RefMap<K, V> {
+    fn resolve<I>(&mut self, index: &I) -> Result<(), K>
+    where
+        I: IndexBy<K, V>,
+    {
+        RefMap::resolve(self, index)
+    }
+}
+
+impl<K: Ord + Clone, V> Resolve<K, V> for OptRefMap<K, V> {
+    fn resolve<I>(&mut self, index: &I) -> Result<(), K>
+    where
+        I: IndexBy<K, V>,
+    {
+        OptRefMap::resolve(self, index);
+        Ok(())
+    }
+}
+
+impl<K, V, T: Resolve<K, V>> Resolve<K, V> for Option<T> {
+    fn resolve<I>(&mut self, index: &I) -> Result<(), K>
+    where
+        I: IndexBy<K, V>,
+    {
+        self.iter_mut().try_for_each(|value| value.resolve(index))
+    }
+}
+
+impl<K, V, T: Resolve<K, V>> Resolve<K, V> for Vec<T> {
+    fn resolve<I>(&mut self, index: &I) -> Result<(), K>
+    where
+        I: IndexBy<K, V>,
+    {
+        self.iter_mut().try_for_each(|value| value.resolve(index))
+    }
+}
+
+/// Resolve a slice of references against an index, returning the
+/// keys of all references that could not be resolved.
+pub fn resolve_all<K, V, I>(refs: &mut [RefBy<K, V>], index: &I) -> Result<(), Vec<K>>
+where
+    K: Ord + Clone,
+    I: IndexBy<K, V>,
+{
+    let missing = refs
+        .iter_mut()
+        .filter_map(|r| r.resolve(index).err())
+        .collect::<Vec<_>>();
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(missing)
+    }
+}
+
+/// Error returned when one or more references could not be resolved.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ResolveError<K> {
+    missing: Vec<K>,
+}
+
+impl<K> ResolveError<K> {
+    pub fn new(missing: Vec<K>) -> Self {
+        Self { missing }
+    }
+
+    /// The keys that could not be found.
+    pub fn missing(&self) -> &[K] {
+        &self.missing
+    }
+
+    pub fn into_missing(self) -> Vec<K> {
+        self.missing
+    }
+}
+
+impl<K> Display for ResolveError<K> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} unresolved reference(s)", self.missing.len())
+    }
+}
+
+impl<K: Debug> core::error::Error for ResolveError<K> {}
+
+#[cfg(test)]
+mod test {
+
+    use crate::{resolve_all, BTreeGraph, RefBy};
+
+    #[test]
+    fn resolve_all_missing() {
+        let graph = [(1, "a"), (2, "b")]
+            .into_iter()
+            .collect::<BTreeGraph<_, _>>();
+        let mut refs = [4, 1, 3, 2].map(RefBy::dangling).to_vec();
+
+        assert_eq!(resolve_all(&mut refs, &graph), Err(vec![4, 3]));
+        assert_eq!(graph.borrow(&refs[1]), &"a");
+        assert_eq!(graph.borrow(&refs[3]), &"b");
+        assert!(refs[0].value_ref().is_invalid());
+    }
+}
@@ -0,0 +1,123 @@
+/******************************************************************************
+ * Copyright 2025 ContinuousC                                                 *
+ *                                                                            *
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may *
+ * not use this file except in compliance with the License. You may  obtain a *
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          *
+ *                                                                            *
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software *
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT *
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the *
+ * License for the  specific language  governing permissions  and limitations *
+ * under the License.                                                         *
+ ******************************************************************************/
+
+/* JSON schemas for the serialized forms. The keyed graphs serialize
+ * as a map of keys to values, references as their key and the ref
+ * maps as an array of (unique) keys. Each schema is that of the
+ * standard type with the same serialized form. */
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap},
+};
+
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+
+use crate::{BTreeGraph, HashGraph, OptRefBy, OptRefMap, RefBy, RefMap};
+
+macro_rules! schema_as {
+    ($ty:ty, $as:ty, [$($param:ident),*]) => {
+        impl<$($param),*> JsonSchema for $ty
+        where
+            $as: JsonSchema,
+        {
+            fn is_referenceable() -> bool {
+                <$as>::is_referenceable()
+            }
+
+            fn schema_name() -> String {
+                <$as>::schema_name()
+            }
+
+            fn schema_id() -> Cow<'static, str> {
+                <$as>::schema_id()
+            }
+
+            fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+                <$as>::json_schema(gen)
+            }
+        }
+    };
+}
+
+schema_as!(BTreeGraph<K, V>, BTreeMap<K, V>, [K, V]);
+schema_as!(HashGraph<K, V, S>, HashMap<K, V>, [K, V, S]);
+schema_as!(RefBy<K, V>, K, [K, V]);
+schema_as!(OptRefBy<K, V>, K, [K, V]);
+schema_as!(RefMap<K, V>, BTreeSet<K>, [K, V]);
+schema_as!(OptRefMap<K, V>, BTreeSet<K>, [K, V]);
+
+#[cfg(test)]
+mod test {
+
+    use schemars::{schema_for, JsonSchema};
+    use serde_json::json;
+
+    use crate::{BTreeGraph, HashGraph, OptRefMap, RefBy, RefMap};
+
+    #[test]
+    fn keyed_graph() {
+        let expected = json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "title": "Map_of_uint32",
+            "type": "object",
+            "additionalProperties": {
+                "type": "integer",
+                "format": "uint32",
+                "minimum": 0.0
+            }
+        });
+        let schema = schema_for!(BTreeGraph<String, u32>);
+        assert_eq!(serde_json::to_value(schema).unwrap(), expected);
+        let schema = schema_for!(HashGraph<String, u32>);
+        assert_eq!(serde_json::to_value(schema).unwrap(), expected);
+    }
+
+    #[test]
+    fn references() {
+        #[allow(dead_code)]
+        #[derive(JsonSchema)]
+        struct Node {
+            parent: RefBy<String, Node>,
+            children: RefMap<String, Node>,
+            links: OptRefMap<String, Node>,
+        }
+
+        let keys = json!({
+            "type": "array",
+            "items": { "type": "string" },
+            "uniqueItems": true
+        });
+        assert_eq!(
+            serde_json::to_value(schema_for!(BTreeGraph<String, Node>)).unwrap(),
+            json!({
+                "$schema": "http://json-schema.org/draft-07/schema#",
+                "title": "Map_of_Node",
+                "type": "object",
+                "additionalProperties": { "$ref": "#/definitions/Node" },
+                "definitions": {
+                    "Node": {
+                        "type": "object",
+                        "required": ["children", "links", "parent"],
+                        "properties": {
+                            "parent": { "type": "string" },
+                            "children": keys,
+                            "links": keys
+                        }
+                    }
+                }
+            })
+        );
+    }
+}
@@ -0,0 +1,258 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+#[cfg(feature = "serde")]
+use alloc::string::String;
+use alloc::{collections::BTreeMap, vec::Vec};
+#[cfg(feature = "serde")]
+use core::{fmt::Formatter, marker::PhantomData};
+
+#[cfg(feature = "serde")]
+use serde::{
+    de::{Deserializer, Error, MapAccess, SeqAccess, Visitor},
+    ser::{SerializeStruct, Serializer},
+    Deserialize, Serialize,
+};
+
+use crate::{BTreeGraph, RefBy, ResolveError};
+
+/// A plain-data representation of a graph as an adjacency list. In
+/// contrast to the graph types, this contains no references and can
+/// be used as an interchange format.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct GraphSpec<K, V> {
+    pub nodes: BTreeMap<K, V>,
+    pub edges: BTreeMap<K, Vec<K>>,
+}
+
+impl<K, V> GraphSpec<K, V> {
+    pub fn new() -> Self {
+        Self {
+            nodes: BTreeMap::new(),
+            edges: BTreeMap::new(),
+        }
+    }
+}
+
+impl<K, V> Default for GraphSpec<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> BTreeGraph<K, V> {
+    /// Build a graph from its adjacency list representation. All
+    /// nodes are inserted first, after which each edge is passed to
+    /// `attach`, together with its source node. Fails with the keys of
+    /// all nodes that were referenced but not found.
+    pub fn from_spec<F>(spec: GraphSpec<K, V>, mut attach: F) -> Result<Self, ResolveError<K>>
+    where
+        K: Ord + Clone,
+        F: FnMut(&mut V, RefBy<K, V>),
+    {
+        let mut graph = spec.nodes.into_iter().collect::<Self>();
+        let mut missing = Vec::new();
+
+        for (key, targets) in spec.edges {
+            let Some(node) = graph.get_ref(&key).cloned() else {
+                missing.push(key);
+                continue;
+            };
+            for target in targets {
+                match graph.get_ref_by(&target) {
+                    Some(target) => attach(graph.borrow_mut(&node), target),
+                    None => missing.push(target),
+                }
+            }
+        }
+
+        if missing.is_empty() {
+            Ok(graph)
+        } else {
+            Err(ResolveError::new(missing))
+        }
+    }
+
+    /// Convert the graph to its adjacency list representation. For
+    /// each node, `detach` should return a copy of the node without
+    /// its outgoing edges, and the keys of the edges' targets. This
+    /// is the inverse of the `attach` function passed to `from_spec`.
+    pub fn to_spec<F>(&self, mut detach: F) -> GraphSpec<K, V>
+    where
+        K: Ord + Clone,
+        F: FnMut(&V) -> (V, Vec<K>),
+    {
+        let mut spec = GraphSpec::new();
+        self.iter().for_each(|(key, value)| {
+            let (value, targets) = detach(value);
+            if !targets.is_empty() {
+                spec.edges.insert(key.clone(), targets);
+            }
+            spec.nodes.insert(key.clone(), value);
+        });
+        spec
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K, V> Serialize for GraphSpec<K, V>
+where
+    K: Serialize,
+    V: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_struct("GraphSpec", 2)?;
+        s.serialize_field("nodes", &self.nodes)?;
+        s.serialize_field("edges", &self.edges)?;
+        s.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V> Deserialize<'de> for GraphSpec<K, V>
+where
+    K: Deserialize<'de> + Ord,
+    V: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct SpecVisitor<K, V>(PhantomData<(K, V)>);
+
+        impl<'de, K, V> Visitor<'de> for SpecVisitor<K, V>
+        where
+            K: Deserialize<'de> + Ord,
+            V: Deserialize<'de>,
+        {
+            type Value = GraphSpec<K, V>;
+
+            fn expecting(&self, formatter: &mut Formatter<'_>) -> core::fmt::Result {
+                write!(formatter, "a graph spec")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let nodes = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(0, &self))?;
+                let edges = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(1, &self))?;
+                Ok(GraphSpec { nodes, edges })
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut nodes = None;
+                let mut edges = None;
+
+                while let Some(field) = map.next_key::<String>()? {
+                    match field.as_str() {
+                        "nodes" if nodes.is_some() => {
+                            return Err(A::Error::duplicate_field("nodes"))
+                        }
+                        "edges" if edges.is_some() => {
+                            return Err(A::Error::duplicate_field("edges"))
+                        }
+                        "nodes" => nodes = Some(map.next_value()?),
+                        "edges" => edges = Some(map.next_value()?),
+                        _ => return Err(A::Error::unknown_field(&field, FIELDS)),
+                    }
+                }
+
+                Ok(GraphSpec {
+                    nodes: nodes.ok_or_else(|| A::Error::missing_field("nodes"))?,
+                    edges: edges.unwrap_or_default(),
+                })
+            }
+        }
+
+        deserializer.deserialize_struct("GraphSpec", FIELDS, SpecVisitor(PhantomData))
+    }
+}
+
+#[cfg(feature = "serde")]
+const FIELDS: &[&str] = &["nodes", "edges"];
+
+#[cfg(test)]
+mod test {
+
+    use crate::{BTreeGraph, GraphSpec, RefBy};
+
+    #[derive(Clone, PartialEq, Debug)]
+    struct Node {
+        label: &'static str,
+        next: Vec<RefBy<u32, Node>>,
+    }
+
+    fn attach(node: &mut Node, target: RefBy<u32, Node>) {
+        node.next.push(target);
+    }
+
+    fn detach(node: &Node) -> (Node, Vec<u32>) {
+        let value = Node {
+            label: node.label,
+            next: Vec::new(),
+        };
+        (
+            value,
+            node.next.iter().map(|target| *target.key()).collect(),
+        )
+    }
+
+    #[test]
+    fn round_trip() {
+        let node = |label| Node {
+            label,
+            next: Vec::new(),
+        };
+        let spec = GraphSpec {
+            nodes: [(1, node("a")), (2, node("b")), (3, node("c"))].into(),
+            edges: [(1, vec![2]), (2, vec![3, 1]), (3, vec![3])].into(),
+        };
+
+        let graph = BTreeGraph::from_spec(spec.clone(), attach).unwrap();
+        let a = graph.get(&1).unwrap();
+        let b = graph.borrow(&a.next[0]);
+        assert_eq!(b.label, "b");
+        assert_eq!(graph.borrow(&b.next[1]).label, "a");
+
+        let round_trip = graph.to_spec(detach);
+        assert_eq!(round_trip, spec);
+        assert_eq!(
+            BTreeGraph::from_spec(round_trip, attach)
+                .unwrap()
+                .to_spec(detach),
+            spec
+        );
+    }
+
+    #[test]
+    fn missing_nodes() {
+        let spec = GraphSpec {
+            nodes: [(1, ())].into(),
+            edges: [(1, vec![1, 2]), (3, vec![1])].into(),
+        };
+        let err = BTreeGraph::from_spec(spec, |_, _| {}).err().unwrap();
+        assert_eq!(err.missing(), &[2, 3]);
+    }
+}
@@ -0,0 +1,164 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+use core::fmt::Display;
+
+#[cfg(feature = "serde")]
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+
+#[cfg(feature = "std")]
+use crate::HashGraph;
+use crate::{BTreeGraph, Graph, Ref};
+
+/// Size statistics of a keyed graph, e.g. to chart its growth over
+/// time. The `Display` implementation writes a single line.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GraphStats {
+    /// Indexed nodes holding a value.
+    pub nodes: usize,
+    /// Indexed nodes that were promised but not (yet) created.
+    pub promised: usize,
+    /// Entries in the graph's index.
+    pub index_entries: usize,
+    /// Allocated slots, including those of removed nodes.
+    pub arena_slots: usize,
+    /// Contiguous chunks of slots in the arena (or custom storage).
+    pub arena_chunks: usize,
+    /// The memory used by the slots and index entries, in bytes, as
+    /// estimated by `memory_usage`.
+    pub approx_bytes: usize,
+}
+
+impl GraphStats {
+    fn from_index<'a, K: 'a, V: 'a, I>(graph: &Graph<V>, index: I, approx_bytes: usize) -> Self
+    where
+        I: ExactSizeIterator<Item = (&'a K, &'a Ref<V>)>,
+    {
+        let index_entries = index.len();
+        let promised = index
+            .filter(|(_, node)| unsafe { node.try_get_unchecked() }.is_none())
+            .count();
+        Self {
+            nodes: index_entries - promised,
+            promised,
+            index_entries,
+            arena_slots: graph.slot_count(),
+            arena_chunks: graph.chunk_count(),
+            approx_bytes,
+        }
+    }
+}
+
+impl<K, V> BTreeGraph<K, V> {
+    /// Collect size statistics about the graph. This walks the index
+    /// to count the promised nodes.
+    pub fn stats(&self) -> GraphStats {
+        GraphStats::from_index(self.as_ref(), self.index().iter(), self.memory_usage())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V, S> HashGraph<K, V, S> {
+    /// Collect size statistics about the graph. See
+    /// `BTreeGraph::stats`.
+    pub fn stats(&self) -> GraphStats {
+        GraphStats::from_index(self.as_ref(), self.index().iter(), self.memory_usage())
+    }
+}
+
+impl Display for GraphStats {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "nodes={} promised={} index_entries={} arena_slots={} arena_chunks={} approx_bytes={}",
+            self.nodes,
+            self.promised,
+            self.index_entries,
+            self.arena_slots,
+            self.arena_chunks,
+            self.approx_bytes
+        )
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for GraphStats {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_struct("GraphStats", 6)?;
+        s.serialize_field("nodes", &self.nodes)?;
+        s.serialize_field("promised", &self.promised)?;
+        s.serialize_field("index_entries", &self.index_entries)?;
+        s.serialize_field("arena_slots", &self.arena_slots)?;
+        s.serialize_field("arena_chunks", &self.arena_chunks)?;
+        s.serialize_field("approx_bytes", &self.approx_bytes)?;
+        s.end()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    #[cfg(feature = "std")]
+    use crate::HashGraph;
+    use crate::{BTreeGraph, GraphStats};
+
+    #[test]
+    fn stats() {
+        let mut graph = BTreeGraph::with_capacity(4);
+        (0..5u32).for_each(|key| {
+            graph.insert(key, key);
+        });
+        graph.promise(5);
+        graph.remove(&0);
+
+        let stats = graph.stats();
+        assert_eq!(
+            stats,
+            GraphStats {
+                nodes: 4,
+                promised: 1,
+                index_entries: 5,
+                arena_slots: 6,
+                arena_chunks: 2,
+                approx_bytes: graph.memory_usage(),
+            }
+        );
+        assert_eq!(
+            stats.to_string(),
+            format!(
+                "nodes=4 promised=1 index_entries=5 arena_slots=6 arena_chunks=2 approx_bytes={}",
+                stats.approx_bytes
+            )
+        );
+
+        #[cfg(feature = "std")]
+        {
+            let graph = [("a", 1)].into_iter().collect::<HashGraph<_, _>>();
+            assert_eq!(graph.stats().nodes, 1);
+            assert_eq!(graph.stats().promised, 0);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize() {
+        let graph = [(1, "a")].into_iter().collect::<BTreeGraph<_, _>>();
+        let value = serde_json::to_value(graph.stats()).unwrap();
+        assert_eq!(value["nodes"], 1);
+        assert_eq!(value["arena_slots"], 1);
+    }
+}
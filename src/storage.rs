@@ -0,0 +1,383 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+use alloc::{
+    alloc::{alloc, dealloc, handle_alloc_error},
+    vec::Vec,
+};
+use core::{
+    alloc::Layout,
+    ptr::{self, NonNull},
+    slice,
+};
+
+/// The memory backing the nodes of a `Graph`. The graph allocates
+/// its slots in chunks, requesting the memory for each chunk from
+/// the storage. The default is `Heap`; a graph using another storage
+/// (e.g. one backed by a bump allocator) is created with
+/// `Graph::with_storage`.
+///
+/// # Safety
+///
+/// `allocate` must return a block of memory fitting `layout`, that
+/// stays valid and is not handed out again until it is passed to
+/// `deallocate`. It is never called with a zero-sized layout.
+pub unsafe trait NodeStorage {
+    /// Allocate a block of memory.
+    fn allocate(&mut self, layout: Layout) -> NonNull<u8>;
+
+    /// Release a block of memory previously returned by `allocate`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `allocate` on this storage,
+    /// with the same `layout`, and not yet have been released.
+    unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout);
+}
+
+/// The default node storage, using the global allocator.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Heap;
+
+unsafe impl NodeStorage for Heap {
+    fn allocate(&mut self, layout: Layout) -> NonNull<u8> {
+        NonNull::new(unsafe { alloc(layout) }).unwrap_or_else(|| handle_alloc_error(layout))
+    }
+
+    unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        dealloc(ptr.as_ptr(), layout)
+    }
+}
+
+/// An arena holding a graph's node slots. Elements are allocated in
+/// chunks that are never reallocated, so that they can be referenced
+/// by pointer until the arena is cleared or dropped. By default, like
+/// `typed_arena`, chunks grow geometrically: each new chunk is at
+/// least twice as large as the previous one. With a fixed chunk
+/// capacity, every chunk after the first holds that many elements.
+/// Clearing the arena keeps its chunks for reuse; the chunks after
+/// `current`, the one being filled, are always empty.
+pub(crate) struct Nodes<E, S: NodeStorage> {
+    storage: S,
+    chunks: Vec<Chunk<E>>,
+    current: usize,
+    len: usize,
+    next_capacity: usize,
+    chunk_capacity: Option<usize>,
+}
+
+struct Chunk<E> {
+    ptr: NonNull<E>,
+    capacity: usize,
+    len: usize,
+}
+
+impl<E> Chunk<E> {
+    fn layout(&self) -> Layout {
+        Layout::array::<E>(self.capacity).expect("capacity overflow")
+    }
+}
+
+/// The size of the first chunk when no capacity is given, as in
+/// `typed_arena`.
+const MIN_CHUNK_BYTES: usize = 1024;
+
+impl<E, S: NodeStorage> Nodes<E, S> {
+    pub(crate) fn new(storage: S) -> Self {
+        Self {
+            storage,
+            chunks: Vec::new(),
+            current: 0,
+            len: 0,
+            next_capacity: (MIN_CHUNK_BYTES / core::mem::size_of::<E>().max(1)).max(1),
+            chunk_capacity: None,
+        }
+    }
+
+    /// Allocate chunks of `capacity` elements from now on, instead of
+    /// growing them geometrically. A larger chunk is still allocated
+    /// when reserving room for more elements at once.
+    pub(crate) fn set_chunk_capacity(&mut self, capacity: usize) {
+        let capacity = capacity.max(1);
+        self.next_capacity = capacity;
+        self.chunk_capacity = Some(capacity);
+    }
+
+    pub(crate) fn with_capacity(storage: S, capacity: usize) -> Self {
+        let mut nodes = Self::new(storage);
+        if capacity > 0 {
+            nodes.next_capacity = capacity;
+            nodes.reserve(capacity);
+        }
+        nodes
+    }
+
+    /// Store an element and return a pointer to it.
+    pub(crate) fn alloc(&mut self, value: E) -> NonNull<E> {
+        self.reserve(1);
+        let chunk = &mut self.chunks[self.current];
+        unsafe {
+            let ptr = NonNull::new_unchecked(chunk.ptr.as_ptr().add(chunk.len));
+            ptr.as_ptr().write(value);
+            chunk.len += 1;
+            self.len += 1;
+            ptr
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The number of chunks holding at least one element.
+    pub(crate) fn chunks(&self) -> usize {
+        self.chunks.iter().filter(|chunk| chunk.len > 0).count()
+    }
+
+    /// Make sure that (at least) `additional` elements can be stored
+    /// contiguously, moving on to a spare chunk that is large enough
+    /// or allocating a new one if needed.
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        if additional == 0
+            || self
+                .chunks
+                .get(self.current)
+                .is_some_and(|chunk| chunk.capacity - chunk.len >= additional)
+        {
+            return;
+        }
+        let next = match self.chunks.is_empty() {
+            true => 0,
+            false => self.current + 1,
+        };
+        match self.chunks[next..]
+            .iter()
+            .position(|chunk| chunk.capacity >= additional)
+        {
+            Some(i) => self.chunks.swap(next, next + i),
+            None => {
+                let capacity = self.next_capacity.max(additional);
+                let layout = Layout::array::<E>(capacity).expect("capacity overflow");
+                let ptr = match layout.size() {
+                    0 => NonNull::dangling(),
+                    _ => self.storage.allocate(layout).cast(),
+                };
+                self.chunks.insert(
+                    next,
+                    Chunk {
+                        ptr,
+                        capacity,
+                        len: 0,
+                    },
+                );
+                self.next_capacity = self
+                    .chunk_capacity
+                    .unwrap_or_else(|| capacity.saturating_mul(2));
+            }
+        }
+        self.current = next;
+    }
+
+    /// Drop all elements, keeping the chunks to be filled again.
+    pub(crate) fn clear(&mut self) {
+        self.len = 0;
+        self.current = 0;
+        for chunk in &mut self.chunks {
+            let len = core::mem::replace(&mut chunk.len, 0);
+            unsafe { ptr::drop_in_place(ptr::slice_from_raw_parts_mut(chunk.ptr.as_ptr(), len)) };
+        }
+    }
+
+    /// Pointers to all elements, in allocation order.
+    pub(crate) fn ptrs(&self) -> Ptrs<'_, E> {
+        Ptrs {
+            chunks: self.chunks.iter(),
+            current: None,
+            index: 0,
+            remaining: self.len,
+        }
+    }
+
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut E> {
+        self.ptrs().map(|ptr| unsafe { &mut *ptr.as_ptr() })
+    }
+
+    fn release(&mut self) {
+        for chunk in self.chunks.drain(..) {
+            unsafe {
+                ptr::drop_in_place(ptr::slice_from_raw_parts_mut(chunk.ptr.as_ptr(), chunk.len));
+                if chunk.layout().size() > 0 {
+                    self.storage.deallocate(chunk.ptr.cast(), chunk.layout());
+                }
+            }
+        }
+    }
+}
+
+impl<E, S: NodeStorage> Drop for Nodes<E, S> {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+impl<E, S: NodeStorage> IntoIterator for Nodes<E, S> {
+    type Item = E;
+    type IntoIter = IntoIter<E, S>;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            nodes: self,
+            chunk: 0,
+            index: 0,
+        }
+    }
+}
+
+/// An iterator over pointers to the elements of a `Nodes` arena.
+pub(crate) struct Ptrs<'a, E> {
+    chunks: slice::Iter<'a, Chunk<E>>,
+    current: Option<&'a Chunk<E>>,
+    index: usize,
+    remaining: usize,
+}
+
+impl<E> Iterator for Ptrs<'_, E> {
+    type Item = NonNull<E>;
+
+    fn next(&mut self) -> Option<NonNull<E>> {
+        loop {
+            if let Some(chunk) = self.current.filter(|chunk| self.index < chunk.len) {
+                let ptr = unsafe { NonNull::new_unchecked(chunk.ptr.as_ptr().add(self.index)) };
+                self.index += 1;
+                self.remaining -= 1;
+                return Some(ptr);
+            }
+            self.current = Some(self.chunks.next()?);
+            self.index = 0;
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<E> ExactSizeIterator for Ptrs<'_, E> {}
+
+/// An iterator over the elements of a `Nodes` arena, by value.
+pub(crate) struct IntoIter<E, S: NodeStorage> {
+    nodes: Nodes<E, S>,
+    chunk: usize,
+    index: usize,
+}
+
+impl<E, S: NodeStorage> Iterator for IntoIter<E, S> {
+    type Item = E;
+
+    fn next(&mut self) -> Option<E> {
+        loop {
+            let chunk = self.nodes.chunks.get(self.chunk)?;
+            if self.index < chunk.len {
+                let value = unsafe { chunk.ptr.as_ptr().add(self.index).read() };
+                self.index += 1;
+                return Some(value);
+            }
+            self.chunk += 1;
+            self.index = 0;
+        }
+    }
+}
+
+impl<E, S: NodeStorage> Drop for IntoIter<E, S> {
+    fn drop(&mut self) {
+        /* Drop the remaining elements and forget about the moved-out
+         * ones before the arena itself is dropped. */
+        for (i, chunk) in self.nodes.chunks.iter_mut().enumerate().skip(self.chunk) {
+            let start = if i == self.chunk { self.index } else { 0 };
+            let len = core::mem::replace(&mut chunk.len, 0);
+            unsafe {
+                ptr::drop_in_place(ptr::slice_from_raw_parts_mut(
+                    chunk.ptr.as_ptr().add(start),
+                    len - start,
+                ))
+            };
+        }
+        for chunk in &mut self.nodes.chunks[..self.chunk] {
+            chunk.len = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use core::{alloc::Layout, cell::Cell, ptr::NonNull};
+    use std::rc::Rc;
+
+    use crate::{Graph, Heap, NodeStorage};
+
+    /// Counts the blocks allocated from the heap.
+    #[derive(Clone, Default)]
+    struct Counting(Rc<Cell<usize>>);
+
+    unsafe impl NodeStorage for Counting {
+        fn allocate(&mut self, layout: Layout) -> NonNull<u8> {
+            self.0.set(self.0.get() + 1);
+            Heap.allocate(layout)
+        }
+
+        unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout) {
+            self.0.set(self.0.get() - 1);
+            Heap.deallocate(ptr, layout)
+        }
+    }
+
+    #[test]
+    fn custom_storage() {
+        let blocks = Counting::default();
+        let mut graph = Graph::with_storage(blocks.clone());
+        let refs = graph.insert_batch(0..10);
+        let promised = graph.promise();
+        graph.create(&promised, 10);
+        assert_eq!(graph.remove(refs[5].clone()), 5);
+        *graph.borrow_mut(&refs[0]) = 100;
+        assert_eq!(*graph.borrow(&refs[9]), 9);
+        assert_eq!(graph.iter_mut().count(), 10);
+        assert_eq!(blocks.0.get(), graph.chunk_count());
+        assert_eq!(
+            graph.into_iter().collect::<Vec<_>>(),
+            vec![100, 1, 2, 3, 4, 6, 7, 8, 9, 10]
+        );
+        assert_eq!(blocks.0.get(), 0);
+
+        let mut graph = Graph::with_storage(blocks.clone());
+        graph.insert("a".to_string());
+        graph.clear();
+        assert_eq!(graph.memory_usage(), 0);
+        assert_eq!(blocks.0.get(), 1);
+        graph.insert_batch((0..10).map(|i| i.to_string()));
+        assert_eq!(blocks.0.get(), 1);
+        drop(graph);
+        assert_eq!(blocks.0.get(), 0);
+    }
+
+    #[test]
+    fn into_iter_drop() {
+        let mut graph = Graph::with_capacity(2);
+        graph.extend((0..5).map(|i| i.to_string()));
+        let mut iter = graph.into_iter();
+        assert_eq!(iter.next().as_deref(), Some("0"));
+        assert_eq!(iter.next().as_deref(), Some("1"));
+        assert_eq!(iter.next().as_deref(), Some("2"));
+    }
+}
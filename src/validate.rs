@@ -0,0 +1,390 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+use alloc::{format, string::String, vec::Vec};
+use core::fmt::{Debug, Display};
+
+#[cfg(feature = "std")]
+use crate::HashGraph;
+use crate::{BTreeGraph, Graph, Ref, VisitRefs};
+
+/// Problems found when validating a graph.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ValidationReport<K> {
+    /// Keys whose node was promised, but never created.
+    pub promised: Vec<K>,
+    /// Keys whose node was removed from the graph, but not from the
+    /// index.
+    pub removed: Vec<K>,
+    /// Keys whose node holds a reference to a node of a different
+    /// graph, repeated for every such reference. Only checked by
+    /// `validate_refs`.
+    pub wrong_graph: Vec<K>,
+}
+
+impl<K> ValidationReport<K> {
+    fn from_index<'a, V: 'a, I>(index: I) -> Self
+    where
+        K: 'a + Clone,
+        I: Iterator<Item = (&'a K, &'a Ref<V>)>,
+    {
+        let mut report = Self {
+            promised: Vec::new(),
+            removed: Vec::new(),
+            wrong_graph: Vec::new(),
+        };
+        for (key, node) in index {
+            match unsafe { node_state(node) } {
+                NodeState::Created => {}
+                NodeState::Promised => report.promised.push(key.clone()),
+                NodeState::Removed => report.removed.push(key.clone()),
+            }
+        }
+        report
+    }
+
+    fn check_refs<'a, V, I>(mut self, graph: &Graph<V>, index: I) -> Self
+    where
+        K: 'a + Clone,
+        V: 'a + VisitRefs<V>,
+        I: Iterator<Item = (&'a K, &'a Ref<V>)>,
+    {
+        for (key, node) in index {
+            let Some(value) = (unsafe { node.try_get_unchecked() }) else {
+                continue;
+            };
+            value.visit_refs(&mut |r| {
+                if !graph.owns(r) {
+                    self.wrong_graph.push(key.clone());
+                }
+            });
+        }
+        self
+    }
+
+    fn into_result(self) -> Result<(), Self> {
+        if self.promised.is_empty() && self.removed.is_empty() && self.wrong_graph.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+/// Invalid references found by `check_integrity`. Keys refer to the
+/// nodes holding the references, and are repeated for every invalid
+/// reference in the node.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct IntegrityError<K> {
+    /// References to nodes of a different graph.
+    pub wrong_graph: Vec<K>,
+    /// References to nodes that were promised but not (yet) created.
+    pub promised: Vec<K>,
+    /// References to nodes that were removed.
+    pub dangling: Vec<K>,
+}
+
+enum NodeState {
+    Promised,
+    Created,
+    Removed,
+}
+
+/// Safety: the reference must belong to the graph being validated.
+unsafe fn node_state<T>(node: &Ref<T>) -> NodeState {
+    if node.is_promised_unchecked() {
+        NodeState::Promised
+    } else if node.try_get_unchecked().is_some() {
+        NodeState::Created
+    } else {
+        NodeState::Removed
+    }
+}
+
+impl<K> IntegrityError<K> {
+    fn from_index<'a, V, I>(graph: &Graph<V>, index: I) -> Result<(), Self>
+    where
+        K: 'a + Clone,
+        V: 'a + VisitRefs<V>,
+        I: Iterator<Item = (&'a K, &'a Ref<V>)>,
+    {
+        let mut err = Self {
+            wrong_graph: Vec::new(),
+            promised: Vec::new(),
+            dangling: Vec::new(),
+        };
+        for (key, node) in index {
+            let Some(value) = (unsafe { node.try_get_unchecked() }) else {
+                continue;
+            };
+            value.visit_refs(&mut |r| {
+                if !graph.owns(r) {
+                    err.wrong_graph.push(key.clone());
+                    return;
+                }
+                match unsafe { node_state(r) } {
+                    NodeState::Created => {}
+                    NodeState::Promised => err.promised.push(key.clone()),
+                    NodeState::Removed => err.dangling.push(key.clone()),
+                }
+            });
+        }
+        if err.wrong_graph.is_empty() && err.promised.is_empty() && err.dangling.is_empty() {
+            Ok(())
+        } else {
+            Err(err)
+        }
+    }
+}
+
+impl<K, V> BTreeGraph<K, V> {
+    /// Check that every node in the index was created. This is
+    /// meant to be called after loading a graph, to catch a
+    /// forgotten `create` before the empty slot is borrowed. Use
+    /// `check_integrity` to check the references inside the nodes.
+    pub fn validate(&self) -> Result<(), ValidationReport<K>>
+    where
+        K: Clone,
+    {
+        ValidationReport::from_index(self.iter_ref()).into_result()
+    }
+
+    /// Like `validate`, but also check that every reference stored
+    /// in the graph's nodes belongs to this graph.
+    pub fn validate_refs(&self) -> Result<(), ValidationReport<K>>
+    where
+        K: Clone,
+        V: VisitRefs<V>,
+    {
+        ValidationReport::from_index(self.iter_ref())
+            .check_refs(self.as_ref(), self.iter_ref())
+            .into_result()
+    }
+
+    /// Check that every reference stored in the graph's nodes belongs
+    /// to this graph and points to a created node. This catches
+    /// references mixed up between graphs, or left dangling by
+    /// `remove`, before they are borrowed.
+    pub fn check_integrity(&self) -> Result<(), IntegrityError<K>>
+    where
+        K: Clone,
+        V: VisitRefs<V>,
+    {
+        IntegrityError::from_index(self.as_ref(), self.iter_ref())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V, S> HashGraph<K, V, S> {
+    /// Check that every node in the index was created. See
+    /// `BTreeGraph::validate`.
+    pub fn validate(&self) -> Result<(), ValidationReport<K>>
+    where
+        K: Clone,
+    {
+        ValidationReport::from_index(self.iter_ref()).into_result()
+    }
+
+    /// Check the index and the graph of the references stored in the
+    /// nodes. See `BTreeGraph::validate_refs`.
+    pub fn validate_refs(&self) -> Result<(), ValidationReport<K>>
+    where
+        K: Clone,
+        V: VisitRefs<V>,
+    {
+        ValidationReport::from_index(self.iter_ref())
+            .check_refs(self.as_ref(), self.iter_ref())
+            .into_result()
+    }
+
+    /// Check the references stored in the graph's nodes. See
+    /// `BTreeGraph::check_integrity`.
+    pub fn check_integrity(&self) -> Result<(), IntegrityError<K>>
+    where
+        K: Clone,
+        V: VisitRefs<V>,
+    {
+        IntegrityError::from_index(self.as_ref(), self.iter_ref())
+    }
+}
+
+/// Write one line per problem, without a trailing newline.
+fn write_lines<I>(f: &mut core::fmt::Formatter<'_>, mut lines: I) -> core::fmt::Result
+where
+    I: Iterator<Item = String>,
+{
+    if let Some(line) = lines.next() {
+        write!(f, "{line}")?;
+    }
+    lines.try_for_each(|line| write!(f, "\n{line}"))
+}
+
+impl<K: Debug> Display for ValidationReport<K> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let promised = self
+            .promised
+            .iter()
+            .map(|key| format!("{key:?}: promised but never created"));
+        let removed = self
+            .removed
+            .iter()
+            .map(|key| format!("{key:?}: removed but still indexed"));
+        let wrong_graph = self
+            .wrong_graph
+            .iter()
+            .map(|key| format!("{key:?}: references a node of another graph"));
+        write_lines(f, promised.chain(removed).chain(wrong_graph))
+    }
+}
+
+impl<K: Debug> core::error::Error for ValidationReport<K> {}
+
+impl<K: Debug> Display for IntegrityError<K> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let wrong_graph = self
+            .wrong_graph
+            .iter()
+            .map(|key| format!("{key:?}: references a node of another graph"));
+        let promised = self
+            .promised
+            .iter()
+            .map(|key| format!("{key:?}: references a promised node"));
+        let dangling = self
+            .dangling
+            .iter()
+            .map(|key| format!("{key:?}: references a removed node"));
+        write_lines(f, wrong_graph.chain(promised).chain(dangling))
+    }
+}
+
+impl<K: Debug> core::error::Error for IntegrityError<K> {}
+
+#[cfg(test)]
+mod test {
+
+    #[cfg(feature = "std")]
+    use crate::HashGraph;
+    use crate::{BTreeGraph, IntegrityError, OptRefMap, Ref, ValidationReport, VisitRefs};
+
+    #[test]
+    fn validate() {
+        let mut graph = BTreeGraph::new();
+        graph.insert(1, "a");
+        assert_eq!(graph.validate(), Ok(()));
+        let node = graph.promise(3);
+        graph.promise(2);
+        let report = graph.validate().unwrap_err();
+        assert_eq!(
+            report,
+            ValidationReport {
+                promised: vec![2, 3],
+                removed: vec![],
+                wrong_graph: vec![],
+            }
+        );
+        assert_eq!(
+            report.to_string(),
+            "2: promised but never created\n3: promised but never created"
+        );
+        graph.create(&node, "c");
+        graph.insert(2, "b");
+        assert_eq!(graph.validate(), Ok(()));
+
+        #[cfg(feature = "std")]
+        {
+            let mut graph = HashGraph::<_, u32>::new();
+            graph.promise("x");
+            assert_eq!(graph.validate().unwrap_err().promised, vec!["x"]);
+        }
+    }
+
+    #[test]
+    fn validate_removed() {
+        let (mut nodes, mut index) = BTreeGraph::<_, &str>::new().into_parts();
+        index.insert(1, nodes.insert("a"));
+        index.insert(2, nodes.promise());
+        let removed = nodes.insert("c");
+        index.insert(3, removed.clone());
+        nodes.remove(removed);
+        let graph = unsafe { BTreeGraph::from_parts(nodes, index) };
+        let report = graph.validate().unwrap_err();
+        assert_eq!(
+            report,
+            ValidationReport {
+                promised: vec![2],
+                removed: vec![3],
+                wrong_graph: vec![],
+            }
+        );
+        assert_eq!(
+            report.to_string(),
+            "2: promised but never created\n3: removed but still indexed"
+        );
+    }
+
+    #[test]
+    fn check_integrity() {
+        struct Node(OptRefMap<u32, Node>);
+
+        impl VisitRefs<Node> for Node {
+            fn visit_refs(&self, f: &mut impl FnMut(&Ref<Node>)) {
+                self.0.visit_refs(f)
+            }
+
+            fn visit_refs_mut(&mut self, f: &mut impl FnMut(&mut Ref<Node>)) {
+                self.0.visit_refs_mut(f)
+            }
+        }
+
+        let mut other = BTreeGraph::new();
+        let foreign = other.insert(0, Node(OptRefMap::new()));
+
+        let mut graph = BTreeGraph::new();
+        let a = graph.insert(1, Node(OptRefMap::new()));
+        let b = graph.insert(2, Node(OptRefMap::new()));
+        graph.borrow_mut(&a).0.insert(2, Some(b.clone()));
+        graph.borrow_mut(&b).0.insert(1, Some(a.clone()));
+        graph.borrow_mut(&b).0.insert(3, None);
+        assert_eq!(graph.check_integrity(), Ok(()));
+
+        graph.remove(&1);
+        graph.borrow_mut(&b).0.insert(0, Some(foreign));
+        let c = graph.promise(3);
+        graph.borrow_mut(&b).0.insert(3, Some(c));
+        let err = graph.check_integrity().unwrap_err();
+        assert_eq!(
+            err,
+            IntegrityError {
+                wrong_graph: vec![2],
+                promised: vec![2],
+                dangling: vec![2],
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "2: references a node of another graph\n\
+             2: references a promised node\n\
+             2: references a removed node"
+        );
+
+        assert_eq!(
+            graph.validate_refs(),
+            Err(ValidationReport {
+                promised: vec![3],
+                removed: vec![],
+                wrong_graph: vec![2],
+            })
+        );
+    }
+}
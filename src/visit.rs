@@ -0,0 +1,86 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+use crate::Ref;
+use alloc::vec::Vec;
+
+/// Structures containing references to nodes of type `V`. This
+/// allows graph-wide operations on the references stored in node
+/// values, such as redirecting them to another node.
+pub trait VisitRefs<V> {
+    /// Call `f` for every reference in the structure.
+    fn visit_refs(&self, f: &mut impl FnMut(&Ref<V>));
+
+    /// Call `f` for every reference in the structure, allowing it to
+    /// be replaced.
+    fn visit_refs_mut(&mut self, f: &mut impl FnMut(&mut Ref<V>));
+}
+
+impl<V> VisitRefs<V> for Ref<V> {
+    fn visit_refs(&self, f: &mut impl FnMut(&Ref<V>)) {
+        f(self)
+    }
+
+    fn visit_refs_mut(&mut self, f: &mut impl FnMut(&mut Ref<V>)) {
+        f(self)
+    }
+}
+
+impl<V, T: VisitRefs<V>> VisitRefs<V> for Option<T> {
+    fn visit_refs(&self, f: &mut impl FnMut(&Ref<V>)) {
+        self.iter().for_each(|value| value.visit_refs(f))
+    }
+
+    fn visit_refs_mut(&mut self, f: &mut impl FnMut(&mut Ref<V>)) {
+        self.iter_mut().for_each(|value| value.visit_refs_mut(f))
+    }
+}
+
+impl<V, T: VisitRefs<V>> VisitRefs<V> for Vec<T> {
+    fn visit_refs(&self, f: &mut impl FnMut(&Ref<V>)) {
+        self.iter().for_each(|value| value.visit_refs(f))
+    }
+
+    fn visit_refs_mut(&mut self, f: &mut impl FnMut(&mut Ref<V>)) {
+        self.iter_mut().for_each(|value| value.visit_refs_mut(f))
+    }
+}
+
+/// Structures containing keyed references (`RefBy`, `OptRefBy` and
+/// the ref maps) to nodes of type `V`. This allows graph-wide
+/// operations to keep the keys in line with the references, e.g.
+/// when `replace_ref_by` redirects them to another node.
+pub trait VisitRefsBy<K, V> {
+    /// Call `f` for every keyed reference in the structure, allowing
+    /// the reference to be replaced. If `f` returns a key, it
+    /// replaces the reference's key.
+    fn visit_refs_by_mut(&mut self, f: &mut impl FnMut(&K, &mut Ref<V>) -> Option<K>);
+}
+
+/// Plain references carry no key.
+impl<K, V> VisitRefsBy<K, V> for Ref<V> {
+    fn visit_refs_by_mut(&mut self, _f: &mut impl FnMut(&K, &mut Ref<V>) -> Option<K>) {}
+}
+
+impl<K, V, T: VisitRefsBy<K, V>> VisitRefsBy<K, V> for Option<T> {
+    fn visit_refs_by_mut(&mut self, f: &mut impl FnMut(&K, &mut Ref<V>) -> Option<K>) {
+        self.iter_mut().for_each(|value| value.visit_refs_by_mut(f))
+    }
+}
+
+impl<K, V, T: VisitRefsBy<K, V>> VisitRefsBy<K, V> for Vec<T> {
+    fn visit_refs_by_mut(&mut self, f: &mut impl FnMut(&K, &mut Ref<V>) -> Option<K>) {
+        self.iter_mut().for_each(|value| value.visit_refs_by_mut(f))
+    }
+}
@@ -0,0 +1,154 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+use alloc::{format, string::String, string::ToString, vec::Vec};
+use core::fmt::Display;
+
+use serde::{
+    ser::{SerializeStruct, Serializer},
+    Deserialize, Serialize,
+};
+use tsify::Tsify;
+use wasm_bindgen::{prelude::wasm_bindgen, JsValue};
+
+use crate::{BTreeGraph, Resolve};
+
+/* References (`RefBy`, `OptRefBy` and the ref maps) are serialized
+ * as their keys. On the TS side, they are typed as a branded `Ref<K>`,
+ * so that they can not be confused with plain keys. */
+#[wasm_bindgen(typescript_custom_section)]
+const TS_REF: &str = r#"
+declare const graphRef: unique symbol;
+/** The key of a graph node, used as a reference to that node. */
+export type Ref<K> = K & { readonly [graphRef]: true };
+"#;
+
+/* With the `js-ref-helpers` feature, the module exports helpers to
+ * convert between keys and references. At runtime they are the
+ * identity. They are opt-in, since they would otherwise end up in
+ * the exports of every module built with this crate. */
+#[cfg(feature = "js-ref-helpers")]
+#[wasm_bindgen(typescript_custom_section)]
+const TS_REF_HELPERS: &str = r#"
+/** Use a key as a reference. */
+export function asRef<K>(key: K): Ref<K>;
+/** Get the key of a reference. */
+export function refKey<K>(node: Ref<K>): K;
+"#;
+
+#[cfg(feature = "js-ref-helpers")]
+#[wasm_bindgen(js_name = asRef, skip_typescript)]
+pub fn js_as_ref(key: JsValue) -> JsValue {
+    key
+}
+
+#[cfg(feature = "js-ref-helpers")]
+#[wasm_bindgen(js_name = refKey, skip_typescript)]
+pub fn js_ref_key(node: JsValue) -> JsValue {
+    node
+}
+
+/// Error returned when a graph received from JS could not be
+/// deserialized or resolved, or when a graph with promised nodes is
+/// passed to JS. This is passed to JS as an object
+/// containing the error message and the missing keys, if any.
+#[derive(Tsify, Debug)]
+#[tsify(into_wasm_abi, type = "{ message: string; missing: K[] }")]
+pub struct JsResolveError<K> {
+    message: String,
+    missing: Vec<K>,
+}
+
+impl<K> JsResolveError<K> {
+    fn other<E: Display>(err: E) -> Self {
+        Self {
+            message: err.to_string(),
+            missing: Vec::new(),
+        }
+    }
+
+    fn unresolved(missing: Vec<K>) -> Self {
+        Self {
+            message: format!("{} unresolved reference(s)", missing.len()),
+            missing,
+        }
+    }
+
+    fn promised(missing: Vec<K>) -> Self {
+        Self {
+            message: format!("{} promised node(s) not created", missing.len()),
+            missing,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn missing(&self) -> &[K] {
+        &self.missing
+    }
+}
+
+impl<K> Display for JsResolveError<K> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl<K: core::fmt::Debug> core::error::Error for JsResolveError<K> {}
+
+impl<K: Serialize> Serialize for JsResolveError<K> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_struct("JsResolveError", 2)?;
+        s.serialize_field("message", &self.message)?;
+        s.serialize_field("missing", &self.missing)?;
+        s.end()
+    }
+}
+
+impl<K, V> BTreeGraph<K, V> {
+    /// Deserialize a graph received from JS and resolve the
+    /// references in its nodes against the graph itself.
+    pub fn from_js_resolved(value: JsValue) -> Result<Self, JsResolveError<K>>
+    where
+        K: for<'de> Deserialize<'de> + Ord + Clone,
+        V: for<'de> Deserialize<'de> + Resolve<K, V>,
+    {
+        let mut graph = Self::from_js(value).map_err(JsResolveError::other)?;
+        graph.resolve().map_err(JsResolveError::unresolved)?;
+        Ok(graph)
+    }
+
+    /// Serialize the graph for passing to JS. Fails with the keys of
+    /// promised nodes that have not been created, if any.
+    pub fn to_js(&self) -> Result<JsValue, JsResolveError<K>>
+    where
+        K: Serialize + Clone,
+        V: Serialize,
+    {
+        let promised = self
+            .iter_ref()
+            .filter(|(_, node)| unsafe { node.try_get_unchecked().is_none() })
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        if !promised.is_empty() {
+            return Err(JsResolveError::promised(promised));
+        }
+        Ok(self.into_js().map_err(JsResolveError::other)?.into())
+    }
+}
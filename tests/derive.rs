@@ -0,0 +1,103 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+#![cfg(feature = "derive")]
+
+use graph::{BTreeGraph, Ref, RefBy, VisitRefs};
+
+#[derive(VisitRefs)]
+#[graph(key = u32)]
+struct Node {
+    #[graph(skip)]
+    value: u32,
+    parent: Option<RefBy<u32, Node>>,
+    children: Vec<Ref<Node>>,
+}
+
+#[derive(VisitRefs)]
+#[graph(node = Node)]
+enum Edge {
+    Weighted(#[graph(skip)] f64, Ref<Node>),
+    Plain { target: Ref<Node> },
+    None,
+}
+
+#[derive(VisitRefs)]
+#[graph(node = Node)]
+struct Wrapper<T> {
+    inner: Vec<T>,
+}
+
+fn node(value: u32, parent: Option<RefBy<u32, Node>>) -> Node {
+    Node {
+        value,
+        parent,
+        children: Vec::new(),
+    }
+}
+
+#[test]
+fn derive_visit_refs() {
+    let mut graph = BTreeGraph::new();
+    let a = graph.insert(1, node(1, None));
+    let b = graph.insert(2, node(2, Some(RefBy::new(1, a.clone()))));
+    let c = graph.insert(3, node(3, Some(RefBy::new(1, a.clone()))));
+    graph.borrow_mut(&a).children = vec![b.clone(), c.clone()];
+    assert_eq!(graph.check_integrity(), Ok(()));
+
+    let mut visited = Vec::new();
+    graph
+        .borrow(&a)
+        .visit_refs(&mut |node| visited.push(graph.borrow(node).value));
+    graph
+        .borrow(&b)
+        .visit_refs(&mut |node| visited.push(graph.borrow(node).value));
+    assert_eq!(visited, [2, 3, 1]);
+
+    graph
+        .borrow_mut(&a)
+        .visit_refs_mut(&mut |node| *node = c.clone());
+    assert!(graph.borrow(&a).children.iter().all(|node| node == &c));
+
+    let edges = vec![
+        Edge::Weighted(0.5, a.clone()),
+        Edge::Plain { target: b.clone() },
+        Edge::None,
+    ];
+    let mut count = 0;
+    edges.visit_refs(&mut |_| count += 1);
+    assert_eq!(count, 2);
+
+    let wrapper = Wrapper {
+        inner: vec![a.clone(), b],
+    };
+    let mut visited = Vec::new();
+    wrapper.visit_refs(&mut |node| visited.push(graph.borrow(node).value));
+    assert_eq!(visited, [1, 2]);
+}
+
+#[test]
+fn derive_visit_refs_by() {
+    let mut graph = BTreeGraph::new();
+    let a = graph.insert(1, node(1, None));
+    let b = graph.insert(2, node(2, None));
+    graph.insert(3, node(3, Some(RefBy::new(2, b.clone()))));
+    graph.borrow_mut(&a).children = vec![b.clone()];
+
+    graph.replace_ref_by(&b, &a);
+    let parent = graph.get(&3).unwrap().parent.as_ref().unwrap();
+    assert_eq!(parent.key(), &1);
+    assert!(parent.value_ref() == &a);
+    assert!(graph.borrow(&a).children == [a.clone()]);
+}
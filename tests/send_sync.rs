@@ -0,0 +1,79 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+use std::{cell::Cell, rc::Rc};
+
+#[cfg(feature = "std")]
+use graph::HashGraph;
+use graph::{BTreeGraph, FrozenGraph, Graph, OptRefBy, Ref, RefBy, RefMap};
+use static_assertions::{assert_impl_all, assert_not_impl_any};
+
+struct Node {
+    _next: Vec<Ref<Node>>,
+}
+
+assert_impl_all!(Graph<String>: Send, Sync);
+assert_impl_all!(Graph<Cell<u8>>: Send);
+assert_not_impl_any!(Graph<Cell<u8>>: Sync);
+assert_not_impl_any!(Graph<Rc<String>>: Send, Sync);
+
+assert_impl_all!(Ref<String>: Send, Sync);
+assert_not_impl_any!(Ref<Cell<u8>>: Send, Sync);
+assert_not_impl_any!(Ref<Rc<String>>: Send, Sync);
+
+assert_impl_all!(RefBy<String, String>: Send, Sync);
+assert_not_impl_any!(RefBy<String, Cell<u8>>: Send, Sync);
+assert_not_impl_any!(RefBy<Rc<String>, String>: Send, Sync);
+assert_impl_all!(OptRefBy<String, String>: Send, Sync);
+assert_not_impl_any!(OptRefBy<String, Cell<u8>>: Send, Sync);
+assert_impl_all!(RefMap<String, String>: Send, Sync);
+assert_not_impl_any!(RefMap<String, Cell<u8>>: Send, Sync);
+
+assert_impl_all!(BTreeGraph<String, String>: Send, Sync);
+assert_impl_all!(BTreeGraph<String, Cell<u8>>: Send);
+assert_not_impl_any!(BTreeGraph<String, Cell<u8>>: Sync);
+assert_not_impl_any!(BTreeGraph<String, Rc<String>>: Send, Sync);
+assert_not_impl_any!(BTreeGraph<Rc<String>, String>: Send, Sync);
+
+#[cfg(feature = "std")]
+mod hash_graph {
+    use super::*;
+
+    assert_impl_all!(HashGraph<String, String>: Send, Sync);
+    assert_impl_all!(HashGraph<String, Cell<u8>>: Send);
+    assert_not_impl_any!(HashGraph<String, Cell<u8>>: Sync);
+    assert_not_impl_any!(HashGraph<String, Rc<String>>: Send, Sync);
+    assert_not_impl_any!(HashGraph<Rc<String>, String>: Send, Sync);
+}
+
+assert_impl_all!(FrozenGraph<String, String>: Send, Sync, Clone);
+assert_not_impl_any!(FrozenGraph<String, Cell<u8>>: Send, Sync);
+assert_not_impl_any!(FrozenGraph<Rc<String>, String>: Send, Sync);
+
+assert_impl_all!(Graph<Node>: Send, Sync);
+assert_impl_all!(BTreeGraph<String, Node>: Send, Sync);
+assert_impl_all!(FrozenGraph<String, Node>: Send, Sync);
+
+#[test]
+fn send_graph() {
+    let mut graph = BTreeGraph::new();
+    let node = graph.insert(1, String::from("a"));
+    let graph = std::thread::spawn(move || {
+        graph.borrow_mut(&node).push('b');
+        graph
+    })
+    .join()
+    .unwrap();
+    assert_eq!(graph.get(&1).map(String::as_str), Some("ab"));
+}
@@ -0,0 +1,144 @@
+/****************************************************************************** 
+ * Copyright 2025 ContinuousC                                                 * 
+ *                                                                            * 
+ * Licensed under the Apache License,  Version 2.0  (the "License");  you may * 
+ * not use this file except in compliance with the License. You may  obtain a * 
+ * copy of the License at http://www.apache.org/licenses/LICENSE-2.0          * 
+ *                                                                            * 
+ * Unless  required  by  applicable  law  or agreed  to in  writing, software * 
+ * distributed under the License is distributed on an "AS IS"  BASIS, WITHOUT * 
+ * WARRANTIES OR CONDITIONS OF ANY KIND, either express  or implied.  See the * 
+ * License for the  specific language  governing permissions  and limitations * 
+ * under the License.                                                         * 
+ ******************************************************************************/
+
+#![cfg(feature = "tsify")]
+
+use graph::{BTreeGraph, Graph, HashGraph, OptRefBy, OptRefMap, RefBy, RefMap};
+use tsify::Tsify;
+
+fn assert_decl(decl: &str, name: &str, ty: &str) {
+    assert!(
+        decl.contains(name) && decl.contains(&format!("= {ty};")),
+        "unexpected declaration: {decl}"
+    );
+}
+
+#[test]
+fn declarations() {
+    assert_decl(<Graph<u32> as Tsify>::DECL, "Graph<T>", "T[]");
+    assert_decl(
+        <BTreeGraph<String, u32> as Tsify>::DECL,
+        "BTreeGraph<K, V>",
+        "K extends string | number ? Record<K, V> : [K, V][]",
+    );
+    assert_decl(
+        <HashGraph<String, u32> as Tsify>::DECL,
+        "HashGraph<K, V>",
+        "K extends string | number ? Record<K, V> : [K, V][]",
+    );
+    assert_decl(<RefBy<String, u32> as Tsify>::DECL, "RefBy<K, V>", "Ref<K>");
+    assert_decl(
+        <OptRefBy<String, u32> as Tsify>::DECL,
+        "OptRefBy<K, V>",
+        "Ref<K>",
+    );
+    assert_decl(
+        <RefMap<String, u32> as Tsify>::DECL,
+        "RefMap<K, V>",
+        "Ref<K>[]",
+    );
+    assert_decl(
+        <OptRefMap<String, u32> as Tsify>::DECL,
+        "OptRefMap<K, V>",
+        "Ref<K>[]",
+    );
+}
+
+#[cfg(target_arch = "wasm32")]
+mod wasm {
+    use graph::{BTreeGraph, IndexBy, RefBy, RefMap, Resolve};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use tsify::Tsify;
+    use wasm_bindgen::JsValue;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    /* The brand only exists on the TS side: at runtime, references
+     * cross the ABI as plain keys. */
+    #[wasm_bindgen_test]
+    fn ref_abi() {
+        let node = RefBy::<String, u32>::dangling("a".to_string());
+        let value: JsValue = node.into_js().unwrap().into();
+        assert_eq!(value.as_string().as_deref(), Some("a"));
+        #[cfg(feature = "js-ref-helpers")]
+        assert_eq!(graph::js_ref_key(graph::js_as_ref(value.clone())), value);
+        let node = RefBy::<String, u32>::from_js(value).unwrap();
+        assert_eq!(node.key(), "a");
+
+        let map = [("b".to_string(), node.value_ref().clone())]
+            .into_iter()
+            .collect::<RefMap<_, u32>>();
+        let map = RefMap::<String, u32>::from_js(map.into_js().unwrap()).unwrap();
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec!["b"]);
+    }
+
+    struct Node(Option<RefBy<String, Node>>);
+
+    impl Serialize for Node {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            self.0.serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Node {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            Option::deserialize(deserializer).map(Node)
+        }
+    }
+
+    impl Resolve<String, Node> for Node {
+        fn resolve<I: IndexBy<String, Node>>(&mut self, index: &I) -> Result<(), String> {
+            self.0.resolve(index)
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn js_roundtrip() {
+        let mut graph = BTreeGraph::new();
+        graph.insert("a".to_string(), Node(None));
+        graph.insert(
+            "b".to_string(),
+            Node(Some(RefBy::dangling("a".to_string()))),
+        );
+        graph.resolve().unwrap();
+
+        let graph = BTreeGraph::<String, Node>::from_js_resolved(graph.to_js().unwrap()).unwrap();
+        let next = graph.get("b").unwrap().0.as_ref().unwrap();
+        assert!(next.value_ref() == graph.get_ref("a").unwrap());
+    }
+
+    #[wasm_bindgen_test]
+    fn js_unresolved() {
+        let mut graph = BTreeGraph::new();
+        graph.insert(
+            "b".to_string(),
+            Node(Some(RefBy::dangling("a".to_string()))),
+        );
+        let value = graph.into_js().unwrap().into();
+
+        let err = BTreeGraph::<String, Node>::from_js_resolved(value).unwrap_err();
+        assert_eq!(err.message(), "1 unresolved reference(s)");
+        assert_eq!(err.missing(), ["a".to_string()]);
+    }
+
+    #[wasm_bindgen_test]
+    fn js_promised() {
+        let mut graph = BTreeGraph::new();
+        graph.insert("a".to_string(), Node(None));
+        graph.promise("b".to_string());
+
+        let err = graph.to_js().unwrap_err();
+        assert_eq!(err.message(), "1 promised node(s) not created");
+        assert_eq!(err.missing(), ["b".to_string()]);
+    }
+}